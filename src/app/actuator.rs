@@ -0,0 +1,274 @@
+/*
+ * Actuators: safety outputs driven directly by native pins. A failed switch
+ * is retried once, then counted, blinked and broadcast - a stuck output
+ * can't be ignored.
+ */
+
+use defmt::{Format, error, info};
+use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+use embassy_sync::channel::{Channel, Sender};
+use embassy_time::{Duration, Instant, with_timeout};
+use embedded_hal::digital::OutputPin;
+
+use crate::components::message::{Message, args};
+use crate::components::queue::WhenFull;
+use crate::components::status::{self, LedLine, Status};
+
+#[derive(Format)]
+pub enum PinType {
+    /* Bistable outputs */
+    ActiveHigh,
+    ActiveLow,
+    /* Monostable outputs, with impulse activation for X ms */
+    // ImpulseHigh(u16),
+    // ImpulseLow(u16),
+
+    /* Toggle on activation */
+    // Toggle,
+}
+
+#[derive(Format, Copy, Clone, Eq, PartialEq)]
+pub enum Action {
+    On,
+    Off,
+}
+
+/// Action for a single actuator.
+#[derive(Format, Copy, Clone, Eq, PartialEq)]
+pub struct Command {
+    pub actuator_idx: usize,
+    pub action: Action,
+}
+
+/// Bus the failures are broadcast on. Mocked in tests.
+#[allow(async_fn_in_trait)]
+pub trait ActuatorBus {
+    async fn transmit(&self, message: &Message, when_full: WhenFull);
+}
+
+/// A single actuator (physical pin + configuration)
+pub struct Actuator<T: OutputPin> {
+    pin: T,
+    pin_type: PinType,
+
+    active_since: Option<Instant>,
+
+    /// Max activation time in milliseconds.
+    activation_limit: Option<u32>,
+}
+
+impl<T: OutputPin> Actuator<T> {
+    pub fn new(pin: T, pin_type: PinType) -> Self {
+        Self {
+            pin,
+            pin_type,
+
+            active_since: None,
+            activation_limit: None,
+        }
+    }
+
+    /// Limit the activation time.
+    pub fn with_activation_limit(mut self, limit_ms: u32) -> Self {
+        self.activation_limit = Some(limit_ms);
+        self
+    }
+
+    fn enable(&mut self) -> Result<(), ()> {
+        info!("Enabling pin {}", self.pin_type);
+        match self.pin_type {
+            PinType::ActiveHigh => self.pin.set_high().map_err(|_| ()),
+            PinType::ActiveLow => self.pin.set_low().map_err(|_| ()),
+        }
+    }
+
+    fn disable(&mut self) -> Result<(), ()> {
+        match self.pin_type {
+            PinType::ActiveHigh => self.pin.set_low().map_err(|_| ()),
+            PinType::ActiveLow => self.pin.set_high().map_err(|_| ()),
+        }
+    }
+
+    /// Call pin operation and retry once on failure.
+    fn with_retry(&mut self, op: fn(&mut Self) -> Result<(), ()>) -> Result<(), ()> {
+        if op(self).is_ok() {
+            return Ok(());
+        }
+        status::COUNTERS.actuator_error.inc();
+        error!("Actuator pin failed, retrying");
+        op(self)
+    }
+
+    /// Active for longer than the activation limit.
+    fn is_expired(&self, now: Instant) -> bool {
+        match (self.active_since, self.activation_limit) {
+            (Some(since), Some(limit)) => now >= since + Duration::from_millis(limit as u64),
+            _ => false,
+        }
+    }
+}
+
+type CommandChannel = Channel<NoopRawMutex, Command, 3>;
+type CommandSender<'a> = Sender<'a, NoopRawMutex, Command, 3>;
+
+/// Actuator controller; manages bunch of actuators.
+pub struct ActuatorCtrl<
+    T: OutputPin,
+    B: ActuatorBus + 'static,
+    L: LedLine + 'static,
+    const N: usize,
+> {
+    actuators: [Actuator<T>; N],
+    channel: CommandChannel,
+    /// Failures are broadcast here.
+    bus: &'static B,
+    /// Blinks a warning when an actuator can't be switched.
+    status: Option<&'static Status<L>>,
+}
+
+impl<T: OutputPin, B: ActuatorBus + 'static, L: LedLine + 'static, const N: usize>
+    ActuatorCtrl<T, B, L, N>
+{
+    pub fn new(
+        actuators: [Actuator<T>; N],
+        bus: &'static B,
+        status: Option<&'static Status<L>>,
+    ) -> Self {
+        Self {
+            actuators,
+            channel: CommandChannel::new(),
+            bus,
+            status,
+        }
+    }
+
+    /// Execute command. Failure after a single retry is broadcast as an
+    /// ActuatorFailure error with the actuator in the detail.
+    pub async fn execute(&mut self, cmd: Command) -> Result<(), ()> {
+        info!("Executing command {}", cmd);
+        let actuator = &mut self.actuators[cmd.actuator_idx];
+        let result = match cmd.action {
+            Action::On => {
+                actuator.active_since = Some(Instant::now());
+                actuator.with_retry(Actuator::enable)
+            }
+            Action::Off => {
+                actuator.active_since = None;
+                actuator.with_retry(Actuator::disable)
+            }
+        };
+        if result.is_err() {
+            error!("Actuator {} failed persistently", cmd.actuator_idx);
+            let message = Message::Error {
+                code: args::ErrorCode::ActuatorFailure.with_detail(cmd.actuator_idx as u8),
+            };
+            if let Some(status) = self.status {
+                status.show_message(&message);
+            }
+            self.bus.transmit(&message, WhenFull::Wait).await;
+        }
+        result
+    }
+
+    pub fn get_channel(&self) -> CommandSender<'_> {
+        self.channel.sender()
+    }
+
+    /// Execute received commands and switch off the actuators active for
+    /// longer than their limit.
+    pub async fn control(&mut self) {
+        let timeout = Duration::from_secs(1);
+        loop {
+            if let Ok(cmd) = with_timeout(timeout, self.channel.receive()).await {
+                // Failures are reported by execute.
+                let _ = self.execute(cmd).await;
+            }
+            let now = Instant::now();
+            for actuator_idx in 0..N {
+                if self.actuators[actuator_idx].is_expired(now) {
+                    let cmd = Command {
+                        actuator_idx,
+                        action: Action::Off,
+                    };
+                    let _ = self.execute(cmd).await;
+                }
+            }
+        }
+    }
+}
+
+pub mod tests {
+    use super::*;
+    use core::cell::RefCell;
+    use embedded_hal::digital::{ErrorKind, ErrorType, PinState};
+
+    /// Pin that fails to go low.
+    struct StuckHighPin;
+
+    impl ErrorType for StuckHighPin {
+        type Error = ErrorKind;
+    }
+
+    impl OutputPin for StuckHighPin {
+        fn set_low(&mut self) -> Result<(), Self::Error> {
+            Err(ErrorKind::Other)
+        }
+
+        fn set_high(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    /// Bus recording the sent error codes.
+    struct MockBus {
+        errors: RefCell<heapless::Vec<u32, 4>>,
+    }
+
+    // Tests are single threaded.
+    unsafe impl Sync for MockBus {}
+
+    impl ActuatorBus for MockBus {
+        async fn transmit(&self, message: &Message, _when_full: WhenFull) {
+            if let Message::Error { code } = message {
+                self.errors.borrow_mut().push(*code).unwrap();
+            }
+        }
+    }
+
+    struct NoLed;
+
+    impl LedLine for NoLed {
+        fn set_level(&mut self, _level: PinState) {}
+    }
+
+    pub fn failed_disable_is_surfaced() {
+        static BUS: MockBus = MockBus {
+            errors: RefCell::new(heapless::Vec::new()),
+        };
+        let errors = status::COUNTERS.actuator_error.get();
+        let mut ctrl: ActuatorCtrl<_, _, NoLed, 1> = ActuatorCtrl::new(
+            [Actuator::new(StuckHighPin, PinType::ActiveHigh)],
+            &BUS,
+            None,
+        );
+
+        let on = Command {
+            actuator_idx: 0,
+            action: Action::On,
+        };
+        assert!(embassy_futures::block_on(ctrl.execute(on)).is_ok());
+        assert!(BUS.errors.borrow().is_empty());
+
+        let off = Command {
+            actuator_idx: 0,
+            action: Action::Off,
+        };
+        assert!(embassy_futures::block_on(ctrl.execute(off)).is_err());
+        // Failed first try and retried once.
+        assert_eq!(status::COUNTERS.actuator_error.get(), errors + 1);
+        assert_eq!(
+            BUS.errors.borrow().as_slice(),
+            &[args::ErrorCode::ActuatorFailure.with_detail(0)]
+        );
+    }
+}
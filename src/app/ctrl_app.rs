@@ -1,130 +1,162 @@
 use crate::buttonsmash::shutters;
-use crate::components::interconnect::WhenFull;
-use defmt::unwrap;
+use crate::components::queue::WhenFull;
 use embassy_executor::Spawner;
+use embassy_futures::select::{Either, select};
 use embassy_stm32::rtc::{DateTime, DayOfWeek};
 use embassy_stm32::uid;
 use embassy_time::{Duration, Instant, Timer};
 use static_cell::StaticCell;
 
 use crate::boards::ctrl_board::Board;
-use crate::components::message::{Message, args};
-use crate::components::status;
-
-use crate::buttonsmash::consts::BINDINGS_COUNT;
-use crate::buttonsmash::{Event, EventChannel, Executor, Opcode};
-use crate::config;
+use crate::components::boot_guard::BootMode;
+use crate::components::bus_watchdog::Liveness;
+use crate::components::diagnostics::Diagnostics;
+use crate::components::message::{Message, MessageRaw, args};
+use crate::components::spawn::{SpawnReport, TaskId};
+use crate::components::time_sync::{self, TimeDecision, TimeSync};
+use crate::components::{coalesce, node_address, queue, status};
+
+use crate::app::direct::DirectMap;
+use crate::app::program::DEFAULT_PROGRAM;
+use crate::buttonsmash::consts::{BINDINGS_COUNT, OutIdx, REGISTERS, sender_address};
+use crate::buttonsmash::{Control, ControlChannel, Event, EventChannel, Executor, Opcode};
+use crate::config::{self, InputMode, StartupOutputs};
 use crate::io::event_converter::run_event_converter;
 
 /// High-level command queue that are consumed by executor.
 static EVENT_CHANNEL: EventChannel = EventChannel::new();
-static EXECUTOR: StaticCell<Executor<BINDINGS_COUNT>> = StaticCell::new();
+/// Executor reconfiguration requests.
+static CONTROL_CHANNEL: ControlChannel = ControlChannel::new();
+static EXECUTOR: StaticCell<Executor<Board, BINDINGS_COUNT>> = StaticCell::new();
+/// Shutters parked for a program reload.
+static SHUTTERS_PARKED: shutters::ParkSignal = shutters::ParkSignal::new();
+
+/// Queue a remote event for the executor, following the node overflow policy.
+async fn emit_event(event: Event) {
+    if let Some(dropped) = queue::send(&EVENT_CHANNEL, event, config::board::QUEUE_OVERFLOW).await {
+        defmt::warn!("Event queue is full, dropped {:?}", dropped);
+    }
+}
 
 /// Main application/business logic entrypoint.
 pub struct CtrlApp {
     /// For all IO needs (and comm peripherals like CAN and USB)
     pub board: &'static Board,
     pub shutters: shutters::ShutterChannel,
-    pub executor: Option<&'static mut Executor<BINDINGS_COUNT>>,
+    pub executor: Option<&'static mut Executor<Board, BINDINGS_COUNT>>,
+    /// Safe mode runs no program and keeps the outputs off.
+    pub mode: BootMode,
 }
 
 impl CtrlApp {
     /// Create CtrlApp as much as you can, but watchout for growing too large.
     /// When used with .awaits, the future grew once to 5kB. That's why it's
     /// split currently between new, configure, spawn_tasks and uses statics.
-    pub fn new(board: &'static Board, spawner: &Spawner) -> Self {
+    pub fn new(board: &'static Board, spawner: &Spawner, mode: BootMode) -> Self {
         let shutters_channel: shutters::ShutterChannel = ector::actor!(
             spawner,
             shutters,
-            shutters::Manager,
-            shutters::Manager::new(board)
+            shutters::Manager<Board>,
+            shutters::Manager::new(board).with_parked(&SHUTTERS_PARKED)
         )
         .into();
 
-        let executor = EXECUTOR.init(Executor::new(board, shutters_channel));
+        // Direct and safe mode don't need the executor at all.
+        let executor = match config::board::INPUT_MODE {
+            InputMode::Microvm if mode == BootMode::Normal => {
+                let mut executor =
+                    Executor::new(board, shutters_channel).with_parked(&SHUTTERS_PARKED);
+                executor.set_default_command(config::board::DEFAULT_COMMAND);
+                Some(EXECUTOR.init(executor))
+            }
+            _ => None,
+        };
         Self {
             board,
-            executor: Some(executor),
+            executor,
             shutters: shutters_channel,
+            mode,
         }
     }
 
-    pub fn spawn_tasks(&mut self, spawner: &Spawner) {
-        // let executor = unsafe { &mut *self.executor.get() };
-        let executor = self.executor.take().expect("This needs to be defined");
-        spawner.spawn(unwrap!(task_pump_switch_events_to_microvm(executor)));
-        spawner.spawn(unwrap!(run_event_converter(
-            self.board.input_q,
-            &EVENT_CHANNEL
-        )));
-        spawner.spawn(unwrap!(task_read_interconnect(self.board, self.shutters)));
+    pub fn spawn_tasks(&mut self, spawner: &Spawner) -> SpawnReport {
+        let mut report = SpawnReport::new();
+        report.spawn(TaskId::EventConverter, true, || {
+            run_event_converter(self.board.input_q, &EVENT_CHANNEL)
+                .map(|token| spawner.spawn(token))
+        });
+        if self.mode == BootMode::Safe {
+            let board = self.board;
+            report.spawn(TaskId::SafeMode, true, || {
+                task_safe_mode(board).map(|token| spawner.spawn(token))
+            });
+            return report;
+        }
+        match config::board::INPUT_MODE {
+            InputMode::Microvm => {
+                // Executor is owned by the listen task from now on.
+                // Reconfigure it through CONTROL_CHANNEL.
+                let executor = self.executor.take().expect("This needs to be defined");
+                report.spawn(TaskId::EventPump, true, || {
+                    task_pump_switch_events_to_microvm(executor).map(|token| spawner.spawn(token))
+                });
+            }
+            InputMode::Direct(table) => {
+                let board = self.board;
+                report.spawn(TaskId::EventPump, true, || {
+                    task_direct_mode(board, DirectMap::new(table)).map(|token| spawner.spawn(token))
+                });
+            }
+        }
+        report.spawn(TaskId::ReadInterconnect, true, || {
+            task_read_interconnect(self.board, self.shutters).map(|token| spawner.spawn(token))
+        });
+        report
     }
 
     /// Returns hard-configured Executor. TODO: This is temporary. Code should
     /// be programmable and read from flash on start.
     pub async fn configure(&mut self) {
-        const PROGRAM: [Opcode; 34] = [
-            // Setup proc.
-            Opcode::Start(0),
-            // Basic usable program for initial setup.
-            Opcode::LayerDefault,
-            Opcode::BindShortToggle(1, 1),
-            // Opcode::BindShortCall(1, 1), // Testing shutters via procedure 1.
-            Opcode::BindShortToggle(2, 2),
-            Opcode::BindShortToggle(3, 3),
-            Opcode::BindShortToggle(4, 4),
-            Opcode::BindShortToggle(5, 5),
-            Opcode::BindShortToggle(6, 6),
-            Opcode::BindShortToggle(7, 7),
-            Opcode::BindShortToggle(8, 8),
-            Opcode::BindShortToggle(9, 9),
-            Opcode::BindShortToggle(10, 10),
-            Opcode::BindShortToggle(11, 11),
-            Opcode::BindShortToggle(12, 12),
-            Opcode::BindShortToggle(13, 13),
-            Opcode::BindShortToggle(14, 14),
-            Opcode::BindShortToggle(15, 15),
-            Opcode::BindShortToggle(16, 16),
-            // Configure shutter down/up. Don't use unconfigured shutters.
-            Opcode::BindShutter(0, 13, 14),
-            Opcode::BindShutter(1, 15, 16),
-            // Opcode::BindLongActivate(1, 2),
-
-            // Send the complete status on initialization.
-            Opcode::SendStatus,
-            Opcode::Stop,
-            /*
-            Opcode::BindShortToggle(1, 10),
-            Opcode::BindShortToggle(2, 11),
-            Opcode::BindLongToggle(3, 20),
-            Opcode::BindShortToggle(3, 21),
-            Opcode::BindShortCall(4, 1),
-            Opcode::BindLayerHold(5, 66),
-            Opcode::LayerPush(66),
-            Opcode::BindShortToggle(1, 13),
-            */
-            Opcode::Stop,
-
-            // Shutter control - Tilt.
-            Opcode::Start(1),
-            Opcode::ShutterCmd(0, shutters::Cmd::TiltReverse),
-            Opcode::Stop,
-
-            // Test procedure 2
-            Opcode::Start(2),
-            Opcode::Activate(100),
-            Opcode::Activate(101),
-            Opcode::Deactivate(110),
-            Opcode::Stop,
-            // Test procedure 3.
-            Opcode::Start(3),
-            Opcode::Noop,
-            Opcode::Stop,
-        ];
-
-        let executor = self.executor.take().expect("This needs to be defined");
-        executor.load_static(&PROGRAM).await;
-        self.executor = Some(executor);
+        if self.mode == BootMode::Safe {
+            defmt::warn!("Safe mode - program is not loaded");
+            return;
+        }
+        if let InputMode::Direct(table) = config::board::INPUT_MODE {
+            defmt::info!("Direct mode with {} mapped inputs", table.len());
+            return;
+        }
+        self.reload(&DEFAULT_PROGRAM).await;
+    }
+
+    /// Replace the executor program. Applied by the listen task between
+    /// events, so it's safe while the executor is running.
+    pub async fn reload(&self, program: &'static [Opcode]) {
+        CONTROL_CHANNEL.send(Control::ReloadProgram(program)).await;
+    }
+
+    /// Show a silent bus. Node keeps working locally, time runs from the
+    /// RTC until the gate is back.
+    fn check_bus(&self, now: Instant) {
+        match self.board.interconnect.liveness.update(now) {
+            Some(Liveness::Silent) => {
+                defmt::warn!("No frames on the bus - gate is silent. Running on local time.");
+                self.board.status.set_attention(true);
+            }
+            Some(Liveness::Recovered) => {
+                defmt::info!("Bus is alive again");
+                self.board.status.set_attention(false);
+            }
+            None => {}
+        }
+    }
+
+    /// Clear the crash loop counter once the node runs long enough.
+    async fn check_stable(&self, stable: &mut bool) {
+        if !*stable && self.board.uptime_secs() >= config::board::SAFE_MODE.stable_s {
+            defmt::info!("Boot is stable");
+            self.board.clear_boot_faults().await;
+            *stable = true;
+        }
     }
 
     pub async fn main(&'static mut self) -> ! {
@@ -132,11 +164,41 @@ impl CtrlApp {
 
         let welcome_message = Message::Info {
             code: args::InfoCode::Started.to_bytes(),
-            arg: 0,
+            arg: args::pack_version(Diagnostics::firmware_version()),
         };
 
-        if !self.board.init_outputs().await.is_ok() {
-            defmt::info!("Error while initializing outputs. Expander error?");
+        let startup = match self.mode {
+            BootMode::Normal => config::board::STARTUP_OUTPUTS,
+            BootMode::Safe => {
+                self.board.status.set_attention(true);
+                StartupOutputs::AllOff
+            }
+        };
+        if let Err(err) = self.board.init_outputs(startup).await {
+            defmt::error!("Error while initializing outputs: {:?}", err);
+            status::COUNTERS.expander_output_error.inc();
+            let code = args::ErrorCode::ExpanderOutputFailure;
+            let message = Message::Error {
+                code: code.with_detail(err.detail()),
+            };
+            self.board
+                .interconnect
+                .transmit_response(&message, WhenFull::Wait)
+                .await;
+        }
+
+        // Report missing or conflicting expanders.
+        for (addr, result) in self.board.probe_expanders().await {
+            if let Some(code) = result.error_code() {
+                defmt::error!("Expander {:#x}: {:?}", addr, code);
+                let message = Message::Error {
+                    code: code.to_u32(),
+                };
+                self.board
+                    .interconnect
+                    .transmit_response(&message, WhenFull::Wait)
+                    .await;
+            }
         }
 
         if !self
@@ -150,12 +212,16 @@ impl CtrlApp {
 
         let mut cnt = 0;
         let mut last_tick = Instant::now();
+        // Safe mode is left only with the recovery command.
+        let mut stable = self.mode == BootMode::Safe;
 
         if cfg!(feature = "deep-sleep") {
             loop {
                 // Prevent deep sleep to allow easy remote debugging.
                 // TODO: Remove for production.
                 Timer::after(Duration::from_secs(10)).await;
+                self.check_bus(Instant::now());
+                self.check_stable(&mut stable).await;
                 defmt::info!("Tick: {:?}", status::COUNTERS);
             }
         } else {
@@ -164,6 +230,8 @@ impl CtrlApp {
                 cnt += 1;
                 if cnt == 300 {
                     let now = Instant::now();
+                    self.check_bus(now);
+                    self.check_stable(&mut stable).await;
                     let passed = (now - last_tick).as_millis();
                     if passed > 10000 {
                         defmt::info!("Tick: {:?}", status::COUNTERS);
@@ -190,8 +258,59 @@ impl CtrlApp {
 }
 
 #[embassy_executor::task(pool_size = 1)]
-pub async fn task_pump_switch_events_to_microvm(executor: &'static mut Executor<BINDINGS_COUNT>) {
-    executor.listen_events(&EVENT_CHANNEL).await;
+pub async fn task_pump_switch_events_to_microvm(
+    executor: &'static mut Executor<Board, BINDINGS_COUNT>,
+) {
+    executor
+        .listen_events(&EVENT_CHANNEL, &CONTROL_CHANNEL)
+        .await;
+}
+
+/// Direct mode replacement of the executor task.
+#[embassy_executor::task(pool_size = 1)]
+pub async fn task_direct_mode(board: &'static Board, map: DirectMap) {
+    loop {
+        let event = EVENT_CHANNEL.receive().await;
+        map.handle(&event, board).await;
+    }
+}
+
+/// Safe mode replacement of the executor and bus tasks. Inputs are dropped,
+/// only the recovery command (ResetRuntime) is handled: it clears the crash
+/// loop counter and reboots.
+#[embassy_executor::task(pool_size = 1)]
+pub async fn task_safe_mode(board: &'static Board) {
+    loop {
+        let raw = match select(EVENT_CHANNEL.receive(), board.interconnect.receive()).await {
+            Either::First(event) => {
+                defmt::info!("Safe mode - ignoring {:?}", event);
+                continue;
+            }
+            Either::Second(Ok(raw)) => raw,
+            Either::Second(Err(())) => {
+                status::COUNTERS.can_frame_error.inc();
+                continue;
+            }
+        };
+        let to_us = raw.addr_type().0 == node_address::ADDRESS.get();
+        if to_us && matches!(Message::from_raw(&raw), Some(Message::ResetRuntime)) {
+            defmt::warn!("Recovery requested - clearing faults and rebooting");
+            board.clear_boot_faults().await;
+            cortex_m::peripheral::SCB::sys_reset();
+        }
+    }
+}
+
+/// Extract a SetOutput addressed to us from a received frame.
+fn parse_set_output(raw: &Result<MessageRaw, ()>) -> Option<(OutIdx, args::OutputChangeRequest)> {
+    let raw = raw.as_ref().ok()?;
+    if !node_address::ADDRESS.accepts(raw.addr_type().0) {
+        return None;
+    }
+    match Message::from_raw(raw)? {
+        Message::SetOutput { output, state, .. } => Some((output, state)),
+        _ => None,
+    }
 }
 
 #[embassy_executor::task(pool_size = 1)]
@@ -199,8 +318,14 @@ pub async fn task_read_interconnect(
     board: &'static Board,
     shutters_channel: shutters::ShutterChannel,
 ) {
+    // Message taken while coalescing a burst, handled before receiving more.
+    let mut pending = None;
+    let mut time_sync = TimeSync::new(config::board::TIME_SYNC);
     loop {
-        let raw = board.interconnect.receive().await;
+        let raw = match pending.take() {
+            Some(raw) => raw,
+            None => board.interconnect.receive().await,
+        };
         defmt::info!("Received raw message {}", raw);
 
         // CAN level parsing.
@@ -216,14 +341,15 @@ pub async fn task_read_interconnect(
         let message = if let Some(message) = Message::from_raw(&raw) {
             message
         } else {
-            defmt::warn!("Error while reading a message {:?}", raw);
+            crate::warn_limited!(50, "Error while reading a message {:?}", raw);
             continue;
         };
 
         // Are we the addressee?
+        let local_address = node_address::ADDRESS.get();
         let to_us = match raw.addr_type().0 {
-            config::LOCAL_ADDRESS => {
-                defmt::warn!("Message is addressed to us - {}", config::LOCAL_ADDRESS);
+            addr if addr == local_address => {
+                defmt::warn!("Message is addressed to us - {}", local_address);
                 true
             }
             config::BROADCAST_ADDRESS => {
@@ -234,10 +360,11 @@ pub async fn task_read_interconnect(
                 true
             }
             addr => {
-                defmt::warn!(
+                crate::warn_limited!(
+                    50,
                     "Message is not addressed to us. (addr {} != local {})",
                     addr,
-                    config::LOCAL_ADDRESS
+                    local_address
                 );
                 false
             }
@@ -258,17 +385,24 @@ pub async fn task_read_interconnect(
                 defmt::warn!("TODO: Emulate input trigger {} as {:?}", input, trigger);
             }
 
-            Message::SetOutput { output, state } => {
+            Message::SetOutput {
+                output,
+                state,
+                source,
+            } => {
                 if !to_us {
                     continue;
                 }
-                let event = match state {
-                    args::OutputChangeRequest::On => Event::RemoteActivate(output),
-                    args::OutputChangeRequest::Off => Event::RemoteDeactivate(output),
-                    args::OutputChangeRequest::Toggle => Event::RemoteToggle(output),
-                };
+                let (state, rest) = coalesce::set_output(
+                    output,
+                    state,
+                    || board.interconnect.try_receive(),
+                    parse_set_output,
+                );
+                pending = rest;
+                let event = Event::set_output(output, state, source);
                 defmt::warn!("Trigger output {} to {:?} -> {:?}", output, state, event);
-                EVENT_CHANNEL.send(event).await;
+                emit_event(event).await;
             }
 
             Message::TimeAnnouncement {
@@ -305,10 +439,35 @@ pub async fn task_read_interconnect(
 
                 match dt {
                     Ok(dt) => {
-                        if board.set_time(dt).await.is_err() {
-                            defmt::error!("RTC returned an error - unable to set time");
-                        } else {
-                            defmt::info!("Time was set.");
+                        let rtc = board.read_time().await;
+                        let rtc = time_sync::epoch_secs(
+                            rtc.year(),
+                            rtc.month(),
+                            rtc.day(),
+                            rtc.hour(),
+                            rtc.minute(),
+                            rtc.second(),
+                        );
+                        let announced =
+                            time_sync::epoch_secs(year, month, day, hour, minute, second);
+                        match time_sync.check(rtc, announced) {
+                            TimeDecision::Apply => {
+                                if board.set_time(dt).await.is_err() {
+                                    defmt::error!("RTC returned an error - unable to set time");
+                                } else {
+                                    time_sync.applied();
+                                    defmt::info!("Time was set.");
+                                }
+                            }
+                            TimeDecision::InSync => {
+                                defmt::debug!("Time announcement matches the RTC");
+                            }
+                            TimeDecision::Reject(jump) => {
+                                defmt::warn!(
+                                    "Rejected time announcement jumping {}s from the RTC",
+                                    jump
+                                );
+                            }
                         }
                     }
                     Err(_err) => {
@@ -326,16 +485,175 @@ pub async fn task_read_interconnect(
                 }
             }
             Message::ShutterCmd { shutter_idx, cmd } => {
+                if !to_us {
+                    continue;
+                }
                 defmt::warn!("Remote shutter cmd to {}: {:?}", shutter_idx, cmd);
                 shutters_channel.send((shutter_idx, cmd)).await;
             }
 
+            Message::ResyncAll { direction, restore } => {
+                if !to_us {
+                    continue;
+                }
+                defmt::warn!("Resync all shutters to {:?}", direction);
+                let cmd = shutters::Cmd::resync_to(direction, restore);
+                shutters_channel.send((shutters::ALL_SHUTTERS, cmd)).await;
+            }
+
+            Message::SwapShutterDirection { shutter_idx } => {
+                if !to_us {
+                    continue;
+                }
+                defmt::warn!("Swap direction of shutter {}", shutter_idx);
+                shutters_channel
+                    .send((shutter_idx, shutters::Cmd::SwapDirection))
+                    .await;
+            }
+
+            Message::RequestShutterConfig { shutter_idx } => {
+                if !to_us {
+                    continue;
+                }
+                if shutter_idx as usize >= config::MAX_SHUTTERS {
+                    defmt::warn!("Invalid shutter {} in RequestShutterConfig", shutter_idx);
+                    continue;
+                }
+                shutters_channel
+                    .send((shutter_idx, shutters::Cmd::RequestConfig))
+                    .await;
+            }
+
+            Message::SetShutterConfig {
+                shutter_idx,
+                rise_time,
+                drop_time,
+                tilt_time,
+            } => {
+                if !to_us {
+                    continue;
+                }
+                if shutter_idx as usize >= config::MAX_SHUTTERS {
+                    defmt::warn!("Invalid shutter {} in SetShutterConfig", shutter_idx);
+                    continue;
+                }
+                let cmd = shutters::Cmd::SetRiseDropTime(rise_time, drop_time);
+                shutters_channel.send((shutter_idx, cmd)).await;
+                let cmd = shutters::Cmd::SetTiltTime(tilt_time);
+                shutters_channel.send((shutter_idx, cmd)).await;
+            }
+
             Message::RequestStatus => {
                 if !to_us {
                     continue;
                 }
                 let event = Event::RemoteStatusRequest;
-                EVENT_CHANNEL.send(event).await;
+                emit_event(event).await;
+            }
+
+            Message::SetAddress { new_addr } => {
+                // Broadcast would give all nodes the same address.
+                if raw.addr_type().0 != local_address {
+                    continue;
+                }
+                if board.set_address(new_addr).await.is_err() {
+                    defmt::error!("Invalid address {} requested", new_addr);
+                    continue;
+                }
+                defmt::warn!("Address changed from {} to {}", local_address, new_addr);
+                let message = Message::Info {
+                    code: args::InfoCode::AddressChanged.to_bytes(),
+                    arg: local_address as u32,
+                };
+                board
+                    .interconnect
+                    .transmit_response(&message, WhenFull::Wait)
+                    .await;
+            }
+
+            Message::RequestDiagnostics => {
+                if !to_us {
+                    continue;
+                }
+                emit_event(Event::RemoteDiagnosticsRequest).await;
+            }
+
+            Message::RequestBindings => {
+                if !to_us {
+                    continue;
+                }
+                emit_event(Event::RemoteBindingsRequest).await;
+            }
+
+            Message::RequestTrace => {
+                if !to_us {
+                    continue;
+                }
+                emit_event(Event::RemoteTraceRequest).await;
+            }
+
+            Message::ResetRuntime => {
+                if !to_us {
+                    continue;
+                }
+                emit_event(Event::RemoteResetRuntime).await;
+            }
+
+            Message::SetRegister { reg, value } => {
+                if !to_us {
+                    continue;
+                }
+                if reg as usize >= REGISTERS {
+                    defmt::warn!("Invalid register {} in SetRegister", reg);
+                    continue;
+                }
+                emit_event(Event::RemoteSetRegister(reg, value)).await;
+            }
+
+            Message::GetRegister { reg } => {
+                if !to_us {
+                    continue;
+                }
+                if reg as usize >= REGISTERS {
+                    defmt::warn!("Invalid register {} in GetRegister", reg);
+                    continue;
+                }
+                emit_event(Event::RemoteGetRegister(reg)).await;
+            }
+
+            Message::CaptureScene { slot } => {
+                if !to_us {
+                    continue;
+                }
+                emit_event(Event::RemoteCaptureScene(slot)).await;
+            }
+
+            Message::RecallScene { slot, source } => {
+                if !to_us {
+                    continue;
+                }
+                let addr = sender_address(source);
+                emit_event(Event::RemoteRecallScene(slot, addr)).await;
+            }
+
+            Message::OverrideOutput {
+                output,
+                state,
+                lock,
+                source,
+            } => {
+                if !to_us {
+                    continue;
+                }
+                let addr = sender_address(source);
+                emit_event(Event::RemoteOverride(output, state, lock, addr)).await;
+            }
+
+            Message::SetMaintenance { enabled } => {
+                if !to_us {
+                    continue;
+                }
+                emit_event(Event::RemoteSetMaintenance(enabled)).await;
             }
 
             Message::Ping { body } => {
@@ -357,6 +675,10 @@ pub async fn task_read_interconnect(
             | Message::StatusIO { .. }
             | Message::InputChanged { .. }
             | Message::Pong { .. }
+            | Message::RegisterValue { .. }
+            | Message::ShutterConfig { .. }
+            | Message::ShutterMotion { .. }
+            | Message::DiagnosticsPart { .. }
             | Message::Status { .. } => {
                 if to_us {
                     defmt::warn!("Unhandled message was addressed to us: {:?}", message);
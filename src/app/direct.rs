@@ -0,0 +1,135 @@
+/*
+ * Direct mode: switch N toggles output N, without the microvm. Simplest
+ * installs don't need bindings, layers nor procedures - a fixed table of
+ * (input, output) pairs is consulted by a small event handler. The executor
+ * is not created at all, so its latency and RAM are saved. Remote output
+ * requests keep working. Selected with `config::board::INPUT_MODE`.
+ */
+use crate::buttonsmash::Event;
+use crate::buttonsmash::consts::{InIdx, OutIdx};
+use crate::io::events::{OutputError, Trigger};
+
+/// Outputs driven by the direct mode.
+pub(crate) trait DirectOutputs {
+    async fn toggle_output(&self, idx: OutIdx) -> Result<bool, OutputError>;
+    async fn set_output(&self, idx: OutIdx, state: bool) -> Result<(), OutputError>;
+}
+
+/// Fixed input to output mapping.
+#[derive(Copy, Clone)]
+pub struct DirectMap {
+    table: &'static [(InIdx, OutIdx)],
+}
+
+impl DirectMap {
+    pub const fn new(table: &'static [(InIdx, OutIdx)]) -> Self {
+        Self { table }
+    }
+
+    /// Output toggled by an input trigger. Double clicks count as clicks.
+    pub fn output_for(&self, input: InIdx, trigger: Trigger) -> Option<OutIdx> {
+        if !matches!(trigger, Trigger::ShortClick | Trigger::DoubleClick) {
+            return None;
+        }
+        self.table
+            .iter()
+            .find(|(mapped, _)| *mapped == input)
+            .map(|(_, output)| *output)
+    }
+
+    /// Apply an event to the outputs.
+    pub(crate) async fn handle(&self, event: &Event, outputs: &impl DirectOutputs) {
+        let result = match event {
+            Event::ButtonEvent(button) => {
+                let Some(output) = self.output_for(button.switch_id, button.trigger) else {
+                    return;
+                };
+                outputs.toggle_output(output).await.map(|_| ())
+            }
+            Event::RemoteToggle(output, _) => outputs.toggle_output(*output).await.map(|_| ()),
+            Event::RemoteActivate(output, _) => outputs.set_output(*output, true).await,
+            Event::RemoteDeactivate(output, _) => outputs.set_output(*output, false).await,
+            event => {
+                defmt::warn!("Event {:?} is not handled in direct mode", event);
+                return;
+            }
+        };
+        if let Err(err) = result {
+            defmt::error!("Unable to change output for {:?}: {:?}", event, err);
+        }
+    }
+}
+
+pub mod tests {
+    use super::*;
+    use crate::io::events::ButtonEvent;
+    use core::cell::RefCell;
+    use embassy_time::Instant;
+
+    /// Outputs recorded in memory.
+    struct FakeOutputs {
+        states: RefCell<[bool; 8]>,
+    }
+
+    impl DirectOutputs for FakeOutputs {
+        async fn toggle_output(&self, idx: OutIdx) -> Result<bool, OutputError> {
+            let mut states = self.states.borrow_mut();
+            let state = states.get_mut(idx as usize).ok_or(OutputError::unknown())?;
+            *state = !*state;
+            Ok(*state)
+        }
+
+        async fn set_output(&self, idx: OutIdx, state: bool) -> Result<(), OutputError> {
+            *self
+                .states
+                .borrow_mut()
+                .get_mut(idx as usize)
+                .ok_or(OutputError::unknown())? = state;
+            Ok(())
+        }
+    }
+
+    fn click(switch_id: InIdx, trigger: Trigger) -> Event {
+        Event::ButtonEvent(ButtonEvent {
+            switch_id,
+            trigger,
+            at: Instant::from_millis(0),
+        })
+    }
+
+    pub fn short_click_toggles() {
+        static TABLE: [(InIdx, OutIdx); 2] = [(1, 3), (2, 5)];
+        let map = DirectMap::new(&TABLE);
+        let outputs = FakeOutputs {
+            states: RefCell::new([false; 8]),
+        };
+        let handle = |event: Event| embassy_futures::block_on(map.handle(&event, &outputs));
+
+        handle(click(1, Trigger::ShortClick));
+        assert_eq!(
+            *outputs.states.borrow(),
+            [false, false, false, true, false, false, false, false]
+        );
+
+        // Other triggers and unmapped inputs are ignored.
+        handle(click(1, Trigger::LongClick));
+        handle(click(1, Trigger::Activated));
+        handle(click(4, Trigger::ShortClick));
+        assert!(outputs.states.borrow()[3]);
+        assert_eq!(outputs.states.borrow().iter().filter(|s| **s).count(), 1);
+
+        // Toggles back, double click acts as a click.
+        handle(click(1, Trigger::DoubleClick));
+        handle(click(2, Trigger::ShortClick));
+        assert!(!outputs.states.borrow()[3]);
+        assert!(outputs.states.borrow()[5]);
+
+        // Remote requests still work.
+        handle(Event::RemoteActivate(0, 1));
+        handle(Event::RemoteDeactivate(5, 1));
+        assert_eq!(
+            *outputs.states.borrow(),
+            [true, false, false, false, false, false, false, false]
+        );
+    }
+}
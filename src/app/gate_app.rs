@@ -1,13 +1,19 @@
-use defmt::unwrap;
 use embassy_executor::Spawner;
 use embassy_stm32::uid;
 use embassy_time::{Duration, Timer};
 
 use crate::boards::ctrl_board::Board;
-use crate::components::interconnect::WhenFull;
+use crate::components::queue::WhenFull;
 use crate::components::{
+    bus_filter::USB_FILTER,
+    diagnostics::{Diagnostics, Reassembler},
     message::{Message, MessageRaw, args},
-    status, usb_connect,
+    sequence::{SequenceCheck, SequenceTracker},
+    spawn::{SpawnReport, TaskId},
+    state_cache::{STATE_CACHE, StateCache},
+    status,
+    trace::{TRACE_PART, TraceEntry},
+    usb_connect,
 };
 
 /// Main application/business logic entrypoint.
@@ -21,9 +27,15 @@ impl GateApp {
         Self { board }
     }
 
-    fn spawn_tasks(&'static self, spawner: &Spawner) {
-        spawner.spawn(unwrap!(task_read_interconnect(self.board)));
-        spawner.spawn(unwrap!(task_read_usb(self.board)));
+    fn spawn_tasks(&'static self, spawner: &Spawner) -> SpawnReport {
+        let mut report = SpawnReport::new();
+        report.spawn(TaskId::ReadInterconnect, true, || {
+            task_read_interconnect(self.board).map(|token| spawner.spawn(token))
+        });
+        report.spawn(TaskId::ReadUsb, true, || {
+            task_read_usb(self.board).map(|token| spawner.spawn(token))
+        });
+        report
     }
 
     pub async fn main(&'static mut self, spawner: &Spawner) -> ! {
@@ -31,7 +43,7 @@ impl GateApp {
 
         let welcome_message = Message::Info {
             code: args::InfoCode::Started.to_bytes(),
-            arg: 0,
+            arg: args::pack_version(Diagnostics::firmware_version()),
         };
 
         // Gate can block because it makes no sense without working CAN.
@@ -40,7 +52,9 @@ impl GateApp {
             .transmit_response(&welcome_message, WhenFull::Block)
             .await;
 
-        self.spawn_tasks(spawner);
+        self.spawn_tasks(spawner)
+            .report(&self.board.interconnect)
+            .await;
 
         let mut cnt = 0;
         loop {
@@ -60,17 +74,81 @@ impl GateApp {
 /// Read interconnect and pump into USB.
 #[embassy_executor::task]
 pub async fn task_read_interconnect(board: &'static Board) {
+    let mut diagnostics = Reassembler::new();
+    let mut sequences = SequenceTracker::new();
     loop {
         let raw = board.interconnect.receive().await;
         defmt::info!("Interconnect: Received message {}. Pushing to USB.", raw);
 
         if let Ok(msg) = raw {
-            let mut buf = usb_connect::CommPacket::default();
-            (buf.data[0], buf.data[1]) = msg.addr_type();
-            buf.data[2] = msg.length();
-            buf.data[3..3 + msg.length() as usize].copy_from_slice(msg.data_as_slice());
-            buf.count = 3 + msg.length();
+            if let Some(code) = msg.error_code() {
+                let node = msg.addr_type().0;
+                match args::ErrorCode::from_u32(code) {
+                    Some(error) => defmt::warn!(
+                        "Node {} reports error: {} (detail {})",
+                        node,
+                        error.name(),
+                        args::ErrorCode::detail(code)
+                    ),
+                    None => defmt::warn!("Node {} reports unknown error {}", node, code),
+                }
+            }
+
+            if let Some([major, minor, patch]) = msg.started_version() {
+                let node = msg.addr_type().0;
+                defmt::info!(
+                    "Node {} started, firmware {}.{}.{}",
+                    node,
+                    major,
+                    minor,
+                    patch
+                );
+            }
+
+            if let Some(seq) = msg.sequence() {
+                let node = msg.addr_type().0;
+                match sequences.check(node, seq) {
+                    SequenceCheck::Gap(missed) => defmt::warn!(
+                        "Node {} lost {} frames ({} in total)",
+                        node,
+                        missed,
+                        sequences.lost
+                    ),
+                    SequenceCheck::Duplicate => {
+                        defmt::warn!("Node {} sent duplicate frame {}", node, seq)
+                    }
+                    SequenceCheck::Restarted => defmt::info!("Node {} restarted numbering", node),
+                    SequenceCheck::First | SequenceCheck::InOrder => {}
+                }
+            }
+
+            // Raw frames go to USB anyway, this is for the local log.
+            if let Some((index, total, data)) = msg.diagnostics_part() {
+                let node = msg.addr_type().0;
+                if index & TRACE_PART != 0 {
+                    match TraceEntry::deserialize(&data) {
+                        Some(entry) => defmt::info!(
+                            "Node {} trace {}/{}: {:?}",
+                            node,
+                            index & !TRACE_PART,
+                            total,
+                            entry
+                        ),
+                        None => defmt::warn!("Node {} sent unknown trace entry", node),
+                    }
+                } else if let Some(dump) = diagnostics.push(node, index, total, &data) {
+                    defmt::info!("Node {} diagnostics: {:?}", node, dump);
+                }
+            }
+
+            STATE_CACHE.lock(|cache| cache.borrow_mut().update(&msg));
+
+            let (addr, msg_type) = msg.addr_type();
+            if !USB_FILTER.lock(|filter| filter.borrow().accepts(addr, msg_type)) {
+                continue;
+            }
 
+            let buf = usb_packet(&msg);
             if !board.usb_up.is_empty() {
                 defmt::warn!(
                     "Non-empty queue (len={}) when sending to USB.",
@@ -79,12 +157,35 @@ pub async fn task_read_interconnect(board: &'static Board) {
             }
             board.usb_up.send(buf).await;
         } else {
-            defmt::warn!("Error while reading a message {:?}", raw);
+            crate::warn_limited!(100, "Error while reading a message {:?}", raw);
             continue;
         };
     }
 }
 
+/// Frame as sent to the host: [addr] [msg_type] [length] [data].
+fn usb_packet(msg: &MessageRaw) -> usb_connect::CommPacket {
+    let mut buf = usb_connect::CommPacket::default();
+    (buf.data[0], buf.data[1]) = msg.addr_type();
+    buf.data[2] = msg.length();
+    buf.data[3..3 + msg.length() as usize].copy_from_slice(msg.data_as_slice());
+    buf.count = 3 + msg.length();
+    buf
+}
+
+/// Send the cached state of all nodes to the host. Nodes are copied one by
+/// one, so the reader isn't blocked by the slow USB.
+async fn dump_state(board: &'static Board) {
+    let mut pos = 0;
+    while let Some(node) = STATE_CACHE.lock(|cache| cache.borrow().node(pos)) {
+        for raw in node.dump() {
+            board.usb_up.send(usb_packet(&raw)).await;
+        }
+        pos += 1;
+    }
+    defmt::info!("Dumped state of {} nodes", pos);
+}
+
 /// Read interconnect and pump into USB.
 #[embassy_executor::task]
 pub async fn task_read_usb(board: &'static Board) {
@@ -92,6 +193,24 @@ pub async fn task_read_usb(board: &'static Board) {
         let raw = board.usb_down.receive().await;
         defmt::info!("USB RX: Received message {}", raw.as_slice());
 
+        // Commands for the gate itself don't go to the bus.
+        if StateCache::is_dump_command(raw.as_slice()) {
+            dump_state(board).await;
+            continue;
+        }
+        let command = USB_FILTER.lock(|filter| filter.borrow_mut().handle_command(raw.as_slice()));
+        match command {
+            Some(Ok(())) => {
+                defmt::info!("USB forward filter updated");
+                continue;
+            }
+            Some(Err(err)) => {
+                defmt::warn!("Invalid gate command {}: {:?}", raw.as_slice(), err);
+                continue;
+            }
+            None => {}
+        }
+
         let length = raw.data[2] as usize;
         if length > 8 {
             defmt::error!("Received message is too big ({}), ignoring.", length);
@@ -1,6 +1,14 @@
 // Code in this module needs to be testable on a PC.
 
+pub mod actuator;
+#[cfg(target_os = "none")]
 pub mod ctrl_app;
+pub mod direct;
+#[cfg(target_os = "none")]
 pub mod gate_app;
+pub mod program;
+pub mod role;
+#[cfg(target_os = "none")]
 pub use ctrl_app::CtrlApp;
+#[cfg(target_os = "none")]
 pub use gate_app::GateApp;
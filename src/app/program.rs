@@ -0,0 +1,66 @@
+/*
+ * Program of the controller. Kept apart from the application so it can be
+ * run on mocks in the host tests.
+ */
+use crate::buttonsmash::{Opcode, shutters};
+
+/// Program loaded by `CtrlApp::configure` until programs are stored in
+/// flash.
+pub const DEFAULT_PROGRAM: [Opcode; 34] = [
+    // Setup proc.
+    Opcode::Start(0),
+    // Basic usable program for initial setup.
+    Opcode::LayerDefault,
+    Opcode::BindShortToggle(1, 1),
+    // Opcode::BindShortCall(1, 1), // Testing shutters via procedure 1.
+    Opcode::BindShortToggle(2, 2),
+    Opcode::BindShortToggle(3, 3),
+    Opcode::BindShortToggle(4, 4),
+    Opcode::BindShortToggle(5, 5),
+    Opcode::BindShortToggle(6, 6),
+    Opcode::BindShortToggle(7, 7),
+    Opcode::BindShortToggle(8, 8),
+    Opcode::BindShortToggle(9, 9),
+    Opcode::BindShortToggle(10, 10),
+    Opcode::BindShortToggle(11, 11),
+    Opcode::BindShortToggle(12, 12),
+    Opcode::BindShortToggle(13, 13),
+    Opcode::BindShortToggle(14, 14),
+    Opcode::BindShortToggle(15, 15),
+    Opcode::BindShortToggle(16, 16),
+    // Configure shutter down/up. Don't use unconfigured shutters.
+    Opcode::BindShutter(0, 13, 14),
+    Opcode::BindShutter(1, 15, 16),
+    // Opcode::BindLongActivate(1, 2),
+
+    // Send the complete status on initialization.
+    Opcode::SendStatus,
+    Opcode::Stop,
+    /*
+    Opcode::BindShortToggle(1, 10),
+    Opcode::BindShortToggle(2, 11),
+    Opcode::BindLongToggle(3, 20),
+    Opcode::BindShortToggle(3, 21),
+    Opcode::BindShortCall(4, 1),
+    Opcode::BindLayerHold(5, 66, false),
+    Opcode::LayerPush(66),
+    Opcode::BindShortToggle(1, 13),
+    */
+    Opcode::Stop,
+
+    // Shutter control - Tilt.
+    Opcode::Start(1),
+    Opcode::ShutterCmd(0, shutters::Cmd::TiltReverse),
+    Opcode::Stop,
+
+    // Test procedure 2
+    Opcode::Start(2),
+    Opcode::Activate(51),
+    Opcode::Activate(52),
+    Opcode::Deactivate(53),
+    Opcode::Stop,
+    // Test procedure 3.
+    Opcode::Start(3),
+    Opcode::Noop,
+    Opcode::Stop,
+];
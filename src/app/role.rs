@@ -0,0 +1,98 @@
+/*
+ * Runtime role selection. The same hardware can work as an IO controller or
+ * as a gate, so a single firmware image can pick the role at boot - from the
+ * config or from a strapping pin - and start the matching task set.
+ */
+use crate::config::{Role, RoleSelect};
+
+/// Starts task sets of the roles on top of the common board tasks.
+#[allow(async_fn_in_trait)]
+pub trait Launcher {
+    /// Local IO tasks and the micro VM.
+    async fn ctrl(&mut self);
+    /// CAN <-> USB gate tasks.
+    async fn gate(&mut self);
+}
+
+/// Resolve the role. Strapping pin is read only when it's used.
+pub fn select(select: RoleSelect, strap_active: impl FnOnce() -> bool) -> Role {
+    match select {
+        RoleSelect::Fixed(role) => role,
+        RoleSelect::Strap => {
+            if strap_active() {
+                Role::Gate
+            } else {
+                Role::Ctrl
+            }
+        }
+    }
+}
+
+/// Start tasks of the role. Apps never return, mocks do.
+pub async fn launch(role: Role, launcher: &mut impl Launcher) {
+    defmt::info!("Starting in {:?} role", role);
+    match role {
+        Role::Ctrl => launcher.ctrl().await,
+        Role::Gate => launcher.gate().await,
+    }
+}
+
+pub mod tests {
+    use super::*;
+
+    /// Board with a strapping pin that records started task sets.
+    struct FakeBoard {
+        strap_active: bool,
+        strap_reads: usize,
+        started: Option<Role>,
+    }
+
+    impl FakeBoard {
+        fn new(strap_active: bool) -> Self {
+            Self {
+                strap_active,
+                strap_reads: 0,
+                started: None,
+            }
+        }
+
+        fn boot(&mut self, role_select: RoleSelect) -> Option<Role> {
+            let role = select(role_select, || {
+                self.strap_reads += 1;
+                self.strap_active
+            });
+            embassy_futures::block_on(launch(role, self));
+            self.started
+        }
+    }
+
+    impl Launcher for FakeBoard {
+        async fn ctrl(&mut self) {
+            assert_eq!(self.started, None);
+            self.started = Some(Role::Ctrl);
+        }
+
+        async fn gate(&mut self) {
+            assert_eq!(self.started, None);
+            self.started = Some(Role::Gate);
+        }
+    }
+
+    pub fn role_selects_task_set() {
+        // Strap decides.
+        let mut board = FakeBoard::new(true);
+        assert_eq!(board.boot(RoleSelect::Strap), Some(Role::Gate));
+        assert_eq!(board.strap_reads, 1);
+
+        let mut board = FakeBoard::new(false);
+        assert_eq!(board.boot(RoleSelect::Strap), Some(Role::Ctrl));
+
+        // Fixed role ignores the pin.
+        let mut board = FakeBoard::new(true);
+        assert_eq!(board.boot(RoleSelect::Fixed(Role::Ctrl)), Some(Role::Ctrl));
+        assert_eq!(board.strap_reads, 0);
+
+        let mut board = FakeBoard::new(false);
+        assert_eq!(board.boot(RoleSelect::Fixed(Role::Gate)), Some(Role::Gate));
+    }
+}
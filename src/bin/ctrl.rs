@@ -1,5 +1,6 @@
 /*
- * Main entry point for IO controller boards.
+ * Main entry point for IO controller boards. Can also serve as a gate when
+ * the role is selected at boot (see config::ROLE_SELECT).
  */
 
 #![no_std]
@@ -7,42 +8,82 @@
 // TODO: Temporarily
 #![allow(unused_imports)]
 
-use panic_probe as _;
+// Panic handler lives in io_ctrl::components::safe_shutdown.
 
 use embassy_executor::Spawner;
 use static_cell::StaticCell;
 
+use io_ctrl::components::safe_shutdown;
+
 use embassy_time::{Duration, Timer};
 
 /// Select HW version here.
 use io_ctrl::boards::ctrl_board;
 
 /// Main testable app logic is here.
-use io_ctrl::app::CtrlApp;
+use io_ctrl::app::role::{self, Launcher};
+use io_ctrl::app::{CtrlApp, GateApp};
+use io_ctrl::config;
 
 static BOARD: StaticCell<ctrl_board::Board> = StaticCell::new();
 static APP: StaticCell<CtrlApp> = StaticCell::new();
+static GATE: StaticCell<GateApp> = StaticCell::new();
+
+struct Node {
+    board: &'static ctrl_board::Board,
+    spawner: Spawner,
+}
+
+impl Launcher for Node {
+    async fn ctrl(&mut self) {
+        // Count the boot before anything can crash.
+        let mode = self.board.count_boot(&config::board::SAFE_MODE).await;
+        self.board
+            .spawn_io_tasks(&self.spawner)
+            .report(&self.board.interconnect)
+            .await;
+
+        let app = APP.init(CtrlApp::new(self.board, &self.spawner, mode));
+
+        app.configure().await;
+        app.spawn_tasks(&self.spawner)
+            .report(&self.board.interconnect)
+            .await;
+        app.main().await;
+    }
+
+    async fn gate(&mut self) {
+        let gate = GATE.init(GateApp::new(self.board).await);
+        gate.main(&self.spawner).await;
+    }
+}
 
 #[embassy_executor::main]
 pub async fn main(spawner: Spawner) {
     rtt_target::rtt_init_defmt!();
     defmt::info!("Preinit");
+    // For the stack watermark in the diagnostics.
+    io_ctrl::paint_stack();
 
     // Create board peripherals (early init)
     let board = BOARD.init(ctrl_board::Board::init());
 
+    // Disable outputs before halting/rebooting on panic.
+    safe_shutdown::register(board);
+
     defmt::info!("Starting board");
 
     // Sleep short time to give peripherals time to initialize.
     Timer::after(Duration::from_millis(50)).await;
 
     // Start board tasks.
-    board.spawn_tasks(&spawner);
-    board.spawn_io_tasks(&spawner);
-
-    let app = APP.init(CtrlApp::new(board, &spawner));
+    board
+        .spawn_tasks(&spawner)
+        .report(&board.interconnect)
+        .await;
 
-    app.configure().await;
-    app.spawn_tasks(&spawner);
-    app.main().await;
+    let role = role::select(config::ROLE_SELECT, || {
+        board.role_strap.is_active().unwrap_or(false)
+    });
+    role::launch(role, &mut Node { board, spawner }).await;
 }
@@ -7,11 +7,13 @@
 // TODO: Temporarily
 #![allow(unused_imports)]
 
-use panic_probe as _;
+// Panic handler lives in io_ctrl::components::safe_shutdown.
 
 use embassy_executor::Spawner;
 use static_cell::StaticCell;
 
+use io_ctrl::components::safe_shutdown;
+
 use embassy_time::{Duration, Timer};
 
 use io_ctrl::boards::ctrl_board;
@@ -29,13 +31,19 @@ pub async fn main(spawner: Spawner) {
     // Create board peripherals (early init)
     let board = BOARD.init(ctrl_board::Board::init());
 
+    // Disable outputs before halting/rebooting on panic.
+    safe_shutdown::register(board);
+
     defmt::info!("Starting gate board");
 
     // Sleep short time to give peripherals time to initialize.
     Timer::after(Duration::from_millis(50)).await;
 
     // Start board tasks.
-    board.spawn_tasks(&spawner);
+    board
+        .spawn_tasks(&spawner)
+        .report(&board.interconnect)
+        .await;
 
     let gate = GATE.init(GateApp::new(board).await);
     gate.main(&spawner).await;
@@ -2,31 +2,60 @@
 /// Represents the Hardware. Pretty much everything in this file is static,
 /// initialized once and available through the lifetime of a program.
 ///
+use core::ops::Range;
+
+use crate::app::direct::DirectOutputs;
 use crate::boards::common;
-use defmt::unwrap;
 use embassy_executor::Spawner;
-use embassy_stm32::rtc::{DateTime, Rtc, RtcConfig, RtcError, RtcTimeProvider};
-
-use crate::components::{interconnect::Interconnect, status::Status, usb_connect};
+use embassy_stm32::rtc::{DateTime, DayOfWeek, Rtc, RtcConfig, RtcError, RtcTimeProvider};
+
+use crate::buttonsmash::scenes::{MAX_SCENES, Scene};
+use crate::buttonsmash::shutters::{MotorOutputs, ShutterIo, StateSlot};
+use crate::buttonsmash::vm_io::{ExpanderState, VmIo};
+use crate::components::boot_guard::{BootFaults, BootMode};
+use crate::components::external_rtc::{self, ExternalRtc, RtcTime};
+use crate::components::persistent_store::{Persist, PersistentStore, Storage, StoreError};
+use crate::components::{
+    interconnect::Interconnect,
+    message::Message,
+    node_address,
+    queue::WhenFull,
+    safe_shutdown::SafeShutdown,
+    spawn::{SpawnReport, TaskId},
+    status::{LedLine, Status},
+    usb_connect,
+};
 
 use defmt::info;
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use embassy_sync::mutex::Mutex;
+use embassy_time::{Duration, with_timeout};
 
-use embassy_stm32::gpio::{Level, Output, Speed};
+use embassy_stm32::gpio::{Input, Level, Output, Speed};
 
+use crate::io::i2c_probe::{self, ProbeResult};
 use crate::io::{
-    events::InputChannel, events::IoIdx, expander_inputs, expander_outputs,
-    indexed_outputs::IndexedOutputs, pcf8575::Pcf8575,
+    adc_inputs::{AdcInput, AdcInputSource, NativeAdc},
+    events::InputChannel,
+    events::IoIdx,
+    events::OutputError,
+    expander_inputs, expander_outputs,
+    indexed_outputs::{self, Direction, IndexedOutputs},
+    logical_output::Polarity,
+    native_inputs::NativeInput,
+    pcf8575::Pcf8575,
+    rotary_encoder::RotaryEncoderSource,
 };
 
 use embassy_embedded_hal::shared_bus::asynch::i2c::I2cDevice;
+use embassy_stm32::adc::{Adc, AdcChannel, AdcConfig, AnyAdcChannel};
 use embassy_stm32::i2c::{Config, I2c};
 use embassy_stm32::time::Hertz;
 use embassy_stm32::{bind_interrupts, can, i2c, peripherals};
+use embedded_hal::digital::{OutputPin, PinState};
 use static_cell::StaticCell;
 
-use crate::config;
+use crate::config::{self, SafeModePolicy, StartupOutputs};
 
 bind_interrupts!(struct CanIrqs {
     FDCAN1_IT0 => can::IT0InterruptHandler<peripherals::FDCAN1>;
@@ -42,10 +71,13 @@ type AsyncI2C = I2c<'static, embassy_stm32::mode::Async, embassy_stm32::i2c::Mas
 type SharedI2C = I2cDevice<'static, NoopRawMutex, AsyncI2C>;
 type ExpanderInputs = expander_inputs::ExpanderInputs<SharedI2C>;
 type ExpanderOutputs = expander_outputs::ExpanderOutputs<SharedI2C>;
+type BoardAdcInput = AdcInputSource<
+    NativeAdc<'static, peripherals::ADC1, AnyAdcChannel<'static, peripherals::ADC1>>,
+>;
 
 static I2C_BUS: StaticCell<Mutex<NoopRawMutex, AsyncI2C>> = StaticCell::new();
 
-static STATUS: StaticCell<Status> = StaticCell::new();
+static STATUS: StaticCell<BoardStatus> = StaticCell::new();
 
 /// A queue that aggregates all hardware event sources (expanders, native IOs, etc).
 /// It's later consumed by EventConverter.
@@ -57,11 +89,78 @@ static USB_DOWN: usb_connect::CommChannel = usb_connect::CommChannel::new();
 
 const INDICES_N: usize = 24;
 
+/// First RTC backup register used for storing scenes (one per slot).
+const SCENE_BACKUP_REG: usize = 0;
+/// RTC backup register with the last output state (for StartupOutputs::RestoreLast).
+const LAST_OUTPUTS_BACKUP_REG: usize = SCENE_BACKUP_REG + MAX_SCENES;
+/// RTC backup registers with persisted shutter positions.
+const SHUTTERS_BACKUP_REGS: Range<usize> = LAST_OUTPUTS_BACKUP_REG + 1..LAST_OUTPUTS_BACKUP_REG + 7;
+/// RTC backup register with the address assigned at runtime.
+const ADDRESS_BACKUP_REG: usize = SHUTTERS_BACKUP_REGS.end;
+/// RTC backup registers with shutters with swapped up/down outputs.
+const SHUTTERS_SWAP_BACKUP_REGS: Range<usize> = ADDRESS_BACKUP_REG + 1..ADDRESS_BACKUP_REG + 3;
+/// RTC backup register counting boots that didn't reach a stable uptime.
+const BOOT_FAULTS_BACKUP_REG: usize = SHUTTERS_SWAP_BACKUP_REGS.end;
+
+/// Range of RTC backup registers seen as a byte storage.
+struct BackupRegisters<'a> {
+    rtc: &'a Rtc,
+    regs: Range<usize>,
+}
+
+impl BackupRegisters<'_> {
+    /// Registers covering the byte range. Only whole registers can be accessed.
+    fn registers(&self, offset: usize, len: usize) -> Result<Range<usize>, StoreError> {
+        if !offset.is_multiple_of(4) || !len.is_multiple_of(4) || offset + len > self.capacity() {
+            return Err(StoreError::OutOfBounds);
+        }
+        let first = self.regs.start + offset / 4;
+        Ok(first..first + len / 4)
+    }
+}
+
+impl Storage for BackupRegisters<'_> {
+    fn capacity(&self) -> usize {
+        self.regs.len() * 4
+    }
+
+    fn read(&mut self, offset: usize, buf: &mut [u8]) -> Result<(), StoreError> {
+        let regs = self.registers(offset, buf.len())?;
+        for (reg, chunk) in regs.zip(buf.chunks_mut(4)) {
+            let value = self
+                .rtc
+                .read_backup_register(reg)
+                .ok_or(StoreError::Storage)?;
+            chunk.copy_from_slice(&value.to_le_bytes());
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), StoreError> {
+        let regs = self.registers(offset, data.len())?;
+        for (reg, chunk) in regs.zip(data.chunks(4)) {
+            let value = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+            self.rtc.write_backup_register(reg, value);
+        }
+        Ok(())
+    }
+}
+
+/// Status LED on a GPIO pin.
+pub type BoardStatus = Status<Output<'static>>;
+
+impl LedLine for Output<'_> {
+    fn set_level(&mut self, level: PinState) {
+        // Infallible on GPIO.
+        let _ = OutputPin::set_state(self, level);
+    }
+}
+
 /// Represents our µC hardware interface. It's 'static and shared by most code.
 pub struct Board {
     // FIXME: ? UnsafeCell? For led maybe ok.
     // led: UnsafeCell<Output<'static>>,
-    pub status: &'static Status,
+    pub status: &'static BoardStatus,
 
     /// Handle physical switches - inputs.
     pub expander_switches: ExpanderInputs,
@@ -72,6 +171,11 @@ pub struct Board {
     /// Queue of input events (from expanders, native IOs, etc.)
     pub input_q: &'static InputChannel,
 
+    /// Shared I²C bus of the expanders.
+    i2c_bus: &'static Mutex<NoopRawMutex, AsyncI2C>,
+    /// Addresses of expanders: switches, sensors, outputs.
+    expander_addrs: [u8; 3],
+
     /// Physical outputs.
     indexed_outputs:
         Mutex<NoopRawMutex, IndexedOutputs<INDICES_N, 1, 8, ExpanderOutputs, Output<'static>>>,
@@ -80,12 +184,65 @@ pub struct Board {
 
     /// Usb group, used by gate.
     pub usb_connect: Mutex<NoopRawMutex, usb_connect::UsbConnect>,
+
+    /// Role strapping pin. Tied to ground selects the gate role.
+    pub role_strap: NativeInput<Input<'static>>,
+    /// Rotary encoder, if fitted. Its task keeps it locked.
+    rotary_encoder: Option<Mutex<NoopRawMutex, RotaryEncoderSource<Input<'static>>>>,
+    /// Analog sensor switch, if fitted. Its task keeps it locked.
+    adc_input: Option<Mutex<NoopRawMutex, BoardAdcInput>>,
     pub usb_up: &'static usb_connect::CommChannel,
     pub usb_down: &'static usb_connect::CommChannel,
 
     /// On board RTC.
     pub rtc: Mutex<NoopRawMutex, Rtc>,
     pub time_provider: RtcTimeProvider,
+    /// Battery backed RTC on the I²C bus, preferred when configured.
+    external_rtc: Option<Mutex<NoopRawMutex, ExternalRtc<SharedI2C>>>,
+}
+
+fn to_rtc_time(dt: &DateTime) -> RtcTime {
+    let day_of_week = match dt.day_of_week() {
+        DayOfWeek::Monday => 0,
+        DayOfWeek::Tuesday => 1,
+        DayOfWeek::Wednesday => 2,
+        DayOfWeek::Thursday => 3,
+        DayOfWeek::Friday => 4,
+        DayOfWeek::Saturday => 5,
+        DayOfWeek::Sunday => 6,
+    };
+    RtcTime {
+        year: dt.year(),
+        month: dt.month(),
+        day: dt.day(),
+        day_of_week,
+        hour: dt.hour(),
+        minute: dt.minute(),
+        second: dt.second(),
+    }
+}
+
+fn from_rtc_time(time: RtcTime) -> Option<DateTime> {
+    let day_of_week = match time.day_of_week {
+        0 => DayOfWeek::Monday,
+        1 => DayOfWeek::Tuesday,
+        2 => DayOfWeek::Wednesday,
+        3 => DayOfWeek::Thursday,
+        4 => DayOfWeek::Friday,
+        5 => DayOfWeek::Saturday,
+        _ => DayOfWeek::Sunday,
+    };
+    DateTime::from(
+        time.year,
+        time.month,
+        time.day,
+        day_of_week,
+        time.hour,
+        time.minute,
+        time.second,
+        0,
+    )
+    .ok()
 }
 
 impl Board {
@@ -100,11 +257,17 @@ impl Board {
     pub fn assign_peripherals(p: embassy_stm32::Peripherals) -> Self {
         /* Basics */
         let led = Output::new(p.PC6, Level::Low, Speed::Low);
-        let status = STATUS.init(Status::new(led));
+        let status = STATUS.init(
+            Status::new(
+                led,
+                Polarity::from_active_low(config::board::STATUS_LED_ACTIVE_LOW),
+            )
+            .with_code_blinks(config::board::CODE_BLINKS),
+        );
 
         /* Initialize CAN */
         let can = can::CanConfigurator::new(p.FDCAN1, p.PB8, p.PB9, CanIrqs);
-        let interconnect = Interconnect::new(can);
+        let interconnect = Interconnect::new(can, status);
 
         let mut cfg: Config = Default::default();
         cfg.frequency = Hertz(400_000);
@@ -127,14 +290,21 @@ impl Board {
          *
          * In future: We need I/O configurable by the VM.
          */
+        let expander = |addr| {
+            Pcf8575::with_address(I2cDevice::new(i2c_bus), addr)
+                .unwrap_or_else(|| defmt::panic!("Invalid expander address {:#x}", addr))
+        };
+
         // Inputs - light switches.
-        let io_ex_inputs = Pcf8575::new(I2cDevice::new(i2c_bus), true, true, true);
+        let io_ex_inputs = expander(config::board::INPUT_EXPANDER_ADDR);
 
         // Inputs - sensors.
-        let io_sensors = Pcf8575::new(I2cDevice::new(i2c_bus), true, true, false);
+        let io_sensors = expander(config::board::SENSOR_EXPANDER_ADDR);
 
         // Outputs
-        let io_ex_outputs = Pcf8575::new(I2cDevice::new(i2c_bus), false, false, false);
+        let io_ex_outputs = expander(config::board::OUTPUT_EXPANDER_ADDR);
+
+        let expander_addrs = [io_ex_inputs.addr(), io_sensors.addr(), io_ex_outputs.addr()];
 
         let expander_switches = ExpanderInputs::new(
             io_ex_inputs,
@@ -143,7 +313,9 @@ impl Board {
             &INPUT_CHANNEL,
             status,
             true, /* required */
-        );
+        )
+        .with_pulse_inputs(config::board::PULSE_INPUTS)
+        .with_startup_grace(config::board::EXPANDER_STARTUP_GRACE);
 
         let expander_sensors = ExpanderInputs::new(
             io_sensors,
@@ -154,9 +326,11 @@ impl Board {
             &INPUT_CHANNEL,
             status,
             false, /* optional - at least for now */
-        );
+        )
+        .with_pulse_inputs(config::board::PULSE_INPUTS)
+        .with_startup_grace(config::board::EXPANDER_STARTUP_GRACE);
 
-        let main_outputs = ExpanderOutputs::new(io_ex_outputs);
+        let main_outputs = ExpanderOutputs::new(0, io_ex_outputs);
 
         #[rustfmt::skip]
         let indexed_outputs = Mutex::new(IndexedOutputs::new(
@@ -180,50 +354,190 @@ impl Board {
                 51, 52, 53, 54, 55, 56, 57, 58,
             ],
             config::board::ACTIVE_LOW,
-        ));
+        )
+        .with_groups(config::board::OUTPUT_GROUPS)
+        .with_fallbacks(config::board::OUTPUT_FALLBACKS));
 
         let (rtc, time_provider) = Rtc::new(p.RTC, RtcConfig::default());
+        if let Some(raw) = rtc.read_backup_register(ADDRESS_BACKUP_REG)
+            && node_address::ADDRESS.restore(raw)
+        {
+            info!("Using assigned address {}", node_address::ADDRESS.get());
+        }
+
+        let external_rtc = config::board::EXTERNAL_RTC
+            .then(|| Mutex::new(ExternalRtc::new(I2cDevice::new(i2c_bus))));
 
         let usb_connect = usb_connect::UsbConnect::new(p.USB, p.PA12, p.PA11);
 
+        let role_strap = NativeInput::configure(p.PB10, config::board::ROLE_STRAP);
+
+        let rotary_encoder = config::board::ROTARY_ENCODER.map(|switch_id| {
+            let pins = config::board::ROTARY_ENCODER_PINS;
+            Mutex::new(RotaryEncoderSource::new(
+                switch_id,
+                NativeInput::configure(p.PA0, pins),
+                NativeInput::configure(p.PA1, pins),
+                &INPUT_CHANNEL,
+            ))
+        });
+
+        let adc_input = config::board::ADC_INPUT.map(|(switch_id, thresholds)| {
+            let adc = NativeAdc::new(Adc::new(p.ADC1, AdcConfig::default()), p.PA2.degrade_adc());
+            Mutex::new(AdcInputSource::new(
+                adc,
+                AdcInput::new(switch_id, thresholds),
+                &INPUT_CHANNEL,
+            ))
+        });
+
         info!("Board initialized");
         Self {
             expander_switches,
             expander_sensors,
+            i2c_bus,
+            expander_addrs,
             indexed_outputs,
             interconnect,
             status,
             usb_connect: Mutex::new(usb_connect),
+            role_strap,
+            rotary_encoder,
+            adc_input,
             usb_up: &USB_UP,
             usb_down: &USB_DOWN,
             rtc: Mutex::new(rtc),
             time_provider,
+            external_rtc,
             input_q: &INPUT_CHANNEL,
         }
     }
 
     /// Spawn main common tasks.
-    pub fn spawn_tasks(&'static self, spawner: &Spawner) {
-        spawner.spawn(unwrap!(task_status(self.status)));
-        spawner.spawn(unwrap!(task_usb_transceiver(self)));
+    /// Status LED and USB are not required for the node to work.
+    pub fn spawn_tasks(&'static self, spawner: &Spawner) -> SpawnReport {
+        let mut report = SpawnReport::new();
+        report.spawn(TaskId::Status, false, || {
+            task_status(self.status).map(|token| spawner.spawn(token))
+        });
+        report.spawn(TaskId::UsbTransceiver, false, || {
+            task_usb_transceiver(self).map(|token| spawner.spawn(token))
+        });
+        report
+    }
+
+    /// Spawn tasks related to IO handling. Without an expander task the rest
+    /// of the inputs still work.
+    pub fn spawn_io_tasks(&'static self, spawner: &Spawner) -> SpawnReport {
+        let mut report = SpawnReport::new();
+        for expander in [&self.expander_switches, &self.expander_sensors] {
+            report.spawn(TaskId::ExpanderInputs, false, || {
+                task_expander_inputs(expander).map(|token| spawner.spawn(token))
+            });
+        }
+        if let Some(encoder) = &self.rotary_encoder {
+            report.spawn(TaskId::RotaryEncoder, false, || {
+                task_rotary_encoder(encoder).map(|token| spawner.spawn(token))
+            });
+        }
+        if let Some(input) = &self.adc_input {
+            report.spawn(TaskId::AdcInput, false, || {
+                task_adc_input(input).map(|token| spawner.spawn(token))
+            });
+        }
+        report
+    }
+
+    /// Verify each expander responds and no two share an address. Input
+    /// expanders with conflicting addresses are disabled. Returns probed
+    /// addresses with results.
+    pub async fn probe_expanders(&self) -> [(u8, ProbeResult); 3] {
+        let mut bus = I2cDevice::new(self.i2c_bus);
+        let results = i2c_probe::probe(&mut bus, &self.expander_addrs).await;
+
+        let inputs = [&self.expander_switches, &self.expander_sensors];
+        for (expander, result) in inputs.iter().zip(results) {
+            if result == ProbeResult::Conflict {
+                expander.disable();
+            }
+        }
+        if results.iter().any(|result| *result != ProbeResult::Present) {
+            self.status.is_warning();
+        }
+
+        let mut report = [(0, ProbeResult::Missing); 3];
+        for (pos, result) in results.iter().enumerate() {
+            report[pos] = (self.expander_addrs[pos], *result);
+        }
+        report
+    }
+
+    /// Set outputs according to the power-on policy.
+    pub async fn init_outputs(&self, policy: StartupOutputs) -> Result<(), OutputError> {
+        let last = {
+            let rtc = self.rtc.lock().await;
+            rtc.read_backup_register(LAST_OUTPUTS_BACKUP_REG)
+                .and_then(Scene::from_backup)
+                .map(|scene| core::array::from_fn(|pos| scene.is_on(pos)))
+        };
+        let initial = indexed_outputs::startup_state(policy, last);
+        let mut outputs = self.indexed_outputs.lock().await;
+        let result = outputs.init_outputs(initial).await;
+        self.persist_outputs(&outputs.get_all()).await;
+        result
     }
 
-    /// Spawn tasks related to IO handling.
-    pub fn spawn_io_tasks(&'static self, spawner: &Spawner) {
-        spawner.spawn(unwrap!(task_expander_inputs(&self.expander_switches)));
-        spawner.spawn(unwrap!(task_expander_inputs(&self.expander_sensors)));
+    pub async fn set_output(&self, idx: IoIdx, state: bool) -> Result<(), OutputError> {
+        let mut outputs = self.indexed_outputs.lock().await;
+        outputs.set(idx, state).await?;
+        self.persist_outputs(&outputs.get_all()).await;
+        Ok(())
     }
 
-    pub async fn init_outputs(&self) -> Result<(), ()> {
-        self.indexed_outputs.lock().await.init_outputs().await
+    /// Set several outputs, each expander in a single write.
+    pub async fn set_outputs(&self, changes: &[(IoIdx, bool)]) -> Result<(), OutputError> {
+        let mut outputs = self.indexed_outputs.lock().await;
+        let result = outputs.set_many(changes).await;
+        self.persist_outputs(&outputs.get_all()).await;
+        result
     }
 
-    pub async fn set_output(&self, idx: IoIdx, state: bool) -> Result<(), ()> {
-        self.indexed_outputs.lock().await.set(idx, state).await
+    /// Set a mutually exclusive pair of outputs atomically.
+    pub async fn set_exclusive_pair(
+        &self,
+        up: IoIdx,
+        down: IoIdx,
+        direction: Direction,
+    ) -> Result<(), OutputError> {
+        let mut outputs = self.indexed_outputs.lock().await;
+        let result = outputs.set_exclusive(up, down, direction).await;
+        self.persist_outputs(&outputs.get_all()).await;
+        result
     }
 
-    pub async fn toggle_output(&self, idx: IoIdx) -> Result<bool, ()> {
-        self.indexed_outputs.lock().await.toggle(idx).await
+    pub async fn toggle_output(&self, idx: IoIdx) -> Result<bool, OutputError> {
+        let mut outputs = self.indexed_outputs.lock().await;
+        let state = outputs.toggle(idx).await?;
+        self.persist_outputs(&outputs.get_all()).await;
+        Ok(state)
+    }
+
+    /// Toggle a group of outputs (see config::board::OUTPUT_GROUPS). Return
+    /// the new state and the outputs of the group.
+    pub async fn toggle_group(
+        &self,
+        id: u8,
+    ) -> Result<(bool, heapless::Vec<IoIdx, 16>), OutputError> {
+        let mut outputs = self.indexed_outputs.lock().await;
+        let state = outputs.toggle_group(id).await?;
+        self.persist_outputs(&outputs.get_all()).await;
+        Ok((state, outputs.group_members(id).unwrap_or_default()))
+    }
+
+    /// Remember output state so it can be restored after reboot.
+    async fn persist_outputs(&self, status: &[(u8, bool)]) {
+        let rtc = self.rtc.lock().await;
+        rtc.write_backup_register(LAST_OUTPUTS_BACKUP_REG, Scene::capture(status).to_backup());
     }
 
     pub async fn get_output(&self, idx: IoIdx) -> Option<bool> {
@@ -234,11 +548,25 @@ impl Board {
         self.indexed_outputs.lock().await.get_all()
     }
 
-    /// Read time from RTC.
+    /// Seconds since boot. Saturates instead of wrapping.
+    pub fn uptime_secs(&self) -> u32 {
+        self.status.uptime_secs()
+    }
+
+    /// Read time from the external RTC if configured, or the internal one.
     pub async fn read_time(&self) -> DateTime {
-        match self.time_provider.now() {
-            Ok(dt) => dt,
-            Err(_rtc_err) => {
+        let internal = match self.time_provider.now() {
+            Ok(dt) => Some(to_rtc_time(&dt)),
+            Err(_rtc_err) => None,
+        };
+        let mut external = match &self.external_rtc {
+            Some(rtc) => Some(rtc.lock().await),
+            None => None,
+        };
+        let time = external_rtc::read_time(external.as_deref_mut(), internal).await;
+        match time.and_then(from_rtc_time) {
+            Some(dt) => dt,
+            None => {
                 defmt::error!("Error while reading RTC.");
 
                 /*
@@ -276,11 +604,209 @@ impl Board {
         }
     }
 
-    /// Set time to RTC.
+    /// Set time to RTC, and to the external one if configured.
     pub async fn set_time(&self, dt: DateTime) -> Result<(), RtcError> {
+        if let Some(external) = &self.external_rtc
+            && external
+                .lock()
+                .await
+                .write(&to_rtc_time(&dt))
+                .await
+                .is_err()
+        {
+            defmt::error!("Unable to set the external RTC");
+        }
         let mut rtc = self.rtc.lock().await;
         rtc.set_datetime(dt)
     }
+
+    /// Store scene in RTC backup registers so it survives reboot.
+    pub async fn store_scene(&self, slot: u8, scene: Scene) -> Result<(), ()> {
+        if slot as usize >= MAX_SCENES {
+            return Err(());
+        }
+        let rtc = self.rtc.lock().await;
+        rtc.write_backup_register(SCENE_BACKUP_REG + slot as usize, scene.to_backup());
+        Ok(())
+    }
+
+    /// Load state persisted in RTC backup registers. Default if blank or corrupt.
+    pub async fn load_state<T: Persist>(&self, regs: Range<usize>) -> T {
+        let rtc = self.rtc.lock().await;
+        PersistentStore::new(BackupRegisters { rtc: &rtc, regs }).load()
+    }
+
+    /// Persist state in RTC backup registers.
+    pub async fn store_state<T: Persist>(
+        &self,
+        regs: Range<usize>,
+        value: &T,
+    ) -> Result<(), StoreError> {
+        let rtc = self.rtc.lock().await;
+        PersistentStore::new(BackupRegisters { rtc: &rtc, regs }).store(value)
+    }
+
+    /// Count this boot in the crash loop counter and decide how to run it.
+    pub async fn count_boot(&self, policy: &SafeModePolicy) -> BootMode {
+        let rtc = self.rtc.lock().await;
+        let mut faults = BootFaults::from_backup(rtc.read_backup_register(BOOT_FAULTS_BACKUP_REG));
+        let mode = faults.boot(policy);
+        rtc.write_backup_register(BOOT_FAULTS_BACKUP_REG, faults.to_backup());
+        if mode == BootMode::Safe {
+            defmt::error!("{} boots crashed in a row - safe mode", faults.count());
+        }
+        mode
+    }
+
+    /// Boot is stable, or recovery was requested.
+    pub async fn clear_boot_faults(&self) {
+        let rtc = self.rtc.lock().await;
+        rtc.write_backup_register(BOOT_FAULTS_BACKUP_REG, BootFaults::default().to_backup());
+    }
+
+    /// Change the bus address and persist it.
+    pub async fn set_address(&self, addr: u8) -> Result<(), node_address::AddressError> {
+        node_address::ADDRESS.set(addr)?;
+        let rtc = self.rtc.lock().await;
+        rtc.write_backup_register(ADDRESS_BACKUP_REG, node_address::ADDRESS.to_backup());
+        Ok(())
+    }
+
+    /// Read scene from RTC backup registers. None if never captured.
+    pub async fn load_scene(&self, slot: u8) -> Option<Scene> {
+        if slot as usize >= MAX_SCENES {
+            return None;
+        }
+        let rtc = self.rtc.lock().await;
+        Scene::from_backup(rtc.read_backup_register(SCENE_BACKUP_REG + slot as usize)?)
+    }
+}
+
+impl DirectOutputs for Board {
+    async fn toggle_output(&self, idx: IoIdx) -> Result<bool, OutputError> {
+        Board::toggle_output(self, idx).await
+    }
+
+    async fn set_output(&self, idx: IoIdx, state: bool) -> Result<(), OutputError> {
+        Board::set_output(self, idx, state).await
+    }
+}
+
+impl MotorOutputs for Board {
+    async fn set_exclusive_pair(
+        &self,
+        up: IoIdx,
+        down: IoIdx,
+        direction: Direction,
+    ) -> Result<(), OutputError> {
+        Board::set_exclusive_pair(self, up, down, direction).await
+    }
+}
+
+impl ShutterIo for Board {
+    async fn transmit(&self, message: &Message, when_full: WhenFull) {
+        self.interconnect
+            .transmit_response(message, when_full)
+            .await;
+    }
+
+    async fn load_state<T: Persist>(&self, slot: StateSlot) -> T {
+        Board::load_state(self, shutter_regs(slot)).await
+    }
+
+    async fn store_state<T: Persist>(&self, slot: StateSlot, value: &T) -> Result<(), StoreError> {
+        Board::store_state(self, shutter_regs(slot), value).await
+    }
+}
+
+/// Backup registers of the persisted shutter state.
+fn shutter_regs(slot: StateSlot) -> Range<usize> {
+    match slot {
+        StateSlot::Positions => SHUTTERS_BACKUP_REGS,
+        StateSlot::Swaps => SHUTTERS_SWAP_BACKUP_REGS,
+    }
+}
+
+impl VmIo for Board {
+    type OutputStatus = [(IoIdx, bool); INDICES_N];
+
+    async fn toggle_output(&self, out: IoIdx) -> Result<bool, OutputError> {
+        Board::toggle_output(self, out).await
+    }
+
+    async fn set_output(&self, out: IoIdx, state: bool) -> Result<(), OutputError> {
+        Board::set_output(self, out, state).await
+    }
+
+    async fn set_outputs(&self, changes: &[(IoIdx, bool)]) -> Result<(), OutputError> {
+        Board::set_outputs(self, changes).await
+    }
+
+    async fn set_brightness(&self, out: IoIdx, percent: u8) -> Result<(), OutputError> {
+        // Relays and expander lines can't be dimmed.
+        Board::set_output(self, out, percent > 0).await
+    }
+
+    async fn toggle_group(&self, id: u8) -> Result<(bool, heapless::Vec<IoIdx, 16>), OutputError> {
+        Board::toggle_group(self, id).await
+    }
+
+    async fn group_members(&self, id: u8) -> Option<heapless::Vec<IoIdx, 16>> {
+        self.indexed_outputs.lock().await.group_members(id)
+    }
+
+    async fn get_output(&self, out: IoIdx) -> Option<bool> {
+        Board::get_output(self, out).await
+    }
+
+    async fn get_output_status(&self) -> Self::OutputStatus {
+        Board::get_output_status(self).await
+    }
+
+    async fn store_scene(&self, slot: u8, scene: Scene) -> Result<(), ()> {
+        Board::store_scene(self, slot, scene).await
+    }
+
+    async fn load_scene(&self, slot: u8) -> Option<Scene> {
+        Board::load_scene(self, slot).await
+    }
+
+    async fn transmit(&self, message: &Message, when_full: WhenFull) -> bool {
+        self.interconnect
+            .transmit_response(message, when_full)
+            .await
+    }
+
+    fn set_maintenance(&self, enabled: bool) {
+        self.status.set_maintenance(enabled);
+    }
+
+    fn uptime_secs(&self) -> u32 {
+        Board::uptime_secs(self)
+    }
+
+    fn input_expanders(&self) -> [ExpanderState; 2] {
+        [&self.expander_switches, &self.expander_sensors].map(|exp| ExpanderState {
+            id: exp.get_id(),
+            indices: *exp.get_indices(),
+            inputs: exp.get_inputs(),
+        })
+    }
+}
+
+impl SafeShutdown for Board {
+    fn safe_shutdown(&self) {
+        // Panicking task might hold the lock. Don't wait for it.
+        let Ok(mut outputs) = self.indexed_outputs.try_lock() else {
+            defmt::error!("Outputs are locked - unable to disable them");
+            return;
+        };
+        let result =
+            embassy_futures::block_on(with_timeout(Duration::from_millis(100), outputs.all_off()));
+        if !matches!(result, Ok(Ok(()))) {
+            defmt::error!("Unable to disable all outputs");
+        }
+    }
 }
 
 #[embassy_executor::task(pool_size = 2)]
@@ -289,7 +815,19 @@ pub async fn task_expander_inputs(switches: &'static ExpanderInputs) {
 }
 
 #[embassy_executor::task]
-pub async fn task_status(status: &'static Status) {
+pub async fn task_rotary_encoder(
+    encoder: &'static Mutex<NoopRawMutex, RotaryEncoderSource<Input<'static>>>,
+) {
+    encoder.lock().await.run().await
+}
+
+#[embassy_executor::task]
+pub async fn task_adc_input(input: &'static Mutex<NoopRawMutex, BoardAdcInput>) {
+    input.lock().await.run().await
+}
+
+#[embassy_executor::task]
+pub async fn task_status(status: &'static BoardStatus) {
     status.update_loop().await
 }
 
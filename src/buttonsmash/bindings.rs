@@ -16,6 +16,16 @@ pub enum Action {
     Noop,
 }
 
+impl Action {
+    /// Command of a Single action bound to the input, tagged with it.
+    pub fn command_for(&self, input: InIdx) -> Option<TaggedCommand> {
+        match self {
+            Action::Single(command) => Some(command.tagged(Origin::LocalButton(input))),
+            Action::Proc(_) | Action::Noop => None,
+        }
+    }
+}
+
 /// Mapping from (button (input), trigger, layer) into action.
 #[derive(Copy, Clone, Format, Eq, PartialEq)]
 pub struct Binding {
@@ -50,6 +60,42 @@ impl Binding {
             trigger: Trigger::LongClick,
         }
     }
+
+    /// Multi-function key: separate actions for a short, long and double
+    /// click. Noop actions are skipped.
+    pub fn multi(
+        idx: InIdx,
+        layer: LayerIdx,
+        short: Action,
+        long: Action,
+        double: Action,
+    ) -> impl Iterator<Item = Self> {
+        [
+            (Trigger::ShortClick, short),
+            (Trigger::LongClick, long),
+            (Trigger::DoubleClick, double),
+        ]
+        .into_iter()
+        .filter(|(_, action)| *action != Action::Noop)
+        .map(move |(trigger, action)| Self {
+            idx,
+            layer,
+            trigger,
+            action,
+        })
+    }
+
+    /// Compact form for a binding dump (InfoCode::Binding). Arg bytes (LE):
+    /// input, layer, trigger, action kind (0 - noop, 1 - command, 2 -
+    /// procedure).
+    pub fn to_info_arg(&self) -> u32 {
+        let action = match self.action {
+            Action::Noop => 0,
+            Action::Single(_) => 1,
+            Action::Proc(_) => 2,
+        };
+        u32::from_le_bytes([self.idx, self.layer, self.trigger.to_bytes(), action])
+    }
 }
 
 impl Default for Binding {
@@ -85,6 +131,20 @@ impl<const N: usize> BindingList<N> {
         Self::default()
     }
 
+    /// Defined bindings, ordered by input and layer.
+    pub fn iter(&self) -> impl Iterator<Item = &Binding> {
+        self.bindings[0..self.added].iter()
+    }
+
+    /// Number of defined bindings.
+    pub fn len(&self) -> usize {
+        self.added
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added == 0
+    }
+
     /// Clear defined bindings
     pub fn clear(&mut self) {
         for i in 0..N {
@@ -150,6 +210,29 @@ impl<const N: usize> BindingList<N> {
             .map(|idx| &self.bindings[idx])
     }
 
+    /// Action for an input event. Short clicks of inputs without any binding
+    /// on the layer fall back to the `default` command, if set.
+    pub fn action_or_default(
+        &self,
+        input_idx: InIdx,
+        layer: LayerIdx,
+        trigger: Trigger,
+        default: Option<Command>,
+    ) -> Option<Action> {
+        if let Some(binding) = self.filter(input_idx, Some(layer), Some(trigger)) {
+            return Some(binding.action);
+        }
+        if trigger == Trigger::DoubleClick {
+            // Keys without a double click action toggle twice as before.
+            return self.action_or_default(input_idx, layer, Trigger::ShortClick, default);
+        }
+        if trigger != Trigger::ShortClick || self.filter(input_idx, Some(layer), None).is_some() {
+            // Only once per click, and only for keys that are not configured.
+            return None;
+        }
+        default.map(Action::Single)
+    }
+
     /// Bind input (overwrite based on input idx and layer or add new binding)
     pub fn bind(&mut self, binding: Binding) {
         assert!(binding.idx != 0);
@@ -234,4 +317,103 @@ pub mod tests {
         blst.clear();
         assert_eq!(blst.added, 0);
     }
+
+    pub fn unbound_key_emits_default() {
+        let mut blst: BindingList<4> = BindingList::new();
+        blst.bind(Binding::short(1, 0, 10));
+        blst.bind(Binding::long(2, 0, 20));
+        let default = Some(Command::ActivateOutput(30));
+
+        // Unbound key with the default set.
+        assert_eq!(
+            blst.action_or_default(5, 0, Trigger::ShortClick, default),
+            Some(Action::Single(Command::ActivateOutput(30)))
+        );
+        // Once per click.
+        assert_eq!(
+            blst.action_or_default(5, 0, Trigger::Activated, default),
+            None
+        );
+        assert_eq!(
+            blst.action_or_default(5, 0, Trigger::LongClick, default),
+            None
+        );
+        // Default disabled.
+        assert_eq!(
+            blst.action_or_default(5, 0, Trigger::ShortClick, None),
+            None
+        );
+
+        // Bound keys are unaffected.
+        assert_eq!(
+            blst.action_or_default(1, 0, Trigger::ShortClick, default),
+            Some(Action::Single(Command::ToggleOutput(10)))
+        );
+        // Key configured for other trigger only.
+        assert_eq!(
+            blst.action_or_default(2, 0, Trigger::ShortClick, default),
+            None
+        );
+        // But not configured on the current layer.
+        assert_eq!(
+            blst.action_or_default(2, 1, Trigger::ShortClick, default),
+            Some(Action::Single(Command::ActivateOutput(30)))
+        );
+    }
+
+    pub fn button_command_origin() {
+        use crate::buttonsmash::microvm::opcode_bindings;
+        use crate::buttonsmash::opcodes::Opcode;
+
+        let mut bindings = BindingList::<4>::new();
+        for binding in opcode_bindings(Opcode::BindShortToggle(7, 12), 0) {
+            bindings.bind(binding);
+        }
+        let action = bindings
+            .action_or_default(7, 0, Trigger::ShortClick, None)
+            .unwrap();
+        assert_eq!(
+            action.command_for(7),
+            Some(TaggedCommand {
+                command: Command::ToggleOutput(12),
+                origin: Origin::LocalButton(7),
+            })
+        );
+        assert_eq!(Origin::LocalButton(7).input(), Some(7));
+        assert_eq!(Origin::Remote(3).input(), None);
+
+        // Procedures run their own opcodes.
+        assert_eq!(Action::Proc(3).command_for(7), None);
+        assert_eq!(Action::Noop.command_for(7), None);
+    }
+
+    pub fn dump_lists_bound() {
+        let mut blst: BindingList<8> = BindingList::new();
+        assert!(blst.is_empty());
+        assert_eq!(blst.iter().count(), 0);
+
+        let first = Binding::long(7, 1, 20);
+        let second = Binding::short(3, 0, 10);
+        blst.bind(first);
+        blst.bind(second);
+        // Rebinding doesn't duplicate.
+        blst.bind(second);
+
+        assert_eq!(blst.len(), 2);
+        {
+            let mut dump = blst.iter();
+            // Ordered by input.
+            assert!(dump.next() == Some(&second));
+            assert!(dump.next() == Some(&first));
+            assert!(dump.next().is_none());
+        }
+
+        assert_eq!(
+            second.to_info_arg().to_le_bytes(),
+            [3, 0, Trigger::ShortClick.to_bytes(), 1]
+        );
+
+        blst.clear();
+        assert_eq!(blst.iter().count(), 0);
+    }
 }
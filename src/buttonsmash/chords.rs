@@ -0,0 +1,168 @@
+/*
+ * Chords: a short click of the tap input while the hold input is held runs
+ * a procedure, like a keyboard shortcut. While the hold input is held, the
+ * tap input belongs to the chord - none of its own actions run. Once a chord
+ * fired, the rest of the hold (release, clicks) is dropped too. Only the
+ * activation of the hold input gets through, as it can't be known yet.
+ */
+use heapless::Vec;
+
+use super::consts::{InIdx, ProcIdx};
+use crate::io::events::{ButtonEvent, Trigger};
+
+/// Max number of chords bound at once.
+pub const MAX_CHORDS: usize = 4;
+
+#[derive(Copy, Clone)]
+struct Chord {
+    hold: InIdx,
+    tap: InIdx,
+    proc_idx: ProcIdx,
+}
+
+/// Held input which is a hold input of a chord.
+#[derive(Copy, Clone)]
+struct Held {
+    input: InIdx,
+    /// A chord fired during this hold.
+    fired: bool,
+}
+
+/// What to do with the event instead of its normal bindings.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ChordAction {
+    /// Not a part of a chord - handle as usual.
+    Pass,
+    /// Chord completed - call the procedure.
+    Fire(ProcIdx),
+    /// Part of a chord - drop.
+    Suppress,
+}
+
+/// Chord rejected by `bind`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ChordError {
+    /// All MAX_CHORDS are bound already.
+    TooMany,
+}
+
+pub struct Chords {
+    chords: Vec<Chord, MAX_CHORDS>,
+    held: Vec<Held, MAX_CHORDS>,
+}
+
+impl Default for Chords {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Chords {
+    pub const fn new() -> Self {
+        Self {
+            chords: Vec::new(),
+            held: Vec::new(),
+        }
+    }
+
+    /// Bind a chord on all layers. Rebinding a pair replaces its procedure.
+    /// Fails if there are too many chords.
+    pub fn bind(&mut self, hold: InIdx, tap: InIdx, proc_idx: ProcIdx) -> Result<(), ChordError> {
+        let chord = Chord {
+            hold,
+            tap,
+            proc_idx,
+        };
+        match self
+            .chords
+            .iter_mut()
+            .find(|chord| chord.hold == hold && chord.tap == tap)
+        {
+            Some(existing) => {
+                *existing = chord;
+                Ok(())
+            }
+            None => self.chords.push(chord).map_err(|_| ChordError::TooMany),
+        }
+    }
+
+    /// Forget all chords and held inputs.
+    pub fn clear(&mut self) {
+        self.chords.clear();
+        self.held.clear();
+    }
+
+    /// Follow the inputs and decide how the event is handled.
+    pub fn track(&mut self, event: &ButtonEvent) -> ChordAction {
+        let input = event.switch_id;
+        if let Some(pos) = self.held.iter().position(|held| held.input == input) {
+            let fired = self.held[pos].fired;
+            if event.trigger == Trigger::Deactivated {
+                self.held.swap_remove(pos);
+            }
+            return if fired {
+                ChordAction::Suppress
+            } else {
+                ChordAction::Pass
+            };
+        }
+
+        if event.trigger == Trigger::Activated && self.chords.iter().any(|c| c.hold == input) {
+            // Can't fail - every held input is a hold input of a chord.
+            let _ = self.held.push(Held {
+                input,
+                fired: false,
+            });
+            return ChordAction::Pass;
+        }
+
+        let Some((proc_idx, hold)) = self.chords.iter().find_map(|chord| {
+            let held = self.held.iter().position(|held| held.input == chord.hold)?;
+            (chord.tap == input).then_some((chord.proc_idx, held))
+        }) else {
+            return ChordAction::Pass;
+        };
+        if event.trigger == Trigger::ShortClick {
+            self.held[hold].fired = true;
+            ChordAction::Fire(proc_idx)
+        } else {
+            ChordAction::Suppress
+        }
+    }
+}
+
+pub mod tests {
+    use super::*;
+    use embassy_time::Instant;
+
+    pub fn chord_slots_limited() {
+        let mut chords = Chords::new();
+        let press = |chords: &mut Chords, switch_id, trigger| {
+            chords.track(&ButtonEvent {
+                switch_id,
+                trigger,
+                at: Instant::from_millis(0),
+            })
+        };
+
+        // Rebinding replaces.
+        assert!(chords.bind(1, 2, 10).is_ok());
+        assert!(chords.bind(1, 2, 11).is_ok());
+        press(&mut chords, 1, Trigger::Activated);
+        assert_eq!(
+            press(&mut chords, 2, Trigger::ShortClick),
+            ChordAction::Fire(11)
+        );
+
+        // Limited number of chords.
+        for tap in 3..3 + MAX_CHORDS as u8 - 1 {
+            assert!(chords.bind(1, tap, 12).is_ok());
+        }
+        assert_eq!(chords.bind(9, 2, 13), Err(ChordError::TooMany));
+        chords.clear();
+        assert_eq!(
+            press(&mut chords, 2, Trigger::ShortClick),
+            ChordAction::Pass
+        );
+    }
+}
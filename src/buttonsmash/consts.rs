@@ -1,28 +1,52 @@
 use defmt::Format;
 
+use super::opcodes::Opcode;
 use super::shutters;
-use crate::io::events::{ButtonEvent, Trigger};
-use embassy_sync::{blocking_mutex::raw::ThreadModeRawMutex, channel::Channel};
+use crate::components::message::args::OutputChangeRequest;
+use crate::config::BROADCAST_ADDRESS;
+use crate::io::events::{ButtonEvent, ChannelMutex, Trigger};
+use embassy_sync::channel::Channel;
+use embassy_time::Instant;
 /*
  * Shared, common constants and trivial structures
  */
 
-// Input IO index. `0` is reserved to simplify things.
+// Input IO index. `0` is reserved to simplify things - see
+// `io::events::RESERVED_IDX`.
 pub type InIdx = u8;
 pub type OutIdx = u8;
 pub type ShutterIdx = u8;
 pub type LayerIdx = u8;
 pub type ProcIdx = u8;
+pub type SceneIdx = u8;
 pub const MAX_PROCEDURES: usize = 128;
 pub const REGISTERS: usize = 32;
+/// Register holding the trigger (Trigger as u8) of the input event which
+/// called the bound procedure. CallRegister on it branches by the trigger.
+/// Programs setting it are rejected.
+pub const TRIGGER_REGISTER: u8 = REGISTERS as u8 - 1;
 pub const MAX_LAYERS: usize = 128;
 pub const MAX_LAYER_STACK: usize = 5;
 
 pub const BINDINGS_COUNT: usize = 30;
 
+/// Outputs toggled together by a single fan-out binding. They are stored
+/// inline, so this sets the size of every opcode of the program and every
+/// bound Command - four still fit in the 8 bytes other variants take.
+pub const MAX_FANOUT: usize = 4;
+
+/// Rotary encoders bound at once.
+pub const MAX_ROTARY: usize = 4;
+/// Brightness change [%] of a single rotary encoder detent.
+pub const BRIGHTNESS_STEP: u8 = 10;
+
 /// Max call stack size.
 pub const MAX_STACK: usize = 3;
 
+/// Opcodes a single procedure call, including its subprocedures, may execute
+/// before it's aborted. Keeps the event loop (and the watchdog) alive.
+pub const EXECUTE_BUDGET: usize = 512;
+
 // FIXME: Those required?
 pub const MAX_INPUTS: usize = 128;
 pub const MAX_OUTPUTS: usize = 128;
@@ -36,6 +60,20 @@ pub enum Command {
     ActivateOutput(OutIdx),
     /// Deactivate output of given ID - Local or remote
     DeactivateOutput(OutIdx),
+    /// Toggle each listed output. Repeated entries are skipped, so fewer
+    /// outputs are listed by repeating the last one.
+    ToggleOutputs([OutIdx; MAX_FANOUT]),
+    /// Keep output on while the input is held (with a safety max-hold).
+    MomentaryOutput(OutIdx),
+    /// Activate output and turn it off after given deciseconds.
+    ActivateFor(OutIdx, u8),
+    /// Blink output `count` times and return it to its prior state.
+    PulseOutput {
+        idx: OutIdx,
+        count: u8,
+        on_ms: u16,
+        off_ms: u16,
+    },
 
     /// Activate layer (public message)
     ActivateLayer(LayerIdx),
@@ -45,10 +83,64 @@ pub enum Command {
     /// Shutter command
     Shutter(ShutterIdx, shutters::Cmd),
 
+    /// Store current outputs into a scene slot.
+    CaptureScene(SceneIdx),
+    /// Restore outputs from a scene slot.
+    RecallScene(SceneIdx),
+
     /// No operation
     Noop,
 }
 
+impl Command {
+    /// Tag the command with its origin.
+    pub fn tagged(self, origin: Origin) -> TaggedCommand {
+        TaggedCommand {
+            command: self,
+            origin,
+        }
+    }
+}
+
+/// What caused a command. Logged with the output changes to tell why an
+/// output changed.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Format)]
+pub enum Origin {
+    /// Binding of a local input.
+    LocalButton(InIdx),
+    /// Bus request from the node of the address. See `sender_address`.
+    Remote(u8),
+    /// Opcode of a running procedure.
+    Procedure(ProcIdx),
+    /// Shutter driver.
+    Shutter(ShutterIdx),
+    /// Executor housekeeping: expired timers, released holds, runtime reset.
+    Internal,
+}
+
+impl Origin {
+    /// Input of a local binding.
+    pub fn input(&self) -> Option<InIdx> {
+        match self {
+            Origin::LocalButton(input) => Some(*input),
+            _ => None,
+        }
+    }
+}
+
+/// Address remote requests are tagged with. The sender is an optional byte
+/// of the request, the broadcast address stands for an unnamed one.
+pub fn sender_address(source: Option<u8>) -> u8 {
+    source.unwrap_or(BROADCAST_ADDRESS)
+}
+
+/// Command on its way through the executor.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Format)]
+pub struct TaggedCommand {
+    pub command: Command,
+    pub origin: Origin,
+}
+
 #[derive(Format)]
 pub enum LayerEvent {
     Activate(u8),
@@ -66,25 +158,70 @@ pub enum Event {
     */
     /// Remotely call a microvm procedure.
     RemoteProcedureCall(ProcIdx),
-    /// Remote IO control: Toggle.
-    RemoteToggle(OutIdx),
+    /// Remote IO control: Toggle. With the address of the sender.
+    RemoteToggle(OutIdx, u8),
 
     /// Remote IO control: Activate
-    RemoteActivate(OutIdx),
+    RemoteActivate(OutIdx, u8),
     /// Remote IO control: Deactivate
-    RemoteDeactivate(OutIdx),
+    RemoteDeactivate(OutIdx, u8),
+    /// Remote forces output state (output, state, lock). With the address
+    /// of the sender.
+    RemoteOverride(OutIdx, bool, bool, u8),
     /// Remote requests our full status.
     RemoteStatusRequest,
+    /// Remote requests a diagnostic dump.
+    RemoteDiagnosticsRequest,
+    /// Remote requests a dump of the active bindings.
+    RemoteBindingsRequest,
+    /// Remote requests a dump of the event trace.
+    RemoteTraceRequest,
+    /// Remote presets a register (register, value).
+    RemoteSetRegister(u8, u8),
+    /// Remote asks for a register value.
+    RemoteGetRegister(u8),
+    /// Remote requests reset of the runtime state.
+    RemoteResetRuntime,
+    /// Remote captures current outputs into a scene slot.
+    RemoteCaptureScene(SceneIdx),
+    /// Remote recalls a scene slot. With the address of the sender.
+    RemoteRecallScene(SceneIdx, u8),
+    /// Remote enables/disables maintenance mode.
+    RemoteSetMaintenance(bool),
+    /// Edges counted on a pulse input since its previous report.
+    PulseCount(InIdx, u16),
+    /// Rotary encoder turned by signed detents.
+    Rotate(InIdx, i8),
 }
 
 impl Event {
-    pub fn new_button(in_idx: InIdx, trigger: Trigger) -> Self {
+    pub fn new_button(in_idx: InIdx, trigger: Trigger, at: Instant) -> Self {
         Event::ButtonEvent(ButtonEvent {
             switch_id: in_idx,
             trigger,
+            at,
         })
     }
+
+    /// Event of a SetOutput request.
+    pub fn set_output(output: OutIdx, state: OutputChangeRequest, source: Option<u8>) -> Self {
+        let addr = sender_address(source);
+        match state {
+            OutputChangeRequest::On => Event::RemoteActivate(output, addr),
+            OutputChangeRequest::Off => Event::RemoteDeactivate(output, addr),
+            OutputChangeRequest::Toggle => Event::RemoteToggle(output, addr),
+        }
+    }
 }
 
+/// Executor reconfiguration, handled by the listen loop between events.
+pub enum Control {
+    /// Replace the program. Runtime state is cleared and setup executed.
+    ReloadProgram(&'static [Opcode]),
+}
+
+/// Channel to reconfigure a running Executor.
+pub type ControlChannel = Channel<ChannelMutex, Control, 1>;
+
 /// Channel to tranport high-level events into the Executor.
-pub type EventChannel = Channel<ThreadModeRawMutex, Event, 5>;
+pub type EventChannel = Channel<ChannelMutex, Event, 5>;
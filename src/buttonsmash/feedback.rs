@@ -0,0 +1,170 @@
+/*
+ * Feedback loop guard. Misconfigured nodes can react to each other's output
+ * changes (A's OutputChanged makes B set an output, whose change makes A
+ * toggle again) and toggle forever, flooding the bus. Changes requested by
+ * remotes are counted per output; an output flipping too often within a
+ * short window ignores remote requests for a cooldown. Local buttons still
+ * work - the loop has to be broken on the bus, not at the wall.
+ */
+use embassy_time::{Duration, Instant};
+
+use super::consts::OutIdx;
+
+/// Max number of outputs tracked at the same time.
+pub const MAX_TRACKED: usize = 8;
+/// More flips of an output within WINDOW are considered a loop.
+pub const MAX_FLIPS: u8 = 6;
+/// Window in which the flips are counted.
+pub const WINDOW: Duration = Duration::from_secs(2);
+/// Remote requests of a looping output are ignored for this long.
+pub const COOLDOWN: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Copy)]
+struct History {
+    out: OutIdx,
+    /// Last state set remotely.
+    state: bool,
+    /// Start of the counting window.
+    since: Instant,
+    /// Flips within the window.
+    flips: u8,
+    /// Remote requests are ignored until then.
+    suppressed_until: Option<Instant>,
+}
+
+/// Recent remote changes of outputs.
+pub struct FeedbackGuard {
+    history: [Option<History>; MAX_TRACKED],
+}
+
+impl Default for FeedbackGuard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FeedbackGuard {
+    pub const fn new() -> Self {
+        Self {
+            history: [None; MAX_TRACKED],
+        }
+    }
+
+    /// Are remote requests of the output ignored?
+    pub fn is_suppressed(&self, out: OutIdx, now: Instant) -> bool {
+        self.history
+            .iter()
+            .flatten()
+            .find(|entry| entry.out == out)
+            .and_then(|entry| entry.suppressed_until)
+            .is_some_and(|until| now < until)
+    }
+
+    /// Record a remotely requested change. Returns true when it starts the
+    /// suppression - the loop should be reported then, once.
+    pub fn record(&mut self, out: OutIdx, state: bool, now: Instant) -> bool {
+        let Some(entry) = self.entry(out, state, now) else {
+            return false;
+        };
+        if now.saturating_duration_since(entry.since) >= WINDOW {
+            entry.since = now;
+            entry.flips = 0;
+        }
+        if entry.state != state {
+            entry.state = state;
+            entry.flips = entry.flips.saturating_add(1);
+        }
+        if entry.flips <= MAX_FLIPS {
+            return false;
+        }
+        entry.flips = 0;
+        entry.since = now;
+        entry.suppressed_until = Some(now + COOLDOWN);
+        true
+    }
+
+    /// History of the output. When full, the stalest entry not in a cooldown
+    /// is reused. None if all are cooling down.
+    fn entry(&mut self, out: OutIdx, state: bool, now: Instant) -> Option<&mut History> {
+        let pos = match self
+            .history
+            .iter()
+            .position(|entry| entry.is_some_and(|entry| entry.out == out))
+        {
+            Some(pos) => pos,
+            None => {
+                let pos = self
+                    .history
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, entry)| {
+                        entry.is_none_or(|entry| {
+                            entry.suppressed_until.is_none_or(|until| now >= until)
+                        })
+                    })
+                    .min_by_key(|(_, entry)| entry.map(|entry| entry.since))
+                    .map(|(pos, _)| pos)?;
+                self.history[pos] = Some(History {
+                    out,
+                    state,
+                    since: now,
+                    flips: 0,
+                    suppressed_until: None,
+                });
+                pos
+            }
+        };
+        self.history[pos].as_mut()
+    }
+}
+
+pub mod tests {
+    use super::*;
+
+    pub fn alternating_changes_suppressed() {
+        let mut guard = FeedbackGuard::new();
+        let start = Instant::from_millis(1000);
+        let step = Duration::from_millis(100);
+
+        // Slow toggling by a user is fine.
+        let mut now = start;
+        for flip in 0..20 {
+            assert!(!guard.record(3, flip % 2 == 0, now));
+            now += WINDOW;
+        }
+        // Repeated requests of the same state are no flips.
+        for _ in 0..20 {
+            assert!(!guard.record(4, true, now));
+        }
+
+        // A storm trips after MAX_FLIPS, exactly once.
+        let mut tripped = None;
+        for flip in 0..(MAX_FLIPS as usize + 4) {
+            if guard.record(5, flip % 2 == 0, now) {
+                assert!(tripped.is_none());
+                tripped = Some(flip);
+            }
+            if tripped.is_none() {
+                assert!(!guard.is_suppressed(5, now));
+            }
+            now += step;
+        }
+        // First record only sets the state, the next ones flip.
+        assert_eq!(tripped, Some(MAX_FLIPS as usize + 1));
+        assert!(guard.is_suppressed(5, now));
+        // Other outputs are not affected.
+        assert!(!guard.is_suppressed(3, now));
+        assert!(!guard.is_suppressed(4, now));
+
+        // Released after the cooldown.
+        let trip = start + WINDOW * 20 + step * (MAX_FLIPS as u32 + 1);
+        assert!(guard.is_suppressed(5, trip + COOLDOWN - Duration::from_millis(1)));
+        assert!(!guard.is_suppressed(5, trip + COOLDOWN));
+
+        // Cooling down outputs are never evicted by new ones.
+        for out in 10..(10 + MAX_TRACKED as u8) {
+            guard.record(out, true, now);
+        }
+        assert!(guard.is_suppressed(5, now));
+    }
+}
@@ -37,6 +37,14 @@ impl Layers {
         self.current = layer;
     }
 
+    /// Was the layer activated by this input and is still held?
+    pub fn is_held(&self, in_idx: InIdx, layer: LayerIdx) -> bool {
+        self.stack
+            .iter()
+            .flatten()
+            .any(|entry| *entry == (in_idx, layer))
+    }
+
     /// Scan stack for activations using this input key and if one is found -
     /// deactivate it and return true. Otherwise return false.
     pub fn maybe_deactivate(&mut self, in_idx: InIdx) -> bool {
@@ -0,0 +1,50 @@
+/*
+ * Output locks: an operator forces an output on or off remotely and locks
+ * it, so neither buttons, procedures nor other remote requests change it
+ * until the operator releases it. Survives runtime resets.
+ */
+use super::consts::{MAX_OUTPUTS, OutIdx};
+
+/// Output rejected by `lock`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum LockError {
+    /// Index past MAX_OUTPUTS.
+    OutOfRange(OutIdx),
+}
+
+/// Outputs ignoring toggle/activate/deactivate commands.
+pub struct OutputLocks {
+    mask: u128,
+}
+
+impl Default for OutputLocks {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OutputLocks {
+    pub const fn new() -> Self {
+        Self { mask: 0 }
+    }
+
+    fn bit(out: OutIdx) -> Option<u128> {
+        ((out as usize) < MAX_OUTPUTS).then(|| 1 << out)
+    }
+
+    /// Lock the output. Fails for indices past MAX_OUTPUTS.
+    pub fn lock(&mut self, out: OutIdx) -> Result<(), LockError> {
+        self.mask |= Self::bit(out).ok_or(LockError::OutOfRange(out))?;
+        Ok(())
+    }
+
+    pub fn unlock(&mut self, out: OutIdx) {
+        if let Some(bit) = Self::bit(out) {
+            self.mask &= !bit;
+        }
+    }
+
+    pub fn is_locked(&self, out: OutIdx) -> bool {
+        Self::bit(out).is_some_and(|bit| self.mask & bit != 0)
+    }
+}
@@ -0,0 +1,171 @@
+/*
+ * Maintenance mode: while replacing a switch or testing the wiring, local
+ * button events are dropped so spurious inputs don't trigger real outputs.
+ * Remote commands are still processed. Toggled remotely or by a very long
+ * hold of a bound input. LongActivated fires once per hold, so a hold is
+ * usually detected on release. With auto-repeat it toggles while held.
+ */
+use embassy_time::{Duration, Instant};
+
+use super::consts::InIdx;
+use crate::io::events::{ButtonEvent, Trigger};
+
+/// How long the bound input has to be held to toggle maintenance mode.
+pub const MAINTENANCE_HOLD: Duration = Duration::from_secs(10);
+
+pub struct Maintenance {
+    enabled: bool,
+    /// Input that toggles the mode on a very long hold.
+    input: Option<InIdx>,
+    /// When the bound input was activated. None after toggling, until released.
+    held_since: Option<Instant>,
+}
+
+impl Default for Maintenance {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Maintenance {
+    pub const fn new() -> Self {
+        Self {
+            enabled: false,
+            input: None,
+            held_since: None,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Bind input which toggles the mode on a very long hold.
+    pub fn bind(&mut self, input: InIdx) {
+        self.input = Some(input);
+        self.held_since = None;
+    }
+
+    /// Forget the bound input. Mode itself is kept.
+    pub fn unbind(&mut self) {
+        self.input = None;
+        self.held_since = None;
+    }
+
+    /// Follow the bound input. Returns true if the mode was toggled.
+    pub fn track(&mut self, event: &ButtonEvent) -> bool {
+        if self.input != Some(event.switch_id) {
+            return false;
+        }
+        match event.trigger {
+            Trigger::Activated => {
+                self.held_since = Some(event.at);
+            }
+            Trigger::LongActivated => {
+                return self.toggle_if_held(event.at);
+            }
+            Trigger::Deactivated => {
+                let toggled = self.toggle_if_held(event.at);
+                self.held_since = None;
+                return toggled;
+            }
+            _ => {}
+        }
+        false
+    }
+
+    /// Toggle once per hold if it was long enough.
+    fn toggle_if_held(&mut self, at: Instant) -> bool {
+        if let Some(since) = self.held_since
+            && at.saturating_duration_since(since) >= MAINTENANCE_HOLD
+        {
+            self.held_since = None;
+            self.enabled = !self.enabled;
+            return true;
+        }
+        false
+    }
+
+    /// Should local button events be processed?
+    pub fn accepts_local(&self) -> bool {
+        !self.enabled
+    }
+}
+
+pub mod tests {
+    use super::*;
+    use crate::buttonsmash::bindings::{Action, Binding, BindingList};
+    use crate::buttonsmash::consts::Command;
+
+    fn event(switch_id: InIdx, trigger: Trigger, at: Instant) -> ButtonEvent {
+        ButtonEvent {
+            switch_id,
+            trigger,
+            at,
+        }
+    }
+
+    /// Command a local event would execute.
+    fn command(
+        maintenance: &Maintenance,
+        bindings: &BindingList<4>,
+        event: &ButtonEvent,
+    ) -> Option<Command> {
+        if !maintenance.accepts_local() {
+            return None;
+        }
+        let binding = bindings.filter(event.switch_id, Some(0), Some(event.trigger))?;
+        match binding.action {
+            Action::Single(command) => Some(command),
+            _ => None,
+        }
+    }
+
+    pub fn maintenance_drops_local_events() {
+        let mut maintenance = Maintenance::new();
+        let mut bindings: BindingList<4> = BindingList::new();
+        bindings.bind(Binding::short(1, 0, 10));
+        let start = Instant::from_millis(1000);
+        let click = event(1, Trigger::ShortClick, start);
+        assert_eq!(
+            command(&maintenance, &bindings, &click),
+            Some(Command::ToggleOutput(10))
+        );
+
+        maintenance.set(true);
+        assert_eq!(command(&maintenance, &bindings, &click), None);
+
+        maintenance.set(false);
+        assert_eq!(
+            command(&maintenance, &bindings, &click),
+            Some(Command::ToggleOutput(10))
+        );
+
+        // Very long hold of the bound input toggles the mode once.
+        maintenance.bind(5);
+        assert!(!maintenance.track(&event(5, Trigger::Activated, start)));
+        let almost = start + MAINTENANCE_HOLD - Duration::from_millis(1);
+        assert!(!maintenance.track(&event(5, Trigger::LongActivated, almost)));
+        let held = start + MAINTENANCE_HOLD;
+        assert!(maintenance.track(&event(5, Trigger::LongActivated, held)));
+        assert!(maintenance.is_enabled());
+        assert!(!maintenance.track(&event(5, Trigger::LongActivated, held)));
+        assert!(!maintenance.track(&event(5, Trigger::Deactivated, held)));
+        assert!(maintenance.is_enabled());
+
+        // Other inputs don't toggle.
+        assert!(!maintenance.track(&event(1, Trigger::Activated, start)));
+        assert!(!maintenance.track(&event(1, Trigger::LongActivated, held)));
+        assert!(maintenance.is_enabled());
+
+        // Without auto-repeat the hold is noticed on release.
+        assert!(!maintenance.track(&event(5, Trigger::Activated, start)));
+        assert!(!maintenance.track(&event(5, Trigger::LongActivated, almost)));
+        assert!(maintenance.track(&event(5, Trigger::Deactivated, held)));
+        assert!(!maintenance.is_enabled());
+    }
+}
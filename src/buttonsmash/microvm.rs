@@ -4,47 +4,131 @@
 */
 
 use defmt::Format;
-use embassy_time::{Duration, Timer};
+use embassy_futures::select::{Either, select};
+use embassy_time::{Duration, Instant, Timer, with_deadline, with_timeout};
+use heapless::Vec;
 
 use super::bindings::*;
+use super::chords::{ChordAction, Chords};
 use super::consts::{
-    Command, Event, EventChannel, InIdx, MAX_LAYERS, MAX_PROCEDURES, MAX_STACK, OutIdx, ProcIdx,
-    REGISTERS,
+    BRIGHTNESS_STEP, Command, Control, ControlChannel, EXECUTE_BUDGET, Event, InIdx, LayerIdx,
+    MAX_LAYERS, MAX_OUTPUTS, MAX_PROCEDURES, MAX_ROTARY, MAX_STACK, Origin, OutIdx, ProcIdx,
+    REGISTERS, SceneIdx, TRIGGER_REGISTER, TaggedCommand,
 };
-use super::{layers::Layers, opcodes::Opcode, shutters};
-use crate::boards::ctrl_board_v1::Board;
-use crate::components::interconnect::WhenFull;
+use super::feedback::FeedbackGuard;
+use super::locks::OutputLocks;
+use super::maintenance::Maintenance;
+use super::pulsed::{self, PulsedOutputs};
+use super::scenes::{SCENE_OUTPUTS, Scene};
+use super::timed::{self, TimedOutputs};
+use super::vm_io::{EventSource, VmIo};
+use super::{layers::Layers, momentary::MomentaryOutputs, opcodes::Opcode, shutters};
+use crate::components::diagnostics::Diagnostics;
 use crate::components::message::{Message, args};
+use crate::components::queue::WhenFull;
 use crate::components::status;
-use crate::io::events::Trigger;
+use crate::components::trace::{self, TraceEvent};
+use crate::io::events::{OutputError, RESERVED_IDX, Trigger};
+
+/// Register access rejected.
+#[derive(Debug, Eq, PartialEq, Format, Clone, Copy)]
+pub enum RegisterError {
+    /// Index past the register file of this node.
+    OutOfRange(u8),
+}
 
 /// MicroVM holds internal state that can be queried by code.
 /// TODO Output status migrated to Board. So now this is WIP.
-pub struct BoardState {
+pub struct BoardState<const REGS: usize = REGISTERS> {
     /// TODO: In progress.
-    registers: [u8; REGISTERS],
+    registers: [u8; REGS],
 }
 
-impl Default for BoardState {
+impl<const REGS: usize> Default for BoardState<REGS> {
     fn default() -> Self {
         Self {
-            registers: [0; REGISTERS],
+            registers: [0; REGS],
         }
     }
 }
 
+impl<const REGS: usize> BoardState<REGS> {
+    /// Clear all registers.
+    pub fn reset(&mut self) {
+        self.registers = [0; REGS];
+    }
+    /// Read register value. None if register is out of range.
+    pub fn get_register(&self, reg: u8) -> Option<u8> {
+        self.registers.get(reg as usize).copied()
+    }
+
+    /// Store the trigger of the input event calling a bound procedure.
+    pub fn set_trigger(&mut self, trigger: Trigger) -> Result<(), RegisterError> {
+        self.set_register(TRIGGER_REGISTER, trigger as u8)
+    }
+
+    /// Set register value. Fails if register is out of range.
+    pub fn set_register(&mut self, reg: u8, value: u8) -> Result<(), RegisterError> {
+        let slot = self
+            .registers
+            .get_mut(reg as usize)
+            .ok_or(RegisterError::OutOfRange(reg))?;
+        *slot = value;
+        Ok(())
+    }
+}
+
+/// Rotary encoder bound to an output, with the brightness it set.
+#[derive(Clone, Copy)]
+struct Dimmer {
+    input: InIdx,
+    out: OutIdx,
+    /// 0-100%.
+    level: u8,
+}
+
 /// Executes actions using a program.
-pub struct Executor<const BINDINGS: usize, const OPCODES: usize = 1024> {
+///
+/// Sizes of code, registers, procedure table and call stack are generic, so
+/// small nodes can save RAM and complex ones can grow.
+pub struct Executor<
+    IO: VmIo + 'static,
+    const BINDINGS: usize,
+    const OPCODES: usize = 1024,
+    const REGS: usize = REGISTERS,
+    const PROCS: usize = MAX_PROCEDURES,
+    const STACK: usize = MAX_STACK,
+> {
     layers: Layers,
     bindings: BindingList<BINDINGS>,
     opcodes: [Opcode; OPCODES],
-    procedures: [usize; MAX_PROCEDURES],
+    procedures: [usize; PROCS],
     // Cached state of the board and VM registers/state.
-    state: BoardState,
+    state: BoardState<REGS>,
+    /// Outputs held on by inputs.
+    momentary: MomentaryOutputs,
+    /// Outputs turned off after a time.
+    timed: TimedOutputs,
+    /// Outputs blinking to confirm something.
+    pulsed: PulsedOutputs,
+    /// Local inputs are ignored when in maintenance.
+    maintenance: Maintenance,
+    /// Hold and tap input combinations.
+    chords: Chords,
+    /// Outputs forced by an operator, ignoring other changes.
+    locks: OutputLocks,
+    /// Outputs toggled by remotes in a loop, ignoring remote requests.
+    feedback: FeedbackGuard,
+    /// Rotary encoders and the outputs they dim.
+    rotary: Vec<Dimmer, MAX_ROTARY>,
+    /// Executed on short click of inputs without a binding. None - disabled.
+    default_command: Option<Command>,
 
     // Our outputs
-    board: &'static Board,
+    board: &'static IO,
     shutters: shutters::ShutterChannel,
+    /// Raised by the shutter manager once parked. None - don't wait.
+    parked: Option<&'static shutters::ParkSignal>,
 }
 
 enum MicroState {
@@ -58,6 +142,24 @@ enum MicroState {
     // Jump(usize),
 }
 
+/// Program rejected at load time.
+#[derive(Debug, Eq, PartialEq, Format, Clone, Copy)]
+pub enum ProgramError {
+    /// Opcode references an output this node doesn't have.
+    UnknownOutput(OutIdx),
+    /// Program doesn't fit into the code memory.
+    TooLong(usize),
+    /// Procedure to run has no Start - eg. setup procedure 0 of an empty or
+    /// malformed program.
+    MissingProcedure(ProcIdx),
+    /// Opcode writes the register the VM stores input triggers in.
+    ReservedRegister(u8),
+    /// Procedure exceeded the execution budget or the call stack (or ran off
+    /// the code) and was aborted at the opcode.
+    Runaway { proc: ProcIdx, pc: usize },
+}
+
+/// Change of a single output. Groups and shutter pairs have their own paths.
 #[derive(Debug, Eq, PartialEq, Format, Clone)]
 pub enum IOCommand {
     /// Toggle output...
@@ -68,27 +170,249 @@ pub enum IOCommand {
     DeactivateOutput(OutIdx),
 }
 
-impl<const BN: usize> Executor<BN> {
-    pub fn new(board: &'static Board, shutters_addr: shutters::ShutterChannel) -> Self {
+/// Check the program against the node configuration before loading.
+pub fn validate(program: &[Opcode], outputs: &[OutIdx]) -> Result<(), ProgramError> {
+    if !program.contains(&Opcode::Start(0)) {
+        return Err(ProgramError::MissingProcedure(0));
+    }
+    for opcode in program {
+        // Overwritten before every bound procedure - don't keep data there.
+        if let Opcode::SetRegister(TRIGGER_REGISTER, _) = opcode {
+            return Err(ProgramError::ReservedRegister(TRIGGER_REGISTER));
+        }
+        for out in opcode.outputs().into_iter().flatten() {
+            if !outputs.contains(&out) {
+                return Err(ProgramError::UnknownOutput(out));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Bindings created by a Bind* opcode on a given layer.
+pub fn opcode_bindings(opcode: Opcode, layer: LayerIdx) -> Vec<Binding, 3> {
+    let proc = |idx, trigger, proc_idx| Binding {
+        idx,
+        trigger,
+        layer,
+        action: Action::Proc(proc_idx),
+    };
+    let single = |idx, trigger, command| Binding {
+        idx,
+        trigger,
+        layer,
+        action: Action::Single(command),
+    };
+
+    let mut bindings = Vec::new();
+    let mut add = |binding| {
+        // Capacity covers the largest shortcut.
+        let _ = bindings.push(binding);
+    };
+    match opcode {
+        Opcode::BindShortCall(idx, proc_idx) => add(proc(idx, Trigger::ShortClick, proc_idx)),
+        Opcode::BindLongCall(idx, proc_idx) => add(proc(idx, Trigger::LongClick, proc_idx)),
+        Opcode::BindActivateCall(idx, proc_idx) => add(proc(idx, Trigger::Activated, proc_idx)),
+        Opcode::BindDeactivateCall(idx, proc_idx) => add(proc(idx, Trigger::Deactivated, proc_idx)),
+        Opcode::BindLongActivate(idx, proc_idx) => add(proc(idx, Trigger::LongActivated, proc_idx)),
+        Opcode::BindLongDeactivate(idx, proc_idx) => {
+            add(proc(idx, Trigger::LongDeactivated, proc_idx))
+        }
+        Opcode::BindMulti(idx, short, long, double) => {
+            let (short, long, double) = (
+                Action::Proc(short),
+                Action::Proc(long),
+                Action::Proc(double),
+            );
+            for binding in Binding::multi(idx, layer, short, long, double) {
+                add(binding);
+            }
+        }
+        Opcode::BindEdges(idx, proc_idx) => {
+            for trigger in [
+                Trigger::Activated,
+                Trigger::LongActivated,
+                Trigger::Deactivated,
+            ] {
+                add(proc(idx, trigger, proc_idx));
+            }
+        }
+
+        // Trivial configuration shortcuts.
+        Opcode::BindShortToggle(idx, out_idx) => add(single(
+            idx,
+            Trigger::ShortClick,
+            Command::ToggleOutput(out_idx),
+        )),
+        Opcode::BindShortToggleMulti(idx, outs) => add(single(
+            idx,
+            Trigger::ShortClick,
+            Command::ToggleOutputs(outs),
+        )),
+        Opcode::BindLongToggle(idx, out_idx) => add(single(
+            idx,
+            Trigger::LongClick,
+            Command::ToggleOutput(out_idx),
+        )),
+        Opcode::BindMomentary(idx, out_idx) => {
+            // NOTE: Release is handled automatically and should not be bound.
+            add(single(
+                idx,
+                Trigger::Activated,
+                Command::MomentaryOutput(out_idx),
+            ))
+        }
+        Opcode::BindLayerHold(idx, layer_idx, deferred) => {
+            // When this is in use + ShortClick is defined for the same key,
+            // then the shortclick should be defined on new layer - unless
+            // the activation is deferred, then a tap stays on this layer.
+            // NOTE: Layer deactivation is handled automatically and should
+            // not be bound.
+            let trigger = if deferred {
+                Trigger::LongActivated
+            } else {
+                Trigger::Activated
+            };
+            add(single(idx, trigger, Command::ActivateLayer(layer_idx)))
+        }
+        Opcode::BindScene(idx, slot) => {
+            add(single(idx, Trigger::ShortClick, Command::RecallScene(slot)));
+            add(single(idx, Trigger::LongClick, Command::CaptureScene(slot)));
+        }
+        _ => {}
+    }
+    bindings
+}
+
+/// Index procedures' starts within the code. Procedures outside of the table
+/// are reported and skipped.
+pub fn index_procedures(opcodes: &[Opcode], procedures: &mut [usize]) {
+    procedures.fill(0);
+
+    for (idx, opcode) in opcodes.iter().enumerate() {
+        if let Opcode::Start(proc_idx) = opcode {
+            let Some(current) = procedures.get_mut(*proc_idx as usize) else {
+                defmt::error!(
+                    "Proc {} at {} exceeds the procedure table of {}",
+                    proc_idx,
+                    idx,
+                    procedures.len()
+                );
+                continue;
+            };
+            if *current != 0 && idx != 0 {
+                defmt::warn!(
+                    "Duplicate proc {}. Was at {} is also at {}",
+                    proc_idx,
+                    *current,
+                    idx
+                );
+            }
+            *current = idx;
+        }
+    }
+}
+
+impl<
+    IO: VmIo + 'static,
+    const BN: usize,
+    const OPCODES: usize,
+    const REGS: usize,
+    const PROCS: usize,
+    const STACK: usize,
+> Executor<IO, BN, OPCODES, REGS, PROCS, STACK>
+{
+    pub fn new(board: &'static IO, shutters_addr: shutters::ShutterChannel) -> Self {
         Self {
             layers: Layers::new(),
             bindings: BindingList::new(),
-            opcodes: [Opcode::Noop; 1024],
-            procedures: [0; MAX_PROCEDURES],
+            opcodes: [Opcode::Noop; OPCODES],
+            procedures: [0; PROCS],
             state: BoardState::default(),
+            momentary: MomentaryOutputs::new(),
+            timed: TimedOutputs::new(),
+            pulsed: PulsedOutputs::new(),
+            maintenance: Maintenance::new(),
+            chords: Chords::new(),
+            locks: OutputLocks::new(),
+            feedback: FeedbackGuard::new(),
+            rotary: Vec::new(),
+            default_command: None,
             board,
             shutters: shutters_addr,
+            parked: None,
         }
     }
 
-    pub async fn load_static(&mut self, program: &[Opcode]) {
+    /// Wait for the shutter manager to park the shutters before a reload.
+    pub fn with_parked(mut self, parked: &'static shutters::ParkSignal) -> Self {
+        self.parked = Some(parked);
+        self
+    }
+
+    /// Set command executed for unbound inputs, so every input does
+    /// something observable before it's configured. None disables it.
+    pub fn set_default_command(&mut self, command: Option<Command>) {
+        self.default_command = command;
+    }
+
+    /// Read microvm register.
+    pub fn get_register(&self, reg: u8) -> Option<u8> {
+        self.state.get_register(reg)
+    }
+
+    /// Preset microvm register (eg. to restore scene state).
+    pub fn set_register(&mut self, reg: u8, value: u8) -> Result<(), RegisterError> {
+        self.state.set_register(reg, value)
+    }
+
+    /// Validate and load the program, then execute the setup procedure.
+    /// Also used to replace a running program - previous code and runtime
+    /// state are dropped.
+    pub async fn load_static(&mut self, program: &[Opcode]) -> Result<(), ProgramError> {
+        if program.len() > OPCODES {
+            return Err(ProgramError::TooLong(program.len()));
+        }
+        let status = self.board.get_output_status().await;
+        let outputs: Vec<OutIdx, MAX_OUTPUTS> =
+            status.as_ref().iter().map(|(idx, _)| *idx).collect();
+        validate(program, &outputs)?;
+
+        self.clear_runtime().await;
+        self.opcodes.fill(Opcode::Noop);
         for (idx, opcode) in program.iter().enumerate() {
             self.opcodes[idx] = *opcode;
         }
         self.index_code();
-        self.execute(0).await;
+        let result = self.execute(0).await;
         // Finish on default layer
         self.layers.reset();
+        result
+    }
+
+    /// Reset layers, registers and bindings to the state right after the
+    /// program was loaded. Code is kept and setup procedure is executed again.
+    pub async fn reset_runtime(&mut self) {
+        defmt::info!("Resetting executor runtime state");
+        self.clear_runtime().await;
+        // Setup procedure was validated on load.
+        let _ = self.execute(0).await;
+        // Finish on default layer, just like after load.
+        self.layers.reset();
+    }
+
+    /// Clear registers, layers, bindings and release held outputs.
+    async fn clear_runtime(&mut self) {
+        self.state.reset();
+        self.layers.reset();
+        self.bindings.clear();
+        self.maintenance.unbind();
+        self.chords.clear();
+        self.rotary.clear();
+        for out in self.momentary.release_all() {
+            self.alter_output(IOCommand::DeactivateOutput(out), Origin::Internal)
+                .await;
+        }
     }
 
     /// Broadcast our output state change
@@ -98,6 +422,10 @@ impl<const BN: usize> Executor<BN> {
             out,
             final_state
         );
+        trace::record(TraceEvent::Output {
+            output: out,
+            state: final_state,
+        });
 
         // TODO: I've mixed feeling about handling this in emit(). Move lower
         // and create emit_message and emit_io?
@@ -113,40 +441,263 @@ impl<const BN: usize> Executor<BN> {
 
         // Transmit information over CAN.
         // In case of broken CAN communication this will be ignored.
-        self.board
-            .interconnect
-            .transmit_response(&message, WhenFull::Drop)
-            .await;
+        self.board.transmit(&message, WhenFull::Drop).await;
+    }
+
+    /// Toggle an output group and announce every member's new state.
+    async fn toggle_group(&mut self, id: u8, origin: Origin) {
+        let members = self.board.group_members(id).await.unwrap_or_default();
+        if let Some(out) = members.iter().find(|out| self.locks.is_locked(**out)) {
+            defmt::warn!(
+                "Output {} is locked, ignoring group {} from {:?}",
+                out,
+                id,
+                origin
+            );
+            return;
+        }
+        match self.board.toggle_group(id).await {
+            Ok((state, members)) => {
+                defmt::info!("Executor toggled group {} from {:?}", id, origin);
+                for out in members {
+                    self.emit_io_message(out, state).await;
+                }
+            }
+            Err(err) => {
+                self.report_output_error(Opcode::ToggleGroup(id), origin, Some(err))
+                    .await
+            }
+        }
+    }
+
+    /// Step the brightness of a rotary encoder's output.
+    async fn dim(&mut self, pos: usize, steps: i8) {
+        let Dimmer { input, out, level } = self.rotary[pos];
+        let origin = Origin::LocalButton(input);
+        if self.locks.is_locked(out) {
+            defmt::warn!("Output {} is locked, ignoring rotation of {}", out, input);
+            return;
+        }
+        // Switched by other bindings since the last rotation.
+        let was_on = self.board.get_output(out).await == Some(true);
+        let level = match (was_on, level) {
+            (true, 0) => 100,
+            (true, level) => level,
+            (false, _) => 0,
+        };
+        let level = (level as i16 + steps as i16 * BRIGHTNESS_STEP as i16).clamp(0, 100) as u8;
+        match self.board.set_brightness(out, level).await {
+            Ok(()) => {
+                defmt::info!("Output {} dimmed to {}% from {:?}", out, level, origin);
+                self.rotary[pos].level = level;
+                if was_on != (level > 0) {
+                    self.emit_io_message(out, level > 0).await;
+                }
+            }
+            Err(err) => {
+                self.report_output_error(Event::Rotate(input, steps), origin, Some(err))
+                    .await
+            }
+        }
     }
 
     /// Handle outputs from Executor: Emit two messages and change internal state.
-    async fn alter_output(&mut self, command: IOCommand) {
+    async fn alter_output(&mut self, command: IOCommand, origin: Origin) {
+        let out = match command {
+            IOCommand::ToggleOutput(out)
+            | IOCommand::ActivateOutput(out)
+            | IOCommand::DeactivateOutput(out) => out,
+        };
+        if self.locks.is_locked(out) {
+            defmt::warn!(
+                "Output {} is locked, ignoring {:?} from {:?}",
+                out,
+                command,
+                origin
+            );
+            if let Origin::Remote(_) = origin {
+                self.acknowledge_unchanged(out).await;
+            }
+            return;
+        }
+        if let Origin::Remote(_) = origin
+            && self.feedback.is_suppressed(out, Instant::now())
+        {
+            // Not acknowledged - a change notification would feed the loop.
+            defmt::warn!(
+                "Output {} in a feedback loop, ignoring {:?} from {:?}",
+                out,
+                command,
+                origin
+            );
+            return;
+        }
+
         // Update local state
-        let (result, out) = match &command {
-            IOCommand::ToggleOutput(out) => (self.board.toggle_output(*out).await, *out),
-            IOCommand::ActivateOutput(out) => {
-                (self.board.set_output(*out, true).await.map(|()| true), *out)
-            }
-            IOCommand::DeactivateOutput(out) => (
-                self.board.set_output(*out, false).await.map(|()| false),
-                *out,
-            ),
+        let result = match command {
+            IOCommand::ToggleOutput(_) => self.board.toggle_output(out).await,
+            IOCommand::ActivateOutput(_) => self.board.set_output(out, true).await.map(|()| true),
+            IOCommand::DeactivateOutput(_) => {
+                self.board.set_output(out, false).await.map(|()| false)
+            }
+        };
+
+        match result {
+            Ok(final_state) => {
+                defmt::info!(
+                    "Executor changed output state {:?} from {:?}",
+                    command,
+                    origin
+                );
+                self.emit_io_message(out, final_state).await;
+                if let Origin::Remote(_) = origin
+                    && self.feedback.record(out, final_state, Instant::now())
+                {
+                    self.report_feedback_loop(out).await;
+                }
+            }
+            Err(err) => {
+                self.report_output_error(&command, origin, Some(err)).await;
+                if let Origin::Remote(_) = origin {
+                    self.acknowledge_unchanged(out).await;
+                }
+            }
+        }
+    }
+
+    /// Set the output regardless of a lock, then lock or release it.
+    async fn override_output(&mut self, out: OutIdx, state: bool, lock: bool, addr: u8) {
+        defmt::warn!("Output {} overridden to {}, lock: {}", out, state, lock);
+        self.locks.unlock(out);
+        let command = if state {
+            IOCommand::ActivateOutput(out)
+        } else {
+            IOCommand::DeactivateOutput(out)
+        };
+        self.alter_output(command, Origin::Remote(addr)).await;
+        if lock && self.locks.lock(out).is_err() {
+            defmt::error!("Output {} can't be locked", out);
+        }
+    }
+
+    /// Report an output toggled back and forth by remotes. Its remote
+    /// requests are ignored for a while.
+    async fn report_feedback_loop(&self, out: OutIdx) {
+        defmt::error!(
+            "Output {} flips in a feedback loop - suppressing remotes",
+            out
+        );
+        let code = args::ErrorCode::FeedbackLoop;
+        trace::record(TraceEvent::Error { code: code as u8 });
+        let message = Message::Error {
+            code: code.with_detail(out),
+        };
+        self.board.transmit(&message, WhenFull::Drop).await;
+    }
+
+    /// Answer a failed remote request with the state the output stayed in,
+    /// so the requester doesn't assume it was applied.
+    async fn acknowledge_unchanged(&self, out: OutIdx) {
+        let Some(state) = self.board.get_output(out).await else {
+            return;
         };
+        let message = Message::OutputChanged {
+            output: out,
+            state: args::OutputChangeRequest::from_bool(state),
+        };
+        self.board.transmit(&message, WhenFull::Drop).await;
+    }
+
+    /// Report a failed output change. Known failures carry the expander
+    /// and line in the error detail.
+    async fn report_output_error(
+        &self,
+        change: impl Format,
+        origin: Origin,
+        error: Option<OutputError>,
+    ) {
+        defmt::error!(
+            "Error while setting output {:?} from {:?}: {:?}",
+            change,
+            origin,
+            error
+        );
+        status::COUNTERS.expander_output_error.inc();
+        let code = args::ErrorCode::ExpanderOutputFailure;
+        trace::record(TraceEvent::Error { code: code as u8 });
+        let message = Message::Error {
+            code: error.map_or(code.to_u32(), |err| code.with_detail(err.detail())),
+        };
+        self.board.transmit(&message, WhenFull::Drop).await;
+    }
 
-        if let Ok(final_state) = result {
-            defmt::info!("Executor changed output state {:?}", command);
-            self.emit_io_message(out, final_state).await;
+    /// Enter or leave maintenance mode.
+    async fn set_maintenance(&mut self, enabled: bool) {
+        defmt::warn!("Maintenance mode: {}", enabled);
+        self.maintenance.set(enabled);
+        self.board.set_maintenance(enabled);
+        if enabled {
+            // Inputs won't be released while in maintenance.
+            self.layers.reset();
+            for out in self.momentary.release_all() {
+                self.alter_output(IOCommand::DeactivateOutput(out), Origin::Internal)
+                    .await;
+            }
+        }
+    }
+
+    /// Store current outputs into a scene slot.
+    async fn capture_scene(&mut self, slot: SceneIdx) {
+        let scene = Scene::capture(self.board.get_output_status().await.as_ref());
+        if self.board.store_scene(slot, scene).await.is_ok() {
+            defmt::info!("Captured scene {} as {:?}", slot, scene);
         } else {
-            defmt::error!("Error while setting output {:?}", command);
-            status::COUNTERS.expander_output_error.inc();
-            // TODO: Send error message over CAN?
+            defmt::warn!("Invalid scene slot {}", slot);
+        }
+    }
+
+    /// Restore outputs from a scene slot. Only differing outputs are changed.
+    async fn recall_scene(&mut self, slot: SceneIdx, origin: Origin) {
+        let Some(scene) = self.board.load_scene(slot).await else {
+            defmt::warn!("Scene {} was not captured", slot);
+            return;
+        };
+        let status = self.board.get_output_status().await;
+        let mut changes: Vec<(OutIdx, bool), SCENE_OUTPUTS> = Vec::new();
+        for (out, on) in scene.changes(status.as_ref()) {
+            if self.locks.is_locked(out) {
+                defmt::warn!("Output {} is locked, skipped by scene {}", out, slot);
+            } else {
+                // Scene has at most SCENE_OUTPUTS changes.
+                let _ = changes.push((out, on));
+            }
+        }
+        let result = self.board.set_outputs(&changes).await;
+        defmt::info!("Executor recalled scene {} from {:?}", slot, origin);
+        for (out, on) in changes {
+            if self.board.get_output(out).await == Some(on) {
+                self.emit_io_message(out, on).await;
+            }
+        }
+        if let Err(err) = result {
+            self.report_output_error(Opcode::RecallScene(slot), origin, Some(err))
+                .await;
         }
     }
 
     /// Send MASS status info.
     async fn send_status(&mut self) {
+        // Data frame answering a status RTR, then the IO states.
+        let (errors, warnings) = status::COUNTERS.totals();
+        let message = Message::Status {
+            uptime: self.board.uptime_secs(),
+            errors,
+            warnings,
+        };
+        self.board.transmit(&message, WhenFull::Wait).await;
+
         let status = self.board.get_output_status().await;
-        for (idx, state) in status {
+        for &(idx, state) in status.as_ref() {
             let state = if state {
                 args::IOState::On
             } else {
@@ -158,10 +709,7 @@ impl<const BN: usize> Executor<BN> {
             };
             // Transmit information over CAN.
             defmt::info!("Sent status message {:?}", message);
-            self.board
-                .interconnect
-                .transmit_response(&message, WhenFull::Wait)
-                .await;
+            self.board.transmit(&message, WhenFull::Wait).await;
 
             // Don't block on CAN in case it died (we are alone on bus for
             // example), but give it some time to send. On 250kBps frame should
@@ -169,9 +717,9 @@ impl<const BN: usize> Executor<BN> {
             Timer::after(Duration::from_millis(1)).await;
         }
 
-        for exp in [&self.board.expander_sensors, &self.board.expander_switches] {
-            let inputs = exp.get_inputs();
-            if let Some(inputs) = inputs {
+        // Sensors first.
+        for exp in self.board.input_expanders().iter().rev() {
+            if let Some(inputs) = exp.inputs {
                 for (idx, state) in inputs {
                     let state = if state {
                         args::IOState::On
@@ -184,26 +732,20 @@ impl<const BN: usize> Executor<BN> {
                     };
                     // Transmit information over CAN.
                     defmt::info!("Sent status input message {:?}", message);
-                    self.board
-                        .interconnect
-                        .transmit_response(&message, WhenFull::Wait)
-                        .await;
+                    self.board.transmit(&message, WhenFull::Wait).await;
                 }
             } else {
-                for idx in exp.get_indices() {
+                for idx in exp.indices {
                     let message = Message::StatusIO {
-                        io: args::IOType::Input(*idx),
+                        io: args::IOType::Input(idx),
                         state: args::IOState::Error,
                     };
-                    self.board
-                        .interconnect
-                        .transmit_response(&message, WhenFull::Wait)
-                        .await;
+                    self.board.transmit(&message, WhenFull::Wait).await;
                 }
                 defmt::info!(
                     "Expander id={} does not respond. Dead: {:?}",
-                    exp.get_id(),
-                    exp.get_indices()
+                    exp.id,
+                    exp.indices
                 );
             }
         }
@@ -211,27 +753,80 @@ impl<const BN: usize> Executor<BN> {
         // TODO: Send global warning/error status as well.
     }
 
-    /// Helper: Bind input/trigger to a call to a given procedure.
-    fn bind_proc(&mut self, idx: InIdx, trigger: Trigger, proc_idx: ProcIdx) {
-        self.bindings.bind(Binding {
-            idx,
-            trigger,
+    /// Send the diagnostic dump as a sequence of frames.
+    async fn send_diagnostics(&mut self) {
+        let mut outputs = 0;
+        let status = self.board.get_output_status().await;
+        for (pos, (_idx, state)) in status.as_ref().iter().enumerate().take(32) {
+            if *state {
+                outputs |= 1 << pos;
+            }
+        }
+        let mut expanders_online = 0;
+        for (bit, exp) in self.board.input_expanders().iter().enumerate() {
+            if exp.inputs.is_some() {
+                expanders_online |= 1 << bit;
+            }
+        }
+
+        let diagnostics = Diagnostics {
+            version: Diagnostics::firmware_version(),
+            uptime: self.board.uptime_secs(),
+            counters: Diagnostics::counters_from(status::COUNTERS.values()),
             layer: self.layers.current,
-            action: Action::Proc(proc_idx),
-        });
+            stack_used: crate::stack_high_water().min(u16::MAX as u32) as u16,
+            outputs,
+            expanders_online,
+        };
+        defmt::info!("Sending diagnostics {:?}", diagnostics);
+        for message in diagnostics.to_messages() {
+            self.board.transmit(&message, WhenFull::Wait).await;
+            // Give CAN time to send, like in the status.
+            Timer::after(Duration::from_millis(1)).await;
+        }
     }
 
-    /// Helper: Bind input/trigger to single command.
-    fn bind_single(&mut self, idx: InIdx, trigger: Trigger, command: Command) {
-        self.bindings.bind(Binding {
-            idx,
-            trigger,
-            layer: self.layers.current,
-            action: Action::Single(command),
-        });
+    /// Send the event trace, oldest entry first.
+    async fn send_trace(&self) {
+        for message in trace::TRACE.messages() {
+            self.board.transmit(&message, WhenFull::Wait).await;
+            Timer::after(Duration::from_millis(1)).await;
+        }
+    }
+
+    /// Call `out` for each active binding, ordered by input and layer.
+    pub fn dump_bindings(&self, out: &mut impl FnMut(&Binding)) {
+        for binding in self.bindings.iter() {
+            out(binding);
+        }
+    }
+
+    /// Stream the active bindings back: count first, then one frame each.
+    async fn send_bindings(&self) {
+        let header = Message::Info {
+            code: args::InfoCode::Bindings.to_bytes(),
+            arg: self.bindings.len() as u32,
+        };
+        self.transmit_paced(&header).await;
+        for binding in self.bindings.iter() {
+            defmt::info!("Binding {:?}", binding);
+            let message = Message::Info {
+                code: args::InfoCode::Binding.to_bytes(),
+                arg: binding.to_info_arg(),
+            };
+            self.transmit_paced(&message).await;
+        }
+    }
+
+    /// Transmit one frame of a longer report.
+    async fn transmit_paced(&self, message: &Message) {
+        self.board.transmit(message, WhenFull::Wait).await;
+        // Give CAN time to send, like in the status.
+        Timer::after(Duration::from_millis(1)).await;
     }
 
-    async fn execute_opcode(&mut self, opcode: Opcode) -> MicroState {
+    async fn execute_opcode(&mut self, opcode: Opcode, proc: ProcIdx) -> MicroState {
+        let origin = Origin::Procedure(proc);
         match opcode {
             Opcode::Noop => { /* Noop */ }
             Opcode::Stop => {
@@ -257,35 +852,52 @@ impl<const BN: usize> Executor<BN> {
             }
             */
             Opcode::CallRegister(register) => {
-                return MicroState::CallProc(self.state.registers[register as usize] as usize);
+                let Some(proc_id) = self.state.get_register(register) else {
+                    defmt::panic!("Register {} out of range {}", register, REGS);
+                };
+                return MicroState::CallProc(proc_id as usize);
             }
             Opcode::SetRegister(register, value) => {
-                self.state.registers[register as usize] = value;
+                if self.state.set_register(register, value).is_err() {
+                    defmt::panic!("Register {} out of range {}", register, REGS);
+                }
             }
             Opcode::Toggle(out_idx) => {
-                self.alter_output(IOCommand::ToggleOutput(out_idx)).await;
+                self.alter_output(IOCommand::ToggleOutput(out_idx), origin)
+                    .await;
             }
             Opcode::Activate(out_idx) => {
-                self.alter_output(IOCommand::ActivateOutput(out_idx)).await;
+                self.alter_output(IOCommand::ActivateOutput(out_idx), origin)
+                    .await;
             }
             Opcode::Deactivate(out_idx) => {
-                self.alter_output(IOCommand::DeactivateOutput(out_idx))
+                self.alter_output(IOCommand::DeactivateOutput(out_idx), origin)
                     .await;
             }
+            Opcode::ActivateFor(out_idx, time) => {
+                self.activate_for(out_idx, time, origin).await;
+            }
+            Opcode::PulseOutput(out_idx, count) => {
+                let timing = (pulsed::PULSE_ON, pulsed::PULSE_OFF);
+                self.pulse_output(out_idx, count, timing, origin).await;
+            }
+            Opcode::ToggleGroup(id) => {
+                self.toggle_group(id, origin).await;
+            }
 
             // Enable a layer (TODO: push layer onto a layer stack?)
             Opcode::LayerPush(layer) => {
                 assert!(layer as usize <= MAX_LAYERS);
                 // Use a `virtual` input idx of 0 when forcing a layer activation.
-                self.layers.activate(0, layer);
+                self.layers.activate(RESERVED_IDX, layer);
             }
             Opcode::LayerPop => {
                 // Deactivate last virtual 0 input.
-                self.layers.maybe_deactivate(0);
+                self.layers.maybe_deactivate(RESERVED_IDX);
             }
             Opcode::LayerSet(layer) => {
                 self.layers.reset();
-                self.layers.activate(0, layer);
+                self.layers.activate(RESERVED_IDX, layer);
             }
 
             // Clear the layer stack - back to default layer.
@@ -301,56 +913,49 @@ impl<const BN: usize> Executor<BN> {
                 self.bindings.clear();
             }
 
-            Opcode::BindShortCall(switch_id, proc_idx) => {
-                self.bind_proc(switch_id, Trigger::ShortClick, proc_idx);
-            }
-            Opcode::BindLongCall(switch_id, proc_idx) => {
-                self.bind_proc(switch_id, Trigger::LongClick, proc_idx);
-            }
-            Opcode::BindActivateCall(switch_id, proc_idx) => {
-                self.bind_proc(switch_id, Trigger::Activated, proc_idx);
-            }
-            Opcode::BindDeactivateCall(switch_id, proc_idx) => {
-                self.bind_proc(switch_id, Trigger::Deactivated, proc_idx);
-            }
-            Opcode::BindLongActivate(switch_id, proc_idx) => {
-                self.bind_proc(switch_id, Trigger::LongActivated, proc_idx);
-            }
-            Opcode::BindLongDeactivate(switch_id, proc_idx) => {
-                self.bind_proc(switch_id, Trigger::LongDeactivated, proc_idx);
+            Opcode::BindShortCall(..)
+            | Opcode::BindLongCall(..)
+            | Opcode::BindActivateCall(..)
+            | Opcode::BindDeactivateCall(..)
+            | Opcode::BindLongActivate(..)
+            | Opcode::BindLongDeactivate(..)
+            | Opcode::BindMulti(..)
+            | Opcode::BindEdges(..)
+            | Opcode::BindShortToggle(..)
+            | Opcode::BindShortToggleMulti(..)
+            | Opcode::BindLongToggle(..)
+            | Opcode::BindMomentary(..)
+            | Opcode::BindLayerHold(..)
+            | Opcode::BindScene(..) => {
+                for binding in opcode_bindings(opcode, self.layers.current) {
+                    self.bindings.bind(binding);
+                }
             }
 
-            /*
-             * Shortcuts
-             */
-            // Trivial configuration shortcuts.
-            Opcode::BindShortToggle(switch_id, out_idx) => {
-                self.bind_single(
-                    switch_id,
-                    Trigger::ShortClick,
-                    Command::ToggleOutput(out_idx),
-                );
+            Opcode::BindRotate(input, out) => {
+                let dimmer = Dimmer {
+                    input,
+                    out,
+                    level: 0,
+                };
+                match self.rotary.iter_mut().find(|bound| bound.input == input) {
+                    Some(bound) => *bound = dimmer,
+                    None => {
+                        if self.rotary.push(dimmer).is_err() {
+                            defmt::warn!("Too many rotary encoders, ignoring {}", input);
+                        }
+                    }
+                }
             }
 
-            Opcode::BindLongToggle(switch_id, out_idx) => {
-                self.bind_single(
-                    switch_id,
-                    Trigger::LongClick,
-                    Command::ToggleOutput(out_idx),
-                );
+            Opcode::BindMaintenance(switch_id) => {
+                self.maintenance.bind(switch_id);
             }
 
-            Opcode::BindLayerHold(switch_id, layer_idx) => {
-                // When this is in use + ShortClick is defined for the same key,
-                // then the shortclick should be defined on new layer.
-                self.bind_single(
-                    switch_id,
-                    Trigger::Activated,
-                    Command::ActivateLayer(layer_idx),
-                );
-
-                // NOTE: Layer deactivation is handled automatically and should
-                // not be bound.
+            Opcode::BindChord(hold, tap, proc_idx) => {
+                if self.chords.bind(hold, tap, proc_idx).is_err() {
+                    defmt::warn!("Too many chords, ignoring {}+{}", hold, tap);
+                }
             }
 
             Opcode::BindShutter(shutter_idx, down_idx, up_idx) => {
@@ -360,13 +965,20 @@ impl<const BN: usize> Executor<BN> {
             }
 
             Opcode::ShutterCmd(shutter_idx, shutter_cmd) => {
-                self.shutters.send((shutter_idx, shutter_cmd)).await;
+                shutters::dispatch(&self.shutters, shutter_idx, shutter_cmd).await;
             }
 
             Opcode::SendStatus => {
                 self.send_status().await;
             }
 
+            Opcode::CaptureScene(slot) => {
+                self.capture_scene(slot).await;
+            }
+            Opcode::RecallScene(slot) => {
+                self.recall_scene(slot, origin).await;
+            }
+
             // Hypothetical?
             // Read input value (local) into register
             /*
@@ -383,18 +995,42 @@ impl<const BN: usize> Executor<BN> {
         MicroState::Continue
     }
 
-    pub async fn execute(&mut self, proc: ProcIdx) {
-        let mut pc = self.procedures[proc as usize];
+    /// Start of the procedure, if the program has it.
+    fn procedure_start(&self, proc: ProcIdx) -> Result<usize, ProgramError> {
+        let Some(&start) = self.procedures.get(proc as usize) else {
+            defmt::error!("Procedure {} out of range {}", proc, PROCS);
+            return Err(ProgramError::MissingProcedure(proc));
+        };
+        // Missing procedures are indexed at 0.
+        if self.opcodes[start] != Opcode::Start(proc) {
+            defmt::error!("Procedure {} is not defined", proc);
+            return Err(ProgramError::MissingProcedure(proc));
+        }
+        Ok(start)
+    }
+
+    /// Run the procedure. Fails if it, or a procedure it calls, is missing.
+    pub async fn execute(&mut self, proc: ProcIdx) -> Result<(), ProgramError> {
+        let mut pc = self.procedure_start(proc)?;
 
         // We start with an empty stack. First procedure doesn't need an entry.
-        let mut stack: [usize; MAX_STACK] = [0; MAX_STACK];
+        let mut stack: [usize; STACK] = [0; STACK];
+        // Procedures returned to, to tag their commands.
+        let mut callers: [ProcIdx; STACK] = [0; STACK];
         let mut stack_idx = 0;
+        let mut current = proc;
+        let mut budget = EXECUTE_BUDGET;
 
-        assert_eq!(self.opcodes[pc], Opcode::Start(proc));
         loop {
             pc += 1;
-            let opcode = self.opcodes[pc];
-            match self.execute_opcode(opcode).await {
+            if budget == 0 {
+                return self.abort_runaway(proc, pc).await;
+            }
+            budget -= 1;
+            let Some(&opcode) = self.opcodes.get(pc) else {
+                return self.abort_runaway(proc, pc).await;
+            };
+            match self.execute_opcode(opcode, current).await {
                 MicroState::Continue => {}
                 MicroState::Stop => {
                     if stack_idx == 0 {
@@ -403,39 +1039,112 @@ impl<const BN: usize> Executor<BN> {
                     }
                     stack_idx -= 1;
                     pc = stack[stack_idx];
+                    current = callers[stack_idx];
                 }
                 MicroState::CallProc(proc_id) => {
                     // Check for overflow.
-                    if stack_idx == MAX_STACK {
-                        defmt::panic!("Stack overflow! ptr={} stack={}", stack_idx, stack);
+                    if stack_idx == STACK {
+                        defmt::error!("Stack overflow! ptr={} stack={}", stack_idx, stack);
+                        return self.abort_runaway(proc, pc).await;
                     }
+                    let start = self.procedure_start(proc_id as ProcIdx)?;
                     stack[stack_idx] = pc;
+                    callers[stack_idx] = current;
                     stack_idx += 1;
-                    pc = self.procedures[proc_id];
+                    pc = start;
+                    current = proc_id as ProcIdx;
                     // pc points to Start now and will be incremented.
                 }
             }
         }
+        Ok(())
+    }
+
+    /// Report a procedure which would hang the event loop.
+    async fn abort_runaway(&mut self, proc: ProcIdx, pc: usize) -> Result<(), ProgramError> {
+        defmt::error!("Procedure {} aborted as a runaway at pc={}", proc, pc);
+        let code = args::ErrorCode::ProgramRunaway;
+        trace::record(TraceEvent::Error { code: code as u8 });
+        let message = Message::Error {
+            code: code.with_detail(proc),
+        };
+        self.board.transmit(&message, WhenFull::Drop).await;
+        Err(ProgramError::Runaway { proc, pc })
     }
 
     /// Index procedures' starts
     fn index_code(&mut self) {
-        for i in 0..MAX_PROCEDURES {
-            self.procedures[i] = 0;
-        }
-
-        for (idx, opcode) in self.opcodes.iter().enumerate() {
-            if let Opcode::Start(proc_idx) = opcode {
-                let current = self.procedures[*proc_idx as usize];
-                if current != 0 && idx != 0 {
-                    defmt::warn!(
-                        "Duplicate proc {}. Was at {} is also at {}",
-                        proc_idx,
-                        current,
-                        idx
-                    );
+        index_procedures(&self.opcodes, &mut self.procedures);
+    }
+
+    /// Execute a command bound to a local input.
+    async fn run_command(&mut self, tagged: TaggedCommand) {
+        let TaggedCommand { command, origin } = tagged;
+        defmt::info!("Running {:?} from {:?}", command, origin);
+        // Layers and holds are tracked per input.
+        let switch_id = origin.input().unwrap_or(RESERVED_IDX);
+        match command {
+            Command::ActivateLayer(layer) => {
+                // Repeated LongActivated of a deferred hold.
+                if !self.layers.is_held(switch_id, layer) {
+                    self.layers.activate(switch_id, layer);
+                }
+            }
+            Command::DeactivateLayer(_layer) => {
+                todo!("deactivation is based on stack list");
+            }
+            Command::Noop => {}
+            Command::ToggleOutput(out) => {
+                self.alter_output(IOCommand::ToggleOutput(out), origin)
+                    .await;
+            }
+            Command::ToggleOutputs(outs) => {
+                for (pos, out) in outs.iter().enumerate() {
+                    if !outs[..pos].contains(out) {
+                        self.alter_output(IOCommand::ToggleOutput(*out), origin)
+                            .await;
+                    }
+                }
+            }
+            Command::ActivateOutput(out) => {
+                self.alter_output(IOCommand::ActivateOutput(out), origin)
+                    .await;
+            }
+            Command::DeactivateOutput(out) => {
+                self.alter_output(IOCommand::DeactivateOutput(out), origin)
+                    .await;
+            }
+            Command::MomentaryOutput(out) => {
+                if self.momentary.start(switch_id, out, Instant::now()) {
+                    self.alter_output(IOCommand::ActivateOutput(out), origin)
+                        .await;
+                } else {
+                    defmt::warn!("Too many momentary outputs held, ignoring {}", out);
                 }
-                self.procedures[*proc_idx as usize] = idx;
+            }
+            Command::ActivateFor(out, time) => {
+                self.activate_for(out, time, origin).await;
+            }
+            Command::PulseOutput {
+                idx,
+                count,
+                on_ms,
+                off_ms,
+            } => {
+                let timing = (
+                    Duration::from_millis(on_ms as u64),
+                    Duration::from_millis(off_ms as u64),
+                );
+                self.pulse_output(idx, count, timing, origin).await;
+            }
+            Command::Shutter(shutter_idx, cmd) => {
+                shutters::dispatch(&self.shutters, shutter_idx, cmd).await;
+            }
+            Command::CaptureScene(slot) => {
+                self.capture_scene(slot).await;
+            }
+            Command::RecallScene(slot) => {
+                self.recall_scene(slot, origin).await;
             }
         }
     }
@@ -445,6 +1154,28 @@ impl<const BN: usize> Executor<BN> {
         match event {
             // Local button press.
             Event::ButtonEvent(data) => {
+                if self.maintenance.track(&data) {
+                    let enabled = self.maintenance.is_enabled();
+                    self.set_maintenance(enabled).await;
+                    return;
+                }
+                if !self.maintenance.accepts_local() {
+                    defmt::info!("Maintenance mode - ignoring {:?}", data);
+                    return;
+                }
+
+                // Tracked before the layers, so releases are always seen.
+                let chord = self.chords.track(&data);
+
+                if data.trigger == Trigger::Deactivated {
+                    // Release momentary outputs held by this input.
+                    let origin = Origin::LocalButton(data.switch_id);
+                    for out in self.momentary.release(data.switch_id) {
+                        self.alter_output(IOCommand::DeactivateOutput(out), origin)
+                            .await;
+                    }
+                }
+
                 if data.trigger == Trigger::Deactivated
                     && self.layers.maybe_deactivate(data.switch_id)
                 {
@@ -454,39 +1185,32 @@ impl<const BN: usize> Executor<BN> {
                     return;
                 }
 
-                let binding = self.bindings.filter(
-                    data.switch_id,
-                    Some(self.layers.current),
-                    Some(data.trigger),
-                );
-                if let Some(binding) = binding {
-                    match binding.action {
-                        Action::Noop => {}
-                        Action::Single(cmd) => match cmd {
-                            Command::ActivateLayer(layer) => {
-                                self.layers.activate(data.switch_id, layer);
-                            }
-                            Command::DeactivateLayer(_layer) => {
-                                todo!("deactivation is based on stack list");
-                            }
-                            Command::Noop => {}
-                            Command::ToggleOutput(out) => {
-                                self.alter_output(IOCommand::ToggleOutput(out)).await;
-                            }
-                            Command::ActivateOutput(out) => {
-                                self.alter_output(IOCommand::ActivateOutput(out)).await;
-                            }
-                            Command::DeactivateOutput(out) => {
-                                self.alter_output(IOCommand::DeactivateOutput(out)).await;
-                            }
-                            Command::Shutter(shutter_idx, cmd) => {
-                                self.shutters.send((shutter_idx, cmd)).await;
-                            }
-                        },
-                        Action::Proc(proc_idx) => {
-                            self.execute(proc_idx).await;
+                let action = match chord {
+                    ChordAction::Pass => self.bindings.action_or_default(
+                        data.switch_id,
+                        self.layers.current,
+                        data.trigger,
+                        self.default_command,
+                    ),
+                    ChordAction::Fire(proc_idx) => Some(Action::Proc(proc_idx)),
+                    ChordAction::Suppress => None,
+                };
+                if let Some(action) = action {
+                    if let Action::Proc(proc_idx) = action {
+                        if self.state.set_trigger(data.trigger).is_err() {
+                            defmt::warn!("No trigger register in {} registers", REGS);
                         }
+                        // Missing procedure is reported by execute.
+                        let _ = self.execute(proc_idx).await;
+                    } else if let Some(command) = action.command_for(data.switch_id) {
+                        self.run_command(command).await;
                     }
+                    defmt::debug!(
+                        "Input {} {:?} handled with latency {}us",
+                        data.switch_id,
+                        data.trigger,
+                        data.at.elapsed().as_micros()
+                    );
                 } else {
                     defmt::info!("No binding for {:?}!", data);
                 }
@@ -497,35 +1221,1419 @@ impl<const BN: usize> Executor<BN> {
                     input: data.switch_id,
                     trigger: data.trigger,
                 };
-                self.board
-                    .interconnect
-                    .transmit_response(&msg, WhenFull::Wait)
-                    .await;
+                self.board.transmit(&msg, WhenFull::Wait).await;
             }
             // Remote call over Interconnect.
             Event::RemoteProcedureCall(proc_idx) => {
-                self.execute(proc_idx).await;
+                let _ = self.execute(proc_idx).await;
             }
-            Event::RemoteToggle(out_idx) => {
-                self.alter_output(IOCommand::ToggleOutput(out_idx)).await;
+            Event::RemoteToggle(out_idx, addr) => {
+                self.alter_output(IOCommand::ToggleOutput(out_idx), Origin::Remote(addr))
+                    .await;
             }
-            Event::RemoteActivate(out_idx) => {
-                self.alter_output(IOCommand::ActivateOutput(out_idx)).await;
+            Event::RemoteActivate(out_idx, addr) => {
+                self.alter_output(IOCommand::ActivateOutput(out_idx), Origin::Remote(addr))
+                    .await;
             }
-            Event::RemoteDeactivate(out_idx) => {
-                self.alter_output(IOCommand::DeactivateOutput(out_idx))
+            Event::RemoteDeactivate(out_idx, addr) => {
+                self.alter_output(IOCommand::DeactivateOutput(out_idx), Origin::Remote(addr))
                     .await;
             }
             Event::RemoteStatusRequest => {
                 self.send_status().await;
             }
-        }
-    }
-
-    pub async fn listen_events(&mut self, event_channel: &'static EventChannel) {
-        loop {
-            let input_event = event_channel.receive().await;
-            self.parse_event(input_event).await;
-        }
+            Event::RemoteDiagnosticsRequest => {
+                self.send_diagnostics().await;
+            }
+            Event::RemoteBindingsRequest => {
+                self.send_bindings().await;
+            }
+            Event::RemoteTraceRequest => {
+                self.send_trace().await;
+            }
+            Event::RemoteSetRegister(reg, value) => {
+                if self.set_register(reg, value).is_err() {
+                    defmt::warn!("Remote tried to set invalid register {}", reg);
+                }
+            }
+            Event::RemoteResetRuntime => {
+                self.reset_runtime().await;
+            }
+            Event::RemoteOverride(out_idx, state, lock, addr) => {
+                self.override_output(out_idx, state, lock, addr).await;
+            }
+            Event::RemoteSetMaintenance(enabled) => {
+                self.set_maintenance(enabled).await;
+            }
+            Event::RemoteCaptureScene(slot) => {
+                self.capture_scene(slot).await;
+            }
+            Event::RemoteRecallScene(slot, addr) => {
+                self.recall_scene(slot, Origin::Remote(addr)).await;
+            }
+            Event::PulseCount(input, edges) => {
+                let [low, high] = edges.to_le_bytes();
+                let msg = Message::Info {
+                    code: args::InfoCode::PulseCount.to_bytes(),
+                    arg: u32::from_le_bytes([input, 0, low, high]),
+                };
+                self.board.transmit(&msg, WhenFull::Wait).await;
+            }
+            Event::Rotate(input, steps) => {
+                if !self.maintenance.accepts_local() {
+                    defmt::info!("Maintenance mode - ignoring rotation of {}", input);
+                    return;
+                }
+                let Some(pos) = self.rotary.iter().position(|bound| bound.input == input) else {
+                    defmt::info!("Rotary encoder {} is not bound", input);
+                    return;
+                };
+                self.dim(pos, steps).await;
+            }
+            Event::RemoteGetRegister(reg) => {
+                if let Some(value) = self.get_register(reg) {
+                    let msg = Message::RegisterValue { reg, value };
+                    self.board.transmit(&msg, WhenFull::Wait).await;
+                } else {
+                    defmt::warn!("Remote asked for invalid register {}", reg);
+                }
+            }
+        }
+    }
+
+    /// Activate output and schedule turning it off.
+    async fn activate_for(&mut self, out: OutIdx, time: u8, origin: Origin) {
+        if self
+            .timed
+            .start(out, timed::deciseconds(time), Instant::now())
+        {
+            self.alter_output(IOCommand::ActivateOutput(out), origin)
+                .await;
+        } else {
+            defmt::warn!("Too many timed outputs running, ignoring {}", out);
+        }
+    }
+
+    /// Blink the output and return it to its current state. Pulses are
+    /// scheduled, events are handled in between.
+    async fn pulse_output(
+        &mut self,
+        out: OutIdx,
+        count: u8,
+        timing: (Duration, Duration),
+        origin: Origin,
+    ) {
+        let Some(prior) = self.board.get_output(out).await else {
+            defmt::warn!("Unable to pulse unknown output {}", out);
+            return;
+        };
+        if self.pulsed.start(out, count, timing, prior, Instant::now()) {
+            self.alter_output(IOCommand::ActivateOutput(out), origin)
+                .await;
+        } else {
+            defmt::warn!("Unable to pulse output {} {} times", out, count);
+        }
+    }
+
+    /// Turn off momentary outputs held for too long and timed outputs whose
+    /// time is up. Advance pulsing outputs.
+    async fn expire_outputs(&mut self, now: Instant) {
+        for out in self.momentary.expired(now) {
+            defmt::warn!("Momentary output {} held for too long - releasing", out);
+            self.alter_output(IOCommand::DeactivateOutput(out), Origin::Internal)
+                .await;
+        }
+        for out in self.timed.expired(now) {
+            defmt::info!("Timed output {} finished", out);
+            self.alter_output(IOCommand::DeactivateOutput(out), Origin::Internal)
+                .await;
+        }
+        for (out, on) in self.pulsed.due(now) {
+            let command = if on {
+                IOCommand::ActivateOutput(out)
+            } else {
+                IOCommand::DeactivateOutput(out)
+            };
+            self.alter_output(command, Origin::Internal).await;
+        }
+    }
+
+    /// Apply reconfiguration request.
+    async fn handle_control(&mut self, control: Control) {
+        match control {
+            Control::ReloadProgram(program) => {
+                defmt::info!("Reloading program of {} opcodes", program.len());
+                self.park_shutters().await;
+                if let Err(err) = self.load_static(program).await {
+                    defmt::error!("Program rejected, keeping the old one: {:?}", err);
+                    let code = args::ErrorCode::ProgramInvalid;
+                    trace::record(TraceEvent::Error { code: code as u8 });
+                    let message = Message::Error {
+                        code: code.to_u32(),
+                    };
+                    self.board.transmit(&message, WhenFull::Wait).await;
+                }
+            }
+        }
+    }
+
+    /// Stop all shutters, so no motor runs through a reload, and wait until
+    /// the manager parks them.
+    async fn park_shutters(&self) {
+        if let Some(parked) = self.parked {
+            parked.reset();
+        }
+        let queued =
+            shutters::dispatch(&self.shutters, shutters::ALL_SHUTTERS, shutters::Cmd::Stop).await;
+        if queued
+            && let Some(parked) = self.parked
+            && with_timeout(shutters::PARK_TIMEOUT, parked.wait())
+                .await
+                .is_err()
+        {
+            defmt::warn!("Shutters not parked in time, reloading anyway");
+        }
+    }
+
+    /// Process events until the end of time. Reconfiguration requests are
+    /// handled between events so nothing else needs to touch the executor.
+    pub async fn listen_events(
+        &mut self,
+        event_source: &impl EventSource,
+        control_channel: &'static ControlChannel,
+    ) {
+        loop {
+            let deadline = [
+                self.momentary.next_deadline(),
+                self.timed.next_deadline(),
+                self.pulsed.next_deadline(),
+            ]
+            .into_iter()
+            .flatten()
+            .min();
+            let next_event = async {
+                match deadline {
+                    Some(deadline) => with_deadline(deadline, event_source.receive()).await.ok(),
+                    None => Some(event_source.receive().await),
+                }
+            };
+            match select(control_channel.receive(), next_event).await {
+                Either::First(control) => self.handle_control(control).await,
+                Either::Second(Some(event)) => self.parse_event(event).await,
+                Either::Second(None) => self.expire_outputs(Instant::now()).await,
+            }
+        }
+    }
+}
+
+pub mod tests {
+    use super::*;
+    use crate::buttonsmash::consts::ShutterIdx;
+    use crate::buttonsmash::vm_io::ExpanderState;
+    use crate::io::events::OutputFault;
+    use core::cell::RefCell;
+    use embassy_futures::block_on;
+    use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+    use embassy_sync::channel::Channel;
+    use static_cell::StaticCell;
+
+    /// Board stand-in which records the output commands and sent errors and
+    /// output changes.
+    struct MockIo {
+        outputs: RefCell<[(OutIdx, bool); 19]>,
+        commands: RefCell<Vec<IOCommand, 16>>,
+        errors: RefCell<Vec<u32, 16>>,
+        changes: RefCell<Vec<(OutIdx, bool), 16>>,
+        /// Output whose writes fail, like behind an offline expander.
+        broken: Option<OutIdx>,
+        /// Scene slot 0.
+        scene: RefCell<Option<Scene>>,
+        /// Calls of set_outputs.
+        batches: RefCell<usize>,
+        /// Brightness set by the rotary encoders.
+        brightness: RefCell<Vec<(OutIdx, u8), 16>>,
+        /// Sent Info frames: code, arg.
+        infos: RefCell<Vec<(u16, u32), 16>>,
+    }
+
+    impl MockIo {
+        fn new() -> Self {
+            // Outputs used by the default program.
+            let mut outputs = [(0, false); 19];
+            for (pos, entry) in outputs.iter_mut().enumerate() {
+                let idx = pos as u8 + 1;
+                entry.0 = if idx <= 16 { idx } else { idx + 34 };
+            }
+            Self {
+                outputs: RefCell::new(outputs),
+                commands: RefCell::new(Vec::new()),
+                errors: RefCell::new(Vec::new()),
+                changes: RefCell::new(Vec::new()),
+                broken: None,
+                scene: RefCell::new(None),
+                batches: RefCell::new(0),
+                brightness: RefCell::new(Vec::new()),
+                infos: RefCell::new(Vec::new()),
+            }
+        }
+
+        fn record(&self, command: IOCommand) {
+            self.commands.borrow_mut().push(command).unwrap();
+        }
+
+        fn set(&self, out: OutIdx, state: bool) -> Result<(), OutputError> {
+            if self.broken == Some(out) {
+                // Outputs 1-16 are lines of the first expander.
+                return Err(OutputError {
+                    expander: 0,
+                    line: out - 1,
+                    fault: OutputFault::NoAcknowledge,
+                });
+            }
+            let mut outputs = self.outputs.borrow_mut();
+            let entry = outputs
+                .iter_mut()
+                .find(|(idx, _)| *idx == out)
+                .ok_or(OutputError::unknown())?;
+            entry.1 = state;
+            Ok(())
+        }
+    }
+
+    impl VmIo for MockIo {
+        type OutputStatus = [(OutIdx, bool); 19];
+
+        async fn toggle_output(&self, out: OutIdx) -> Result<bool, OutputError> {
+            self.record(IOCommand::ToggleOutput(out));
+            let state = !self.get_output(out).await.ok_or(OutputError::unknown())?;
+            self.set(out, state).map(|()| state)
+        }
+
+        async fn set_output(&self, out: OutIdx, state: bool) -> Result<(), OutputError> {
+            let command = if state {
+                IOCommand::ActivateOutput(out)
+            } else {
+                IOCommand::DeactivateOutput(out)
+            };
+            self.record(command);
+            self.set(out, state)
+        }
+
+        async fn set_outputs(&self, changes: &[(OutIdx, bool)]) -> Result<(), OutputError> {
+            *self.batches.borrow_mut() += 1;
+            let mut result = Ok(());
+            for (out, state) in changes {
+                if let Err(err) = self.set(*out, *state) {
+                    result = Err(err);
+                }
+            }
+            result
+        }
+
+        async fn set_brightness(&self, out: OutIdx, percent: u8) -> Result<(), OutputError> {
+            self.brightness.borrow_mut().push((out, percent)).unwrap();
+            self.set(out, percent > 0)
+        }
+
+        async fn toggle_group(&self, id: u8) -> Result<(bool, Vec<OutIdx, 16>), OutputError> {
+            let members = self.group_members(id).await.ok_or(OutputError::unknown())?;
+            let mut on = false;
+            for out in &members {
+                on |= self.get_output(*out).await.ok_or(OutputError::unknown())?;
+            }
+            for out in &members {
+                self.set(*out, !on)?;
+            }
+            Ok((!on, members))
+        }
+
+        async fn group_members(&self, id: u8) -> Option<Vec<OutIdx, 16>> {
+            // Single group of outputs 3 and 4.
+            (id == 1).then(|| Vec::from_slice(&[3, 4]).unwrap())
+        }
+
+        async fn get_output(&self, out: OutIdx) -> Option<bool> {
+            let outputs = self.outputs.borrow();
+            outputs
+                .iter()
+                .find(|(idx, _)| *idx == out)
+                .map(|(_, state)| *state)
+        }
+
+        async fn get_output_status(&self) -> Self::OutputStatus {
+            *self.outputs.borrow()
+        }
+
+        async fn store_scene(&self, slot: SceneIdx, scene: Scene) -> Result<(), ()> {
+            if slot != 0 {
+                return Err(());
+            }
+            *self.scene.borrow_mut() = Some(scene);
+            Ok(())
+        }
+
+        async fn load_scene(&self, slot: SceneIdx) -> Option<Scene> {
+            if slot != 0 {
+                return None;
+            }
+            *self.scene.borrow()
+        }
+
+        async fn transmit(&self, message: &Message, _when_full: WhenFull) -> bool {
+            match message {
+                Message::Error { code } => {
+                    self.errors.borrow_mut().push(*code).unwrap();
+                }
+                Message::OutputChanged { output, state } => {
+                    let on = *state == args::OutputChangeRequest::On;
+                    self.changes.borrow_mut().push((*output, on)).unwrap();
+                }
+                Message::Info { code, arg } => {
+                    self.infos.borrow_mut().push((*code, *arg)).unwrap();
+                }
+                _ => {}
+            }
+            true
+        }
+
+        fn set_maintenance(&self, _enabled: bool) {}
+
+        fn uptime_secs(&self) -> u32 {
+            0
+        }
+
+        fn input_expanders(&self) -> [ExpanderState; 2] {
+            [ExpanderState {
+                id: 0,
+                indices: [0; 16],
+                inputs: None,
+            }; 2]
+        }
+    }
+
+    /// Inbox standing for the shutter manager.
+    type ShutterInbox = Channel<CriticalSectionRawMutex, (ShutterIdx, shutters::Cmd), 4>;
+
+    /// Executor on a fresh MockIo, each call site gets its own statics.
+    /// Sizes follow the Executor generics. Returns the mock, the executor
+    /// and the shutter inbox.
+    macro_rules! mock_executor {
+        ($bindings:expr, $opcodes:expr) => {
+            mock_executor!(MockIo::new(), $bindings, $opcodes)
+        };
+        ($io:expr, $bindings:expr, $opcodes:expr $(, $size:expr)*) => {{
+            static IO: StaticCell<MockIo> = StaticCell::new();
+            static INBOX: ShutterInbox = Channel::new();
+            let io: &'static MockIo = IO.init($io);
+            let executor: Executor<MockIo, { $bindings }, { $opcodes } $(, { $size })*> =
+                Executor::new(io, INBOX.sender().into());
+            (io, executor, &INBOX)
+        }};
+    }
+
+    pub fn set_register_changes_called_proc() {
+        let (io, mut executor, _) = mock_executor!(4, 16);
+        let program = [
+            Opcode::Start(0),
+            Opcode::Stop,
+            // Calls whatever procedure is stored in register 2.
+            Opcode::Start(1),
+            Opcode::CallRegister(2),
+            Opcode::Stop,
+            Opcode::Start(3),
+            Opcode::SetRegister(2, 9),
+            Opcode::Stop,
+            Opcode::Start(7),
+            Opcode::Toggle(7),
+            Opcode::Stop,
+            Opcode::Start(9),
+            Opcode::Toggle(9),
+            Opcode::Stop,
+        ];
+        assert_eq!(block_on(executor.load_static(&program)), Ok(()));
+        assert_eq!(executor.get_register(2), Some(0));
+
+        // Preset remotely.
+        block_on(executor.parse_event(Event::RemoteSetRegister(2, 7)));
+        assert_eq!(block_on(executor.execute(1)), Ok(()));
+        assert_eq!(
+            io.commands.borrow().as_slice(),
+            &[IOCommand::ToggleOutput(7)]
+        );
+
+        // Changed by the program.
+        io.commands.borrow_mut().clear();
+        assert_eq!(block_on(executor.execute(3)), Ok(()));
+        assert_eq!(executor.get_register(2), Some(9));
+        assert_eq!(block_on(executor.execute(1)), Ok(()));
+        assert_eq!(
+            io.commands.borrow().as_slice(),
+            &[IOCommand::ToggleOutput(9)]
+        );
+
+        // Out of range.
+        assert_eq!(
+            executor.set_register(REGISTERS as u8, 1),
+            Err(RegisterError::OutOfRange(REGISTERS as u8))
+        );
+        assert_eq!(executor.get_register(REGISTERS as u8), None);
+    }
+
+    pub fn small_procedure_table() {
+        const PROCS: usize = 4;
+        let (io, mut executor, _) = mock_executor!(MockIo::new(), 4, 16, 4, PROCS);
+        let program = [
+            Opcode::Start(0),
+            Opcode::Call(3),
+            Opcode::Stop,
+            Opcode::Start(1),
+            Opcode::Toggle(1),
+            Opcode::Stop,
+            Opcode::Start(3),
+            Opcode::SetRegister(1, 1),
+            Opcode::Stop,
+            Opcode::Start(2),
+            Opcode::CallRegister(1),
+            Opcode::Stop,
+        ];
+        assert_eq!(block_on(executor.load_static(&program)), Ok(()));
+        assert_eq!(executor.procedures, [0, 3, 9, 6]);
+        // Setup set the register, procedure 2 calls through it.
+        assert_eq!(block_on(executor.execute(2)), Ok(()));
+        assert_eq!(
+            io.commands.borrow().as_slice(),
+            &[IOCommand::ToggleOutput(1)]
+        );
+
+        // Procedure beyond the table is skipped, not indexed over the bounds.
+        let program = [
+            Opcode::Start(0),
+            Opcode::Stop,
+            Opcode::Start(PROCS as u8),
+            Opcode::Toggle(2),
+            Opcode::Stop,
+        ];
+        assert_eq!(block_on(executor.load_static(&program)), Ok(()));
+        assert_eq!(executor.procedures, [0; PROCS]);
+        assert_eq!(
+            block_on(executor.execute(PROCS as u8)),
+            Err(ProgramError::MissingProcedure(PROCS as u8))
+        );
+        assert_eq!(io.commands.borrow().len(), 1);
+
+        // Smaller register file.
+        assert!(executor.set_register(3, 1).is_ok());
+        assert_eq!(
+            executor.set_register(4, 1),
+            Err(RegisterError::OutOfRange(4))
+        );
+    }
+
+    pub fn unknown_output_is_rejected() {
+        // 18-output node.
+        let outputs: [OutIdx; 18] = core::array::from_fn(|i| i as u8 + 1);
+        let program = [
+            Opcode::Start(0),
+            Opcode::BindShortToggle(1, 18),
+            Opcode::BindShutter(0, 13, 14),
+            Opcode::Stop,
+            Opcode::Start(1),
+            Opcode::Activate(1),
+            Opcode::Stop,
+        ];
+        assert_eq!(validate(&program, &outputs), Ok(()));
+
+        let mut program = program;
+        program[5] = Opcode::Activate(99);
+        assert_eq!(
+            validate(&program, &outputs),
+            Err(ProgramError::UnknownOutput(99))
+        );
+
+        program[5] = Opcode::Activate(1);
+        program[2] = Opcode::BindShutter(0, 13, 19);
+        assert_eq!(
+            validate(&program, &outputs),
+            Err(ProgramError::UnknownOutput(19))
+        );
+    }
+
+    pub fn missing_setup_is_rejected() {
+        let outputs: [OutIdx; 2] = [1, 2];
+        let missing = Err(ProgramError::MissingProcedure(0));
+        assert_eq!(validate(&[], &outputs), missing);
+        assert_eq!(validate(&[Opcode::Noop, Opcode::Stop], &outputs), missing);
+        assert_eq!(
+            validate(
+                &[Opcode::Start(1), Opcode::Activate(1), Opcode::Stop],
+                &outputs
+            ),
+            missing
+        );
+        // Setup doesn't have to come first.
+        let program = [
+            Opcode::Start(1),
+            Opcode::Activate(1),
+            Opcode::Stop,
+            Opcode::Start(0),
+            Opcode::Stop,
+        ];
+        assert_eq!(validate(&program, &outputs), Ok(()));
+    }
+
+    pub fn reload_replaces_bindings() {
+        let (io, mut executor, _) = mock_executor!(4, 8);
+        let first = [
+            Opcode::Start(0),
+            Opcode::BindShortToggle(1, 10),
+            Opcode::Stop,
+        ];
+        let second = [
+            Opcode::Start(0),
+            Opcode::BindLongToggle(1, 11),
+            Opcode::BindScene(2, 0),
+            Opcode::Stop,
+        ];
+        let action = |executor: &Executor<MockIo, 4, 8>, idx, trigger| {
+            executor
+                .bindings
+                .filter(idx, Some(0), Some(trigger))
+                .map(|binding| binding.action)
+        };
+        let press = |executor: &mut Executor<MockIo, 4, 8>, trigger| {
+            block_on(executor.parse_event(Event::new_button(1, trigger, Instant::now())));
+        };
+
+        assert_eq!(block_on(executor.load_static(&first)), Ok(()));
+        press(&mut executor, Trigger::ShortClick);
+        assert_eq!(
+            io.commands.borrow().as_slice(),
+            &[IOCommand::ToggleOutput(10)]
+        );
+
+        // Reload on the same executor drops the old bindings.
+        io.commands.borrow_mut().clear();
+        assert_eq!(block_on(executor.load_static(&second)), Ok(()));
+        assert_eq!(action(&executor, 1, Trigger::ShortClick), None);
+        press(&mut executor, Trigger::ShortClick);
+        assert!(io.commands.borrow().is_empty());
+        press(&mut executor, Trigger::LongClick);
+        assert_eq!(
+            io.commands.borrow().as_slice(),
+            &[IOCommand::ToggleOutput(11)]
+        );
+        assert_eq!(
+            action(&executor, 2, Trigger::ShortClick),
+            Some(Action::Single(Command::RecallScene(0)))
+        );
+        assert_eq!(
+            action(&executor, 2, Trigger::LongClick),
+            Some(Action::Single(Command::CaptureScene(0)))
+        );
+        assert_eq!(executor.bindings.len(), 3);
+    }
+
+    pub fn multi_binding_actions() {
+        use crate::io::event_converter::EventConverter;
+        use crate::io::events::{SwitchEvent, SwitchState};
+
+        let mut bindings: BindingList<8> = BindingList::new();
+        for binding in opcode_bindings(Opcode::BindMulti(4, 1, 2, 3), 0) {
+            bindings.bind(binding);
+        }
+        bindings.bind(Binding::short(5, 0, 10));
+        assert_eq!(bindings.len(), 4);
+
+        let mut converter =
+            EventConverter::new(false).with_double_click_window(Some(Duration::from_millis(500)));
+        // Actions run for a release of the input after a press of given ms.
+        let mut release = |switch_id, ms, at| {
+            let mut actions: Vec<Action, 3> = Vec::new();
+            let event = SwitchEvent {
+                switch_id,
+                state: SwitchState::Deactivated(ms),
+                at: Instant::from_millis(at),
+            };
+            for event in converter.convert(&event) {
+                let Event::ButtonEvent(button) = event else {
+                    continue;
+                };
+                if let Some(action) = bindings.action_or_default(switch_id, 0, button.trigger, None)
+                {
+                    let _ = actions.push(action);
+                }
+            }
+            actions
+        };
+
+        // Short, long and double click get distinct procedures.
+        assert_eq!(release(4, 100, 1000).as_slice(), &[Action::Proc(1)]);
+        assert_eq!(release(4, 1000, 5000).as_slice(), &[Action::Proc(2)]);
+        assert_eq!(release(4, 100, 9000).as_slice(), &[Action::Proc(1)]);
+        assert_eq!(release(4, 100, 9300).as_slice(), &[Action::Proc(3)]);
+        // Too slow for a double click.
+        assert_eq!(release(4, 100, 12000).as_slice(), &[Action::Proc(1)]);
+        assert_eq!(release(4, 100, 13000).as_slice(), &[Action::Proc(1)]);
+
+        // Key without a double click action toggles twice, as before.
+        let toggle = Action::Single(Command::ToggleOutput(10));
+        assert_eq!(release(5, 100, 20000).as_slice(), &[toggle]);
+        assert_eq!(release(5, 100, 20200).as_slice(), &[toggle]);
+    }
+
+    pub fn edge_binding_passes_trigger() {
+        use crate::io::event_converter::EventConverter;
+        use crate::io::events::{SwitchEvent, SwitchState};
+
+        let (io, mut executor, _) = mock_executor!(4, 16);
+        // Procedure 1 branches by the trigger to the procedure of the same
+        // number, each toggles the output of its number.
+        let mut program: Vec<Opcode, 16> = Vec::new();
+        program
+            .extend_from_slice(&[
+                Opcode::Start(0),
+                Opcode::BindEdges(4, 1),
+                Opcode::Stop,
+                Opcode::Start(1),
+                Opcode::CallRegister(TRIGGER_REGISTER),
+                Opcode::Stop,
+            ])
+            .unwrap();
+        for trigger in [
+            Trigger::Activated,
+            Trigger::Deactivated,
+            Trigger::LongActivated,
+        ] {
+            let proc_idx = trigger as u8;
+            program
+                .extend_from_slice(&[
+                    Opcode::Start(proc_idx),
+                    Opcode::Toggle(proc_idx),
+                    Opcode::Stop,
+                ])
+                .unwrap();
+        }
+        assert_eq!(block_on(executor.load_static(&program)), Ok(()));
+        assert_eq!(executor.bindings.len(), 3);
+
+        let mut converter = EventConverter::new(false);
+        // Procedures run for the switch state change.
+        let mut press = |switch_state| {
+            io.commands.borrow_mut().clear();
+            let event = SwitchEvent {
+                switch_id: 4,
+                state: switch_state,
+                at: Instant::now(),
+            };
+            for event in converter.convert(&event) {
+                block_on(executor.parse_event(event));
+            }
+            io.commands.borrow().clone()
+        };
+        let ran = |trigger: Trigger| IOCommand::ToggleOutput(trigger as u8);
+
+        // Short press: activation and release.
+        assert_eq!(
+            press(SwitchState::Activated).as_slice(),
+            &[ran(Trigger::Activated)]
+        );
+        assert_eq!(
+            press(SwitchState::Deactivated(100)).as_slice(),
+            &[ran(Trigger::Deactivated)]
+        );
+
+        // Long press goes through the long activation.
+        press(SwitchState::Activated);
+        assert_eq!(
+            press(SwitchState::Active(500)).as_slice(),
+            &[ran(Trigger::LongActivated)]
+        );
+        // Clicks aren't bound.
+        assert_eq!(
+            press(SwitchState::Deactivated(600)).as_slice(),
+            &[ran(Trigger::Deactivated)]
+        );
+
+        // Programs can't write the trigger register.
+        let program = [
+            Opcode::Start(0),
+            Opcode::SetRegister(TRIGGER_REGISTER, 1),
+            Opcode::Stop,
+        ];
+        assert_eq!(
+            block_on(executor.load_static(&program)),
+            Err(ProgramError::ReservedRegister(TRIGGER_REGISTER))
+        );
+
+        // Register file without the trigger register.
+        let mut small: BoardState<4> = BoardState::default();
+        assert_eq!(
+            small.set_trigger(Trigger::Activated),
+            Err(RegisterError::OutOfRange(TRIGGER_REGISTER))
+        );
+    }
+
+    pub fn runtime_state_resets() {
+        let (io, mut executor, _) = mock_executor!(4, 16);
+        let program = [
+            Opcode::Start(0),
+            Opcode::BindShortToggle(1, 2),
+            Opcode::BindMomentary(4, 6),
+            Opcode::Stop,
+            // Rebinds input 1, changes a register and the layer.
+            Opcode::Start(1),
+            Opcode::BindShortToggle(1, 3),
+            Opcode::SetRegister(1, 5),
+            Opcode::LayerPush(2),
+            Opcode::Stop,
+        ];
+        assert_eq!(block_on(executor.load_static(&program)), Ok(()));
+        let press = |executor: &mut Executor<MockIo, 4, 16>, switch_id, trigger| {
+            block_on(executor.parse_event(Event::new_button(switch_id, trigger, Instant::now())));
+        };
+
+        // Mutate everything that `reset_runtime` restores.
+        press(&mut executor, 4, Trigger::Activated);
+        assert_eq!(block_on(io.get_output(6)), Some(true));
+        assert_eq!(block_on(executor.execute(1)), Ok(()));
+        assert!(executor.set_register(2, 7).is_ok());
+        assert_eq!(executor.layers.current, 2);
+
+        block_on(executor.parse_event(Event::RemoteResetRuntime));
+        assert_eq!(executor.get_register(1), Some(0));
+        assert_eq!(executor.get_register(2), Some(0));
+        assert_eq!(executor.layers.current, 0);
+        // Held output released, bindings of the setup are back.
+        assert_eq!(block_on(io.get_output(6)), Some(false));
+        io.commands.borrow_mut().clear();
+        press(&mut executor, 1, Trigger::ShortClick);
+        assert_eq!(
+            io.commands.borrow().as_slice(),
+            &[IOCommand::ToggleOutput(2)]
+        );
+        // Code is kept.
+        assert_eq!(block_on(executor.execute(1)), Ok(()));
+        assert_eq!(executor.get_register(1), Some(5));
+    }
+
+    pub fn default_program_runs_on_mock() {
+        use crate::app::program::DEFAULT_PROGRAM;
+        use crate::buttonsmash::consts::BINDINGS_COUNT;
+
+        let (io, mut executor, inbox) = mock_executor!(BINDINGS_COUNT, 64);
+
+        assert_eq!(block_on(executor.load_static(&DEFAULT_PROGRAM)), Ok(()));
+        // Setup configures the shutters and touches no outputs.
+        assert_eq!(inbox.try_receive(), Ok((0, shutters::Cmd::SetIO(13, 14))));
+        assert_eq!(inbox.try_receive(), Ok((1, shutters::Cmd::SetIO(15, 16))));
+        assert!(io.commands.borrow().is_empty());
+
+        // Short click of switch 1 toggles output 1.
+        let click = |trigger| Event::new_button(1, trigger, Instant::now());
+        block_on(executor.parse_event(click(Trigger::ShortClick)));
+        assert_eq!(
+            io.commands.borrow().as_slice(),
+            &[IOCommand::ToggleOutput(1)]
+        );
+        assert_eq!(block_on(io.get_output(1)), Some(true));
+
+        // Long click isn't bound.
+        block_on(executor.parse_event(click(Trigger::LongClick)));
+        assert_eq!(io.commands.borrow().len(), 1);
+    }
+
+    pub fn reload_waits_for_park() {
+        use embassy_futures::join::join;
+
+        static PARKED: shutters::ParkSignal = shutters::ParkSignal::new();
+        static PROGRAM: [Opcode; 3] = [Opcode::Start(0), Opcode::Activate(5), Opcode::Stop];
+        let (io, executor, inbox) = mock_executor!(4, 8);
+        let mut executor = executor.with_parked(&PARKED);
+
+        // Manager parks the shutters before the new program runs.
+        let manager = async {
+            assert_eq!(
+                inbox.receive().await,
+                (shutters::ALL_SHUTTERS, shutters::Cmd::Stop)
+            );
+            assert_eq!(block_on(io.get_output(5)), Some(false));
+            PARKED.signal(());
+        };
+        block_on(join(
+            executor.handle_control(Control::ReloadProgram(&PROGRAM)),
+            manager,
+        ));
+        assert_eq!(block_on(io.get_output(5)), Some(true));
+
+        // Silent manager delays the reload, but doesn't block it.
+        block_on(io.set_output(5, false)).unwrap();
+        let start = Instant::now();
+        block_on(executor.handle_control(Control::ReloadProgram(&PROGRAM)));
+        assert!(start.elapsed() >= shutters::PARK_TIMEOUT);
+        assert_eq!(block_on(io.get_output(5)), Some(true));
+        assert_eq!(
+            inbox.try_receive(),
+            Ok((shutters::ALL_SHUTTERS, shutters::Cmd::Stop))
+        );
+    }
+
+    pub fn runaway_procedure_aborted() {
+        let (io, mut executor, _) = mock_executor!(4, 64);
+
+        let mut program: Vec<Opcode, 64> = Vec::new();
+        let mut procedure = |proc_idx, body: &[Opcode]| {
+            program.push(Opcode::Start(proc_idx)).unwrap();
+            program.extend_from_slice(body).unwrap();
+            program.push(Opcode::Stop).unwrap();
+        };
+        procedure(0, &[]);
+        // Calls itself.
+        procedure(1, &[Opcode::Toggle(1), Opcode::Call(1)]);
+        // Finite, but fans out over the budget: 10 * 10 * 6 opcodes.
+        procedure(2, &[Opcode::Call(3); 10]);
+        procedure(3, &[Opcode::Call(4); 10]);
+        procedure(4, &[Opcode::Noop; 5]);
+        procedure(5, &[Opcode::Toggle(2)]);
+        assert_eq!(block_on(executor.load_static(&program)), Ok(()));
+
+        let runaway = args::ErrorCode::ProgramRunaway;
+        assert!(matches!(
+            block_on(executor.execute(1)),
+            Err(ProgramError::Runaway { proc: 1, .. })
+        ));
+        // Outer call and each nested one toggled before the stack ran out.
+        assert_eq!(io.commands.borrow().len(), MAX_STACK + 1);
+        assert!(matches!(
+            block_on(executor.execute(2)),
+            Err(ProgramError::Runaway { proc: 2, .. })
+        ));
+        assert_eq!(
+            io.errors.borrow().as_slice(),
+            &[runaway.with_detail(1), runaway.with_detail(2)]
+        );
+
+        // Executor still works.
+        io.commands.borrow_mut().clear();
+        assert_eq!(block_on(executor.execute(5)), Ok(()));
+        assert_eq!(
+            io.commands.borrow().as_slice(),
+            &[IOCommand::ToggleOutput(2)]
+        );
+    }
+
+    pub fn remote_set_output_acknowledged() {
+        let (io, mut executor, _) = mock_executor!(
+            MockIo {
+                broken: Some(3),
+                ..MockIo::new()
+            },
+            4,
+            8
+        );
+        let program = [
+            Opcode::Start(0),
+            Opcode::Stop,
+            Opcode::Start(1),
+            Opcode::ToggleGroup(1),
+            Opcode::Stop,
+        ];
+        assert_eq!(block_on(executor.load_static(&program)), Ok(()));
+
+        // Applied request is confirmed with the new state.
+        block_on(executor.parse_event(Event::RemoteActivate(4, 1)));
+        assert_eq!(io.changes.borrow().as_slice(), &[(4, true)]);
+        assert!(io.errors.borrow().is_empty());
+
+        // Failed write: error naming the expander line and the state the
+        // output stayed in.
+        block_on(executor.parse_event(Event::RemoteActivate(3, 1)));
+        assert_eq!(io.changes.borrow().as_slice(), &[(4, true), (3, false)]);
+        assert_eq!(
+            io.errors.borrow().as_slice(),
+            &[args::ErrorCode::ExpanderOutputFailure.with_detail(0x02)]
+        );
+
+        // Local failures are only reported.
+        executor.set_default_command(Some(Command::ToggleOutput(3)));
+        block_on(executor.parse_event(Event::new_button(1, Trigger::ShortClick, Instant::now())));
+        assert_eq!(io.changes.borrow().len(), 2);
+        assert_eq!(io.errors.borrow().len(), 2);
+
+        // Group write names the failing line as well.
+        assert_eq!(block_on(executor.execute(1)), Ok(()));
+        assert_eq!(io.changes.borrow().len(), 2);
+        assert_eq!(
+            io.errors.borrow().last(),
+            Some(&args::ErrorCode::ExpanderOutputFailure.with_detail(0x02))
+        );
+    }
+
+    pub fn deferred_layer_hold_ignores_tap() {
+        let (io, mut executor, _) = mock_executor!(4, 8);
+        // Tap of input 5 toggles output 3, holding it switches to layer 2
+        // where input 1 toggles output 4 instead of 2.
+        let program = [
+            Opcode::Start(0),
+            Opcode::BindLayerHold(5, 2, true),
+            Opcode::BindShortToggle(5, 3),
+            Opcode::BindShortToggle(1, 2),
+            Opcode::LayerPush(2),
+            Opcode::BindShortToggle(1, 4),
+            Opcode::LayerPop,
+            Opcode::Stop,
+        ];
+        assert_eq!(block_on(executor.load_static(&program)), Ok(()));
+        let mut press = |switch_id, trigger| {
+            block_on(executor.parse_event(Event::new_button(switch_id, trigger, Instant::now())));
+        };
+
+        // Tap never leaves the base layer.
+        press(5, Trigger::Activated);
+        press(1, Trigger::ShortClick);
+        press(5, Trigger::ShortClick);
+        press(5, Trigger::Deactivated);
+        assert_eq!(
+            io.commands.borrow().as_slice(),
+            &[IOCommand::ToggleOutput(2), IOCommand::ToggleOutput(3)]
+        );
+
+        // Hold activates the layer once, release returns to the base one.
+        io.commands.borrow_mut().clear();
+        press(5, Trigger::Activated);
+        press(5, Trigger::LongActivated);
+        press(5, Trigger::LongActivated);
+        press(1, Trigger::ShortClick);
+        press(5, Trigger::LongClick);
+        press(5, Trigger::LongDeactivated);
+        press(5, Trigger::Deactivated);
+        press(1, Trigger::ShortClick);
+        assert_eq!(
+            io.commands.borrow().as_slice(),
+            &[IOCommand::ToggleOutput(4), IOCommand::ToggleOutput(2)]
+        );
+        assert_eq!(executor.layers.current, 0);
+
+        // Immediate hold activates on the press.
+        let program = [
+            Opcode::Start(0),
+            Opcode::BindLayerHold(5, 2, false),
+            Opcode::Stop,
+        ];
+        assert_eq!(block_on(executor.load_static(&program)), Ok(()));
+        block_on(executor.parse_event(Event::new_button(5, Trigger::Activated, Instant::now())));
+        assert_eq!(executor.layers.current, 2);
+    }
+
+    pub fn short_click_toggles_all_listed() {
+        let (io, mut executor, _) = mock_executor!(4, 8);
+        // Three outputs, padded with the last one.
+        let program = [
+            Opcode::Start(0),
+            Opcode::BindShortToggleMulti(1, [2, 5, 7, 7]),
+            Opcode::Stop,
+        ];
+        assert_eq!(block_on(executor.load_static(&program)), Ok(()));
+        io.set(5, true).unwrap();
+
+        let click = || Event::new_button(1, Trigger::ShortClick, Instant::now());
+        block_on(executor.parse_event(click()));
+        assert_eq!(
+            io.commands.borrow().as_slice(),
+            &[
+                IOCommand::ToggleOutput(2),
+                IOCommand::ToggleOutput(5),
+                IOCommand::ToggleOutput(7)
+            ]
+        );
+        let state = |out| block_on(io.get_output(out));
+        assert_eq!(
+            (state(2), state(5), state(7)),
+            (Some(true), Some(false), Some(true))
+        );
+
+        // Unknown outputs in the list are rejected with the program.
+        let program = [
+            Opcode::Start(0),
+            Opcode::BindShortToggleMulti(1, [2, 99, 7, 7]),
+            Opcode::Stop,
+        ];
+        assert_eq!(
+            block_on(executor.load_static(&program)),
+            Err(ProgramError::UnknownOutput(99))
+        );
+    }
+
+    pub fn rotary_dims_output() {
+        let (io, mut executor, _) = mock_executor!(4, 8);
+        let program = [
+            Opcode::Start(0),
+            Opcode::BindShortToggle(1, 3),
+            Opcode::BindRotate(7, 3),
+            Opcode::Stop,
+        ];
+        assert_eq!(block_on(executor.load_static(&program)), Ok(()));
+        // Handle an event, returns the brightness it set.
+        let mut send = |event| {
+            io.brightness.borrow_mut().clear();
+            block_on(executor.parse_event(event));
+            io.brightness.borrow().last().map(|(_, level)| *level)
+        };
+        let rotate = |steps| Event::Rotate(7, steps);
+
+        // Turned up from off in steps, announced once when switched on.
+        assert_eq!(send(rotate(1)), Some(BRIGHTNESS_STEP));
+        assert_eq!(send(rotate(2)), Some(BRIGHTNESS_STEP * 3));
+        assert_eq!(send(rotate(-1)), Some(BRIGHTNESS_STEP * 2));
+        assert_eq!(block_on(io.get_output(3)), Some(true));
+        assert_eq!(io.changes.borrow().as_slice(), &[(3, true)]);
+
+        // Down to off, then it stays there.
+        assert_eq!(send(rotate(-5)), Some(0));
+        assert_eq!(send(rotate(-1)), Some(0));
+        assert_eq!(block_on(io.get_output(3)), Some(false));
+        assert_eq!(io.changes.borrow().as_slice(), &[(3, true), (3, false)]);
+
+        // Switched on by a click - dimmed down from the full brightness.
+        let click = Event::new_button(1, Trigger::ShortClick, Instant::now());
+        assert_eq!(send(click), None);
+        assert_eq!(send(rotate(-1)), Some(100 - BRIGHTNESS_STEP));
+        assert_eq!(send(rotate(100)), Some(100));
+
+        // Unbound encoders and locked outputs are left alone.
+        assert_eq!(send(Event::Rotate(8, 1)), None);
+        assert_eq!(send(Event::RemoteOverride(3, false, true, 9)), None);
+        assert_eq!(send(rotate(1)), None);
+        assert_eq!(block_on(io.get_output(3)), Some(false));
+    }
+
+    pub fn bindings_dump() {
+        let (io, mut executor, _) = mock_executor!(4, 8);
+        let program = [
+            Opcode::Start(0),
+            Opcode::BindShortToggle(1, 3),
+            Opcode::BindLongToggle(2, 4),
+            Opcode::Stop,
+        ];
+        assert_eq!(block_on(executor.load_static(&program)), Ok(()));
+
+        let mut dumped: Vec<Binding, 4> = Vec::new();
+        executor.dump_bindings(&mut |binding| assert!(dumped.push(*binding).is_ok()));
+        assert_eq!(dumped.len(), 2);
+        assert_eq!((dumped[0].idx, dumped[0].trigger), (1, Trigger::ShortClick));
+        assert_eq!((dumped[1].idx, dumped[1].trigger), (2, Trigger::LongClick));
+
+        // Streamed back: the count, then a frame per binding in order.
+        block_on(executor.parse_event(Event::RemoteBindingsRequest));
+        let binding = args::InfoCode::Binding.to_bytes();
+        assert_eq!(
+            io.infos.borrow().as_slice(),
+            &[
+                (args::InfoCode::Bindings.to_bytes(), 2),
+                (binding, dumped[0].to_info_arg()),
+                (binding, dumped[1].to_info_arg()),
+            ]
+        );
+    }
+
+    pub fn locked_output_ignores_changes() {
+        let (io, mut executor, _) = mock_executor!(4, 8);
+        let program = [
+            Opcode::Start(0),
+            Opcode::BindShortToggle(1, 4),
+            Opcode::Stop,
+        ];
+        assert_eq!(block_on(executor.load_static(&program)), Ok(()));
+        let click = || Event::new_button(1, Trigger::ShortClick, Instant::now());
+        let state = || block_on(io.get_output(4));
+
+        // Locked on - buttons and remote requests don't change it.
+        block_on(executor.parse_event(Event::RemoteOverride(4, true, true, 9)));
+        assert_eq!(state(), Some(true));
+        block_on(executor.parse_event(click()));
+        assert_eq!(state(), Some(true));
+        block_on(executor.parse_event(Event::RemoteToggle(4, 9)));
+        assert_eq!(state(), Some(true));
+        assert_eq!(io.commands.borrow().len(), 1);
+        // Remote is told the state it stayed in.
+        assert_eq!(io.changes.borrow().as_slice(), &[(4, true), (4, true)]);
+
+        // Group with a locked member is left alone too.
+        let program = [
+            Opcode::Start(0),
+            Opcode::BindShortToggle(1, 4),
+            Opcode::BindShortCall(2, 1),
+            Opcode::Stop,
+            Opcode::Start(1),
+            Opcode::ToggleGroup(1),
+            Opcode::Stop,
+        ];
+        assert_eq!(block_on(executor.load_static(&program)), Ok(()));
+        let group = || Event::new_button(2, Trigger::ShortClick, Instant::now());
+        block_on(executor.parse_event(group()));
+        assert_eq!(block_on(io.get_output(3)), Some(false));
+        assert_eq!(state(), Some(true));
+
+        // Released - responds again.
+        block_on(executor.parse_event(Event::RemoteOverride(4, true, false, 9)));
+        block_on(executor.parse_event(click()));
+        assert_eq!(state(), Some(false));
+        block_on(executor.parse_event(group()));
+        assert_eq!(block_on(io.get_output(3)), Some(true));
+        assert_eq!(state(), Some(true));
+    }
+
+    pub fn scene_recalled_in_one_write() {
+        let mut status = [(0, false); 19];
+        for (pos, entry) in status.iter_mut().enumerate() {
+            entry.1 = pos < 3;
+        }
+        let io = MockIo {
+            scene: RefCell::new(Some(Scene::capture(&status))),
+            ..MockIo::new()
+        };
+        let (io, mut executor, _) = mock_executor!(io, 4, 8);
+        let program = [
+            Opcode::Start(0),
+            Opcode::Stop,
+            Opcode::Start(1),
+            Opcode::RecallScene(0),
+            Opcode::Stop,
+        ];
+        assert_eq!(block_on(executor.load_static(&program)), Ok(()));
+        io.set(5, true).unwrap();
+        block_on(executor.parse_event(Event::RemoteOverride(3, false, true, 9)));
+        io.changes.borrow_mut().clear();
+
+        // Locked output 3 is skipped, the rest changes in one batch.
+        assert_eq!(block_on(executor.execute(1)), Ok(()));
+        assert_eq!(*io.batches.borrow(), 1);
+        assert_eq!(io.commands.borrow().len(), 1);
+        assert_eq!(
+            io.changes.borrow().as_slice(),
+            &[(1, true), (2, true), (5, false)]
+        );
+        assert_eq!(block_on(io.get_output(3)), Some(false));
+    }
+
+    pub fn remote_feedback_loop_suppressed() {
+        use crate::buttonsmash::feedback::MAX_FLIPS;
+
+        let (io, mut executor, _) = mock_executor!(4, 8);
+        let program = [
+            Opcode::Start(0),
+            Opcode::BindShortToggle(1, 4),
+            Opcode::Stop,
+        ];
+        assert_eq!(block_on(executor.load_static(&program)), Ok(()));
+        let state = || block_on(io.get_output(4));
+
+        // Another node toggles the output back and forth.
+        for _ in 0..=MAX_FLIPS {
+            block_on(executor.parse_event(Event::RemoteToggle(4, 9)));
+            assert!(io.errors.borrow().is_empty());
+        }
+        // One flip too many - reported once.
+        block_on(executor.parse_event(Event::RemoteToggle(4, 9)));
+        let errors = io.errors.borrow().clone();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            args::ErrorCode::from_u32(errors[0]),
+            Some(args::ErrorCode::FeedbackLoop)
+        );
+        assert_eq!(args::ErrorCode::detail(errors[0]), 4);
+
+        // Further remote requests are ignored.
+        let looped = state();
+        block_on(executor.parse_event(Event::RemoteToggle(4, 9)));
+        block_on(executor.parse_event(Event::RemoteActivate(4, 9)));
+        block_on(executor.parse_event(Event::RemoteDeactivate(4, 9)));
+        assert_eq!(state(), looped);
+        assert_eq!(io.errors.borrow().len(), 1);
+
+        // Local button still works.
+        let click = Event::new_button(1, Trigger::ShortClick, Instant::now());
+        block_on(executor.parse_event(click));
+        assert_eq!(state(), looped.map(|on| !on));
+    }
+
+    pub fn activate_for_expires() {
+        let (io, mut executor, _) = mock_executor!(4, 8);
+        let program = [
+            Opcode::Start(0),
+            Opcode::Stop,
+            Opcode::Start(1),
+            Opcode::ActivateFor(3, 5),
+            Opcode::Stop,
+        ];
+        assert_eq!(block_on(executor.load_static(&program)), Ok(()));
+
+        // From the program.
+        assert_eq!(block_on(executor.execute(1)), Ok(()));
+        assert_eq!(block_on(io.get_output(3)), Some(true));
+        let deadline = executor.timed.next_deadline().unwrap();
+        block_on(executor.expire_outputs(deadline - Duration::from_millis(1)));
+        assert_eq!(block_on(io.get_output(3)), Some(true));
+        block_on(executor.expire_outputs(deadline));
+        assert_eq!(block_on(io.get_output(3)), Some(false));
+        assert_eq!(
+            io.commands.borrow().as_slice(),
+            &[IOCommand::ActivateOutput(3), IOCommand::DeactivateOutput(3)]
+        );
+        assert_eq!(executor.timed.next_deadline(), None);
+
+        // From an input.
+        io.commands.borrow_mut().clear();
+        executor.set_default_command(Some(Command::ActivateFor(4, 1)));
+        block_on(executor.parse_event(Event::new_button(9, Trigger::ShortClick, Instant::now())));
+        assert_eq!(block_on(io.get_output(4)), Some(true));
+        let deadline = executor.timed.next_deadline().unwrap();
+        block_on(executor.expire_outputs(deadline));
+        assert_eq!(
+            io.commands.borrow().as_slice(),
+            &[IOCommand::ActivateOutput(4), IOCommand::DeactivateOutput(4)]
+        );
+    }
+
+    pub fn pulse_restores_prior_state() {
+        let (io, mut executor, _) = mock_executor!(4, 8);
+        let program = [
+            Opcode::Start(0),
+            Opcode::Stop,
+            Opcode::Start(1),
+            Opcode::PulseOutput(5, 2),
+            Opcode::Stop,
+        ];
+        assert_eq!(block_on(executor.load_static(&program)), Ok(()));
+        // States of the output at each scheduled change, until done.
+        let run = |executor: &mut Executor<MockIo, 4, 8>| {
+            let mut states: Vec<bool, 8> = Vec::new();
+            while let Some(deadline) = executor.pulsed.next_deadline() {
+                block_on(executor.expire_outputs(deadline));
+                states.push(block_on(io.get_output(5)).unwrap()).unwrap();
+            }
+            states
+        };
+        let (on, off) = (IOCommand::ActivateOutput(5), IOCommand::DeactivateOutput(5));
+
+        // Output that was on blinks and stays on.
+        io.set(5, true).unwrap();
+        assert_eq!(block_on(executor.execute(1)), Ok(()));
+        assert_eq!(run(&mut executor).as_slice(), &[false, true, false, true]);
+        assert_eq!(
+            io.commands.borrow().as_slice(),
+            &[on.clone(), off.clone(), on.clone(), off.clone(), on.clone()]
+        );
+
+        // Output that was off returns to off.
+        io.commands.borrow_mut().clear();
+        io.set(5, false).unwrap();
+        assert_eq!(block_on(executor.execute(1)), Ok(()));
+        assert_eq!(block_on(io.get_output(5)), Some(true));
+        assert_eq!(run(&mut executor).as_slice(), &[false, true, false]);
+        assert_eq!(
+            io.commands.borrow().as_slice(),
+            &[on.clone(), off.clone(), on, off]
+        );
+    }
+
+    pub fn chord_replaces_tap_binding() {
+        let (io, mut executor, _) = mock_executor!(4, 16);
+        let program = [
+            Opcode::Start(0),
+            Opcode::BindShortToggle(1, 5),
+            Opcode::BindShortToggle(2, 6),
+            Opcode::BindChord(1, 2, 10),
+            Opcode::Stop,
+            Opcode::Start(10),
+            Opcode::Toggle(7),
+            Opcode::Stop,
+        ];
+        assert_eq!(block_on(executor.load_static(&program)), Ok(()));
+        let mut press = |switch_id, trigger| {
+            block_on(executor.parse_event(Event::new_button(switch_id, trigger, Instant::now())));
+        };
+        let toggled = || {
+            let toggled = io.commands.borrow().clone();
+            io.commands.borrow_mut().clear();
+            toggled
+        };
+
+        // Hold 1, click 2, release 1 - only the chord runs.
+        press(1, Trigger::Activated);
+        press(2, Trigger::Activated);
+        press(2, Trigger::ShortClick);
+        press(2, Trigger::Deactivated);
+        press(1, Trigger::LongClick);
+        press(1, Trigger::Deactivated);
+        assert_eq!(toggled().as_slice(), &[IOCommand::ToggleOutput(7)]);
+
+        // Alone, both keys work as usual.
+        press(2, Trigger::ShortClick);
+        press(1, Trigger::Activated);
+        press(1, Trigger::ShortClick);
+        press(1, Trigger::Deactivated);
+        assert_eq!(
+            toggled().as_slice(),
+            &[IOCommand::ToggleOutput(6), IOCommand::ToggleOutput(5)]
+        );
+
+        // Hold without the tap click keeps its own action.
+        press(1, Trigger::Activated);
+        press(2, Trigger::LongClick);
+        press(1, Trigger::ShortClick);
+        press(1, Trigger::Deactivated);
+        assert_eq!(toggled().as_slice(), &[IOCommand::ToggleOutput(5)]);
+    }
+
+    pub fn remote_origin_from_sender() {
+        use crate::components::message::{MessageRaw, msg_type};
+        use crate::config::BROADCAST_ADDRESS;
+
+        let (io, mut executor, _) = mock_executor!(4, 8);
+        let program = [
+            Opcode::Start(0),
+            Opcode::BindShortToggle(1, 4),
+            Opcode::Stop,
+        ];
+        assert_eq!(block_on(executor.load_static(&program)), Ok(()));
+        // Request frames, as received from node 12 or from an older sender.
+        let receive =
+            |msg_type, data: &[u8]| Message::from_raw(&MessageRaw::from_bytes(5, msg_type, data));
+
+        let Some(Message::SetOutput {
+            output,
+            state,
+            source,
+        }) = receive(msg_type::SET_OUTPUT, &[4, 2, 12])
+        else {
+            panic!("SetOutput not decoded");
+        };
+        let event = Event::set_output(output, state, source);
+        assert!(matches!(event, Event::RemoteToggle(4, 12)));
+        block_on(executor.parse_event(event));
+        assert_eq!(block_on(io.get_output(4)), Some(true));
+        assert_eq!(io.changes.borrow().as_slice(), &[(4, true)]);
+
+        let Some(Message::SetOutput {
+            output,
+            state,
+            source,
+        }) = receive(msg_type::SET_OUTPUT, &[4, 0])
+        else {
+            panic!("SetOutput not decoded");
+        };
+        let event = Event::set_output(output, state, source);
+        assert!(matches!(
+            event,
+            Event::RemoteDeactivate(4, BROADCAST_ADDRESS)
+        ));
+
+        // Remote requests of a locked output are answered, local clicks not.
+        block_on(executor.parse_event(Event::RemoteOverride(4, true, true, 12)));
+        io.changes.borrow_mut().clear();
+        block_on(executor.parse_event(Event::new_button(1, Trigger::ShortClick, Instant::now())));
+        assert!(io.changes.borrow().is_empty());
+        block_on(executor.parse_event(event));
+        assert_eq!(io.changes.borrow().as_slice(), &[(4, true)]);
     }
 }
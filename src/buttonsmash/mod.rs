@@ -1,11 +1,20 @@
 pub mod bindings;
+pub mod chords;
 pub mod consts;
+pub mod feedback;
 pub mod layers;
+pub mod locks;
+pub mod maintenance;
 pub mod microvm;
+pub mod momentary;
 pub mod opcodes;
+pub mod pulsed;
+pub mod scenes;
 pub mod shutters;
+pub mod timed;
+pub mod vm_io;
 
 pub use consts::Command;
-pub use consts::{Event, EventChannel};
+pub use consts::{Control, ControlChannel, Event, EventChannel};
 pub use microvm::Executor;
 pub use opcodes::Opcode;
@@ -0,0 +1,137 @@
+/*
+ * Momentary outputs: output follows the input - on while the button is held
+ * (doorbell, intercom unlock). Output is turned off on release, or after a
+ * safety max-hold in case the release event was lost.
+ */
+use embassy_time::{Duration, Instant};
+use heapless::Vec;
+
+use super::consts::{InIdx, OutIdx};
+
+/// Max number of outputs held at the same time.
+pub const MAX_MOMENTARY: usize = 4;
+
+/// Output is released after that time even if the input is still active.
+pub const MOMENTARY_MAX_HOLD: Duration = Duration::from_secs(30);
+
+/// Tracks momentary outputs that are currently on.
+pub struct MomentaryOutputs {
+    /// (input holding the output, output, when activated)
+    active: [Option<(InIdx, OutIdx, Instant)>; MAX_MOMENTARY],
+}
+
+impl Default for MomentaryOutputs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MomentaryOutputs {
+    pub const fn new() -> Self {
+        Self {
+            active: [None; MAX_MOMENTARY],
+        }
+    }
+
+    /// Start tracking output held by input. Returns false if there's no free
+    /// slot - caller should not activate the output then.
+    pub fn start(&mut self, in_idx: InIdx, out_idx: OutIdx, now: Instant) -> bool {
+        // Restart the timer if it's already held.
+        if let Some(entry) = self
+            .active
+            .iter_mut()
+            .flatten()
+            .find(|(i, o, _)| *i == in_idx && *o == out_idx)
+        {
+            entry.2 = now;
+            return true;
+        }
+        if let Some(slot) = self.active.iter_mut().find(|slot| slot.is_none()) {
+            *slot = Some((in_idx, out_idx, now));
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Input was released. Returns outputs to turn off.
+    pub fn release(&mut self, in_idx: InIdx) -> Vec<OutIdx, MAX_MOMENTARY> {
+        let mut outputs = Vec::new();
+        for slot in self.active.iter_mut() {
+            if let Some((i, o, _)) = *slot
+                && i == in_idx
+            {
+                let _ = outputs.push(o);
+                *slot = None;
+            }
+        }
+        outputs
+    }
+
+    /// Forget all held outputs. Returns outputs to turn off.
+    pub fn release_all(&mut self) -> Vec<OutIdx, MAX_MOMENTARY> {
+        let mut outputs = Vec::new();
+        for slot in self.active.iter_mut() {
+            if let Some((_, o, _)) = slot.take() {
+                let _ = outputs.push(o);
+            }
+        }
+        outputs
+    }
+
+    /// Outputs held for longer than the safety max-hold. Returns outputs to turn off.
+    pub fn expired(&mut self, now: Instant) -> Vec<OutIdx, MAX_MOMENTARY> {
+        let mut outputs = Vec::new();
+        for slot in self.active.iter_mut() {
+            if let Some((_, o, since)) = *slot
+                && now.saturating_duration_since(since) >= MOMENTARY_MAX_HOLD
+            {
+                let _ = outputs.push(o);
+                *slot = None;
+            }
+        }
+        outputs
+    }
+
+    /// When the earliest held output should be forcibly released.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.active
+            .iter()
+            .flatten()
+            .map(|(_, _, since)| *since + MOMENTARY_MAX_HOLD)
+            .min()
+    }
+}
+
+pub mod tests {
+    use super::*;
+
+    pub fn momentary_release_and_timeout() {
+        let mut momentary = MomentaryOutputs::new();
+        let start = Instant::from_millis(1000);
+        assert_eq!(momentary.next_deadline(), None);
+
+        // Released with the input.
+        assert!(momentary.start(3, 10, start));
+        assert_eq!(momentary.next_deadline(), Some(start + MOMENTARY_MAX_HOLD));
+        assert!(momentary.release(4).is_empty());
+        assert_eq!(momentary.release(3).as_slice(), &[10]);
+        assert_eq!(momentary.next_deadline(), None);
+
+        // Released on timeout when deactivation was lost.
+        assert!(momentary.start(3, 10, start));
+        let almost = start + MOMENTARY_MAX_HOLD - Duration::from_millis(1);
+        assert!(momentary.expired(almost).is_empty());
+        assert_eq!(
+            momentary.expired(start + MOMENTARY_MAX_HOLD).as_slice(),
+            &[10]
+        );
+        assert!(momentary.release(3).is_empty());
+
+        // Limited number of slots.
+        for out in 0..MAX_MOMENTARY as u8 {
+            assert!(momentary.start(1, out, start));
+        }
+        assert!(!momentary.start(2, 50, start));
+    }
+}
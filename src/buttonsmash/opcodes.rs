@@ -1,6 +1,6 @@
 use defmt::Format;
 
-use super::consts::{InIdx, LayerIdx, OutIdx, ProcIdx, ShutterIdx};
+use super::consts::{InIdx, LayerIdx, MAX_FANOUT, OutIdx, ProcIdx, SceneIdx, ShutterIdx};
 use super::shutters;
 
 /// Opcodes of the internal micro vm.
@@ -37,10 +37,25 @@ pub enum Opcode {
     Activate(OutIdx),
     /// Direct output control: Deactivate IO (no matter state)
     Deactivate(OutIdx),
+    /// Direct output control: Activate IO and deactivate it after given
+    /// deciseconds (stairwell light). Repeating restarts the time.
+    ActivateFor(OutIdx, u8),
+    /// Blink output given number of times (visual confirmation) and return
+    /// it to its prior state.
+    PulseOutput(OutIdx, u8),
+
+    /// Toggle an output group defined by the board: all off if any of its
+    /// outputs is on, otherwise all on.
+    ToggleGroup(u8),
 
     /// Generate a series of status events.
     SendStatus,
 
+    /// Store current state of all outputs into a scene slot.
+    CaptureScene(SceneIdx),
+    /// Restore outputs from a scene slot.
+    RecallScene(SceneIdx),
+
     /// Enable a layer (later: push layer onto a layer stack)
     LayerPush(LayerIdx),
     LayerPop,
@@ -63,6 +78,16 @@ pub enum Opcode {
     BindLongActivate(InIdx, ProcIdx),
     /// Map deactivation after over short click time to a procedure (on a current layer)
     BindLongDeactivate(InIdx, ProcIdx),
+    /// Multi-function key: short, long and double click call given
+    /// procedures (on a current layer).
+    BindMulti(InIdx, ProcIdx, ProcIdx, ProcIdx),
+    /// Map activation, long activation and deactivation to a single
+    /// procedure, which reads the trigger from TRIGGER_REGISTER (on a current
+    /// layer). Eg. press starts moving, release stops unless it was short.
+    BindEdges(InIdx, ProcIdx),
+    /// Short click of the second input while the first one is held calls
+    /// the procedure instead of actions of both inputs (on all layers).
+    BindChord(InIdx, InIdx, ProcIdx),
 
     /*
      * Shortcuts
@@ -70,11 +95,33 @@ pub enum Opcode {
     /// Bind short click to a toggle of an output
     BindShortToggle(InIdx, OutIdx),
 
+    /// Bind short click to a toggle of several outputs (master switch).
+    /// Pad shorter lists by repeating the last output.
+    BindShortToggleMulti(InIdx, [OutIdx; MAX_FANOUT]),
+
     /// Bind long click to a toggle of an output
     BindLongToggle(InIdx, OutIdx),
 
-    /// Bind layer to activate/deactivate triggers.
-    BindLayerHold(InIdx, LayerIdx),
+    /// Output follows the input: on while held, off on release or after a
+    /// safety timeout (doorbell, intercom unlock).
+    BindMomentary(InIdx, OutIdx),
+
+    /// Bind layer to activate/deactivate triggers. Deferred (true) layer
+    /// activates only once the press becomes long (LongActivated), so a tap
+    /// of a key with a ShortClick action doesn't flash the layer. The delay
+    /// is the long-press threshold of the input.
+    BindLayerHold(InIdx, LayerIdx, bool),
+
+    /// Short click recalls the scene, long click captures current outputs
+    /// into it.
+    BindScene(InIdx, SceneIdx),
+
+    /// Very long hold of the input toggles maintenance mode.
+    BindMaintenance(InIdx),
+
+    /// Each detent of the rotary encoder steps the output brightness by
+    /// BRIGHTNESS_STEP, clockwise up. Zero is off.
+    BindRotate(InIdx, OutIdx),
     /*
      * Native Shutter support. UP/DOWN control
      */
@@ -102,3 +149,23 @@ pub enum Opcode {
 
     */
 }
+
+impl Opcode {
+    /// Local outputs referenced by the opcode.
+    pub fn outputs(&self) -> [Option<OutIdx>; MAX_FANOUT] {
+        match *self {
+            Opcode::Toggle(out)
+            | Opcode::Activate(out)
+            | Opcode::Deactivate(out)
+            | Opcode::ActivateFor(out, _)
+            | Opcode::PulseOutput(out, _)
+            | Opcode::BindShortToggle(_, out)
+            | Opcode::BindLongToggle(_, out)
+            | Opcode::BindMomentary(_, out)
+            | Opcode::BindRotate(_, out) => [Some(out), None, None, None],
+            Opcode::BindShortToggleMulti(_, outs) => outs.map(Some),
+            Opcode::BindShutter(_, down, up) => [Some(down), Some(up), None, None],
+            _ => [None; MAX_FANOUT],
+        }
+    }
+}
@@ -0,0 +1,187 @@
+/*
+ * Pulsed outputs: output blinks a number of times (eg. to confirm a scene
+ * was stored) and then returns to its prior state. Pulses are scheduled, so
+ * the executor keeps handling events in between.
+ */
+use embassy_time::{Duration, Instant};
+use heapless::Vec;
+
+use super::consts::OutIdx;
+
+/// Max number of outputs pulsing at the same time.
+pub const MAX_PULSED: usize = 2;
+
+/// Timing of PulseOutput opcode pulses.
+pub const PULSE_ON: Duration = Duration::from_millis(300);
+pub const PULSE_OFF: Duration = Duration::from_millis(300);
+
+#[derive(Copy, Clone)]
+struct Pulse {
+    out: OutIdx,
+    count: u8,
+    on: Duration,
+    off: Duration,
+    /// State to return to after the last pulse.
+    prior: bool,
+    /// Level changes done so far.
+    step: u8,
+    /// When the next change is due.
+    next_at: Instant,
+}
+
+impl Pulse {
+    /// Pulses alternate on and off, a prior on state is restored at the end.
+    fn steps(&self) -> u8 {
+        self.count.saturating_mul(2) + self.prior as u8
+    }
+
+    fn level(&self, step: u8) -> bool {
+        if step < self.count.saturating_mul(2) {
+            step.is_multiple_of(2)
+        } else {
+            self.prior
+        }
+    }
+}
+
+/// Tracks outputs in a pulse sequence.
+pub struct PulsedOutputs {
+    active: [Option<Pulse>; MAX_PULSED],
+}
+
+impl Default for PulsedOutputs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PulsedOutputs {
+    pub const fn new() -> Self {
+        Self {
+            active: [None; MAX_PULSED],
+        }
+    }
+
+    /// Start pulsing the output which is now in the `prior` state. The caller
+    /// turns it on right away. Returns false if there's no free slot.
+    pub fn start(
+        &mut self,
+        out: OutIdx,
+        count: u8,
+        timing: (Duration, Duration),
+        prior: bool,
+        now: Instant,
+    ) -> bool {
+        if count == 0 {
+            return false;
+        }
+        let (on, off) = timing;
+        // Restart keeps the state from before the first sequence.
+        let slot = match self
+            .active
+            .iter()
+            .position(|slot| matches!(slot, Some(pulse) if pulse.out == out))
+        {
+            Some(pos) => pos,
+            None => match self.active.iter().position(Option::is_none) {
+                Some(pos) => pos,
+                None => return false,
+            },
+        };
+        let prior = self.active[slot].map_or(prior, |pulse| pulse.prior);
+        self.active[slot] = Some(Pulse {
+            out,
+            count,
+            on,
+            off,
+            prior,
+            step: 1,
+            next_at: now + on,
+        });
+        true
+    }
+
+    /// Level changes which are due, as (output, on).
+    pub fn due(&mut self, now: Instant) -> Vec<(OutIdx, bool), MAX_PULSED> {
+        let mut changes = Vec::new();
+        for slot in self.active.iter_mut() {
+            let Some(pulse) = slot else {
+                continue;
+            };
+            if now < pulse.next_at {
+                continue;
+            }
+            let on = pulse.level(pulse.step);
+            let _ = changes.push((pulse.out, on));
+            pulse.step += 1;
+            if pulse.step >= pulse.steps() {
+                *slot = None;
+            } else {
+                pulse.next_at = now + if on { pulse.on } else { pulse.off };
+            }
+        }
+        changes
+    }
+
+    /// When the earliest level change is due.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.active
+            .iter()
+            .flatten()
+            .map(|pulse| pulse.next_at)
+            .min()
+    }
+}
+
+pub mod tests {
+    use super::*;
+
+    /// Run the sequence, returns the levels with ms since start.
+    fn run(pulsed: &mut PulsedOutputs, start: Instant) -> Vec<(u64, bool), 8> {
+        let mut levels = Vec::new();
+        while let Some(deadline) = pulsed.next_deadline() {
+            for (out, on) in pulsed.due(deadline) {
+                assert_eq!(out, 5);
+                levels
+                    .push((deadline.duration_since(start).as_millis(), on))
+                    .unwrap();
+            }
+        }
+        levels
+    }
+
+    pub fn pulses_then_restores() {
+        let mut pulsed = PulsedOutputs::new();
+        let start = Instant::from_millis(1000);
+        let timing = (Duration::from_millis(200), Duration::from_millis(100));
+        assert_eq!(pulsed.next_deadline(), None);
+
+        // PulseOutput(5, 2) of an output which was off. First on is set by
+        // the caller.
+        assert!(pulsed.start(5, 2, timing, false, start));
+        assert!(pulsed.due(start).is_empty());
+        assert_eq!(
+            run(&mut pulsed, start).as_slice(),
+            &[(200, false), (300, true), (500, false)]
+        );
+
+        // Output which was on ends on.
+        assert!(pulsed.start(5, 2, timing, true, start));
+        assert_eq!(
+            run(&mut pulsed, start).as_slice(),
+            &[(200, false), (300, true), (500, false), (600, true)]
+        );
+
+        // Restart in the middle keeps the original state.
+        assert!(pulsed.start(5, 1, timing, false, start));
+        assert!(pulsed.start(5, 1, timing, true, start));
+        assert_eq!(run(&mut pulsed, start).as_slice(), &[(200, false)]);
+
+        // Limited number of slots, nothing to pulse.
+        for out in 0..MAX_PULSED as u8 {
+            assert!(pulsed.start(out, 1, timing, false, start));
+        }
+        assert!(!pulsed.start(50, 1, timing, false, start));
+        assert!(!pulsed.start(0, 0, timing, false, start));
+    }
+}
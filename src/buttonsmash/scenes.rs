@@ -0,0 +1,120 @@
+/*
+ * Scenes: snapshot of all local outputs that can be captured (set the lights
+ * manually, press and hold) and later recalled. Stored in RTC backup registers
+ * by the Board so they survive reboot.
+ */
+use super::consts::OutIdx;
+
+/// Number of scene slots.
+pub const MAX_SCENES: usize = 4;
+
+/// Scene covers that many output positions - one bit each.
+pub const SCENE_OUTPUTS: usize = 24;
+
+/// Marker in the top byte of the stored value. Distinguishes a captured
+/// all-off scene from an empty (never written) backup register.
+const SCENE_MAGIC: u32 = 0xA5 << SCENE_OUTPUTS;
+
+/// Output state bitmap indexed by output position (not by OutIdx).
+#[derive(Copy, Clone, Eq, PartialEq, Debug, defmt::Format)]
+pub struct Scene(u32);
+
+impl Scene {
+    /// Capture current output status as returned by the Board.
+    pub fn capture(status: &[(OutIdx, bool)]) -> Self {
+        if status.len() > SCENE_OUTPUTS {
+            defmt::warn!(
+                "Scene covers only {} of {} outputs",
+                SCENE_OUTPUTS,
+                status.len()
+            );
+        }
+        let mut bits = 0;
+        for (pos, (_, on)) in status.iter().take(SCENE_OUTPUTS).enumerate() {
+            if *on {
+                bits |= 1 << pos;
+            }
+        }
+        Self(bits)
+    }
+
+    /// Output state at a given position.
+    pub fn is_on(&self, pos: usize) -> bool {
+        pos < SCENE_OUTPUTS && self.0 & (1 << pos) != 0
+    }
+
+    /// Outputs that need to change to bring the current status to this scene.
+    pub fn changes<'a>(
+        &'a self,
+        status: &'a [(OutIdx, bool)],
+    ) -> impl Iterator<Item = (OutIdx, bool)> + 'a {
+        status
+            .iter()
+            .take(SCENE_OUTPUTS)
+            .enumerate()
+            .filter_map(|(pos, (out, on))| {
+                let wanted = self.0 & (1 << pos) != 0;
+                (wanted != *on).then_some((*out, wanted))
+            })
+    }
+
+    /// Encode for a backup register.
+    pub fn to_backup(self) -> u32 {
+        SCENE_MAGIC | self.0
+    }
+
+    /// Decode from a backup register. None if the slot was never written.
+    pub fn from_backup(raw: u32) -> Option<Self> {
+        let mask = (1 << SCENE_OUTPUTS) - 1;
+        if raw & !mask != SCENE_MAGIC {
+            return None;
+        }
+        Some(Self(raw & mask))
+    }
+}
+
+pub mod tests {
+    use super::*;
+
+    pub fn capture_and_recall() {
+        let mut outputs: [(OutIdx, bool); 8] = core::array::from_fn(|i| (i as u8 + 1, false));
+        let set = |outputs: &mut [(OutIdx, bool)], out: OutIdx, on: bool| {
+            for entry in outputs.iter_mut() {
+                if entry.0 == out {
+                    entry.1 = on;
+                }
+            }
+        };
+
+        // Set outputs {1, 3, 5} and capture them to slot.
+        for out in [1, 3, 5] {
+            set(&mut outputs, out, true);
+        }
+        let stored = Scene::capture(&outputs).to_backup();
+        let expected = outputs;
+
+        // Change outputs.
+        set(&mut outputs, 1, false);
+        set(&mut outputs, 2, true);
+        set(&mut outputs, 8, true);
+
+        // Recall.
+        let scene = Scene::from_backup(stored).unwrap();
+        let mut changes = 0;
+        let current = outputs;
+        for (out, on) in scene.changes(&current) {
+            set(&mut outputs, out, on);
+            changes += 1;
+        }
+        assert_eq!(changes, 3);
+        assert_eq!(outputs, expected);
+
+        // Nothing to do when already in the scene.
+        assert_eq!(scene.changes(&outputs).count(), 0);
+
+        // Empty backup register and captured all-off are different.
+        assert_eq!(Scene::from_backup(0), None);
+        let off = Scene::capture(&[(1, false), (2, false)]);
+        assert_eq!(Scene::from_backup(off.to_backup()), Some(off));
+    }
+}
@@ -6,26 +6,48 @@
  */
 use ector;
 use embassy_futures::select::{Either, select};
-use embassy_time::{Duration, Instant, Timer};
-
-use crate::boards::ctrl_board_v1::Board;
-use crate::buttonsmash::consts::{OutIdx, ShutterIdx};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Instant, Timer, with_timeout};
+
+use crate::buttonsmash::consts::{Origin, OutIdx, ShutterIdx};
+use crate::components::message::{Message, args};
+use crate::components::persistent_store::{Persist, StoreError};
+use crate::components::queue::WhenFull;
 use crate::config::MAX_SHUTTERS;
+use crate::io::events::OutputError;
+use crate::io::indexed_outputs::Direction;
 
 use defmt::Format;
 use defmt::info;
 
 // TODO: Maybe that should be time hysteresis for both cases?
-/// Accuracy of position that's considered good enough. In percentage points.
+/// Default accuracy of position that's considered good enough. In percentage
+/// points.
 const HYSTERESIS: f32 = 5.0;
-/// Accuracy of tilt position.
+/// Default accuracy of tilt position.
 const HYSTERESIS_TILT: f32 = 15.0;
+/// Default smallest requested change of height or tilt worth a motor start.
+/// Above HYSTERESIS, so a slider nudge that would pass it is still dropped.
+const MIN_MOVEMENT: f32 = 8.0;
 /// Time after movement stops before we can start another one.
 const COOLDOWN: Duration = Duration::from_millis(500);
 /// When in motion, how often should we report position change.
 const UPDATE_PERIOD: Duration = Duration::from_millis(1000);
 /// If completely nothing happens, how often?
 const NOOP_UPDATE_PERIOD: Duration = Duration::from_millis(10000);
+/// Added to the longest ride when capping the motor energize time. Has to
+/// exceed a tilt plus the stuck margin, so the sweep acts first.
+const OVER_TRAVEL_MARGIN: Duration = Duration::from_secs(5);
+/// Default time a motor may run past the full travel of its direction before
+/// the sweep stops it.
+const STUCK_MARGIN: Duration = Duration::from_secs(2);
+/// Minimal time between motor starts of shutters in the same group.
+const STAGGER: Duration = Duration::from_millis(300);
+/// How often estimates of moving shutters are persisted. None - only once the
+/// shutters stop, so a travel costs a single write on any storage. Backup
+/// registers don't wear and could take an interval as short as UPDATE_PERIOD.
+const PERSIST_INTERVAL: Option<Duration> = None;
 
 /// Internal commands handled by a shutter driver.
 #[derive(Format, Eq, PartialEq, Clone, Copy, Debug)]
@@ -52,11 +74,84 @@ pub enum Cmd {
     TiltHalf,
     /// Open if not completely open; otherwise - close.
     TiltReverse,
+    /// Stop at the current position.
+    Stop,
 
     /// Shutters are configured with commands.
     SetIO(/* down */ OutIdx, /* up */ OutIdx),
-    // TODO SetRiseDropTime(u16, u16),
-    // TODO SetTiltOverTime(u16, u16),
+    /// Assign shutter to a group sharing a motor supply. 0 - no group.
+    SetGroup(u8),
+    /// Rise and drop time in deciseconds.
+    SetRiseDropTime(u16, u16),
+    /// Tilt time in deciseconds.
+    SetTiltTime(u16),
+    // TODO SetOverTime(u16),
+    /// Report the configuration with Message::ShutterConfig.
+    RequestConfig,
+    /// Report projected position and time to finish with Message::Info.
+    RequestState,
+
+    /// Run into the closed limit for the full drop and over-travel to regain
+    /// the known position. True - return to the prior estimate afterwards.
+    ResyncToClosed(bool),
+    /// Same as ResyncToClosed, towards the open limit.
+    ResyncToOpen(bool),
+    /// Swap the up and down outputs to fix swapped motor wires. Persisted,
+    /// applies to later SetIO too.
+    SwapDirection,
+}
+
+/// End of the shutter travel.
+#[derive(Format, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Limit {
+    Open,
+    Closed,
+}
+
+impl Limit {
+    /// Position at the limit.
+    pub fn position(self) -> Position {
+        match self {
+            Limit::Open => Position::new(0, 0),
+            Limit::Closed => Position::new(100, 100),
+        }
+    }
+
+    /// The other end of the travel.
+    fn opposite(self) -> Self {
+        match self {
+            Limit::Open => Limit::Closed,
+            Limit::Closed => Limit::Open,
+        }
+    }
+}
+
+/// Motor motion reported on the bus with Message::ShutterMotion.
+#[derive(Format, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Motion {
+    /// Motor started in a direction (Up or Down).
+    Started(Direction),
+    /// Motor stopped.
+    Stopped,
+}
+
+impl Motion {
+    pub fn to_u8(self) -> u8 {
+        match self {
+            Motion::Started(Direction::Up) => 1,
+            Motion::Started(Direction::Down) => 2,
+            Motion::Started(Direction::Stop) | Motion::Stopped => 0,
+        }
+    }
+
+    pub fn from_u8(raw: u8) -> Option<Self> {
+        match raw {
+            0 => Some(Motion::Stopped),
+            1 => Some(Motion::Started(Direction::Up)),
+            2 => Some(Motion::Started(Direction::Down)),
+            _ => None,
+        }
+    }
 }
 
 mod codes {
@@ -68,10 +163,36 @@ mod codes {
     pub const TILT_OPEN: u8 = 0x06;
     pub const TILT_HALF: u8 = 0x07;
     pub const TILT_REVERSE: u8 = 0x08;
+    pub const STOP: u8 = 0x09;
     pub const SET_IO: u8 = 0x10;
+    pub const SET_GROUP: u8 = 0x11;
+    pub const SET_RISE_DROP_TIME: u8 = 0x12;
+    pub const SET_TILT_TIME: u8 = 0x13;
+    pub const REQUEST_CONFIG: u8 = 0x14;
+    pub const REQUEST_STATE: u8 = 0x15;
+    pub const RESYNC_TO_CLOSED: u8 = 0x16;
+    pub const RESYNC_TO_OPEN: u8 = 0x17;
+    pub const SWAP_DIRECTION: u8 = 0x18;
 }
 
 impl Cmd {
+    /// Resync command towards the limit.
+    pub fn resync_to(limit: Limit, restore: bool) -> Self {
+        match limit {
+            Limit::Closed => Cmd::ResyncToClosed(restore),
+            Limit::Open => Cmd::ResyncToOpen(restore),
+        }
+    }
+
+    /// Limit and the restore flag of a resync command.
+    pub fn resync(&self) -> Option<(Limit, bool)> {
+        match self {
+            Cmd::ResyncToClosed(restore) => Some((Limit::Closed, *restore)),
+            Cmd::ResyncToOpen(restore) => Some((Limit::Open, *restore)),
+            _ => None,
+        }
+    }
+
     pub fn from_raw(raw: &[u8; 5]) -> Option<Self> {
         Some(match raw[0] {
             codes::GO => Cmd::Go(TargetPosition::new(raw[1], raw[2])),
@@ -82,7 +203,19 @@ impl Cmd {
             codes::TILT_OPEN => Cmd::TiltOpen,
             codes::TILT_HALF => Cmd::TiltHalf,
             codes::TILT_REVERSE => Cmd::TiltReverse,
+            codes::STOP => Cmd::Stop,
             codes::SET_IO => Cmd::SetIO(raw[1], raw[2]),
+            codes::SET_GROUP => Cmd::SetGroup(raw[1]),
+            codes::SET_RISE_DROP_TIME => Cmd::SetRiseDropTime(
+                u16::from_le_bytes([raw[1], raw[2]]),
+                u16::from_le_bytes([raw[3], raw[4]]),
+            ),
+            codes::SET_TILT_TIME => Cmd::SetTiltTime(u16::from_le_bytes([raw[1], raw[2]])),
+            codes::REQUEST_CONFIG => Cmd::RequestConfig,
+            codes::REQUEST_STATE => Cmd::RequestState,
+            codes::RESYNC_TO_CLOSED => Cmd::ResyncToClosed(raw[1] != 0),
+            codes::RESYNC_TO_OPEN => Cmd::ResyncToOpen(raw[1] != 0),
+            codes::SWAP_DIRECTION => Cmd::SwapDirection,
             _ => {
                 return None;
             }
@@ -120,18 +253,51 @@ impl Cmd {
             Cmd::TiltReverse => {
                 raw[0] = codes::TILT_REVERSE;
             }
+            Cmd::Stop => {
+                raw[0] = codes::STOP;
+            }
             Cmd::SetIO(down, up) => {
                 raw[0] = codes::SET_IO;
                 raw[1] = *down;
                 raw[2] = *up;
             }
+            Cmd::SetGroup(group) => {
+                raw[0] = codes::SET_GROUP;
+                raw[1] = *group;
+            }
+            Cmd::SetRiseDropTime(rise, drop) => {
+                raw[0] = codes::SET_RISE_DROP_TIME;
+                raw[1..3].copy_from_slice(&rise.to_le_bytes());
+                raw[3..5].copy_from_slice(&drop.to_le_bytes());
+            }
+            Cmd::SetTiltTime(tilt) => {
+                raw[0] = codes::SET_TILT_TIME;
+                raw[1..3].copy_from_slice(&tilt.to_le_bytes());
+            }
+            Cmd::RequestConfig => {
+                raw[0] = codes::REQUEST_CONFIG;
+            }
+            Cmd::RequestState => {
+                raw[0] = codes::REQUEST_STATE;
+            }
+            Cmd::ResyncToClosed(restore) => {
+                raw[0] = codes::RESYNC_TO_CLOSED;
+                raw[1] = *restore as u8;
+            }
+            Cmd::ResyncToOpen(restore) => {
+                raw[0] = codes::RESYNC_TO_OPEN;
+                raw[1] = *restore as u8;
+            }
+            Cmd::SwapDirection => {
+                raw[0] = codes::SWAP_DIRECTION;
+            }
         }
     }
 }
 
 /// Current shutter position, or partial position during computation.
 #[derive(Format, Debug, Clone, Copy, PartialEq)]
-struct Position {
+pub struct Position {
     // Accuracy should allow for 1ms resolution of time. Height 0-100 in 60s
     // would mean 1% takes 600ms. 65535 would have 0.92ms resolution, but we
     // would have to convert. f32 is fine on stm32g4.
@@ -166,6 +332,85 @@ impl TargetPosition {
     }
 }
 
+/// Bit per shutter with swapped up/down outputs, persisted across reboots.
+#[derive(Format, Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub struct Swaps(u8);
+
+impl Persist for Swaps {
+    const VERSION: u8 = 1;
+    const SIZE: usize = 1;
+
+    fn serialize(&self, buf: &mut [u8]) {
+        buf[0] = self.0;
+    }
+
+    fn deserialize(buf: &[u8]) -> Option<Self> {
+        Some(Self(buf[0]))
+    }
+}
+
+/// Positions of synchronized shutters persisted across reboots. None if
+/// position is unknown.
+#[derive(Format, Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub struct Positions([Option<TargetPosition>; MAX_SHUTTERS]);
+
+impl Persist for Positions {
+    const VERSION: u8 = 1;
+    const SIZE: usize = 2 * MAX_SHUTTERS;
+
+    fn serialize(&self, buf: &mut [u8]) {
+        for (position, chunk) in self.0.iter().zip(buf.chunks_mut(2)) {
+            match position {
+                Some(position) => chunk.copy_from_slice(&[position.height, position.tilt]),
+                None => chunk.fill(u8::MAX),
+            }
+        }
+    }
+
+    fn deserialize(buf: &[u8]) -> Option<Self> {
+        let mut positions = Self::default();
+        for (position, chunk) in positions.0.iter_mut().zip(buf.chunks(2)) {
+            let (height, tilt) = (chunk[0], chunk[1]);
+            if height <= 100 && tilt <= 100 {
+                *position = Some(TargetPosition::new(height, tilt));
+            }
+        }
+        Some(positions)
+    }
+}
+
+/// Motion times in deciseconds, as exchanged over CAN.
+#[derive(Format, Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Timing {
+    pub rise: u16,
+    pub drop: u16,
+    pub tilt: u16,
+}
+
+impl Timing {
+    /// Zero times would break position estimation.
+    pub fn is_valid(&self) -> bool {
+        self.rise > 0 && self.drop > 0 && self.tilt > 0
+    }
+}
+
+/// Timing rejected by `Config::set_timing`.
+#[derive(Format, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum TimingError {
+    /// Zero rise, drop or tilt time.
+    Zero,
+}
+
+/// Deciseconds as Duration.
+fn from_ds(ds: u16) -> Duration {
+    Duration::from_millis(ds as u64 * 100)
+}
+
+/// Duration in deciseconds, saturated.
+fn to_ds(duration: Duration) -> u16 {
+    (duration.as_millis() / 100).min(u16::MAX as u64) as u16
+}
+
 /// Shutter configuration.
 #[derive(Format)]
 pub struct Config {
@@ -185,6 +430,24 @@ pub struct Config {
     /// When reaching 0 or 100% how much time to spend on the limit switch to
     /// synchronize position information.
     pub over_time: Duration,
+    /// Motor running longer than the full travel of its direction plus this
+    /// is stopped by the periodic sweep, eg. after a lost Stop.
+    pub stuck_margin: Duration,
+
+    /// Minimal time an output stays energized once switched on. Relays can't
+    /// follow shorter pulses and their contacts might weld.
+    pub min_pulse: Duration,
+
+    /// Height difference considered close enough to the target [%].
+    pub hysteresis: f32,
+    /// Tilt difference considered close enough to the target [%].
+    pub hysteresis_tilt: f32,
+    /// Smaller requested changes of height or tilt are ignored [%]. Noisy
+    /// sliders shouldn't click the relays.
+    pub min_movement: f32,
+    /// Motor wires are swapped: `up` and `down` hold the outputs given by
+    /// SetIO exchanged.
+    pub swapped: bool,
 }
 
 /// Internal state machine for changing state in asynchronous manner.
@@ -206,10 +469,22 @@ enum Action {
     Cooldown(Instant),
 }
 
+/// Outputs driving the shutter motors. Mocked in tests.
+#[allow(async_fn_in_trait)]
+pub trait MotorOutputs {
+    /// Drive the (up, down) pair. Implementations interlock the directions.
+    async fn set_exclusive_pair(
+        &self,
+        up: OutIdx,
+        down: OutIdx,
+        direction: Direction,
+    ) -> Result<(), OutputError>;
+}
+
 /// Single shutter parameters.
-pub struct Shutter {
-    /// Output channel for commands
-    board: &'static Board,
+pub(crate) struct Shutter<M: 'static> {
+    /// Motor outputs.
+    board: &'static M,
     /// Shutter config.
     cfg: Config,
     /// Current estimated shutter position.
@@ -221,9 +496,24 @@ pub struct Shutter {
     /// If we restarted, the shutter position is unknown. We can fix it by
     /// overshooting first movement a bit. Sometimes.
     in_sync: bool,
+    /// When the up/down output was energized. None if idle.
+    energized_at: Option<Instant>,
+    /// Motor can't be started before that time. Set by the Manager to stagger
+    /// starts within a group.
+    start_after: Option<Instant>,
+    /// Resynchronization in progress.
+    resync: Option<Resync>,
+}
+
+/// Ride into a limit with the motor on for the full travel time.
+#[derive(Format, Debug, Clone, Copy, PartialEq)]
+struct Resync {
+    limit: Limit,
+    /// Position to return to once synchronized.
+    restore: Option<Position>,
 }
 
-impl Format for Shutter {
+impl<M> Format for Shutter<M> {
     fn format(&self, fmt: defmt::Formatter) {
         defmt::write!(
             fmt,
@@ -245,7 +535,69 @@ impl Config {
             drop_time: Duration::from_millis(57260), // Measured 57.26
             tilt_time: Duration::from_millis(1500),  // Measured 1.5s.
             over_time: Duration::from_secs(2),
+            stuck_margin: STUCK_MARGIN,
+            min_pulse: Duration::from_millis(100),
+            hysteresis: HYSTERESIS,
+            hysteresis_tilt: HYSTERESIS_TILT,
+            min_movement: MIN_MOVEMENT,
+            swapped: false,
+        }
+    }
+
+    /// Assign outputs as wired by the book. Swapped if the wires are.
+    pub fn set_io(&mut self, down: OutIdx, up: OutIdx) {
+        (self.down, self.up) = if self.swapped { (up, down) } else { (down, up) };
+    }
+
+    /// Mark the motor wires as swapped or not and exchange the outputs
+    /// accordingly.
+    pub fn set_swapped(&mut self, swapped: bool) {
+        if self.swapped != swapped {
+            (self.down, self.up) = (self.up, self.down);
+            self.swapped = swapped;
+        }
+    }
+
+    pub fn timing(&self) -> Timing {
+        Timing {
+            rise: to_ds(self.rise_time),
+            drop: to_ds(self.drop_time),
+            tilt: to_ds(self.tilt_time),
+        }
+    }
+
+    /// Apply timing received over CAN. Invalid timing is rejected.
+    pub fn set_timing(&mut self, timing: Timing) -> Result<(), TimingError> {
+        if !timing.is_valid() {
+            return Err(TimingError::Zero);
         }
+        self.rise_time = from_ds(timing.rise);
+        self.drop_time = from_ds(timing.drop);
+        self.tilt_time = from_ds(timing.tilt);
+        Ok(())
+    }
+
+    /// How much longer output energized at `energized_at` has to stay on to
+    /// honor the `min_pulse`.
+    fn pulse_remaining(&self, energized_at: Instant, now: Instant) -> Duration {
+        let elapsed = now.saturating_duration_since(energized_at);
+        if elapsed >= self.min_pulse {
+            Duration::from_secs(0)
+        } else {
+            self.min_pulse - elapsed
+        }
+    }
+
+    /// Hard limit of continuous motor energize time: the longer ride with an
+    /// over-travel on the end stop, plus the margin. The margin covers the
+    /// tilt preceding a full ride.
+    fn max_energized(&self) -> Duration {
+        self.rise_time.max(self.drop_time) + self.over_time + OVER_TRAVEL_MARGIN
+    }
+
+    /// Was the motor energized at `energized_at` on for too long?
+    fn over_travel(&self, energized_at: Instant, now: Instant) -> bool {
+        now.saturating_duration_since(energized_at) > self.max_energized()
     }
 
     /// Time it will take to move from position to position.
@@ -288,44 +640,13 @@ impl Config {
         };
         movement.clamp(0.0, 100.0)
     }
-}
-
-impl Position {
-    pub fn new(height: u8, tilt: u8) -> Self {
-        assert!(height <= 100);
-        assert!(tilt <= 100);
-        Self {
-            height: height as f32,
-            tilt: tilt as f32,
-        }
-    }
-
-    pub fn new_zero() -> Self {
-        Self {
-            height: 0.0,
-            tilt: 0.0,
-        }
-    }
-}
-
-impl Shutter {
-    pub fn new(up: OutIdx, down: OutIdx, board: &'static Board) -> Self {
-        Self {
-            board,
-            cfg: Config::new(up, down),
-            position: Position::new_zero(),
-            target: Position::new_zero(),
-            action: Action::Sleep,
-            in_sync: false,
-        }
-    }
 
     /// We want to tilt from start position to the target one, and some time passed.
     /// Return current tilt (movement in one direction for x ms) and residual ms
     /// time that changed the height.
     /// Returns (current tilt, rest of time for consumption)
-    fn consume_tilt(&mut self, now: Instant) -> (f32, Duration) {
-        let (since, dir, max_tilt) = match self.action {
+    fn consume_tilt(&self, position: &Position, action: &Action, now: Instant) -> (f32, Duration) {
+        let (since, dir, max_tilt) = match *action {
             Action::Up(since) => {
                 // Up, opens. Towards 0.
                 (since, -1.0, 0.0)
@@ -336,14 +657,14 @@ impl Shutter {
             }
             _ => {
                 // Nothing will change
-                return (self.position.tilt, Duration::from_secs(0));
+                return (position.tilt, Duration::from_secs(0));
             }
         };
 
         // Max time that will be taken by tilt in current direction.
-        let max_time = self.cfg.tilt_as_time(self.position.tilt, max_tilt);
+        let max_time = self.tilt_as_time(position.tilt, max_tilt);
         // True time taken.
-        let elapsed = now.duration_since(since);
+        let elapsed = now.saturating_duration_since(since);
 
         if elapsed >= max_time {
             // We reached the final tilt in max_time. Rest of elapsed time
@@ -353,13 +674,13 @@ impl Shutter {
             // We are within the tilt movement still.
 
             // How much did we tilt already?
-            let tilted = self.cfg.time_as_tilt(elapsed);
+            let tilted = self.time_as_tilt(elapsed);
 
             // If tilt-time conversion was not perfect, we might not be able to
             // consume exactly the time that passed. But with f32 that should be
             // accurate enough to assume we consume everything.
 
-            let mut final_tilt = self.position.tilt;
+            let mut final_tilt = position.tilt;
             final_tilt += dir * tilted;
             assert!((0.0..=100.0).contains(&final_tilt)); // TODO: Dev time only.
 
@@ -369,55 +690,347 @@ impl Shutter {
     }
 
     // Consume time for movement. Tilt should be calculated first.
-    fn consume_height(&self, elapsed: Duration) -> f32 {
-        let (dir, _conf_time) = match self.action {
+    fn consume_height(&self, position: &Position, action: &Action, elapsed: Duration) -> f32 {
+        let (dir, _conf_time) = match action {
             Action::Up(_since) => {
                 // Up, opens. Towards 0.
-                (-1i8, self.cfg.rise_time.as_millis())
+                (-1i8, self.rise_time.as_millis())
             }
             Action::Down(_since) => {
                 // Down closes, towards 100.
-                (1, self.cfg.drop_time.as_millis())
+                (1, self.drop_time.as_millis())
             }
             _ => {
                 // Nothing will change
-                return self.position.height;
+                return position.height;
             }
         };
 
-        let height_delta = dir as f32 * self.cfg.time_as_travel(dir, elapsed);
+        let height_delta = dir as f32 * self.time_as_travel(dir, elapsed);
 
-        let mut height = self.position.height;
+        let mut height = position.height;
         height += height_delta;
         height = height.clamp(0.0, 100.0);
         height
     }
 
-    /// Stop movement.
-    async fn go_idle(&self) {
-        // Report error?
-        let _ = self.board.set_output(self.cfg.up, false).await;
-        let _ = self.board.set_output(self.cfg.down, false).await;
+    /// Position at `at` if the action continues. Movement stops at the target.
+    fn project(
+        &self,
+        position: &Position,
+        target: &Position,
+        action: &Action,
+        at: Instant,
+    ) -> Position {
+        let (mut tilt, elapsed) = self.consume_tilt(position, action, at);
+        let mut height = self.consume_height(position, action, elapsed);
+        let tilt_only = (target.height - position.height).abs() <= self.hysteresis;
+        match action {
+            Action::Down(_) => {
+                height = height.min(target.height.max(position.height));
+                if tilt_only {
+                    tilt = tilt.min(target.tilt.max(position.tilt));
+                }
+            }
+            Action::Up(_) => {
+                height = height.max(target.height.min(position.height));
+                if tilt_only {
+                    tilt = tilt.max(target.tilt.min(position.tilt));
+                }
+            }
+            _ => {}
+        }
+        Position { height, tilt }
     }
 
-    /// Start movement UP.
-    async fn go_up(&self) {
-        // NOTE: Should not be needed. Just for security.
-        if self.board.set_output(self.cfg.down, false).await.is_err() {
-            // Security - don't enable if can't disable the other one.
-            return;
+    /// Settled position of a shutter stopped right now, and whether its motor
+    /// runs and has to be cut.
+    fn park(
+        &self,
+        position: &Position,
+        target: &Position,
+        action: &Action,
+        now: Instant,
+    ) -> (Position, bool) {
+        let moving = matches!(action, Action::Up(_) | Action::Down(_));
+        (self.project(position, target, action, now), moving)
+    }
+
+    /// Motor time that reaches the limit from anywhere, with the over-travel
+    /// to make sure the end switch was hit.
+    fn resync_time(&self, limit: Limit) -> Duration {
+        let travel = match limit {
+            Limit::Open => self.rise_time,
+            Limit::Closed => self.drop_time,
+        };
+        self.tilt_time + travel + self.over_time
+    }
+
+    /// Longest legit motor run in the direction of the limit.
+    fn max_travel(&self, limit: Limit) -> Duration {
+        self.resync_time(limit) + self.stuck_margin
+    }
+
+    /// Target with too small changes dropped. Limits are kept, so Open and
+    /// Close still finish a ride.
+    fn snap_target(&self, position: &Position, target: &Position) -> Position {
+        let snap = |current: f32, wanted: f32| {
+            let limit = wanted == 0.0 || wanted == 100.0;
+            if !limit && (wanted - current).abs() < self.min_movement {
+                current
+            } else {
+                wanted
+            }
+        };
+        Position {
+            height: snap(position.height, target.height),
+            tilt: snap(position.tilt, target.tilt),
         }
-        let _ = self.board.set_output(self.cfg.up, true).await;
     }
 
-    /// Start movement DOWN.
-    async fn go_down(&self) {
-        // NOTE: Should not be needed. Just for security.
-        if self.board.set_output(self.cfg.up, false).await.is_err() {
-            // Security - don't enable if can't disable the other one.
+    /// Is the height and the tilt too far from the target?
+    fn off_target(&self, position: &Position, target: &Position) -> (bool, bool) {
+        (
+            (target.height - position.height).abs() > self.hysteresis,
+            (target.tilt - position.tilt).abs() > self.hysteresis_tilt,
+        )
+    }
+
+    /// Estimated time to get from the position to the target. Includes the
+    /// direction change needed to set the final tilt after travel.
+    fn remaining_time(&self, position: &Position, target: &Position) -> Duration {
+        if (target.height - position.height).abs() <= self.hysteresis {
+            return self.tilt_as_time(position.tilt, target.tilt);
+        }
+        // Travel starts with tilting fully in the direction of movement.
+        let travel_tilt = if target.height > position.height {
+            100.0
+        } else {
+            0.0
+        };
+        let mut time = self.tilt_as_time(position.tilt, travel_tilt)
+            + self.travel_as_time(position.height, target.height);
+        if (target.tilt - travel_tilt).abs() > self.hysteresis_tilt {
+            time += COOLDOWN + self.tilt_as_time(travel_tilt, target.tilt);
+        }
+        time
+    }
+}
+
+impl Position {
+    pub fn new(height: u8, tilt: u8) -> Self {
+        assert!(height <= 100);
+        assert!(tilt <= 100);
+        Self {
+            height: height as f32,
+            tilt: tilt as f32,
+        }
+    }
+
+    pub fn new_zero() -> Self {
+        Self {
+            height: 0.0,
+            tilt: 0.0,
+        }
+    }
+
+    pub fn height(&self) -> f32 {
+        self.height
+    }
+
+    pub fn tilt(&self) -> f32 {
+        self.tilt
+    }
+}
+
+impl<M: MotorOutputs> Shutter<M> {
+    pub fn new(up: OutIdx, down: OutIdx, board: &'static M) -> Self {
+        Self {
+            board,
+            cfg: Config::new(up, down),
+            position: Position::new_zero(),
+            target: Position::new_zero(),
+            action: Action::Sleep,
+            in_sync: false,
+            energized_at: None,
+            start_after: None,
+            resync: None,
+        }
+    }
+
+    /// Time to keep the output energized until the minimal pulse is reached.
+    fn pulse_remaining(&self, now: Instant) -> Duration {
+        match self.energized_at {
+            Some(energized_at) => self.cfg.pulse_remaining(energized_at, now),
+            None => Duration::from_secs(0),
+        }
+    }
+
+    /// Time to keep the motor on after the target was reached: the minimal
+    /// pulse, or the rest of the resync travel.
+    fn hold_remaining(&self, now: Instant) -> Duration {
+        let pulse = self.pulse_remaining(now);
+        match (self.resync, self.energized_at) {
+            (Some(resync), Some(energized_at)) => {
+                let until = energized_at + self.cfg.resync_time(resync.limit);
+                pulse.max(until.saturating_duration_since(now))
+            }
+            _ => pulse,
+        }
+    }
+
+    /// Ride to the limit from the worst case - the opposite end. Returns the
+    /// target.
+    fn start_resync(&mut self, limit: Limit, restore: bool) -> Position {
+        let restore = restore.then_some(self.position);
+        self.position = limit.opposite().position();
+        self.in_sync = false;
+        self.resync = Some(Resync { limit, restore });
+        limit.position()
+    }
+
+    /// Motor stopped at the limit - position is known again.
+    fn resync_done(&mut self) {
+        let Some(resync) = self.resync.take() else {
             return;
+        };
+        self.position = resync.limit.position();
+        self.target = resync.restore.unwrap_or(self.position);
+        self.in_sync = true;
+        info!("Shutter resynchronized at {:?}", resync.limit);
+    }
+
+    /// Time to wait until the motor can be started.
+    fn start_delay(&self, now: Instant) -> Duration {
+        match self.start_after {
+            Some(start_after) => start_after.saturating_duration_since(now),
+            None => Duration::from_secs(0),
+        }
+    }
+
+    /// Interpolated position at a given instant if the current movement
+    /// continues. State is not changed - UIs can animate between reports.
+    pub fn projected_position(&self, at: Instant) -> Position {
+        self.cfg
+            .project(&self.position, &self.target, &self.action, at)
+    }
+
+    /// When the target is expected to be reached. None if not moving.
+    pub fn estimated_finish(&self, now: Instant) -> Option<Instant> {
+        if !matches!(self.action, Action::Up(_) | Action::Down(_)) {
+            return None;
+        }
+        let position = self.projected_position(now);
+        Some(now + self.cfg.remaining_time(&position, &self.target))
+    }
+
+    /// Direction the motor is driven in by the current action.
+    fn direction(&self) -> Direction {
+        match self.action {
+            Action::Up(_) => Direction::Up,
+            Action::Down(_) => Direction::Down,
+            _ => Direction::Stop,
+        }
+    }
+
+    /// Drive the motor outputs, logging the failing expander line.
+    async fn drive(&self, direction: Direction) {
+        if let Err(err) = self
+            .board
+            .set_exclusive_pair(self.cfg.up, self.cfg.down, direction)
+            .await
+        {
+            defmt::error!("Shutter outputs failed: {:?}", err);
+        }
+    }
+
+    /// Stop movement.
+    async fn go_idle(&mut self) {
+        self.energized_at = None;
+        self.drive(Direction::Stop).await;
+    }
+
+    /// Cut the motor if it runs over the safety cap, regardless of the state.
+    /// Position is unknown afterwards. Returns true if it was cut.
+    async fn enforce_cap(&mut self, now: Instant) -> bool {
+        let Some(energized_at) = self.energized_at else {
+            return false;
+        };
+        if !self.cfg.over_travel(energized_at, now) {
+            return false;
+        }
+        defmt::error!(
+            "Shutter motor on for {}ms - cutting it. {:?}",
+            now.saturating_duration_since(energized_at).as_millis(),
+            self
+        );
+        self.cut(now).await;
+        true
+    }
+
+    /// Stop a motor running longer than a full travel of its direction, eg.
+    /// when a Stop got lost. Tighter than the cap, run by the manager sweep
+    /// even if no commands arrive. Returns true if it was stopped.
+    async fn sweep_stuck(&mut self, now: Instant) -> bool {
+        let limit = match self.action {
+            Action::Up(_) => Limit::Open,
+            Action::Down(_) => Limit::Closed,
+            _ => return false,
+        };
+        let Some(energized_at) = self.energized_at else {
+            return false;
+        };
+        let energized = now.saturating_duration_since(energized_at);
+        if energized <= self.cfg.max_travel(limit) {
+            return false;
         }
-        let _ = self.board.set_output(self.cfg.down, true).await;
+        defmt::warn!(
+            "Shutter left energized for {}ms - forcing idle. {:?}",
+            energized.as_millis(),
+            self
+        );
+        self.cut(now).await;
+        true
+    }
+
+    /// Stop the motor mid-way. Position is unknown afterwards.
+    async fn cut(&mut self, now: Instant) {
+        self.go_idle().await;
+        self.action = Action::Cooldown(now);
+        self.target = self.position;
+        self.in_sync = false;
+    }
+
+    /// Stop at once, without waiting for the minimal pulse, and settle at the
+    /// interpolated position. Pending movement is dropped. Returns true if the
+    /// motor was running.
+    async fn park(&mut self, now: Instant) -> bool {
+        let (position, moving) = self
+            .cfg
+            .park(&self.position, &self.target, &self.action, now);
+        self.position = position;
+        self.target = position;
+        self.resync = None;
+        let energized = moving || self.energized_at.is_some();
+        if energized {
+            self.go_idle().await;
+            self.action = Action::Cooldown(now);
+        }
+        energized
+    }
+
+    /// Start movement UP.
+    async fn go_up(&mut self, now: Instant) {
+        self.energized_at = Some(now);
+        // Pair is interlocked - the other direction is released first.
+        self.drive(Direction::Up).await;
+    }
+
+    /// Start movement DOWN.
+    async fn go_down(&mut self, now: Instant) {
+        self.energized_at = Some(now);
+        // Pair is interlocked - the other direction is released first.
+        self.drive(Direction::Down).await;
     }
 
     /// This is an universal state 'tick':
@@ -426,8 +1039,10 @@ impl Shutter {
     /// - Return the duration after which update should again be called.
     async fn update(&mut self, now: Instant) -> Duration {
         // Step I: Update tilt / height if we are in motion.
-        let (tilt, elapsed) = self.consume_tilt(now);
-        let height = self.consume_height(elapsed);
+        let (tilt, elapsed) = self.cfg.consume_tilt(&self.position, &self.action, now);
+        let height = self
+            .cfg
+            .consume_height(&self.position, &self.action, elapsed);
         info!(
             "Update: from h{}t{} -> h{}t{} delta h{}t{} residual tilt time {}ms",
             self.position.height,
@@ -448,36 +1063,42 @@ impl Shutter {
             Action::Idle | Action::Sleep => {
                 // We are inactive, maybe a new action can be started if target
                 // position is not reached yet.
-                let height_diff = (self.target.height - self.position.height).abs();
-                let tilt_diff = (self.target.tilt - self.position.tilt).abs();
+                let (height_off, tilt_off) = self.cfg.off_target(&self.position, &self.target);
+                let pending = height_off || tilt_off;
+                let delay = self.start_delay(now);
 
-                if height_diff > HYSTERESIS {
+                if pending && delay > Duration::from_secs(0) {
+                    // Other shutter in the group has just started its motor.
+                    info!("Idle: start delayed by {}ms", delay.as_millis());
+                    self.action = Action::Idle;
+                    delay
+                } else if height_off {
                     if self.target.height < self.position.height {
                         // We should move up.
                         info!("INIT: Idle -> Up (Height)");
                         self.action = Action::Up(now);
-                        self.go_up().await;
+                        self.go_up(now).await;
                         // Return 0 to we got called again shortly and calculate proper time.
                         Duration::from_secs(0)
                     } else {
                         // We should move down.
                         info!("INIT: Idle -> Down (Height)");
                         self.action = Action::Down(now);
-                        self.go_down().await;
+                        self.go_down(now).await;
                         Duration::from_secs(0)
                     }
-                } else if tilt_diff > HYSTERESIS_TILT {
+                } else if tilt_off {
                     if self.target.tilt < self.position.tilt {
                         // Tilt is too high, we should move `up` to open the shutters angle.
                         info!("INIT: Idle -> Up (Tilt)");
                         self.action = Action::Up(now);
-                        self.go_up().await;
+                        self.go_up(now).await;
                         Duration::from_secs(0)
                     } else {
                         // Tilt is too low (we are too open), move down a bit.
                         info!("INIT: Idle -> Down (Tilt)");
                         self.action = Action::Down(now);
-                        self.go_down().await;
+                        self.go_down(now).await;
                         Duration::from_secs(0)
                     }
                 } else {
@@ -505,10 +1126,16 @@ impl Shutter {
                 if self.position.height <= self.target.height {
                     // Height achieved! What about the tilt? In UP, the tilt decreases.
                     if self.position.tilt <= self.target.tilt {
-                        // Tilt achieved! Stop movement.
-                        self.go_idle().await;
-                        self.action = Action::Cooldown(now);
-                        COOLDOWN
+                        // Tilt achieved! Stop movement - unless the pulse would be too short.
+                        let remaining = self.hold_remaining(now);
+                        if remaining > Duration::from_secs(0) {
+                            remaining
+                        } else {
+                            self.go_idle().await;
+                            self.resync_done();
+                            self.action = Action::Cooldown(now);
+                            COOLDOWN
+                        }
                     } else {
                         // We're still in motion until the tilt is fine.
                         self.cfg.tilt_as_time(self.position.tilt, self.target.tilt)
@@ -525,10 +1152,16 @@ impl Shutter {
                 if self.position.height >= self.target.height {
                     // Height achieved! What about the tilt?
                     if self.position.tilt >= self.target.tilt {
-                        // Tilt achieved! Stop movement.
-                        self.go_idle().await;
-                        self.action = Action::Cooldown(now);
-                        COOLDOWN
+                        // Tilt achieved! Stop movement - unless the pulse would be too short.
+                        let remaining = self.hold_remaining(now);
+                        if remaining > Duration::from_secs(0) {
+                            remaining
+                        } else {
+                            self.go_idle().await;
+                            self.resync_done();
+                            self.action = Action::Cooldown(now);
+                            COOLDOWN
+                        }
                     } else {
                         // We're still in motion until the tilt is fine.
                         self.cfg.tilt_as_time(self.position.tilt, self.target.tilt)
@@ -549,8 +1182,14 @@ impl Shutter {
             Action::Idle | Action::Sleep => {}
             Action::Cooldown(_) => { /* Update can finish a cooldown. We don't have to. */ }
             Action::Up(_) | Action::Down(_) => {
+                // Interrupted right after start. Keep the relay on for a
+                // minimal pulse. Position drift within min_pulse is negligible.
+                let remaining = self.pulse_remaining(now);
+                if remaining > Duration::from_secs(0) {
+                    Timer::after(remaining).await;
+                }
                 self.go_idle().await;
-                self.action = Action::Cooldown(now);
+                self.action = Action::Cooldown(now + remaining);
             }
         }
     }
@@ -571,7 +1210,7 @@ impl Shutter {
                 );
             }
         }
-        self.target = target;
+        self.target = self.cfg.snap_target(&self.position, &target);
         self.update(now).await
     }
 
@@ -589,6 +1228,8 @@ impl Shutter {
         }
 
         info!("Shutter after finishing previous actions: {:?}", self);
+        // Interrupted resync leaves the position unknown.
+        self.resync = None;
 
         let target = match cmd {
             Cmd::Go(target) => target.as_position(),
@@ -635,10 +1276,50 @@ impl Shutter {
                 height: self.position.height,
                 tilt: tilt as f32,
             },
+            Cmd::Stop => {
+                // Movement was finished above, don't resume it.
+                self.target = self.position;
+                return;
+            }
+            Cmd::ResyncToClosed(restore) => self.start_resync(Limit::Closed, restore),
+            Cmd::ResyncToOpen(restore) => self.start_resync(Limit::Open, restore),
             Cmd::SetIO(down_idx, up_idx) => {
-                assert_eq!(self.action, Action::Sleep);
-                self.cfg.down = down_idx;
-                self.cfg.up = up_idx;
+                // Movement (if any) was finished above, outputs are off. A
+                // rerun setup (runtime reset) can find us idle or cooling down.
+                assert!(!matches!(self.action, Action::Up(_) | Action::Down(_)));
+                self.cfg.set_io(down_idx, up_idx);
+                return;
+            }
+            Cmd::SwapDirection => {
+                self.cfg.set_swapped(!self.cfg.swapped);
+                // Estimate was built while moving the wrong way.
+                self.in_sync = false;
+                self.target = self.position;
+                return;
+            }
+            Cmd::SetGroup(_) | Cmd::RequestConfig | Cmd::RequestState => {
+                // Handled by the Manager.
+                return;
+            }
+            Cmd::SetRiseDropTime(rise, drop) => {
+                let timing = Timing {
+                    rise,
+                    drop,
+                    ..self.cfg.timing()
+                };
+                if self.cfg.set_timing(timing).is_err() {
+                    defmt::warn!("Invalid shutter timing {:?}", timing);
+                }
+                return;
+            }
+            Cmd::SetTiltTime(tilt) => {
+                let timing = Timing {
+                    tilt,
+                    ..self.cfg.timing()
+                };
+                if self.cfg.set_timing(timing).is_err() {
+                    defmt::warn!("Invalid shutter timing {:?}", timing);
+                }
                 return;
             }
         };
@@ -646,17 +1327,256 @@ impl Shutter {
     }
 }
 
-pub struct Manager {
-    shutters: [Shutter; MAX_SHUTTERS],
+/// Spreads motor starts of shutters sharing a supply to limit the inrush
+/// current. Shutters in the same group don't start within `stagger` of each
+/// other - the later start is queued.
+pub struct Stagger {
+    stagger: Duration,
+    groups: [Option<u8>; MAX_SHUTTERS],
+    /// Last motor start of each shutter.
+    starts: [Option<Instant>; MAX_SHUTTERS],
 }
 
-impl Manager {
-    pub fn new(board: &'static Board) -> Self {
+impl Stagger {
+    pub const fn new(stagger: Duration) -> Self {
         Self {
-            shutters: [
-                // Shutters start unconfigured, and can later be set dynamically with commands.
-                Shutter::new(OutIdx::MAX, OutIdx::MAX, board),
-                Shutter::new(OutIdx::MAX, OutIdx::MAX, board),
+            stagger,
+            groups: [None; MAX_SHUTTERS],
+            starts: [None; MAX_SHUTTERS],
+        }
+    }
+
+    pub fn set_group(&mut self, shutter: usize, group: Option<u8>) {
+        self.groups[shutter] = group;
+    }
+
+    /// Earliest time the shutter can start its motor. None if unconstrained.
+    pub fn start_after(&self, shutter: usize) -> Option<Instant> {
+        let group = self.groups[shutter]?;
+        self.groups
+            .iter()
+            .zip(self.starts.iter())
+            .enumerate()
+            .filter(|(other, (other_group, _))| *other != shutter && **other_group == Some(group))
+            .filter_map(|(_, (_, start))| *start)
+            .max()
+            .map(|start| start + self.stagger)
+    }
+
+    /// Record a motor start.
+    pub fn started(&mut self, shutter: usize, at: Instant) {
+        self.starts[shutter] = Some(at);
+    }
+
+    pub fn in_group(&self, shutter: usize, group: u8) -> bool {
+        self.groups[shutter] == Some(group)
+    }
+}
+
+/// Shutter index addressing all configured shutters.
+pub const ALL_SHUTTERS: ShutterIdx = 0xFF;
+/// Shutter index with this bit set addresses a group (lower bits) instead.
+pub const GROUP_TARGET: ShutterIdx = 0x80;
+
+/// Shutters addressed by a command.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Format)]
+pub enum Target {
+    Single(usize),
+    Group(u8),
+    All,
+}
+
+impl Target {
+    /// Decode the shutter index of a command. None if it addresses nothing.
+    pub fn from_idx(idx: ShutterIdx) -> Option<Self> {
+        match idx {
+            ALL_SHUTTERS => Some(Target::All),
+            // Group 0 means "no group".
+            GROUP_TARGET => None,
+            idx if idx & GROUP_TARGET != 0 => Some(Target::Group(idx & !GROUP_TARGET)),
+            idx if (idx as usize) < MAX_SHUTTERS => Some(Target::Single(idx as usize)),
+            _ => None,
+        }
+    }
+}
+
+/// Applies a group command to its shutters one by one in the index order,
+/// `stagger` apart, so the motors don't start at once. A new group command
+/// cancels the starts still pending from the previous one.
+pub struct FanOut {
+    stagger: Duration,
+    cmd: Cmd,
+    /// Shutters still waiting for the command.
+    pending: [bool; MAX_SHUTTERS],
+    next_at: Instant,
+}
+
+impl FanOut {
+    pub const fn new(stagger: Duration) -> Self {
+        Self {
+            stagger,
+            cmd: Cmd::Stop,
+            pending: [false; MAX_SHUTTERS],
+            next_at: Instant::from_ticks(0),
+        }
+    }
+
+    /// Start applying the command to the members.
+    pub fn start(&mut self, cmd: Cmd, members: [bool; MAX_SHUTTERS], now: Instant) {
+        if self.deadline().is_some() {
+            info!("Group {:?} cancels pending {:?}", cmd, self.cmd);
+        }
+        self.cmd = cmd;
+        self.pending = members;
+        self.next_at = now;
+    }
+
+    /// Next shutter to command, if it's due already.
+    pub fn next(&mut self, now: Instant) -> Option<(usize, Cmd)> {
+        if now < self.next_at {
+            return None;
+        }
+        let idx = self.pending.iter().position(|pending| *pending)?;
+        self.pending[idx] = false;
+        // Stopping doesn't draw the inrush current.
+        if self.cmd != Cmd::Stop {
+            self.next_at = now + self.stagger;
+        }
+        Some((idx, self.cmd))
+    }
+
+    /// Drop the pending starts.
+    pub fn cancel(&mut self) {
+        self.pending = [false; MAX_SHUTTERS];
+    }
+
+    /// When the next shutter should be commanded.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.pending
+            .iter()
+            .any(|pending| *pending)
+            .then_some(self.next_at)
+    }
+}
+
+/// Coalesces position writes in RAM. Unchanged positions are never written,
+/// changed ones are written once the shutters stop, and while they move at
+/// most once per `interval` (never if None).
+pub struct PersistThrottle {
+    interval: Option<Duration>,
+    /// Last written positions.
+    written: Option<Positions>,
+    /// Start of the current interval.
+    since: Option<Instant>,
+}
+
+impl PersistThrottle {
+    pub const fn new(interval: Option<Duration>) -> Self {
+        Self {
+            interval,
+            written: None,
+            since: None,
+        }
+    }
+
+    /// Should the positions be written now? `settled` - no motor runs.
+    pub fn should_write(&mut self, positions: &Positions, settled: bool, now: Instant) -> bool {
+        if settled {
+            // Next travel starts its own interval.
+            self.since = None;
+            return self.written.as_ref() != Some(positions);
+        }
+        if self.written.as_ref() == Some(positions) {
+            return false;
+        }
+        let since = *self.since.get_or_insert(now);
+        self.interval
+            .is_some_and(|interval| now.saturating_duration_since(since) >= interval)
+    }
+
+    /// Positions were written. Restarts the interval of a travel.
+    pub fn written(&mut self, positions: Positions, now: Instant) {
+        self.written = Some(positions);
+        if self.since.is_some() {
+            self.since = Some(now);
+        }
+    }
+}
+
+/// Last reported motion of each shutter. Turns the actions into discrete
+/// start/stop events - one per transition into or out of Up/Down.
+pub struct MotionTracker {
+    last: [Direction; MAX_SHUTTERS],
+}
+
+impl Default for MotionTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MotionTracker {
+    pub const fn new() -> Self {
+        Self {
+            last: [Direction::Stop; MAX_SHUTTERS],
+        }
+    }
+
+    /// Record the current direction of a shutter. Returns the event to
+    /// report if it changed.
+    pub fn observe(&mut self, shutter: usize, direction: Direction) -> Option<Motion> {
+        let last = core::mem::replace(&mut self.last[shutter], direction);
+        if last == direction {
+            return None;
+        }
+        Some(match direction {
+            Direction::Stop => Motion::Stopped,
+            direction => Motion::Started(direction),
+        })
+    }
+}
+
+/// Persisted state of the shutters.
+#[derive(Format, Eq, PartialEq, Clone, Copy, Debug)]
+pub enum StateSlot {
+    /// Positions of the synchronized shutters.
+    Positions,
+    /// Shutters with swapped up/down outputs.
+    Swaps,
+}
+
+/// Board services used by the Manager. Mocked in tests.
+#[allow(async_fn_in_trait)]
+pub trait ShutterIo: MotorOutputs {
+    /// Send a message from this node.
+    async fn transmit(&self, message: &Message, when_full: WhenFull);
+    /// Load the persisted state. Default if none was stored.
+    async fn load_state<T: Persist>(&self, slot: StateSlot) -> T;
+    async fn store_state<T: Persist>(&self, slot: StateSlot, value: &T) -> Result<(), StoreError>;
+}
+
+/// Raised by the Manager each time all shutters are parked.
+pub type ParkSignal = Signal<CriticalSectionRawMutex, ()>;
+
+pub struct Manager<M: ShutterIo + 'static> {
+    board: &'static M,
+    shutters: [Shutter<M>; MAX_SHUTTERS],
+    stagger: Stagger,
+    fan_out: FanOut,
+    persist: PersistThrottle,
+    motion: MotionTracker,
+    /// Tells the waiting program reload the motors are off.
+    parked: Option<&'static ParkSignal>,
+}
+
+impl<M: ShutterIo + 'static> Manager<M> {
+    pub fn new(board: &'static M) -> Self {
+        Self {
+            board,
+            shutters: [
+                // Shutters start unconfigured, and can later be set dynamically with commands.
+                Shutter::new(OutIdx::MAX, OutIdx::MAX, board),
+                Shutter::new(OutIdx::MAX, OutIdx::MAX, board),
                 Shutter::new(OutIdx::MAX, OutIdx::MAX, board),
                 Shutter::new(OutIdx::MAX, OutIdx::MAX, board),
                 Shutter::new(OutIdx::MAX, OutIdx::MAX, board),
@@ -664,39 +1584,345 @@ impl Manager {
                 Shutter::new(OutIdx::MAX, OutIdx::MAX, board),
                 Shutter::new(OutIdx::MAX, OutIdx::MAX, board),
             ],
+            stagger: Stagger::new(STAGGER),
+            fan_out: FanOut::new(STAGGER),
+            persist: PersistThrottle::new(PERSIST_INTERVAL),
+            motion: MotionTracker::new(),
+            parked: None,
+        }
+    }
+
+    /// Raise the signal after parking the shutters.
+    pub fn with_parked(mut self, parked: &'static ParkSignal) -> Self {
+        self.parked = Some(parked);
+        self
+    }
+
+    /// Shutters addressed by a group command.
+    fn members(&self, target: Target) -> [bool; MAX_SHUTTERS] {
+        let mut members = [false; MAX_SHUTTERS];
+        for (idx, member) in members.iter_mut().enumerate() {
+            *member = match target {
+                Target::Single(single) => idx == single,
+                Target::Group(group) => self.stagger.in_group(idx, group),
+                // Unconfigured shutters have no outputs to drive.
+                Target::All => self.shutters[idx].cfg.down != OutIdx::MAX,
+            };
+        }
+        members
+    }
+
+    /// Handle a command of a single shutter.
+    async fn apply(&mut self, idx: usize, cmd: Cmd, now: Instant) {
+        match cmd {
+            Cmd::SetGroup(group) => {
+                self.stagger.set_group(idx, (group != 0).then_some(group));
+            }
+            Cmd::RequestConfig => self.report_config(idx as ShutterIdx).await,
+            Cmd::RequestState => self.report_state(idx as ShutterIdx, now).await,
+            cmd => {
+                let previous = self.before_action(idx);
+                self.shutters[idx].command(cmd, now).await;
+                self.after_action(idx, previous, now).await;
+                if cmd == Cmd::SwapDirection {
+                    defmt::info!("Shutter {} direction swapped", idx);
+                    self.persist_swaps().await;
+                }
+            }
+        }
+    }
+
+    /// Stop all shutters before a reboot or a program reload: cut the motors,
+    /// settle the position estimates and persist them. Calling it again is a
+    /// cheap no-op.
+    pub async fn park_all(&mut self, now: Instant) {
+        self.fan_out.cancel();
+        let mut parked = false;
+        for idx in 0..self.shutters.len() {
+            parked |= self.shutters[idx].park(now).await;
+            self.report_motion(idx).await;
+        }
+        if parked {
+            defmt::info!("Shutters parked");
+            self.persist(true, now).await;
+        }
+        if let Some(signal) = self.parked {
+            signal.signal(());
+        }
+    }
+
+    /// Apply the group constraint before the shutter gets a chance to start.
+    /// Returns the previous motor start.
+    fn before_action(&mut self, idx: usize) -> Option<Instant> {
+        let shutter = &mut self.shutters[idx];
+        shutter.start_after = self.stagger.start_after(idx);
+        shutter.energized_at
+    }
+
+    /// Record the motor start if it happened. Persist positions once stopped.
+    async fn after_action(&mut self, idx: usize, previous: Option<Instant>, now: Instant) {
+        let energized_at = self.shutters[idx].energized_at;
+        if energized_at.is_some() != previous.is_some() {
+            let cfg = &self.shutters[idx].cfg;
+            info!(
+                "Outputs {}/{} energized={} from {:?}",
+                cfg.up,
+                cfg.down,
+                energized_at.is_some(),
+                Origin::Shutter(idx as ShutterIdx)
+            );
+        }
+        if energized_at != previous
+            && let Some(at) = energized_at
+        {
+            self.stagger.started(idx, at);
+        }
+        self.report_motion(idx).await;
+        if previous.is_some() && energized_at.is_none() {
+            self.persist(true, now).await;
+        }
+    }
+
+    /// Report a start or a stop of the shutter motor since the last call.
+    async fn report_motion(&mut self, idx: usize) {
+        let direction = self.shutters[idx].direction();
+        let Some(state) = self.motion.observe(idx, direction) else {
+            return;
+        };
+        let message = Message::ShutterMotion {
+            shutter_idx: idx as ShutterIdx,
+            state,
+        };
+        self.board.transmit(&message, WhenFull::Wait).await;
+    }
+
+    async fn report_config(&self, shutter_idx: ShutterIdx) {
+        let timing = self.shutters[shutter_idx as usize].cfg.timing();
+        let message = Message::ShutterConfig {
+            shutter_idx,
+            rise_time: timing.rise,
+            drop_time: timing.drop,
+            tilt_time: timing.tilt,
+        };
+        self.board.transmit(&message, WhenFull::Wait).await;
+    }
+
+    async fn report_state(&self, shutter_idx: ShutterIdx, now: Instant) {
+        let shutter = &self.shutters[shutter_idx as usize];
+        let position = shutter.projected_position(now);
+        let remaining = shutter
+            .estimated_finish(now)
+            .map(|finish| finish.saturating_duration_since(now).as_secs())
+            .unwrap_or(0);
+        let arg = u32::from_le_bytes([
+            shutter_idx,
+            position.height as u8,
+            position.tilt as u8,
+            remaining.min(u8::MAX as u64) as u8,
+        ]);
+        let message = Message::Info {
+            code: args::InfoCode::ShutterState.to_bytes(),
+            arg,
+        };
+        self.board.transmit(&message, WhenFull::Wait).await;
+    }
+
+    async fn report_over_travel(&mut self, now: Instant) {
+        let message = Message::Error {
+            code: args::ErrorCode::ShutterOverTravel.to_u32(),
+        };
+        self.board.transmit(&message, WhenFull::Drop).await;
+        self.persist(true, now).await;
+    }
+
+    /// Current positions of synchronized shutters.
+    fn positions(&self) -> Positions {
+        let mut positions = Positions::default();
+        for (stored, shutter) in positions.0.iter_mut().zip(self.shutters.iter()) {
+            if shutter.in_sync {
+                // Non-negative, rounded without the std `f32::round`.
+                *stored = Some(TargetPosition::new(
+                    (shutter.position.height + 0.5) as u8,
+                    (shutter.position.tilt + 0.5) as u8,
+                ));
+            }
+        }
+        positions
+    }
+
+    /// Persist positions if changed. While moving (not `settled`) the writes
+    /// are throttled.
+    async fn persist(&mut self, settled: bool, now: Instant) {
+        let positions = self.positions();
+        if !self.persist.should_write(&positions, settled, now) {
+            return;
+        }
+        match self
+            .board
+            .store_state(StateSlot::Positions, &positions)
+            .await
+        {
+            Ok(()) => self.persist.written(positions, now),
+            Err(err) => defmt::warn!("Unable to persist shutter positions: {:?}", err),
+        }
+    }
+
+    /// Shutters with swapped outputs.
+    fn swaps(&self) -> Swaps {
+        let mut swaps = 0;
+        for (idx, shutter) in self.shutters.iter().enumerate() {
+            swaps |= (shutter.cfg.swapped as u8) << idx;
+        }
+        Swaps(swaps)
+    }
+
+    async fn persist_swaps(&self) {
+        let swaps = self.swaps();
+        if let Err(err) = self.board.store_state(StateSlot::Swaps, &swaps).await {
+            defmt::warn!("Unable to persist shutter swaps: {:?}", err);
+        }
+    }
+
+    /// Restore output swaps persisted before reboot.
+    fn restore_swaps(&mut self, swaps: Swaps) {
+        for (idx, shutter) in self.shutters.iter_mut().enumerate() {
+            shutter.cfg.set_swapped(swaps.0 & (1 << idx) != 0);
+        }
+    }
+
+    /// Restore the state persisted before reboot.
+    pub async fn load(&mut self) {
+        let positions: Positions = self.board.load_state(StateSlot::Positions).await;
+        defmt::info!("Restored shutter positions {:?}", positions);
+        self.restore(positions);
+        let swaps: Swaps = self.board.load_state(StateSlot::Swaps).await;
+        self.restore_swaps(swaps);
+    }
+
+    /// Start due group commands, enforce the caps and update the moving
+    /// shutters. Returns how long to wait for the next update.
+    pub async fn tick(&mut self, now: Instant) -> Duration {
+        // Group commands due by now, in the index order.
+        while let Some((idx, cmd)) = self.fan_out.next(now) {
+            self.apply(idx, cmd, now).await;
+        }
+
+        let mut min_duration = NOOP_UPDATE_PERIOD;
+        let mut all_sleep = true;
+        for idx in 0..self.shutters.len() {
+            if self.shutters[idx].enforce_cap(now).await {
+                self.report_motion(idx).await;
+                self.report_over_travel(now).await;
+            }
+            let duration = if self.shutters[idx].action == Action::Sleep {
+                NOOP_UPDATE_PERIOD
+            } else {
+                all_sleep = false;
+                let previous = self.before_action(idx);
+                let duration = self.shutters[idx].update(now).await;
+                self.after_action(idx, previous, now).await;
+                duration
+            };
+            if duration < min_duration {
+                min_duration = duration;
+            }
+        }
+        if !all_sleep {
+            // Recent estimate in case of a power loss mid-travel, as often
+            // as PERSIST_INTERVAL allows.
+            self.persist(false, now).await;
+        }
+        if !all_sleep && min_duration > UPDATE_PERIOD {
+            // When something is happening the minimal state-update time is
+            // UPDATE_PERIOD, not NOOP_UPDATE_PERIOD to update shutter state
+            // correctly.
+            min_duration = UPDATE_PERIOD;
+        }
+        if let Some(deadline) = self.fan_out.deadline() {
+            let wait = deadline.saturating_duration_since(now);
+            if wait < min_duration {
+                min_duration = wait;
+            }
+        }
+        min_duration
+    }
+
+    /// Handle a command from the inbox.
+    pub async fn handle(&mut self, shutter_idx: ShutterIdx, cmd: Cmd, now: Instant) {
+        defmt::info!("Shutter: cmd={:?} idx={:?}", cmd, shutter_idx);
+        match Target::from_idx(shutter_idx) {
+            Some(Target::Single(idx)) => self.apply(idx, cmd, now).await,
+            Some(Target::All) if cmd == Cmd::Stop => self.park_all(now).await,
+            Some(target) => {
+                let mut members = self.members(target);
+                if cmd.resync().is_some() {
+                    // Shutters with a known position are left alone.
+                    for (member, shutter) in members.iter_mut().zip(self.shutters.iter()) {
+                        *member &= !shutter.in_sync;
+                    }
+                }
+                self.fan_out.start(cmd, members, now);
+            }
+            None => defmt::warn!("Invalid shutter {} for {:?}", shutter_idx, cmd),
+        }
+    }
+
+    /// Stop motors running past their travel, in case an update was lost.
+    pub async fn sweep_stuck(&mut self, now: Instant) {
+        for idx in 0..self.shutters.len() {
+            if self.shutters[idx].sweep_stuck(now).await {
+                self.report_motion(idx).await;
+            }
+        }
+    }
+
+    /// Restore positions persisted before reboot.
+    fn restore(&mut self, positions: Positions) {
+        for (stored, shutter) in positions.0.iter().zip(self.shutters.iter_mut()) {
+            if let Some(stored) = stored {
+                shutter.position = stored.as_position();
+                shutter.target = stored.as_position();
+                shutter.in_sync = true;
+            }
         }
     }
 }
 
 pub type ShutterChannel = ector::DynamicAddress<(ShutterIdx, Cmd)>;
 
-impl ector::Actor for Manager {
+/// How long the executor waits for room in the manager inbox.
+const DISPATCH_TIMEOUT: Duration = Duration::from_millis(50);
+/// How long a program reload waits for the shutters to park.
+pub const PARK_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Queue a command for the manager. Waits for a short while if the inbox is
+/// full, so a busy manager can't stall the input handling. Returns false if
+/// the command was dropped.
+pub async fn dispatch(channel: &ShutterChannel, shutter_idx: ShutterIdx, cmd: Cmd) -> bool {
+    match with_timeout(DISPATCH_TIMEOUT, channel.send((shutter_idx, cmd))).await {
+        Ok(()) => true,
+        Err(_) => {
+            defmt::warn!(
+                "Shutter manager busy, dropped {:?} for shutter {}",
+                cmd,
+                shutter_idx
+            );
+            false
+        }
+    }
+}
+
+impl<B: ShutterIo + 'static> ector::Actor for Manager<B> {
     type Message = (ShutterIdx, Cmd);
 
     async fn on_mount<M>(&mut self, _: ector::DynamicAddress<Self::Message>, mut inbox: M) -> !
     where
         M: ector::Inbox<Self::Message>,
     {
+        self.load().await;
+
         loop {
-            let mut min_duration = NOOP_UPDATE_PERIOD;
-            let mut all_sleep = true;
-            for shutter in self.shutters.iter_mut() {
-                let duration = if shutter.action == Action::Sleep {
-                    NOOP_UPDATE_PERIOD
-                } else {
-                    all_sleep = false;
-                    shutter.update(Instant::now()).await
-                };
-                if duration < min_duration {
-                    min_duration = duration;
-                }
-            }
-            if !all_sleep && min_duration > UPDATE_PERIOD {
-                // When something is happening the minimal state-update time is
-                // UPDATE_PERIOD, not NOOP_UPDATE_PERIOD to update shutter state
-                // correctly.
-                min_duration = UPDATE_PERIOD;
-            }
+            let min_duration = self.tick(Instant::now()).await;
             if min_duration != NOOP_UPDATE_PERIOD {
                 defmt::info!(
                     "Will wait for {:?}ms and revisit shutters",
@@ -707,172 +1933,1063 @@ impl ector::Actor for Manager {
             let max_time_future = Timer::after(min_duration);
             match select(inbox_future, max_time_future).await {
                 Either::First((shutter_idx, cmd)) => {
-                    defmt::info!("Shutter: cmd={:?} idx={:?}", cmd, shutter_idx);
-                    let shutter = &mut self.shutters[shutter_idx as usize];
-                    shutter.command(cmd, Instant::now()).await;
+                    self.handle(shutter_idx, cmd, Instant::now()).await;
                 }
                 Either::Second(()) => {
                     // Timeout happened - Will rescan to see what needs an update.
+                    self.sweep_stuck(Instant::now()).await;
                 }
             }
         }
     }
 }
 
-// How to build only when cfg test?
-/*
-
-After simplification and migration from output queue to simply calling Board the
-tests are invalid. I'd probably need a Board mock.
-
 pub mod tests {
     use super::*;
+    use core::cell::RefCell;
+    use embassy_futures::block_on;
+    use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+    use embassy_sync::channel::Channel;
+    use static_cell::StaticCell;
+
+    /// Motor outputs recording the calls.
+    struct MockMotor {
+        calls: Channel<CriticalSectionRawMutex, (OutIdx, OutIdx, Direction), 8>,
+    }
+
+    impl MockMotor {
+        const fn new() -> Self {
+            Self {
+                calls: Channel::new(),
+            }
+        }
+
+        /// Pop the next call - it must drive the (up, down) pair.
+        fn expect(&self, up: OutIdx, down: OutIdx, direction: Direction) {
+            assert_eq!(self.calls.try_receive().ok(), Some((up, down, direction)));
+        }
+
+        fn expect_none(&self) {
+            assert_eq!(self.calls.try_receive().ok(), None);
+        }
+    }
+
+    impl MotorOutputs for MockMotor {
+        async fn set_exclusive_pair(
+            &self,
+            up: OutIdx,
+            down: OutIdx,
+            direction: Direction,
+        ) -> Result<(), OutputError> {
+            assert!(self.calls.try_send((up, down, direction)).is_ok());
+            Ok(())
+        }
+    }
 
-    pub async fn single_shutter() {
-        let channel = OutputChannel::new();
-        let mut shutter = Shutter::new(1, 2, &channel);
+    /// Board stand-in for the Manager: motor calls, sent errors, motion and
+    /// configs and the persisted state with its write count.
+    struct MockBoard {
+        motor: MockMotor,
+        errors: RefCell<heapless::Vec<u32, 8>>,
+        motion: RefCell<heapless::Vec<(ShutterIdx, Motion), 16>>,
+        configs: RefCell<heapless::Vec<(ShutterIdx, Timing), 4>>,
+        stored: RefCell<[Option<[u8; 16]>; 2]>,
+        writes: RefCell<[usize; 2]>,
+    }
 
-        // Let's assume it thinks it's synced
+    impl MockBoard {
+        fn new() -> Self {
+            Self {
+                motor: MockMotor::new(),
+                errors: RefCell::new(heapless::Vec::new()),
+                motion: RefCell::new(heapless::Vec::new()),
+                configs: RefCell::new(heapless::Vec::new()),
+                stored: RefCell::new([None; 2]),
+                writes: RefCell::new([0; 2]),
+            }
+        }
+
+        /// Persisted state, as after a reboot.
+        fn stored<T: Persist>(&self, slot: StateSlot) -> Option<T> {
+            let buf = self.stored.borrow()[slot as usize]?;
+            T::deserialize(&buf[..T::SIZE])
+        }
+
+        fn writes(&self, slot: StateSlot) -> usize {
+            self.writes.borrow()[slot as usize]
+        }
+    }
+
+    impl MotorOutputs for MockBoard {
+        async fn set_exclusive_pair(
+            &self,
+            up: OutIdx,
+            down: OutIdx,
+            direction: Direction,
+        ) -> Result<(), OutputError> {
+            self.motor.set_exclusive_pair(up, down, direction).await
+        }
+    }
+
+    impl ShutterIo for MockBoard {
+        async fn transmit(&self, message: &Message, _when_full: WhenFull) {
+            match message {
+                Message::Error { code } => self.errors.borrow_mut().push(*code).unwrap(),
+                Message::ShutterMotion { shutter_idx, state } => self
+                    .motion
+                    .borrow_mut()
+                    .push((*shutter_idx, *state))
+                    .unwrap(),
+                Message::ShutterConfig {
+                    shutter_idx,
+                    rise_time,
+                    drop_time,
+                    tilt_time,
+                } => {
+                    let timing = Timing {
+                        rise: *rise_time,
+                        drop: *drop_time,
+                        tilt: *tilt_time,
+                    };
+                    self.configs
+                        .borrow_mut()
+                        .push((*shutter_idx, timing))
+                        .unwrap()
+                }
+                _ => {}
+            }
+        }
+
+        async fn load_state<T: Persist>(&self, slot: StateSlot) -> T {
+            self.stored(slot).unwrap_or_default()
+        }
+
+        async fn store_state<T: Persist>(
+            &self,
+            slot: StateSlot,
+            value: &T,
+        ) -> Result<(), StoreError> {
+            let mut buf = [0; 16];
+            value.serialize(&mut buf[..T::SIZE]);
+            self.stored.borrow_mut()[slot as usize] = Some(buf);
+            self.writes.borrow_mut()[slot as usize] += 1;
+            Ok(())
+        }
+    }
+
+    /// Manager on a fresh MockBoard, each call site gets its own static.
+    /// Positions are restored from the board, so a board with stored
+    /// positions gives synchronized shutters.
+    macro_rules! mock_manager {
+        () => {
+            mock_manager!(MockBoard::new())
+        };
+        ($board:expr) => {{
+            static BOARD: StaticCell<MockBoard> = StaticCell::new();
+            let board: &'static MockBoard = BOARD.init($board);
+            let mut manager = Manager::new(board);
+            block_on(manager.load());
+            (board, manager)
+        }};
+    }
+
+    /// Board with shutters persisted at the given positions.
+    fn board_at(positions: &[(u8, u8)]) -> MockBoard {
+        let mut stored = Positions::default();
+        for (slot, (height, tilt)) in stored.0.iter_mut().zip(positions) {
+            *slot = Some(TargetPosition::new(*height, *tilt));
+        }
+        let board = MockBoard::new();
+        block_on(board.store_state(StateSlot::Positions, &stored)).unwrap();
+        *board.writes.borrow_mut() = [0; 2];
+        board
+    }
+
+    /// Assign outputs (up = 2 * idx + 1, down = 2 * idx + 2) to shutters.
+    fn configure(manager: &mut Manager<MockBoard>, count: usize, now: Instant) {
+        for idx in 0..count {
+            let up = 2 * idx as OutIdx + 1;
+            block_on(manager.handle(idx as ShutterIdx, Cmd::SetIO(up + 1, up), now));
+        }
+    }
+
+    pub fn single_shutter() {
+        static MOTOR: MockMotor = MockMotor::new();
+        let (up, down) = (1, 2);
+        let mut shutter = Shutter::new(up, down, &MOTOR);
+        // Let's assume it thinks it's synced and open.
         shutter.in_sync = true;
+        let tilt_time = shutter.cfg.tilt_time;
+        let drop_time = shutter.cfg.drop_time;
+
+        // It's already open - a noop.
+        let mut now = Instant::from_millis(10_000);
+        block_on(shutter.command(Cmd::Open, now));
+        assert_eq!(shutter.action, Action::Sleep);
+        MOTOR.expect_none();
 
-        defmt::info!("Initial test shutter: {:?}", shutter);
-        assert_eq!(shutter.cfg.tilt_as_time(0, 100), shutter.cfg.tilt_time);
-        assert_eq!(shutter.cfg.tilt_as_time(100, 0), shutter.cfg.tilt_time);
-        assert_eq!(shutter.cfg.tilt_as_time(50, 0), shutter.cfg.tilt_time / 2);
-        assert_eq!(shutter.cfg.tilt_as_time(0, 50), shutter.cfg.tilt_time / 2);
+        // Closing starts the down output of this shutter.
+        block_on(shutter.command(Cmd::Close, now));
+        assert_eq!(shutter.action, Action::Down(now));
+        MOTOR.expect(up, down, Direction::Down);
+        MOTOR.expect_none();
 
+        // Halfway - still moving, outputs untouched.
+        now += tilt_time + drop_time / 2;
+        block_on(shutter.update(now));
+        assert_eq!(shutter.action, Action::Down(now));
+        assert_eq!(shutter.position.tilt(), 100.0);
+        assert!((shutter.position.height() - 50.0).abs() < 1.0);
+        MOTOR.expect_none();
+
+        // Closed - stops and cools down.
+        now += drop_time / 2 + Duration::from_secs(1);
+        block_on(shutter.update(now));
+        assert_eq!(shutter.position.height(), 100.0);
+        assert_eq!(shutter.action, Action::Cooldown(now));
+        MOTOR.expect(up, down, Direction::Stop);
+        MOTOR.expect_none();
+
+        // New target during the cooldown waits for it.
+        block_on(shutter.command(Cmd::Go(TargetPosition::new(50, 50)), now));
+        assert_eq!(shutter.action, Action::Cooldown(now));
+        MOTOR.expect_none();
+
+        // Then opens with the up output.
+        now += COOLDOWN;
+        block_on(shutter.update(now));
+        assert_eq!(shutter.action, Action::Idle);
+        block_on(shutter.update(now));
+        assert_eq!(shutter.action, Action::Up(now));
+        MOTOR.expect(up, down, Direction::Up);
+        MOTOR.expect_none();
+
+        // Wires swapped: the same command drives the other output.
+        now += tilt_time;
+        block_on(shutter.command(Cmd::SwapDirection, now));
+        MOTOR.expect(up, down, Direction::Stop);
+        block_on(shutter.command(Cmd::Close, now + COOLDOWN));
+        MOTOR.expect(down, up, Direction::Down);
+        MOTOR.expect_none();
+    }
+
+    pub fn min_pulse() {
+        let cfg = Config::new(1, 2);
+        let start = Instant::from_millis(1000);
+
+        assert_eq!(cfg.pulse_remaining(start, start), cfg.min_pulse);
         assert_eq!(
-            shutter.cfg.travel_as_time(0, 100), // down
-            shutter.cfg.drop_time
+            cfg.pulse_remaining(start, start + Duration::from_millis(30)),
+            cfg.min_pulse - Duration::from_millis(30)
         );
         assert_eq!(
-            shutter.cfg.travel_as_time(100, 0), // down
-            shutter.cfg.rise_time
+            cfg.pulse_remaining(start, start + cfg.min_pulse),
+            Duration::from_secs(0)
+        );
+        // Clock before the start doesn't underflow.
+        assert_eq!(
+            cfg.pulse_remaining(start, Instant::from_millis(500)),
+            cfg.min_pulse
         );
-        assert_eq!(shutter.action, Action::Idle);
-        assert!(channel.try_receive().is_err());
 
-        // It's already up, should be a noop and no commands sent.
-        let mut now = Instant::now();
-        shutter.command(Cmd::Open, now).await;
-        assert_eq!(shutter.action, Action::Idle);
-        assert!(channel.try_receive().is_err());
+        // Smallest movements started from idle are at least a minimal pulse long.
+        assert!(cfg.tilt_as_time(0.0, cfg.hysteresis_tilt) >= cfg.min_pulse);
+        assert!(cfg.travel_as_time(0.0, cfg.hysteresis) >= cfg.min_pulse);
+
+        // Slow relay: the target is reached before the pulse ends.
+        let (board, mut manager) = mock_manager!(board_at(&[(40, 50)]));
+        let start = Instant::from_millis(10_000);
+        configure(&mut manager, 1, start);
+        manager.shutters[0].cfg.min_pulse = Duration::from_millis(500);
+        let tilt = manager.shutters[0].cfg.tilt_as_time(50.0, 70.0);
+        assert!(tilt < manager.shutters[0].cfg.min_pulse);
+        block_on(manager.handle(0, Cmd::Go(TargetPosition::new(40, 70)), start));
+        board.motor.expect(1, 2, Direction::Down);
+
+        // Held on past the target until the pulse is long enough.
+        let wait = block_on(manager.tick(start + tilt));
+        board.motor.expect_none();
+        assert_eq!(wait, Duration::from_millis(500) - tilt);
+        block_on(manager.tick(start + tilt + wait));
+        board.motor.expect(1, 2, Direction::Stop);
+        assert!(manager.shutters[0].position.tilt >= 70.0);
+
+        // Stop right after a start is deferred as well.
+        manager.shutters[0].cfg.min_pulse = cfg.min_pulse;
+        let start = start + Duration::from_secs(5);
+        block_on(manager.handle(0, Cmd::Go(TargetPosition::new(40, 100)), start));
+        board.motor.expect(1, 2, Direction::Down);
+        block_on(manager.handle(0, Cmd::Stop, start + Duration::from_millis(10)));
+        board.motor.expect(1, 2, Direction::Stop);
+        assert_eq!(
+            manager.shutters[0].action,
+            Action::Cooldown(start + cfg.min_pulse)
+        );
+    }
 
-        // Closing will make it go down.
-        shutter.command(Cmd::Close, now).await;
-        assert_eq!(shutter.action, Action::Down(now));
+    pub fn per_shutter_hysteresis() {
+        // Patio door tolerates more than a small window blind.
+        let mut door = Config::new(1, 2);
+        door.hysteresis = 8.0;
+        door.hysteresis_tilt = 20.0;
+        let mut blind = Config::new(3, 4);
+        blind.hysteresis = 2.0;
+        blind.hysteresis_tilt = 5.0;
+        assert_eq!(Config::new(5, 6).hysteresis, HYSTERESIS);
+        assert_eq!(Config::new(5, 6).hysteresis_tilt, HYSTERESIS_TILT);
+
+        // 4% off in height, 10% in tilt.
+        let position = Position::new(46, 40);
+        let target = Position::new(50, 50);
+        assert_eq!(door.off_target(&position, &target), (false, false));
+        assert_eq!(blind.off_target(&position, &target), (true, true));
+
+        // Looser one only corrects the tilt, stricter one travels as well.
+        assert!(blind.remaining_time(&position, &target) > door.remaining_time(&position, &target));
+
+        // Same command to both: the blind moves, the door is close enough.
+        let (board, mut manager) = mock_manager!(board_at(&[(40, 50); 2]));
+        let start = Instant::from_millis(10_000);
+        configure(&mut manager, 2, start);
+        for (shutter, cfg) in manager.shutters.iter_mut().zip([door, blind]) {
+            shutter.cfg.hysteresis = cfg.hysteresis;
+            shutter.cfg.hysteresis_tilt = cfg.hysteresis_tilt;
+        }
+        let target = TargetPosition::new(48, 60);
+        block_on(manager.handle(0, Cmd::Go(target), start));
+        block_on(manager.handle(1, Cmd::Go(target), start));
+        board.motor.expect(3, 4, Direction::Down);
+        board.motor.expect_none();
+
+        let later = start + UPDATE_PERIOD;
+        block_on(manager.tick(later));
+        board.motor.expect_none();
+        assert_eq!(manager.shutters[0].action, Action::Sleep);
+        assert_eq!(manager.shutters[1].action, Action::Down(later));
+        assert_eq!(
+            board.motion.borrow().as_slice(),
+            &[(1, Motion::Started(Direction::Down))]
+        );
+    }
 
-        // Cleanup output queue.
-        assert!(channel.try_receive().is_ok());
-        assert!(channel.try_receive().is_ok());
+    pub fn tiny_moves_ignored() {
+        let cfg = Config::new(1, 2);
+        assert!(cfg.min_movement > cfg.hysteresis);
+        let position = Position::new(40, 50);
+        // Real move keeps the target, small part of it is dropped.
+        let target = cfg.snap_target(&position, &Position::new(60, 52));
+        assert_eq!(target, Position::new(60, 50));
+        // Limits are always reachable.
+        let target = cfg.snap_target(&Position::new(3, 97), &Position::new(0, 100));
+        assert_eq!(target, Position::new(0, 100));
+
+        let (board, mut manager) = mock_manager!(board_at(&[(40, 50)]));
+        let start = Instant::from_millis(10_000);
+        configure(&mut manager, 1, start);
+
+        // Slider noise past the hysteresis: no motor activation.
+        let nudge = TargetPosition::new(46, 44);
+        assert_eq!(
+            cfg.off_target(&position, &nudge.as_position()),
+            (true, false)
+        );
+        block_on(manager.handle(0, Cmd::Go(nudge), start));
+        block_on(manager.tick(start + UPDATE_PERIOD));
+        board.motor.expect_none();
+        assert_eq!(manager.shutters[0].action, Action::Sleep);
+        assert_eq!(manager.shutters[0].target, position);
+
+        // Past the guard it rides.
+        let later = start + UPDATE_PERIOD * 2;
+        block_on(manager.handle(0, Cmd::Go(TargetPosition::new(48, 50)), later));
+        board.motor.expect(1, 2, Direction::Down);
+        assert_eq!(manager.shutters[0].target, Position::new(48, 50));
+    }
 
-        // Nothing should change. Time didn't pass.
-        shutter.update(now).await;
-        assert_eq!(shutter.position.tilt, 0);
-        assert_eq!(shutter.position.height, 0);
+    pub fn resync_to_closed() {
+        let cfg = Config::new(1, 2);
+        let mut slow = Config::new(3, 4);
+        slow.drop_time = Duration::from_secs(90);
 
-        now += shutter.cfg.tilt_time;
+        // Whole drop from the worst case, then the over-travel.
+        let full = cfg.tilt_time + cfg.drop_time + cfg.over_time;
+        assert_eq!(cfg.resync_time(Limit::Closed), full);
+        assert_eq!(
+            slow.resync_time(Limit::Closed),
+            slow.tilt_time + slow.drop_time + slow.over_time
+        );
+        assert_eq!(
+            cfg.resync_time(Limit::Open),
+            cfg.tilt_time + cfg.rise_time + cfg.over_time
+        );
+        // Safety cap doesn't cut it short.
+        assert!(slow.resync_time(Limit::Closed) < slow.max_energized());
+        assert_eq!(Limit::Closed.opposite().position(), Position::new(0, 0));
+
+        let mut raw = [0; 5];
+        Cmd::ResyncToClosed(true).to_raw(&mut raw);
+        assert_eq!(Cmd::from_raw(&raw), Some(Cmd::ResyncToClosed(true)));
+        assert_eq!(
+            Cmd::ResyncToClosed(true).resync(),
+            Some((Limit::Closed, true))
+        );
 
-        shutter.update(now).await;
-        assert_eq!(shutter.position.tilt, 100);
-        assert_eq!(shutter.position.height, 0);
+        // Group resync is fanned out with the stagger.
+        let mut fan_out = FanOut::new(STAGGER);
+        let start = Instant::from_millis(10_000);
+        let cmd = Cmd::resync_to(Limit::Closed, false);
+        let mut members = [false; MAX_SHUTTERS];
+        members[0] = true;
+        members[2] = true;
+        fan_out.start(cmd, members, start);
+        assert_eq!(fan_out.next(start), Some((0, cmd)));
+        assert_eq!(fan_out.next(start), None);
+        assert_eq!(fan_out.next(start + STAGGER), Some((2, cmd)));
+
+        // Sent over the bus as a shutter command to all shutters.
+        let raw = Message::ResyncAll {
+            direction: Limit::Closed,
+            restore: true,
+        }
+        .to_raw(1);
+        assert!(matches!(
+            Message::from_raw(&raw),
+            Some(Message::ResyncAll {
+                direction: Limit::Closed,
+                restore: true
+            })
+        ));
+        let raw = Message::ShutterCmd {
+            shutter_idx: 1,
+            cmd: Cmd::ResyncToOpen(false),
+        }
+        .to_raw(1);
+        assert!(matches!(
+            Message::from_raw(&raw),
+            Some(Message::ShutterCmd {
+                shutter_idx: 1,
+                cmd: Cmd::ResyncToOpen(false)
+            })
+        ));
+    }
+
+    pub fn grouped_start_stagger() {
+        let mut stagger = Stagger::new(STAGGER);
+        stagger.set_group(0, Some(1));
+        stagger.set_group(1, Some(1));
+        stagger.set_group(2, Some(2));
+        let now = Instant::from_millis(1000);
+
+        // Both commanded at the same time. First one starts right away.
+        assert_eq!(stagger.start_after(0), None);
+        stagger.started(0, now);
+
+        // Second one is queued.
+        let second = stagger.start_after(1).unwrap();
+        assert!(second > now);
+        assert!(second - now >= STAGGER);
+        stagger.started(1, second);
+
+        // Next start in the group waits for the latest one.
+        assert_eq!(stagger.start_after(0), Some(second + STAGGER));
+
+        // Other groups and ungrouped shutters are not constrained.
+        assert_eq!(stagger.start_after(2), None);
+        assert_eq!(stagger.start_after(3), None);
+        stagger.set_group(1, None);
+        assert_eq!(stagger.start_after(0), None);
+    }
 
-        // Let's wait 50% of drop time.
-        now += shutter.cfg.drop_time / 2;
+    pub fn group_fan_out() {
+        let mut fan_out = FanOut::new(STAGGER);
+        let start = Instant::from_millis(1000);
+        assert_eq!(fan_out.deadline(), None);
 
-        shutter.update(now).await;
-        assert_eq!(shutter.position.tilt, 100);
-        assert_eq!(shutter.position.height, 50);
-        assert_ne!(shutter.action, Action::Idle);
-        assert_ne!(
-            core::mem::discriminant(&shutter.action),
-            core::mem::discriminant(&Action::Cooldown(now))
+        // Group close of 1, 3 and 4 starts in index order, stagger apart.
+        let mut members = [false; MAX_SHUTTERS];
+        for idx in [4, 1, 3] {
+            members[idx] = true;
+        }
+        fan_out.start(Cmd::Close, members, start);
+        assert_eq!(fan_out.next(start), Some((1, Cmd::Close)));
+        assert_eq!(fan_out.next(start), None);
+        assert_eq!(fan_out.deadline(), Some(start + STAGGER));
+        let second = start + STAGGER;
+        assert_eq!(fan_out.next(second - Duration::from_millis(1)), None);
+        assert_eq!(fan_out.next(second), Some((3, Cmd::Close)));
+
+        // Group stop before the third one started - it never gets the close.
+        fan_out.start(Cmd::Stop, members, second);
+        assert_eq!(fan_out.next(second), Some((1, Cmd::Stop)));
+        assert_eq!(fan_out.next(second), Some((3, Cmd::Stop)));
+        assert_eq!(fan_out.next(second), Some((4, Cmd::Stop)));
+        assert_eq!(fan_out.next(second + STAGGER), None);
+        assert_eq!(fan_out.deadline(), None);
+
+        // Addressing.
+        assert_eq!(Target::from_idx(2), Some(Target::Single(2)));
+        assert_eq!(Target::from_idx(MAX_SHUTTERS as u8), None);
+        assert_eq!(Target::from_idx(GROUP_TARGET | 3), Some(Target::Group(3)));
+        assert_eq!(Target::from_idx(GROUP_TARGET), None);
+        assert_eq!(Target::from_idx(ALL_SHUTTERS), Some(Target::All));
+
+        let mut raw = [0; 5];
+        Cmd::Stop.to_raw(&mut raw);
+        assert_eq!(Cmd::from_raw(&raw), Some(Cmd::Stop));
+    }
+
+    pub fn group_close_staggered() {
+        let (board, mut manager) = mock_manager!(board_at(&[(0, 0); 3]));
+        let start = Instant::from_millis(10_000);
+        configure(&mut manager, 3, start);
+        for idx in [2, 0, 1] {
+            block_on(manager.handle(idx, Cmd::SetGroup(1), start));
+        }
+
+        // Group close starts the shutters in index order, stagger apart.
+        block_on(manager.handle(GROUP_TARGET | 1, Cmd::Close, start));
+        board.motor.expect_none();
+        assert_eq!(block_on(manager.tick(start)), STAGGER);
+        board.motor.expect(1, 2, Direction::Down);
+        board.motor.expect_none();
+        let second = start + STAGGER;
+        block_on(manager.tick(second - Duration::from_millis(1)));
+        board.motor.expect_none();
+        block_on(manager.tick(second));
+        board.motor.expect(3, 4, Direction::Down);
+        board.motor.expect_none();
+
+        // Group stop mid-sequence: the started ones stop, the third never starts.
+        let stop = second + Duration::from_millis(150);
+        block_on(manager.handle(GROUP_TARGET | 1, Cmd::Stop, stop));
+        block_on(manager.tick(stop));
+        board.motor.expect(1, 2, Direction::Stop);
+        board.motor.expect(3, 4, Direction::Stop);
+        board.motor.expect_none();
+        block_on(manager.tick(start + STAGGER * 4));
+        board.motor.expect_none();
+        assert_eq!(manager.shutters[2].action, Action::Sleep);
+        assert_eq!(
+            board.motion.borrow().as_slice(),
+            &[
+                (0, Motion::Started(Direction::Down)),
+                (1, Motion::Started(Direction::Down)),
+                (0, Motion::Stopped),
+                (1, Motion::Stopped),
+            ]
         );
-        assert!(channel.try_receive().is_err());
-
-        // Let's wait another 50% of time.
-        now += shutter.cfg.drop_time / 2;
-
-        shutter.update(now).await;
-        assert_eq!(shutter.position.tilt, 100);
-        assert_eq!(shutter.position.height, 100);
-        // Idle commands were sent.
-        assert!(channel.try_receive().is_ok());
-        assert!(channel.try_receive().is_ok());
-
-        let cooldown_start = now;
-        assert_eq!(shutter.action, Action::Cooldown(cooldown_start));
-
-        // Finish half of a cooldown period.
-        now += COOLDOWN / 2;
-        shutter.update(now).await;
-        assert_eq!(shutter.position.tilt, 100);
-        assert_eq!(shutter.position.height, 100);
-        assert!(channel.try_receive().is_err());
-        assert_eq!(shutter.action, Action::Cooldown(cooldown_start));
-
-        // Move to 50% height, but set tilt to 45deg. Still half cooldown period to go.
-        shutter.command(Cmd::Go(Position::new(50, 50)), now).await;
-        assert_eq!(shutter.action, Action::Cooldown(cooldown_start));
-
-        // Still idle after rest of cooldown.
-        now += COOLDOWN / 2;
-        shutter.update(now).await;
-        assert_eq!(shutter.action, Action::Idle);
+    }
 
-        // Will immediately start motion if Idle.
-        shutter.update(now).await;
-        assert_eq!(shutter.action, Action::Up(now));
-        assert!(channel.try_receive().is_ok());
-        assert!(channel.try_receive().is_ok());
-        assert_eq!(shutter.position.tilt, 100);
-        assert_eq!(shutter.position.height, 100);
-
-        // First we consume tilt.
-        now += shutter.cfg.tilt_time;
-        shutter.update(now).await;
-        assert_eq!(shutter.position.tilt, 0);
-        assert_eq!(shutter.position.height, 100);
-        info!("Should be tilted {:?}", shutter);
-
-        // Should reach height and switch to cooldown.
-        now += shutter.cfg.rise_time / 2;
-        shutter.update(now).await;
-        info!("Should be in the middle {:?}", shutter);
-        assert_eq!(shutter.action, Action::Cooldown(now));
-        assert!(channel.try_receive().is_ok());
-        assert!(channel.try_receive().is_ok());
+    pub fn resync_all_out_of_sync() {
+        // Shutter 0 has a persisted position, 1 and 2 were lost.
+        let (board, mut manager) = mock_manager!(board_at(&[(50, 50)]));
+        let start = Instant::from_millis(10_000);
+        configure(&mut manager, 3, start);
+        assert!(manager.shutters[0].in_sync);
+        assert!(!manager.shutters[1].in_sync);
+
+        block_on(manager.handle(ALL_SHUTTERS, Cmd::ResyncToClosed(false), start));
+        block_on(manager.tick(start));
+        board.motor.expect(3, 4, Direction::Down);
+        board.motor.expect_none();
+        block_on(manager.tick(start + STAGGER));
+        board.motor.expect(5, 6, Direction::Down);
+        board.motor.expect_none();
+
+        // Full drop plus the over-travel, regardless of the estimate.
+        let full = manager.shutters[1].cfg.resync_time(Limit::Closed);
+        let mut now = start + STAGGER;
+        while now + UPDATE_PERIOD < start + full {
+            now += UPDATE_PERIOD;
+            block_on(manager.tick(now));
+            board.motor.expect_none();
+        }
+        block_on(manager.tick(start + full));
+        board.motor.expect(3, 4, Direction::Stop);
+        block_on(manager.tick(start + STAGGER + full));
+        board.motor.expect(5, 6, Direction::Stop);
+        board.motor.expect_none();
+
+        let closed = Limit::Closed.position();
+        for idx in [1, 2] {
+            assert!(manager.shutters[idx].in_sync);
+            assert_eq!(manager.shutters[idx].position, closed);
+        }
+        // The synchronized one never moved.
+        assert_eq!(manager.shutters[0].action, Action::Sleep);
+        assert_eq!(manager.shutters[0].position, Position::new(50, 50));
+        assert_eq!(
+            board.motion.borrow().as_slice(),
+            &[
+                (1, Motion::Started(Direction::Down)),
+                (2, Motion::Started(Direction::Down)),
+                (1, Motion::Stopped),
+                (2, Motion::Stopped),
+            ]
+        );
+    }
 
-        now += COOLDOWN;
-        let time = shutter.update(now).await;
-        assert_eq!(time, Duration::from_millis(0));
-        assert_eq!(shutter.action, Action::Idle);
-        info!("Should be idle {:?}", shutter);
+    pub fn over_travel_cap() {
+        let cfg = Config::new(1, 2);
+        let start = Instant::from_millis(1000);
+
+        // Longest legit motion: full ride with tilt and over-travel.
+        let ride = cfg.rise_time.max(cfg.drop_time) + cfg.over_time;
+        assert!(!cfg.over_travel(start, start + cfg.tilt_time + ride));
+
+        // Stuck in motion - the motor is cut at the cap.
+        let cap = cfg.max_energized();
+        assert_eq!(cap, ride + OVER_TRAVEL_MARGIN);
+        assert!(!cfg.over_travel(start, start + cap));
+        assert!(cfg.over_travel(start, start + cap + Duration::from_millis(1)));
+
+        // Misconfigured over_time still yields a finite cap.
+        let mut cfg = Config::new(1, 2);
+        cfg.over_time = Duration::from_secs(600);
+        assert!(cfg.over_travel(start, start + Duration::from_secs(3600)));
+
+        // Manager with a shutter left energized, the first tick is past the
+        // cap. The cap is checked before the state machine is updated.
+        let (board, mut manager) = mock_manager!(board_at(&[(100, 100)]));
+        configure(&mut manager, 1, start);
+        block_on(manager.handle(0, Cmd::Open, start));
+        board.motor.expect(1, 2, Direction::Up);
+        assert!(board.errors.borrow().is_empty());
+
+        let late = start + cap + Duration::from_millis(1);
+        block_on(manager.tick(late));
+        board.motor.expect(1, 2, Direction::Stop);
+        assert_eq!(manager.shutters[0].action, Action::Cooldown(late));
+        assert!(!manager.shutters[0].in_sync);
+        assert_eq!(
+            board.errors.borrow().as_slice(),
+            &[args::ErrorCode::ShutterOverTravel.to_u32()]
+        );
+        assert_eq!(
+            board.motion.borrow().as_slice(),
+            &[(0, Motion::Started(Direction::Up)), (0, Motion::Stopped)]
+        );
+        // Position is unknown, so isn't restored after a reboot.
+        let stored: Positions = board.stored(StateSlot::Positions).unwrap();
+        assert_eq!(stored.0[0], None);
+    }
 
-        // Immediately after cooldown, should go down to close tilt.
-        let start_time = shutter.update(now).await;
-        assert_eq!(start_time, Duration::from_millis(0));
-        assert_eq!(shutter.action, Action::Down(now));
-        assert!(channel.try_receive().is_ok());
-        assert!(channel.try_receive().is_ok());
-
-        // Next update (same moment) has proper time.
-        let time = shutter.update(now).await;
-        assert_eq!(time, shutter.cfg.tilt_time / 2);
-
-        now += time;
-        let time = shutter.update(now).await;
-        assert!(channel.try_receive().is_ok());
-        assert!(channel.try_receive().is_ok());
-        assert_eq!(time, COOLDOWN);
-        assert_eq!(shutter.action, Action::Cooldown(now));
-        assert_eq!(shutter.position.tilt, 50);
-        assert_eq!(shutter.position.height, 50);
+    pub fn config_round_trip() {
+        let (board, mut manager) = mock_manager!();
+        let start = Instant::from_millis(10_000);
+        configure(&mut manager, 3, start);
+
+        // Set rise time remotely.
+        let raw = Message::SetShutterConfig {
+            shutter_idx: 2,
+            rise_time: 452,
+            drop_time: 449,
+            tilt_time: 15,
+        }
+        .to_raw(1);
+        let Some(Message::SetShutterConfig {
+            shutter_idx: 2,
+            rise_time,
+            drop_time,
+            tilt_time,
+        }) = Message::from_raw(&raw)
+        else {
+            panic!("SetShutterConfig not decoded");
+        };
 
-        now += COOLDOWN;
-        shutter.update(now).await;
-        assert_eq!(shutter.action, Action::Idle);
-        assert!(channel.try_receive().is_err());
+        // Goes to the manager as commands, through their raw form.
+        let mut cmd_raw = [0; 5];
+        for cmd in [
+            Cmd::SetRiseDropTime(rise_time, drop_time),
+            Cmd::SetTiltTime(tilt_time),
+        ] {
+            cmd.to_raw(&mut cmd_raw);
+            let cmd = Cmd::from_raw(&cmd_raw).unwrap();
+            block_on(manager.handle(2, cmd, start));
+        }
+        assert_eq!(
+            manager.shutters[2].cfg.rise_time,
+            Duration::from_millis(45200)
+        );
+        assert_eq!(
+            manager.shutters[1].cfg.rise_time,
+            Config::new(1, 2).rise_time
+        );
+        board.motor.expect_none();
+
+        // Get it back.
+        block_on(manager.handle(2, Cmd::RequestConfig, start));
+        let timing = Timing {
+            rise: 452,
+            drop: 449,
+            tilt: 15,
+        };
+        assert_eq!(board.configs.borrow().as_slice(), &[(2, timing)]);
+        let raw = Message::ShutterConfig {
+            shutter_idx: 2,
+            rise_time: timing.rise,
+            drop_time: timing.drop,
+            tilt_time: timing.tilt,
+        }
+        .to_raw(1);
+        assert!(matches!(
+            Message::from_raw(&raw),
+            Some(Message::ShutterConfig {
+                shutter_idx: 2,
+                rise_time: 452,
+                drop_time: 449,
+                tilt_time: 15,
+            })
+        ));
+
+        // Zero times are rejected.
+        let invalid = Timing { rise: 0, ..timing };
+        assert_eq!(
+            manager.shutters[2].cfg.set_timing(invalid),
+            Err(TimingError::Zero)
+        );
+        block_on(manager.handle(2, Cmd::SetRiseDropTime(0, 449), start));
+        assert_eq!(manager.shutters[2].cfg.timing(), timing);
+        let raw = Message::SetShutterConfig {
+            shutter_idx: 2,
+            rise_time: 0,
+            drop_time: 449,
+            tilt_time: 15,
+        }
+        .to_raw(1);
+        assert!(Message::from_raw(&raw).is_none());
+    }
+
+    pub fn projected_halfway() {
+        let cfg = Config::new(1, 2);
+        let start = Instant::from_millis(10_000);
+
+        // Already tilted down, so the whole time goes into the drop.
+        let position = Position::new(0, 100);
+        let target = Position::new(100, 100);
+        let action = Action::Down(start);
+        let halfway = start + cfg.drop_time / 2;
+        let projected = cfg.project(&position, &target, &action, halfway);
+        assert!((projected.height() - 50.0).abs() < 1.0);
+        assert_eq!(projected.tilt(), 100.0);
+        assert_eq!(cfg.remaining_time(&position, &target), cfg.drop_time);
+        let remaining = cfg.remaining_time(&projected, &target).as_millis();
+        assert!(remaining.abs_diff(cfg.drop_time.as_millis() / 2) <= 1);
+
+        // Open shutter tilts first.
+        let open = Position::new(0, 0);
+        let projected = cfg.project(&open, &target, &action, start + cfg.tilt_time);
+        assert_eq!(projected.height(), 0.0);
+        assert_eq!(projected.tilt(), 100.0);
+
+        // Movement stops at the target.
+        let target = Position::new(60, 100);
+        let late = start + cfg.drop_time;
+        assert_eq!(cfg.project(&position, &target, &action, late), target);
+
+        // Not moving - nothing changes.
+        let projected = cfg.project(&position, &target, &Action::Sleep, late);
+        assert_eq!(projected, position);
+    }
+
+    pub fn park_mid_drop() {
+        static PARKED: ParkSignal = ParkSignal::new();
+        let (board, manager) = mock_manager!(board_at(&[(0, 100), (0, 0)]));
+        let mut manager = manager.with_parked(&PARKED);
+        let start = Instant::from_millis(10_000);
+        configure(&mut manager, 2, start);
+        let drop_time = manager.shutters[0].cfg.drop_time;
+
+        // Tilted already, so the whole time goes into the drop.
+        block_on(manager.handle(0, Cmd::Close, start));
+        board.motor.expect(1, 2, Direction::Down);
+        let halfway = start + drop_time / 2;
+        block_on(manager.tick(halfway));
+        board.motor.expect_none();
+        let writes = board.writes(StateSlot::Positions);
+
+        // Mid-drop: the motor is cut and the interpolated position persisted.
+        block_on(manager.handle(ALL_SHUTTERS, Cmd::Stop, halfway));
+        board.motor.expect(1, 2, Direction::Stop);
+        board.motor.expect_none();
+        assert!(PARKED.signaled());
+        let positions: Positions = board.stored(StateSlot::Positions).unwrap();
+        assert_eq!(positions.0[0], Some(TargetPosition::new(50, 100)));
+        assert_eq!(positions.0[1], Some(TargetPosition::new(0, 0)));
+        assert_eq!(board.writes(StateSlot::Positions), writes + 1);
+        assert_eq!(
+            board.motion.borrow().as_slice(),
+            &[(0, Motion::Started(Direction::Down)), (0, Motion::Stopped)]
+        );
+
+        // Parked shutter stays where it is.
+        let later = halfway + drop_time;
+        block_on(manager.tick(later));
+        board.motor.expect_none();
+        assert!((manager.shutters[0].position.height() - 50.0).abs() < 1.0);
+
+        // Parking again is a no-op, but still signalled.
+        PARKED.reset();
+        block_on(manager.park_all(later));
+        board.motor.expect_none();
+        assert!(PARKED.signaled());
+        assert_eq!(board.writes(StateSlot::Positions), writes + 1);
+
+        // Pending group starts are dropped.
+        block_on(manager.handle(0, Cmd::SetGroup(1), later));
+        block_on(manager.handle(1, Cmd::SetGroup(1), later));
+        block_on(manager.handle(GROUP_TARGET | 1, Cmd::Open, later));
+        block_on(manager.tick(later));
+        board.motor.expect(1, 2, Direction::Up);
+        block_on(manager.park_all(later));
+        board.motor.expect(1, 2, Direction::Stop);
+        block_on(manager.tick(later + STAGGER * 2));
+        board.motor.expect_none();
+    }
+
+    pub fn persist_throttled() {
+        let cfg = Config::new(1, 2);
+        let start = Instant::from_millis(10_000);
+        let open = Position::new(0, 0);
+        let closed = Position::new(100, 100);
+        let finish = start + cfg.tilt_time + cfg.drop_time;
+        let at = |now| {
+            let pos = cfg.project(&open, &closed, &Action::Down(start), now);
+            let mut positions = Positions::default();
+            positions.0[0] = Some(TargetPosition::new(
+                (pos.height() + 0.5) as u8,
+                (pos.tilt() + 0.5) as u8,
+            ));
+            positions
+        };
+
+        // Flash-like: a full travel ticking every update is written once.
+        let mut throttle = PersistThrottle::new(Some(Duration::from_secs(600)));
+        let mut writes = 0;
+        let mut now = start;
+        while now < finish {
+            if throttle.should_write(&at(now), false, now) {
+                throttle.written(at(now), now);
+                writes += 1;
+            }
+            now += UPDATE_PERIOD;
+        }
+        assert_eq!(writes, 0);
+        assert!(throttle.should_write(&at(finish), true, finish));
+        throttle.written(at(finish), finish);
+        // Settling again without a change doesn't write.
+        let later = finish + COOLDOWN;
+        assert!(!throttle.should_write(&at(finish), true, later));
+
+        // Only when stopped.
+        let mut throttle = PersistThrottle::new(None);
+        let late = start + Duration::from_secs(3600);
+        assert!(!throttle.should_write(&at(start), false, start));
+        assert!(!throttle.should_write(&at(finish), false, late));
+
+        // Interval counts from the start of a travel, not the last settle.
+        let interval = Duration::from_secs(10);
+        let mut throttle = PersistThrottle::new(Some(interval));
+        assert!(throttle.should_write(&at(start), true, start));
+        throttle.written(at(start), start);
+        let moving = start + interval * 2;
+        assert!(!throttle.should_write(&at(moving), false, moving));
+        assert!(throttle.should_write(&at(moving + interval), false, moving + interval));
+        throttle.written(at(moving + interval), moving + interval);
+        let next = moving + interval + UPDATE_PERIOD;
+        assert!(!throttle.should_write(&at(next), false, next));
+
+        // Full travel through the manager: a single write once it settles.
+        let (board, mut manager) = mock_manager!(board_at(&[(0, 0)]));
+        configure(&mut manager, 1, start);
+        block_on(manager.handle(0, Cmd::Close, start));
+        board.motor.expect(1, 2, Direction::Down);
+        let mut now = start;
+        while manager.shutters[0].action != Action::Sleep {
+            now += block_on(manager.tick(now)).max(Duration::from_millis(1));
+            assert!(now < finish + Duration::from_secs(60));
+        }
+        board.motor.expect(1, 2, Direction::Stop);
+        assert_eq!(board.writes(StateSlot::Positions), 1);
+        let positions: Positions = board.stored(StateSlot::Positions).unwrap();
+        assert_eq!(positions.0[0], Some(TargetPosition::new(100, 100)));
+    }
+
+    pub fn swap_direction() {
+        // Program assigns down=2, up=1, but the motor wires are swapped.
+        let mut cfg = Config::new(OutIdx::MAX, OutIdx::MAX);
+        cfg.set_io(2, 1);
+        let wired_down = cfg.down;
+        cfg.set_swapped(true);
+        // Open now drives the output that was `down`.
+        assert_eq!(cfg.up, wired_down);
+        assert_eq!(cfg.down, 1);
+        // Reloaded program keeps the swap.
+        cfg.set_io(2, 1);
+        assert_eq!((cfg.down, cfg.up), (1, 2));
+        cfg.set_swapped(true);
+        assert_eq!((cfg.down, cfg.up), (1, 2));
+
+        // Persisted as a bit per shutter.
+        let swaps = Swaps(0b0000_0100);
+        let mut buf = [0; Swaps::SIZE];
+        swaps.serialize(&mut buf);
+        assert_eq!(Swaps::deserialize(&buf), Some(swaps));
+
+        let raw = Message::SwapShutterDirection { shutter_idx: 2 }.to_raw(3);
+        assert!(matches!(
+            Message::from_raw(&raw),
+            Some(Message::SwapShutterDirection { shutter_idx: 2 })
+        ));
+
+        // Swap through the manager is persisted...
+        let (board, mut manager) = mock_manager!();
+        let start = Instant::from_millis(10_000);
+        configure(&mut manager, 3, start);
+        block_on(manager.handle(2, Cmd::SwapDirection, start));
+        assert_eq!(board.stored(StateSlot::Swaps), Some(Swaps(0b0000_0100)));
+        assert_eq!(board.writes(StateSlot::Swaps), 1);
+
+        // ...and restored after a reboot, before the program sets the outputs.
+        let rebooted = MockBoard::new();
+        *rebooted.stored.borrow_mut() = *board.stored.borrow();
+        let (rebooted, mut manager) = mock_manager!(rebooted);
+        configure(&mut manager, 3, start);
+        assert!(manager.shutters[2].cfg.swapped);
+        assert!(!manager.shutters[1].cfg.swapped);
+        block_on(manager.handle(2, Cmd::Open, start));
+        rebooted.motor.expect(6, 5, Direction::Up);
+
+        // Swapping back clears the bit.
+        let stop = start + UPDATE_PERIOD;
+        block_on(manager.handle(2, Cmd::Stop, stop));
+        rebooted.motor.expect(6, 5, Direction::Stop);
+        block_on(manager.handle(2, Cmd::SwapDirection, stop + COOLDOWN));
+        assert_eq!(rebooted.stored(StateSlot::Swaps), Some(Swaps(0)));
+    }
+
+    pub fn positions_serialization() {
+        let mut positions = Positions::default();
+        positions.0[0] = Some(TargetPosition::new(100, 0));
+        positions.0[3] = Some(TargetPosition::new(40, 55));
+
+        let mut buf = [0; Positions::SIZE];
+        positions.serialize(&mut buf);
+        assert_eq!(Positions::deserialize(&buf), Some(positions));
+
+        // Out of range values are treated as unknown.
+        buf[0] = 101;
+        let decoded = Positions::deserialize(&buf).unwrap();
+        assert_eq!(decoded.0[0], None);
+        assert_eq!(decoded.0[3], positions.0[3]);
+    }
+
+    pub fn dispatch_reaches_manager() {
+        use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+        use embassy_sync::channel::Channel;
+
+        // Stands for the manager inbox.
+        static INBOX: Channel<CriticalSectionRawMutex, (ShutterIdx, Cmd), 2> = Channel::new();
+        let address: ShutterChannel = INBOX.sender().into();
+
+        assert!(embassy_futures::block_on(dispatch(&address, 1, Cmd::Close)));
+        assert!(embassy_futures::block_on(dispatch(&address, 3, Cmd::Open)));
+
+        // Full inbox drops the command instead of blocking.
+        let start = Instant::now();
+        assert!(!embassy_futures::block_on(dispatch(&address, 2, Cmd::Open)));
+        assert!(start.elapsed() >= DISPATCH_TIMEOUT);
+
+        assert_eq!(INBOX.try_receive(), Ok((1, Cmd::Close)));
+        assert_eq!(INBOX.try_receive(), Ok((3, Cmd::Open)));
+        assert!(INBOX.try_receive().is_err());
+    }
+
+    pub fn stuck_shutter_swept() {
+        let (board, mut manager) = mock_manager!(board_at(&[(100, 100)]));
+        let start = Instant::from_millis(10_000);
+        configure(&mut manager, 1, start);
+
+        // Opening, then the state machine is never updated (lost Stop).
+        block_on(manager.handle(0, Cmd::Open, start));
+        assert_eq!(manager.shutters[0].action, Action::Up(start));
+        board.motor.expect(1, 2, Direction::Up);
+
+        let cfg = &manager.shutters[0].cfg;
+        let limit = cfg.max_travel(Limit::Open);
+        assert_eq!(
+            limit,
+            cfg.tilt_time + cfg.rise_time + cfg.over_time + cfg.stuck_margin
+        );
+        // Timer branch of the manager loop, no commands arriving.
+        block_on(manager.sweep_stuck(start + limit));
+        board.motor.expect_none();
+
+        // Past its travel it's stopped, before the over-travel cap.
+        let late = start + limit + Duration::from_millis(1);
+        assert!(!manager.shutters[0].cfg.over_travel(start, late));
+        block_on(manager.sweep_stuck(late));
+        assert_eq!(manager.shutters[0].action, Action::Cooldown(late));
+        assert!(!manager.shutters[0].in_sync);
+        board.motor.expect(1, 2, Direction::Stop);
+        assert_eq!(
+            board.motion.borrow().as_slice(),
+            &[(0, Motion::Started(Direction::Up)), (0, Motion::Stopped)]
+        );
+
+        // Idle shutters are left alone.
+        block_on(manager.sweep_stuck(late + limit * 2));
+        board.motor.expect_none();
+        assert_eq!(board.motion.borrow().len(), 2);
+    }
+
+    pub fn motion_events_full_open() {
+        let (board, mut manager) = mock_manager!(board_at(&[(100, 100)]));
+        let start = Instant::from_millis(10_000);
+        configure(&mut manager, 1, start);
+
+        block_on(manager.handle(0, Cmd::Open, start));
+        // Ride the whole way up, through cooldown and asleep.
+        let mut now = start;
+        for _ in 0..200 {
+            if manager.shutters[0].action == Action::Sleep {
+                break;
+            }
+            now += UPDATE_PERIOD;
+            block_on(manager.tick(now));
+        }
+        assert_eq!(manager.shutters[0].action, Action::Sleep);
+        assert_eq!(manager.shutters[0].position.height(), 0.0);
+
+        assert_eq!(
+            board.motion.borrow().as_slice(),
+            &[(0, Motion::Started(Direction::Up)), (0, Motion::Stopped)]
+        );
+        board.motor.expect(1, 2, Direction::Up);
+        board.motor.expect(1, 2, Direction::Stop);
+        board.motor.expect_none();
+
+        // Sent as an info, decoded for the endpoints.
+        let message = Message::ShutterMotion {
+            shutter_idx: 3,
+            state: Motion::Started(Direction::Down),
+        };
+        let raw = message.to_raw(5);
+        assert_eq!(
+            raw.shutter_motion(),
+            Some((3, Motion::Started(Direction::Down)))
+        );
+        assert!(matches!(
+            Message::from_raw(&raw),
+            Some(Message::ShutterMotion {
+                shutter_idx: 3,
+                state: Motion::Started(Direction::Down),
+            })
+        ));
+        let started = Message::Info {
+            code: args::InfoCode::Started.to_bytes(),
+            arg: 0,
+        };
+        assert!(Message::from_raw(&started.to_raw(5)).is_none());
     }
 }
-*/
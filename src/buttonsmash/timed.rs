@@ -0,0 +1,114 @@
+/*
+ * Timed outputs: output is turned on and automatically turned off after a
+ * given time (stairwell lights). Activating it again restarts the timer.
+ */
+use embassy_time::{Duration, Instant};
+use heapless::Vec;
+
+use super::consts::OutIdx;
+
+/// Max number of timed outputs running at the same time.
+pub const MAX_TIMED: usize = 4;
+
+/// Time of a timed activation, given in deciseconds (up to 25.5s).
+pub const fn deciseconds(time: u8) -> Duration {
+    Duration::from_millis(time as u64 * 100)
+}
+
+/// Tracks timed outputs that are currently on.
+pub struct TimedOutputs {
+    /// (output, when to turn it off)
+    active: [Option<(OutIdx, Instant)>; MAX_TIMED],
+}
+
+impl Default for TimedOutputs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TimedOutputs {
+    pub const fn new() -> Self {
+        Self {
+            active: [None; MAX_TIMED],
+        }
+    }
+
+    /// Schedule turning the output off. Returns false if there's no free
+    /// slot - caller should not activate the output then.
+    pub fn start(&mut self, out_idx: OutIdx, time: Duration, now: Instant) -> bool {
+        let off_at = now + time;
+        // Retrigger restarts the timer.
+        if let Some(entry) = self
+            .active
+            .iter_mut()
+            .flatten()
+            .find(|(o, _)| *o == out_idx)
+        {
+            entry.1 = off_at;
+            return true;
+        }
+        if let Some(slot) = self.active.iter_mut().find(|slot| slot.is_none()) {
+            *slot = Some((out_idx, off_at));
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Outputs whose time is up. Returns outputs to turn off.
+    pub fn expired(&mut self, now: Instant) -> Vec<OutIdx, MAX_TIMED> {
+        let mut outputs = Vec::new();
+        for slot in self.active.iter_mut() {
+            if let Some((o, off_at)) = *slot
+                && now >= off_at
+            {
+                let _ = outputs.push(o);
+                *slot = None;
+            }
+        }
+        outputs
+    }
+
+    /// When the earliest timed output should be turned off.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.active
+            .iter()
+            .flatten()
+            .map(|(_, off_at)| *off_at)
+            .min()
+    }
+}
+
+pub mod tests {
+    use super::*;
+
+    pub fn on_then_off_after_time() {
+        let mut timed = TimedOutputs::new();
+        let start = Instant::from_millis(1000);
+        assert_eq!(timed.next_deadline(), None);
+
+        // ActivateFor(5, 30) - on for 3s.
+        let time = deciseconds(30);
+        assert_eq!(time, Duration::from_secs(3));
+        assert!(timed.start(5, time, start));
+        assert_eq!(timed.next_deadline(), Some(start + time));
+        let almost = start + time - Duration::from_millis(1);
+        assert!(timed.expired(almost).is_empty());
+        assert_eq!(timed.expired(start + time).as_slice(), &[5]);
+        assert_eq!(timed.next_deadline(), None);
+
+        // Retrigger extends the time.
+        assert!(timed.start(5, time, start));
+        let later = start + Duration::from_secs(2);
+        assert!(timed.start(5, time, later));
+        assert!(timed.expired(start + time).is_empty());
+        assert_eq!(timed.expired(later + time).as_slice(), &[5]);
+
+        // Limited number of slots.
+        for out in 0..MAX_TIMED as u8 {
+            assert!(timed.start(out, time, start));
+        }
+        assert!(!timed.start(50, time, start));
+    }
+}
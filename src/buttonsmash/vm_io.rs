@@ -0,0 +1,66 @@
+/*
+ * Everything the Executor touches outside of itself: outputs, scenes, the
+ * bus and the event source. The board implements it on the device, tests
+ * use mocks so whole programs can run without the hardware.
+ */
+use heapless::Vec;
+
+use super::consts::{Event, EventChannel, OutIdx, SceneIdx};
+use super::scenes::Scene;
+use crate::components::message::Message;
+use crate::components::queue::WhenFull;
+use crate::io::events::{IoIdx, OutputError};
+
+/// State of an input expander as reported in the status.
+#[derive(Copy, Clone)]
+pub struct ExpanderState {
+    pub id: u8,
+    pub indices: [IoIdx; 16],
+    /// None when the expander doesn't respond.
+    pub inputs: Option<[(IoIdx, bool); 16]>,
+}
+
+/// Outputs, persistent state and the bus used by the Executor.
+#[allow(async_fn_in_trait)]
+pub trait VmIo {
+    /// Output indices with their states.
+    type OutputStatus: AsRef<[(OutIdx, bool)]>;
+
+    async fn toggle_output(&self, out: OutIdx) -> Result<bool, OutputError>;
+    async fn set_output(&self, out: OutIdx, state: bool) -> Result<(), OutputError>;
+    /// Set several outputs at once, the ones sharing an expander in a
+    /// single write.
+    async fn set_outputs(&self, changes: &[(OutIdx, bool)]) -> Result<(), OutputError>;
+    /// Dim an output to 0-100%. Outputs which can't be dimmed are on for
+    /// any brightness above zero.
+    async fn set_brightness(&self, out: OutIdx, percent: u8) -> Result<(), OutputError>;
+    /// Returns the new group state and its members.
+    async fn toggle_group(&self, id: u8) -> Result<(bool, Vec<OutIdx, 16>), OutputError>;
+    /// Outputs of a group, None for an unknown group.
+    async fn group_members(&self, id: u8) -> Option<Vec<OutIdx, 16>>;
+    async fn get_output(&self, out: OutIdx) -> Option<bool>;
+    async fn get_output_status(&self) -> Self::OutputStatus;
+
+    async fn store_scene(&self, slot: SceneIdx, scene: Scene) -> Result<(), ()>;
+    async fn load_scene(&self, slot: SceneIdx) -> Option<Scene>;
+
+    /// Send a message from this node. False if it was dropped.
+    async fn transmit(&self, message: &Message, when_full: WhenFull) -> bool;
+
+    fn set_maintenance(&self, enabled: bool);
+    fn uptime_secs(&self) -> u32;
+    /// Switches expander first, then sensors.
+    fn input_expanders(&self) -> [ExpanderState; 2];
+}
+
+/// Source of the Executor events.
+#[allow(async_fn_in_trait)]
+pub trait EventSource {
+    async fn receive(&self) -> Event;
+}
+
+impl EventSource for EventChannel {
+    async fn receive(&self) -> Event {
+        EventChannel::receive(self).await
+    }
+}
@@ -0,0 +1,95 @@
+/*
+ * Crash loop detection. A buggy program can panic shortly after every boot
+ * and flap the outputs each time. Every boot is counted in an RTC backup
+ * register early and the counter is cleared once the node runs for a while.
+ * When too many boots in a row didn't get that far, the node boots into safe
+ * mode: no executor, all outputs off, and only the recovery command
+ * (ResetRuntime) is handled - it clears the counter and reboots.
+ */
+use crate::config::SafeModePolicy;
+
+/// Marks an initialized counter in the upper half of the register.
+const MAGIC: u32 = 0xB007_0000;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, defmt::Format)]
+pub enum BootMode {
+    Normal,
+    /// Crash loop detected.
+    Safe,
+}
+
+/// Boots that didn't reach a stable uptime, persisted across reboots.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, defmt::Format)]
+pub struct BootFaults {
+    count: u8,
+}
+
+impl BootFaults {
+    /// Blank or garbage register reads as no faults.
+    pub fn from_backup(raw: Option<u32>) -> Self {
+        match raw {
+            Some(raw) if raw & 0xFFFF_FF00 == MAGIC => Self { count: raw as u8 },
+            _ => Self::default(),
+        }
+    }
+
+    pub fn to_backup(self) -> u32 {
+        MAGIC | self.count as u32
+    }
+
+    pub fn count(&self) -> u8 {
+        self.count
+    }
+
+    /// Count a starting boot and decide how to run it.
+    pub fn boot(&mut self, policy: &SafeModePolicy) -> BootMode {
+        let mode = if self.count >= policy.max_faults {
+            BootMode::Safe
+        } else {
+            BootMode::Normal
+        };
+        self.count = self.count.saturating_add(1);
+        mode
+    }
+
+    /// Boot was stable or the fault was cleared by the recovery command.
+    pub fn clear(&mut self) {
+        self.count = 0;
+    }
+}
+
+pub mod tests {
+    use super::*;
+
+    pub fn crash_loop_enters_safe_mode() {
+        let policy = SafeModePolicy {
+            max_faults: 3,
+            stable_s: 60,
+        };
+
+        // Blank register.
+        let mut faults = BootFaults::from_backup(None);
+        assert_eq!(faults.count(), 0);
+        assert_eq!(BootFaults::from_backup(Some(0x1234_5678)), faults);
+
+        // Three boots crash before the stable uptime - each reboot reads the
+        // counter back from the register.
+        for _ in 0..policy.max_faults {
+            assert_eq!(faults.boot(&policy), BootMode::Normal);
+            faults = BootFaults::from_backup(Some(faults.to_backup()));
+        }
+        assert_eq!(faults.boot(&policy), BootMode::Safe);
+        // Safe mode doesn't clear itself.
+        assert_eq!(faults.boot(&policy), BootMode::Safe);
+
+        // Recovery command.
+        faults.clear();
+        assert_eq!(faults.boot(&policy), BootMode::Normal);
+
+        // Stable boots never accumulate.
+        for _ in 0..10 {
+            assert_eq!(faults.boot(&policy), BootMode::Normal);
+            faults.clear();
+        }
+    }
+}
@@ -0,0 +1,158 @@
+/*
+ * Gate-side filter of frames forwarded to USB. The gate forwards the whole
+ * bus by default, a host tool interested in a few message types can set an
+ * allowlist of (addr, msg_type) pairs instead. Frames coming from the host
+ * are not filtered.
+ *
+ * The allowlist is set with a USB packet that's not forwarded to the bus:
+ * [GATE_COMMAND] [FILTER_SET] [length] [addr, msg_type]*
+ * ANY matches all addresses or types. Empty list forwards everything.
+ * Other gate commands are listed with their handlers (eg. state_cache).
+ */
+use core::cell::RefCell;
+
+use embassy_sync::blocking_mutex::CriticalSectionMutex;
+use heapless::Vec;
+
+/// First byte of a USB packet handled by the gate. Bus addresses are 6-bit.
+pub const GATE_COMMAND: u8 = 0x80;
+/// Gate command replacing the allowlist.
+pub const FILTER_SET: u8 = 0x01;
+/// Wildcard address or message type.
+pub const ANY: u8 = 0xFF;
+/// Max number of allowlist entries.
+pub const MAX_RULES: usize = 16;
+
+/// Gate command rejected, the allowlist is kept.
+#[derive(Debug, Eq, PartialEq, Clone, Copy, defmt::Format)]
+pub enum FilterError {
+    /// Allowlist of an odd number of bytes - not (addr, msg_type) pairs.
+    OddLength,
+    /// More than MAX_RULES pairs.
+    TooManyRules,
+    /// Packet shorter than its length byte.
+    Truncated,
+    /// Gate command we don't know.
+    UnknownCommand(u8),
+}
+
+#[derive(Default)]
+pub struct BusFilter {
+    /// Allowed (addr, msg_type). Empty - everything passes.
+    rules: Vec<(u8, u8), MAX_RULES>,
+}
+
+impl BusFilter {
+    pub const fn new() -> Self {
+        Self { rules: Vec::new() }
+    }
+
+    /// Replace the allowlist with pairs of (addr, msg_type) bytes. Rejects an
+    /// odd length or too many pairs and keeps the old list.
+    pub fn set(&mut self, pairs: &[u8]) -> Result<(), FilterError> {
+        if !pairs.len().is_multiple_of(2) {
+            return Err(FilterError::OddLength);
+        }
+        if pairs.len() / 2 > MAX_RULES {
+            return Err(FilterError::TooManyRules);
+        }
+        self.rules = pairs
+            .chunks_exact(2)
+            .map(|pair| (pair[0], pair[1]))
+            .collect();
+        Ok(())
+    }
+
+    /// Should a frame be forwarded to the host?
+    pub fn accepts(&self, addr: u8, msg_type: u8) -> bool {
+        self.rules.is_empty()
+            || self.rules.iter().any(|(rule_addr, rule_type)| {
+                (*rule_addr == ANY || *rule_addr == addr)
+                    && (*rule_type == ANY || *rule_type == msg_type)
+            })
+    }
+
+    /// Handle a gate command packet: [GATE_COMMAND, command, length, body].
+    /// Returns None if it's a frame for the bus.
+    pub fn handle_command(&mut self, packet: &[u8]) -> Option<Result<(), FilterError>> {
+        let [GATE_COMMAND, command, length, body @ ..] = packet else {
+            return None;
+        };
+        let Some(body) = body.get(..*length as usize) else {
+            return Some(Err(FilterError::Truncated));
+        };
+        Some(match *command {
+            FILTER_SET => self.set(body),
+            command => Err(FilterError::UnknownCommand(command)),
+        })
+    }
+}
+
+/// Filter consulted on the gate forward path.
+pub static USB_FILTER: CriticalSectionMutex<RefCell<BusFilter>> =
+    CriticalSectionMutex::new(RefCell::new(BusFilter::new()));
+
+pub mod tests {
+    use super::*;
+    use crate::components::message::{Message, MessageRaw, args, msg_type};
+
+    pub fn allowlist_forwarding() {
+        let error = Message::Error {
+            code: args::ErrorCode::CanBusOff.to_u32(),
+        }
+        .to_raw(5);
+        let status = Message::Status {
+            uptime: 10,
+            errors: 0,
+            warnings: 0,
+        }
+        .to_raw(5);
+        let forwarded = |filter: &BusFilter, raw: &MessageRaw| {
+            let (addr, msg_type) = raw.addr_type();
+            filter.accepts(addr, msg_type)
+        };
+
+        // Everything passes by default.
+        let mut filter = BusFilter::new();
+        assert!(forwarded(&filter, &status));
+        assert!(forwarded(&filter, &error));
+
+        // Only errors, from any node.
+        let packet = [GATE_COMMAND, FILTER_SET, 2, ANY, msg_type::ERROR];
+        assert_eq!(filter.handle_command(&packet), Some(Ok(())));
+        assert!(!forwarded(&filter, &status));
+        assert!(forwarded(&filter, &error));
+
+        // Node specific rule.
+        assert!(filter.set(&[7, ANY]).is_ok());
+        assert!(!forwarded(&filter, &error));
+        assert!(filter.accepts(7, msg_type::STATUS));
+
+        // Malformed commands keep the list.
+        assert_eq!(
+            filter.handle_command(&[GATE_COMMAND, FILTER_SET, 3, 1, 2, 3]),
+            Some(Err(FilterError::OddLength))
+        );
+        assert_eq!(
+            filter.handle_command(&[GATE_COMMAND, FILTER_SET, 4, 1, 2]),
+            Some(Err(FilterError::Truncated))
+        );
+        assert_eq!(
+            filter.set(&[0; 2 * (MAX_RULES + 1)]),
+            Err(FilterError::TooManyRules)
+        );
+        assert_eq!(
+            filter.handle_command(&[GATE_COMMAND, 0x7f, 0]),
+            Some(Err(FilterError::UnknownCommand(0x7f)))
+        );
+        assert!(filter.accepts(7, msg_type::STATUS));
+
+        // Bus frames are not commands. Empty list forwards everything again.
+        assert_eq!(filter.handle_command(&[5, msg_type::STATUS, 0]), None);
+        assert_eq!(
+            filter.handle_command(&[GATE_COMMAND, FILTER_SET, 0]),
+            Some(Ok(()))
+        );
+        assert!(forwarded(&filter, &status));
+    }
+}
@@ -0,0 +1,91 @@
+/*
+ * Bus liveness watchdog. Nodes depend on the gate for the time sync and
+ * remote commands, but a silent bus looks the same as a quiet day. Each
+ * received frame feeds the watchdog; when nothing arrives for the timeout,
+ * the node shows Attention and keeps running on its local RTC time until the
+ * next frame arrives.
+ */
+use core::cell::Cell;
+use embassy_sync::blocking_mutex::{Mutex, raw::NoopRawMutex};
+use embassy_time::{Duration, Instant};
+
+/// Gate forwards the server traffic and time announcements - a few minutes
+/// without any frame means it's gone.
+pub const SILENCE_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Change of the bus state reported by `BusWatchdog::update`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, defmt::Format)]
+pub enum Liveness {
+    /// No frame for longer than the timeout.
+    Silent,
+    /// Frame received after a silence.
+    Recovered,
+}
+
+pub struct BusWatchdog {
+    timeout: Duration,
+    /// Last received frame (or start).
+    last_rx: Mutex<NoopRawMutex, Cell<Instant>>,
+    silent: Mutex<NoopRawMutex, Cell<bool>>,
+}
+
+impl BusWatchdog {
+    pub const fn new(timeout: Duration, now: Instant) -> Self {
+        Self {
+            timeout,
+            last_rx: Mutex::new(Cell::new(now)),
+            silent: Mutex::new(Cell::new(false)),
+        }
+    }
+
+    /// Frame was received.
+    pub fn feed(&self, now: Instant) {
+        self.last_rx.lock(|last| last.set(now));
+    }
+
+    pub fn is_silent(&self) -> bool {
+        self.silent.lock(|silent| silent.get())
+    }
+
+    /// Check the bus state periodically. Returns a change, if any.
+    pub fn update(&self, now: Instant) -> Option<Liveness> {
+        let last = self.last_rx.lock(|last| last.get());
+        let silent = now.saturating_duration_since(last) > self.timeout;
+        let was_silent = self.silent.lock(|cell| cell.replace(silent));
+        match (was_silent, silent) {
+            (false, true) => Some(Liveness::Silent),
+            (true, false) => Some(Liveness::Recovered),
+            _ => None,
+        }
+    }
+}
+
+pub mod tests {
+    use super::*;
+
+    pub fn silent_then_recovered() {
+        let start = Instant::from_secs(10);
+        let timeout = Duration::from_secs(60);
+        let watchdog = BusWatchdog::new(timeout, start);
+
+        // Regular traffic.
+        watchdog.feed(start + Duration::from_secs(30));
+        assert_eq!(watchdog.update(start + Duration::from_secs(80)), None);
+        assert!(!watchdog.is_silent());
+
+        // Gate went quiet.
+        let quiet = start + Duration::from_secs(91);
+        assert_eq!(watchdog.update(quiet), Some(Liveness::Silent));
+        assert!(watchdog.is_silent());
+        // Reported once.
+        assert_eq!(watchdog.update(quiet + timeout), None);
+        assert!(watchdog.is_silent());
+
+        // Next frame clears it.
+        let back = quiet + timeout + Duration::from_secs(1);
+        watchdog.feed(back);
+        assert_eq!(watchdog.update(back), Some(Liveness::Recovered));
+        assert!(!watchdog.is_silent());
+        assert_eq!(watchdog.update(back + timeout), None);
+    }
+}
@@ -0,0 +1,92 @@
+/*
+ * Remote SetOutput bursts (eg. a slider spammed in HA) would otherwise turn
+ * into an event per frame and make the output flicker. Requests for the same
+ * output that are already buffered are merged - the last state wins.
+ */
+use crate::buttonsmash::consts::OutIdx;
+use crate::components::message::args::OutputChangeRequest;
+
+/// Merge buffered requests following a SetOutput of `output`. `next` returns
+/// already received items without waiting and `parse` extracts a SetOutput
+/// addressed to us. Toggles depend on the current state and are never merged.
+///
+/// Returns the state to apply and the item that ended the burst - it was
+/// already taken from the queue and must be handled next to keep the order.
+pub fn set_output<T>(
+    output: OutIdx,
+    state: OutputChangeRequest,
+    mut next: impl FnMut() -> Option<T>,
+    parse: impl Fn(&T) -> Option<(OutIdx, OutputChangeRequest)>,
+) -> (OutputChangeRequest, Option<T>) {
+    if state == OutputChangeRequest::Toggle {
+        return (state, None);
+    }
+    let mut state = state;
+    while let Some(item) = next() {
+        match parse(&item) {
+            Some((next_output, next_state))
+                if next_output == output && next_state != OutputChangeRequest::Toggle =>
+            {
+                state = next_state;
+            }
+            _ => return (state, Some(item)),
+        }
+    }
+    (state, None)
+}
+
+pub mod tests {
+    use super::*;
+
+    /// Received frame - SetOutput addressed to us or anything else.
+    #[derive(Debug, PartialEq)]
+    enum Frame {
+        Set(OutIdx, OutputChangeRequest),
+        Other,
+    }
+
+    fn parse(frame: &Frame) -> Option<(OutIdx, OutputChangeRequest)> {
+        match frame {
+            Frame::Set(output, state) => Some((*output, *state)),
+            Frame::Other => None,
+        }
+    }
+
+    pub fn last_command_wins() {
+        use OutputChangeRequest::{Off, On, Toggle};
+        let set = Frame::Set;
+
+        // On, Off, On for output 5 results in a single On.
+        let mut queue = [set(5, Off), set(5, On)].into_iter();
+        let (state, rest) = set_output(5, On, || queue.next(), parse);
+        assert_eq!(state, On);
+        assert_eq!(rest, None);
+        assert_eq!(queue.next(), None);
+
+        // Other output ends the burst and is handled next, in order.
+        let mut queue = [set(5, Off), set(6, On), set(5, On)].into_iter();
+        let (state, rest) = set_output(5, On, || queue.next(), parse);
+        assert_eq!(state, Off);
+        assert_eq!(rest, Some(set(6, On)));
+        assert_eq!(queue.next(), Some(set(5, On)));
+
+        // Toggles are not merged.
+        let mut queue = [set(5, Off)].into_iter();
+        assert_eq!(
+            set_output(5, Toggle, || queue.next(), parse),
+            (Toggle, None)
+        );
+        let mut queue = [set(5, Toggle)].into_iter();
+        assert_eq!(
+            set_output(5, On, || queue.next(), parse),
+            (On, Some(set(5, Toggle)))
+        );
+
+        // Other messages end the burst too.
+        let mut queue = [Frame::Other].into_iter();
+        assert_eq!(
+            set_output(5, On, || queue.next(), parse),
+            (On, Some(Frame::Other))
+        );
+    }
+}
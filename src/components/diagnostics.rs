@@ -0,0 +1,229 @@
+/*
+ * Diagnostic dump for field debugging. Doesn't fit into a single CAN frame,
+ * so it's serialized and sent as a short sequence of DiagnosticsPart frames
+ * carrying an index. The gate reassembles them and logs the dump.
+ *
+ * Layout (little endian):
+ * [version: 3] [uptime s: 4] [counters: 2 each, saturated] [layer: 1]
+ * [stack used: 2] [output bitmap: 4] [expanders online bitmap: 1]
+ */
+use heapless::Vec;
+
+use crate::components::message::Message;
+use crate::components::status::Counters;
+
+/// Payload bytes carried by a single frame (after index and total).
+pub const PART_SIZE: usize = 6;
+/// Size of the serialized dump.
+pub const SIZE: usize = 3 + 4 + 2 * Counters::COUNT + 1 + 2 + 4 + 1;
+/// Number of frames of a dump.
+pub const FRAMES: usize = SIZE.div_ceil(PART_SIZE);
+
+#[derive(Debug, Default, Clone, Eq, PartialEq, defmt::Format)]
+pub struct Diagnostics {
+    /// Firmware version: major, minor, patch.
+    pub version: [u8; 3],
+    /// Seconds since boot.
+    pub uptime: u32,
+    /// Status counters, saturated to u16.
+    pub counters: [u16; Counters::COUNT],
+    /// Active microvm layer.
+    pub layer: u8,
+    /// Deepest stack use since boot [bytes].
+    pub stack_used: u16,
+    /// Output states in the order of the board output indices.
+    pub outputs: u32,
+    /// Bit per input expander: switches, sensors.
+    pub expanders_online: u8,
+}
+
+impl Diagnostics {
+    /// Version of this firmware.
+    pub fn firmware_version() -> [u8; 3] {
+        [
+            env!("CARGO_PKG_VERSION_MAJOR").parse().unwrap_or(0),
+            env!("CARGO_PKG_VERSION_MINOR").parse().unwrap_or(0),
+            env!("CARGO_PKG_VERSION_PATCH").parse().unwrap_or(0),
+        ]
+    }
+
+    /// Saturate counters to fit the dump.
+    pub fn counters_from(values: [u32; Counters::COUNT]) -> [u16; Counters::COUNT] {
+        values.map(|value| value.min(u16::MAX as u32) as u16)
+    }
+
+    pub fn serialize(&self) -> [u8; SIZE] {
+        let mut buf = [0; SIZE];
+        buf[0..3].copy_from_slice(&self.version);
+        buf[3..7].copy_from_slice(&self.uptime.to_le_bytes());
+        let mut pos = 7;
+        for counter in self.counters {
+            buf[pos..pos + 2].copy_from_slice(&counter.to_le_bytes());
+            pos += 2;
+        }
+        buf[pos] = self.layer;
+        buf[pos + 1..pos + 3].copy_from_slice(&self.stack_used.to_le_bytes());
+        buf[pos + 3..pos + 7].copy_from_slice(&self.outputs.to_le_bytes());
+        buf[pos + 7] = self.expanders_online;
+        buf
+    }
+
+    pub fn deserialize(buf: &[u8; SIZE]) -> Self {
+        let mut counters = [0; Counters::COUNT];
+        let mut pos = 7;
+        for counter in counters.iter_mut() {
+            *counter = u16::from_le_bytes([buf[pos], buf[pos + 1]]);
+            pos += 2;
+        }
+        Self {
+            version: [buf[0], buf[1], buf[2]],
+            uptime: u32::from_le_bytes([buf[3], buf[4], buf[5], buf[6]]),
+            counters,
+            layer: buf[pos],
+            stack_used: u16::from_le_bytes([buf[pos + 1], buf[pos + 2]]),
+            outputs: u32::from_le_bytes([buf[pos + 3], buf[pos + 4], buf[pos + 5], buf[pos + 6]]),
+            expanders_online: buf[pos + 7],
+        }
+    }
+
+    /// Split the dump into messages to transmit in order.
+    pub fn to_messages(&self) -> Vec<Message, FRAMES> {
+        let buf = self.serialize();
+        let mut messages = Vec::new();
+        for (index, chunk) in buf.chunks(PART_SIZE).enumerate() {
+            let mut data = [0; PART_SIZE];
+            data[..chunk.len()].copy_from_slice(chunk);
+            let part = Message::DiagnosticsPart {
+                index: index as u8,
+                total: FRAMES as u8,
+                data,
+            };
+            // Vec has room for all the chunks.
+            let _ = messages.push(part);
+        }
+        messages
+    }
+}
+
+/// Collects dump frames of a single node.
+pub struct Reassembler {
+    node: Option<u8>,
+    buf: [u8; FRAMES * PART_SIZE],
+    /// Bit per received frame.
+    received: u32,
+}
+
+impl Default for Reassembler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Reassembler {
+    pub const fn new() -> Self {
+        Self {
+            node: None,
+            buf: [0; FRAMES * PART_SIZE],
+            received: 0,
+        }
+    }
+
+    /// Add a frame. Returns the dump once all its frames arrived. A frame of
+    /// another node or the first frame restarts the reassembly.
+    pub fn push(
+        &mut self,
+        node: u8,
+        index: u8,
+        total: u8,
+        data: &[u8; PART_SIZE],
+    ) -> Option<Diagnostics> {
+        let index = index as usize;
+        if total as usize != FRAMES || index >= FRAMES {
+            defmt::warn!(
+                "Node {} sent diagnostics frame {}/{} of unknown layout",
+                node,
+                index,
+                total
+            );
+            return None;
+        }
+        if self.node != Some(node) || index == 0 {
+            self.node = Some(node);
+            self.received = 0;
+        }
+        self.buf[index * PART_SIZE..(index + 1) * PART_SIZE].copy_from_slice(data);
+        self.received |= 1 << index;
+
+        if self.received != (1 << FRAMES) - 1 {
+            return None;
+        }
+        self.node = None;
+        self.received = 0;
+        let mut dump = [0; SIZE];
+        dump.copy_from_slice(&self.buf[..SIZE]);
+        Some(Diagnostics::deserialize(&dump))
+    }
+}
+
+pub mod tests {
+    use super::*;
+
+    pub fn dump_round_trip() {
+        let diagnostics = Diagnostics {
+            version: [0, 1, 2],
+            uptime: 123_456,
+            counters: Diagnostics::counters_from([0, 1, 2, 3, 4, 5, 6, 7, 100_000]),
+            layer: 2,
+            stack_used: 4321,
+            outputs: 0x00a5_0f01,
+            expanders_online: 0b01,
+        };
+        assert_eq!(diagnostics.counters[8], u16::MAX);
+
+        let messages = diagnostics.to_messages();
+        assert_eq!(SIZE, 33);
+        assert_eq!(messages.len(), 6);
+
+        // Through the wire and out of order.
+        let parts = |node| {
+            messages.iter().map(move |message| {
+                let raw = message.to_raw(node);
+                assert_eq!(raw.length(), 8);
+                raw.diagnostics_part().unwrap()
+            })
+        };
+        let mut reassembler = Reassembler::new();
+        let mut received: Vec<_, FRAMES> = parts(7).collect();
+        received.swap(1, 2);
+        let (last, first) = received.split_last().unwrap();
+        for (index, total, data) in first {
+            assert_eq!(reassembler.push(7, *index, *total, data), None);
+        }
+        let (index, total, data) = last;
+        assert_eq!(
+            reassembler.push(7, *index, *total, data),
+            Some(diagnostics.clone())
+        );
+
+        // Frame of another node restarts the reassembly.
+        for (index, total, data) in parts(7).take(3) {
+            assert_eq!(reassembler.push(7, index, total, &data), None);
+        }
+        let (_, total, data) = parts(9).next().unwrap();
+        assert_eq!(reassembler.push(9, 0, total, &data), None);
+        let mut dump = None;
+        for (index, total, data) in parts(7).skip(3) {
+            dump = reassembler.push(7, index, total, &data);
+        }
+        assert_eq!(dump, None);
+        for (index, total, data) in parts(7) {
+            dump = reassembler.push(7, index, total, &data);
+        }
+        assert_eq!(dump, Some(diagnostics));
+
+        // Frames of an unknown layout are ignored.
+        let mut reassembler = Reassembler::new();
+        assert_eq!(reassembler.push(7, 0, 7, &[0; PART_SIZE]), None);
+        assert_eq!(reassembler.received, 0);
+    }
+}
@@ -0,0 +1,202 @@
+/*
+ * Optional external RTC (DS3231) on the shared I²C bus. The internal RTC of
+ * the STM32G4 loses time on power loss without a backup battery, the DS3231
+ * module carries its own. When configured, it's the authoritative time
+ * source and the internal RTC is only a fallback.
+ */
+use embedded_hal_async::i2c::I2c;
+
+/// Fixed I²C address of the DS3231.
+const ADDR: u8 = 0x68;
+/// First time register (seconds), followed by minutes, hours, day of week,
+/// date, month/century and year.
+const TIME_REG: u8 = 0x00;
+const STATUS_REG: u8 = 0x0F;
+/// Oscillator stopped - time is not valid since.
+const STATUS_OSF: u8 = 0x80;
+const CENTURY: u8 = 0x80;
+
+/// Civil time independent of the RTC peripheral types.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, defmt::Format)]
+pub struct RtcTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    /// 0 - Monday, 6 - Sunday.
+    pub day_of_week: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+fn from_bcd(raw: u8) -> u8 {
+    (raw >> 4) * 10 + (raw & 0x0F)
+}
+
+fn to_bcd(value: u8) -> u8 {
+    ((value / 10) << 4) | (value % 10)
+}
+
+pub struct ExternalRtc<BUS: I2c> {
+    i2c: BUS,
+}
+
+impl<BUS: I2c> ExternalRtc<BUS> {
+    pub fn new(i2c: BUS) -> Self {
+        Self { i2c }
+    }
+
+    /// Read the time. Fails if the chip doesn't respond or its oscillator
+    /// stopped (eg. on a dead battery).
+    pub async fn read(&mut self) -> Result<RtcTime, ()> {
+        let mut status = [0];
+        self.i2c
+            .write_read(ADDR, &[STATUS_REG], &mut status)
+            .await
+            .map_err(|_e| ())?;
+        if status[0] & STATUS_OSF != 0 {
+            defmt::warn!("External RTC oscillator stopped - time is invalid");
+            return Err(());
+        }
+
+        let mut regs = [0; 7];
+        self.i2c
+            .write_read(ADDR, &[TIME_REG], &mut regs)
+            .await
+            .map_err(|_e| ())?;
+        let century = if regs[5] & CENTURY != 0 { 100 } else { 0 };
+        Ok(RtcTime {
+            second: from_bcd(regs[0] & 0x7F),
+            minute: from_bcd(regs[1] & 0x7F),
+            // 24-hour mode is always written.
+            hour: from_bcd(regs[2] & 0x3F),
+            day_of_week: (regs[3] & 0x07).saturating_sub(1),
+            day: from_bcd(regs[4] & 0x3F),
+            month: from_bcd(regs[5] & 0x1F),
+            year: 2000 + century + from_bcd(regs[6]) as u16,
+        })
+    }
+
+    /// Set the time and clear the oscillator stop flag.
+    pub async fn write(&mut self, time: &RtcTime) -> Result<(), ()> {
+        let years = time.year.saturating_sub(2000);
+        let century = if years >= 100 { CENTURY } else { 0 };
+        let frame = [
+            TIME_REG,
+            to_bcd(time.second),
+            to_bcd(time.minute),
+            to_bcd(time.hour),
+            time.day_of_week + 1,
+            to_bcd(time.day),
+            to_bcd(time.month) | century,
+            to_bcd((years % 100) as u8),
+        ];
+        self.i2c.write(ADDR, &frame).await.map_err(|_e| ())?;
+        self.i2c
+            .write(ADDR, &[STATUS_REG, 0])
+            .await
+            .map_err(|_e| ())
+    }
+}
+
+/// Time from the external RTC if present and valid, otherwise from the
+/// internal one.
+pub async fn read_time<BUS: I2c>(
+    external: Option<&mut ExternalRtc<BUS>>,
+    internal: Option<RtcTime>,
+) -> Option<RtcTime> {
+    if let Some(external) = external {
+        match external.read().await {
+            Ok(time) => return Some(time),
+            Err(()) => defmt::warn!("External RTC unavailable, using the internal one"),
+        }
+    }
+    internal
+}
+
+pub mod tests {
+    use super::*;
+    use embedded_hal_async::i2c::{ErrorKind, ErrorType, NoAcknowledgeSource, Operation};
+
+    /// DS3231 register file behind a register pointer.
+    struct MockRtc {
+        regs: [u8; 0x13],
+        present: bool,
+    }
+
+    impl ErrorType for MockRtc {
+        type Error = ErrorKind;
+    }
+
+    impl I2c for MockRtc {
+        async fn transaction(
+            &mut self,
+            address: u8,
+            operations: &mut [Operation<'_>],
+        ) -> Result<(), Self::Error> {
+            if !self.present || address != ADDR {
+                return Err(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address));
+            }
+            let mut pointer = 0;
+            for operation in operations {
+                match operation {
+                    Operation::Write(data) => {
+                        pointer = data[0] as usize;
+                        for byte in &data[1..] {
+                            self.regs[pointer] = *byte;
+                            pointer += 1;
+                        }
+                    }
+                    Operation::Read(buf) => {
+                        for byte in buf.iter_mut() {
+                            *byte = self.regs[pointer];
+                            pointer += 1;
+                        }
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+
+    pub fn external_rtc_preferred() {
+        let internal = RtcTime {
+            year: 2025,
+            month: 1,
+            day: 1,
+            day_of_week: 2,
+            hour: 0,
+            minute: 0,
+            second: 0,
+        };
+        let external_time = RtcTime {
+            year: 2026,
+            month: 10,
+            day: 18,
+            day_of_week: 6,
+            hour: 21,
+            minute: 37,
+            second: 59,
+        };
+        let mut rtc = ExternalRtc::new(MockRtc {
+            regs: [0; 0x13],
+            present: true,
+        });
+        // Fresh chip reports a stopped oscillator.
+        rtc.i2c.regs[STATUS_REG as usize] = STATUS_OSF;
+        let read = |rtc: Option<&mut ExternalRtc<MockRtc>>| {
+            embassy_futures::block_on(read_time(rtc, Some(internal)))
+        };
+        assert_eq!(read(Some(&mut rtc)), Some(internal));
+
+        // Set time is read back and wins over the internal RTC.
+        assert!(embassy_futures::block_on(rtc.write(&external_time)).is_ok());
+        assert_eq!(rtc.i2c.regs[0..7], [0x59, 0x37, 0x21, 7, 0x18, 0x10, 0x26]);
+        assert_eq!(read(Some(&mut rtc)), Some(external_time));
+
+        // Missing chip or none configured - internal RTC.
+        rtc.i2c.present = false;
+        assert_eq!(read(Some(&mut rtc)), Some(internal));
+        assert_eq!(read(None), Some(internal));
+    }
+}
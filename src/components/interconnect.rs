@@ -1,11 +1,17 @@
-use crate::components::message::MessageRaw;
+use crate::boards::ctrl_board::BoardStatus;
+use crate::components::bus_watchdog::{self, BusWatchdog};
+use crate::components::message::{CanFrame, MessageRaw};
+use crate::components::node_address;
+use crate::components::queue::WhenFull;
+use crate::components::reply::{RecvError, ReplyTap};
+use crate::components::retry::{RetryAction, RetryPolicy};
+use crate::components::sequence::Sequencer;
 use crate::components::status;
-use crate::config::LOCAL_ADDRESS;
-use defmt::*;
+use crate::config;
 use embassy_stm32::can::{self, BufferedCanReceiver, BufferedCanSender};
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use embassy_sync::mutex::Mutex;
-use embassy_time::{Duration, Timer};
+use embassy_time::{Duration, Instant, Timer};
 use static_cell::StaticCell;
 
 use super::message::Message;
@@ -13,8 +19,21 @@ use super::message::Message;
 pub struct Interconnect {
     can_tx: Mutex<NoopRawMutex, BufferedCanSender>,
     can_rx: BufferedCanReceiver,
+    /// Backs off on repeated frame errors so callers don't spin.
+    rx_retry: RetryPolicy,
+    /// Numbers our frames so receivers can detect losses.
+    tx_sequence: Sequencer,
+    /// Detects a silent bus (missing gate).
+    pub liveness: BusWatchdog,
+    /// Received frames for a pending `request`.
+    replies: ReplyTap,
+    /// Shows our errors and infos.
+    status: &'static BoardStatus,
 }
 
+/// First delay after a receive error. Bus errors are not fatal.
+const RX_BACKOFF: Duration = Duration::from_millis(1);
+
 // NOTE: Use loopback for single-device tests.
 static USE_LOOPBACK: bool = false;
 
@@ -23,17 +42,8 @@ static RX_BUF: StaticCell<can::RxBuf<4>> = StaticCell::new();
 // I only keep this around so that can keeps working.
 static BUFFERED_CAN: StaticCell<embassy_stm32::can::BufferedCan<'static, 4, 4>> = StaticCell::new();
 
-pub enum WhenFull {
-    /// Output queue is full and can't immediately schedule message? Drop message.
-    Drop,
-    /// Output queue is full? Block until it's free. Might block indefinetely if CAN failed.
-    Block,
-    /// Wait a bit and retry, but don't block forever.
-    Wait,
-}
-
 impl Interconnect {
-    pub fn new(mut can: can::CanConfigurator<'static>) -> Self {
+    pub fn new(mut can: can::CanConfigurator<'static>, status: &'static BoardStatus) -> Self {
         let mode = if USE_LOOPBACK {
             can::OperatingMode::InternalLoopbackMode
         } else {
@@ -58,6 +68,11 @@ impl Interconnect {
         Self {
             can_tx: Mutex::new(writer),
             can_rx: reader,
+            rx_retry: RetryPolicy::new(RetryPolicy::UNLIMITED, RX_BACKOFF),
+            tx_sequence: Sequencer::new(),
+            liveness: BusWatchdog::new(bus_watchdog::SILENCE_TIMEOUT, Instant::now()),
+            replies: ReplyTap::new(),
+            status,
         }
     }
 
@@ -67,36 +82,16 @@ impl Interconnect {
         let can = &self.can_rx;
         match can.receive().await {
             Ok(envelope) => {
-                let (ts, rx_frame) = (envelope.ts, envelope.frame);
-                let header = rx_frame.header();
-                let addr: u16 = match header.id() {
-                    embedded_can::Id::Extended(_id) => {
-                        defmt::info!("Got extended CAN frame - ignoring");
-                        return Err(());
-                    }
-                    embedded_can::Id::Standard(id) => id.as_raw(),
-                };
-
-                let length: usize = rx_frame.header().len().into();
-
-                let delta = if ts > start {
-                    // This panics on start > ts
-                    (ts - start).as_millis()
-                } else {
-                    // Message was already buffered when we were called.
-                    0
-                };
-                defmt::trace!(
-                    "CAN RX: can_addr={:#02x} len={} {:02x} --- {}ms",
-                    addr,
-                    header.len(),
-                    rx_frame.data()[0..length],
-                    delta,
-                );
-                Ok(MessageRaw::from_can(addr, &rx_frame.data()[0..length]))
+                self.rx_retry.record_success();
+                self.liveness.feed(start);
+                let raw = Self::parse_envelope(envelope, start);
+                if let Ok(raw) = &raw {
+                    self.replies.offer(raw);
+                }
+                raw
             }
             Err(_err) => {
-                // FIXME: This can start looping wildly on gate.
+                // This used to loop wildly on gate - hence the backoff.
                 /*
                  * 17251.164398 ERROR Error in frame
                  * └─ io_ctrl::components::interconnect::{impl#0}::receive::{async_fn#0} @ src/components/interconnect.rs:74
@@ -111,15 +106,81 @@ impl Interconnect {
                  * 17251.164489 WARN  Error while reading a message Err(())
                  * └─ io_ctrl::app::gate_app::__task_read_interconnect_task::{async_fn#0} @ src/app/gate_app.rs:83
                  */
-                error!("Error in frame");
+                crate::error_limited!(100, "Error in frame");
+                if let RetryAction::Backoff(delay) = self.rx_retry.record_failure() {
+                    Timer::after(delay).await;
+                }
                 Err(())
             }
         }
     }
 
+    /// Take an already buffered message without waiting. Used to coalesce
+    /// bursts - errors are only counted, without the backoff.
+    pub fn try_receive(&self) -> Option<Result<MessageRaw, ()>> {
+        match self.can_rx.try_receive().ok()? {
+            Ok(envelope) => {
+                self.rx_retry.record_success();
+                let now = embassy_time::Instant::now();
+                self.liveness.feed(now);
+                let raw = Self::parse_envelope(envelope, now);
+                if let Ok(raw) = &raw {
+                    self.replies.offer(raw);
+                }
+                Some(raw)
+            }
+            Err(_err) => {
+                crate::error_limited!(100, "Error in frame");
+                self.rx_retry.record_failure();
+                Some(Err(()))
+            }
+        }
+    }
+
+    fn parse_envelope(
+        envelope: can::frame::Envelope,
+        start: embassy_time::Instant,
+    ) -> Result<MessageRaw, ()> {
+        let (ts, rx_frame) = (envelope.ts, envelope.frame);
+        let Some(frame) = CanFrame::from_embassy(&rx_frame) else {
+            defmt::info!("Got foreign extended CAN frame - ignoring");
+            return Err(());
+        };
+
+        if frame.is_remote() {
+            defmt::trace!("CAN RX: remote request can_addr={:#02x}", frame.id());
+            return Ok(MessageRaw::from_frame(&frame));
+        }
+
+        let delta = if ts > start {
+            // This panics on start > ts
+            (ts - start).as_millis()
+        } else {
+            // Message was already buffered when we were called.
+            0
+        };
+        defmt::trace!(
+            "CAN RX: can_addr={:#02x} len={} {:02x} --- {}ms",
+            frame.id(),
+            frame.len(),
+            frame.data(),
+            delta,
+        );
+        Ok(MessageRaw::from_frame(&frame))
+    }
+
     pub async fn transmit_standard(&self, raw: &MessageRaw, when_full: WhenFull) -> bool {
+        self.transmit_frame(raw, raw.to_frame(), when_full).await
+    }
+
+    async fn transmit_frame(&self, raw: &MessageRaw, frame: CanFrame, when_full: WhenFull) -> bool {
+        if !raw.is_sendable() {
+            defmt::error!("Refusing to send a message of the invalid type {:?}", raw);
+            status::COUNTERS.can_drop.inc();
+            return false;
+        }
         // RTR False
-        let frame = raw.to_can_frame();
+        let frame = frame.to_embassy();
 
         // Happy path.
         let ret = {
@@ -138,7 +199,6 @@ impl Interconnect {
                 }
                 WhenFull::Block => {
                     defmt::warn!("Output CAN buffer is full - will block and wait.");
-                    let frame = raw.to_can_frame();
                     let mut tx = self.can_tx.lock().await;
                     tx.write(frame).await;
                     true
@@ -172,12 +232,29 @@ impl Interconnect {
     /// Schedule transmission of a interconnect message - from this node.
     /// TODO: Nicer API than bool?
     pub async fn transmit_response(&self, msg: &Message, when_full: WhenFull) -> bool {
-        let raw = msg.to_raw(LOCAL_ADDRESS);
-        self.transmit_standard(&raw, when_full).await
+        self.status.show_message(msg);
+        let mut raw = msg.to_raw(node_address::ADDRESS.get());
+        self.tx_sequence.stamp(&mut raw);
+        let frame = raw.to_prioritized_frame(config::board::TX_PRIORITY_OFFSET);
+        self.transmit_frame(&raw, frame, when_full).await
     }
 
     pub async fn transmit_request(&self, dst_addr: u8, msg: &Message, when_full: WhenFull) -> bool {
         let raw = msg.to_raw(dst_addr);
         self.transmit_standard(&raw, when_full).await
     }
+
+    /// Send a request and await the first reply accepted by `matches` (called
+    /// with the sender address and the message). The read loop still gets
+    /// all frames - it has to be running for the reply to arrive.
+    pub async fn request(
+        &self,
+        dst_addr: u8,
+        msg: &Message,
+        matches: impl Fn(u8, &Message) -> bool,
+        timeout: Duration,
+    ) -> Result<Message, RecvError> {
+        let send = self.transmit_request(dst_addr, msg, WhenFull::Wait);
+        self.replies.request(send, matches, timeout).await
+    }
 }
@@ -1,7 +1,10 @@
+#[cfg(target_os = "none")]
 use embassy_stm32::can;
 
+use crate::components::status;
+
 use crate::buttonsmash::{
-    consts::{InIdx, OutIdx, ProcIdx, ShutterIdx},
+    consts::{InIdx, OutIdx, ProcIdx, SceneIdx, ShutterIdx},
     shutters,
 };
 
@@ -11,25 +14,55 @@ use crate::buttonsmash::{
  * - We want up to 64 devices on the bus.
  * - This gives 6 bit for device address and 5 for message type, ie. 32 different messages
  * TTTTTAAAAAA (T)ype + (A)ddress
+ *
+ * Arbitration compares the type first, so any message of a lower type wins
+ * over a higher one, no matter the node. Frames of the same type are ordered
+ * by the address - node address for responses/notifications, destination for
+ * requests.
+ *
+ * A node with a priority offset (config::board::TX_PRIORITY_OFFSET) sends
+ * its notifications in extended (29-bit) frames instead:
+ * PPPPPAAAAAA TTTTT 0000000000000
+ * The standard part carries the type lowered by the offset (P) and is
+ * arbitrated exactly like a standard id; the real type (T) follows, so
+ * receivers decode the same message. Consequences:
+ * - the boosted message competes with the standard messages of type P, and
+ *   wins over every higher type of all other nodes,
+ * - on the same standard part the standard frame wins (IDE bit),
+ * - the type is never lowered to ERROR or below, errors stay on top,
+ * - extended frames are 20 bits longer, the bus is occupied a bit longer.
  */
+/// Largest uptime carried by the Status frame. The top nibble holds the
+/// sequence number, so longer uptimes (~8.5 years) saturate.
+pub const STATUS_UPTIME_MAX: u32 = (1 << 28) - 1;
 
 /// The lower the code, the more important the message on the CAN BUS.
-mod msg_type {
+pub mod msg_type {
     // Start with rare important events.
     // Range: 5 bits, 0x00 <-> 0x1f
 
-    // 0 Reserved as invalid message
-    // 1 Reserved for high-priority grouped type.
+    /// Reserved as invalid message - eg. a zeroed frame. Never sent.
+    pub const INVALID: u8 = 0x00;
+    /// Reserved for high-priority grouped type.
+    pub const GROUPED: u8 = 0x01;
 
     /// Erroneous situation happened. Includes error code. See Info/Warning
     pub const ERROR: u8 = 0x02;
 
-    // 3 reserved
+    /// Force an output state, optionally locking it against any other
+    /// change. Operator override, so it wins over regular requests.
+    pub const OVERRIDE_OUTPUT: u8 = 0x03;
 
     /// My output was changed, because of reasons.
     pub const OUTPUT_CHANGED: u8 = 0x04;
     /// My input was changed.
     pub const INPUT_CHANGED: u8 = 0x05;
+    /// Part of a diagnostic dump. Response to REQUEST_STATUS with the
+    /// diagnostics flag.
+    pub const DIAGNOSTICS: u8 = 0x06;
+
+    /// Adjust shutter timing.
+    pub const SET_SHUTTER_CONFIG: u8 = 0x07;
 
     /// Set output X to Y (or invert state)
     pub const SET_OUTPUT: u8 = 0x08;
@@ -39,11 +72,16 @@ mod msg_type {
     pub const CALL_PROC: u8 = 0x0A;
     /// Extended set (shutters, etc)
     pub const CALL_SHUTTER: u8 = 0x0B;
+    /// Preset a microvm register.
+    pub const SET_REGISTER: u8 = 0x0C;
 
-    /// `Ping` of sorts.
+    /// `Ping` of sorts. Optional byte selects a diagnostic dump or an address
+    /// change instead.
     pub const REQUEST_STATUS: u8 = 0x0D;
     /// My output status, not necessarily changed. Requested or initial.
     pub const STATUS_IO: u8 = 0x0E;
+    /// Reset microvm runtime state (layers, registers, bindings) - code stays.
+    pub const RESET_RUNTIME: u8 = 0x0F;
 
     /// Periodic not triggered by an event status.
     pub const STATUS: u8 = 0x10;
@@ -53,6 +91,21 @@ mod msg_type {
     /// eg. Device started
     pub const INFO: u8 = 0x12;
 
+    /// Request value of a microvm register.
+    pub const GET_REGISTER: u8 = 0x13;
+    /// Value of a microvm register. Response to GET_REGISTER.
+    pub const REGISTER_VALUE: u8 = 0x14;
+    /// Store current outputs into a scene slot.
+    pub const CAPTURE_SCENE: u8 = 0x15;
+    /// Restore outputs from a scene slot.
+    pub const RECALL_SCENE: u8 = 0x16;
+    /// Enable/disable maintenance mode (local inputs ignored).
+    pub const SET_MAINTENANCE: u8 = 0x17;
+    /// Request timing of a shutter.
+    pub const REQUEST_SHUTTER_CONFIG: u8 = 0x18;
+    /// Shutter timing. Response to REQUEST_SHUTTER_CONFIG.
+    pub const SHUTTER_CONFIG: u8 = 0x19;
+
     /*
     /// TODO: We will need something for OTA config updates.
     /// To whom this may concern (device ID), total length of OTA
@@ -66,19 +119,137 @@ mod msg_type {
     pub const PING: u8 = 0x1E;
 
     // 0x1F Reserved for low-priority grouped type
+
+    /// REQUEST_STATUS argument requesting the diagnostic dump.
+    pub const STATUS_DIAGNOSTICS: u8 = 0x01;
+    /// REQUEST_STATUS argument changing the node address (given next).
+    pub const STATUS_SET_ADDRESS: u8 = 0x02;
+    /// REQUEST_STATUS argument requesting a dump of the active bindings.
+    pub const STATUS_BINDINGS: u8 = 0x03;
+    /// REQUEST_STATUS argument requesting the event trace.
+    pub const STATUS_TRACE: u8 = 0x04;
 }
 
 pub mod args {
     use super::{InIdx, OutIdx};
     pub use crate::io::events::Trigger;
 
-    #[derive(Clone, Copy, defmt::Format)]
+    #[derive(Clone, Copy, Debug, Eq, PartialEq, defmt::Format)]
     #[repr(u16)]
     pub enum InfoCode {
+        /// Node booted. Arg is the firmware version, see `pack_version`.
         Started = 10,
+        /// Shutter state. Arg bytes (LE): shutter index, projected height,
+        /// projected tilt, seconds until the target is reached.
+        ShutterState = 20,
+        /// Shutter motor started or stopped. Decoded as
+        /// Message::ShutterMotion. Arg bytes (LE): shutter index, motion
+        /// (see shutters::Motion::to_u8), unused, unused.
+        ShutterMotion = 21,
+        /// Node took a new address. Sent from the new one, arg is the old one.
+        AddressChanged = 30,
+        /// Start of a binding dump. Arg is the number of bindings that follow.
+        Bindings = 40,
+        /// Single binding of a dump, see Binding::to_info_arg.
+        Binding = 41,
+        /// Edges counted on a pulse input. Arg bytes (LE): input index,
+        /// unused, edge count (u16).
+        PulseCount = 50,
     }
 
-    #[derive(Clone, Copy, defmt::Format)]
+    /// Codes of Message::Error. The top byte of the raw code can carry
+    /// a detail, eg. which task failed.
+    #[derive(Clone, Copy, Debug, Eq, PartialEq, defmt::Format)]
+    #[repr(u32)]
+    pub enum ErrorCode {
+        /// Input expander can't be configured or read.
+        ExpanderInputFailure = 1,
+        /// Output expander write failed. Detail is the expander (high
+        /// nibble) and its line (low nibble), 0xFF for unmapped outputs.
+        ExpanderOutputFailure = 2,
+        /// Configured expander does not respond at init.
+        ExpanderMissing = 3,
+        /// Two expanders configured for the same I²C address.
+        ExpanderAddressConflict = 4,
+        /// CAN controller went bus-off.
+        CanBusOff = 10,
+        /// Internal event queue overflowed.
+        QueueOverflow = 20,
+        /// Loaded microvm program is invalid.
+        ProgramInvalid = 30,
+        /// Procedure ran out of the execution budget or the call stack and
+        /// was aborted. Detail is the procedure.
+        ProgramRunaway = 31,
+        /// Output flipped by remote requests in a loop between nodes. Its
+        /// remote requests are ignored for a while. Detail is the output.
+        FeedbackLoop = 32,
+        /// Shutter motor energized for too long and was cut.
+        ShutterOverTravel = 40,
+        /// Task couldn't be spawned at boot. Detail is the task id.
+        TaskSpawnFailed = 50,
+        /// Actuator pin didn't switch, even after a retry. Detail is the
+        /// actuator.
+        ActuatorFailure = 60,
+    }
+
+    impl ErrorCode {
+        const DETAIL_SHIFT: u32 = 24;
+
+        pub const ALL: [ErrorCode; 12] = [
+            Self::ExpanderInputFailure,
+            Self::ExpanderOutputFailure,
+            Self::ExpanderMissing,
+            Self::ExpanderAddressConflict,
+            Self::CanBusOff,
+            Self::QueueOverflow,
+            Self::ProgramInvalid,
+            Self::ProgramRunaway,
+            Self::FeedbackLoop,
+            Self::ShutterOverTravel,
+            Self::TaskSpawnFailed,
+            Self::ActuatorFailure,
+        ];
+
+        pub fn to_u32(self) -> u32 {
+            self as u32
+        }
+
+        /// Raw code with a detail byte.
+        pub fn with_detail(self, detail: u8) -> u32 {
+            self.to_u32() | (detail as u32) << Self::DETAIL_SHIFT
+        }
+
+        /// Detail byte of a raw code.
+        pub fn detail(raw: u32) -> u8 {
+            (raw >> Self::DETAIL_SHIFT) as u8
+        }
+
+        /// Decode the code, ignoring the detail.
+        pub fn from_u32(raw: u32) -> Option<Self> {
+            let raw = raw & ((1 << Self::DETAIL_SHIFT) - 1);
+            Self::ALL.into_iter().find(|code| code.to_u32() == raw)
+        }
+
+        /// Human readable name, eg. for the gate to display.
+        pub fn name(self) -> &'static str {
+            match self {
+                Self::ExpanderInputFailure => "Input expander failure",
+                Self::ExpanderOutputFailure => "Output expander failure",
+                Self::ExpanderMissing => "Expander missing",
+                Self::ExpanderAddressConflict => "Expander address conflict",
+                Self::CanBusOff => "CAN bus off",
+                Self::QueueOverflow => "Queue overflow",
+                Self::ProgramInvalid => "Program invalid",
+                Self::ProgramRunaway => "Program runaway",
+                Self::FeedbackLoop => "Feedback loop",
+                Self::ShutterOverTravel => "Shutter over-travel",
+                Self::TaskSpawnFailed => "Task spawn failed",
+                Self::ActuatorFailure => "Actuator failure",
+            }
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, Eq, PartialEq, defmt::Format)]
     #[repr(u8)]
     pub enum OutputChangeRequest {
         /// Disable output
@@ -133,6 +304,17 @@ pub mod args {
         }
     }
 
+    /// Pack major, minor, patch into the Started arg as 0x00MMmmpp.
+    pub fn pack_version(version: [u8; 3]) -> u32 {
+        u32::from_be_bytes([0, version[0], version[1], version[2]])
+    }
+
+    /// Major, minor, patch from the Started arg.
+    pub fn unpack_version(arg: u32) -> [u8; 3] {
+        let [_, major, minor, patch] = arg.to_be_bytes();
+        [major, minor, patch]
+    }
+
     impl OutputChangeRequest {
         pub fn to_bytes(self) -> u8 {
             self as u8
@@ -180,6 +362,7 @@ pub mod args {
                 3 => Some(Trigger::Deactivated),
                 4 => Some(Trigger::LongActivated),
                 5 => Some(Trigger::LongDeactivated),
+                6 => Some(Trigger::DoubleClick),
                 _ => None,
             }
         }
@@ -218,6 +401,19 @@ pub enum Message {
     SetOutput {
         output: OutIdx,
         state: args::OutputChangeRequest,
+        /// Address of the sender. Optional trailing byte - the CAN id of a
+        /// request holds the destination.
+        source: Option<u8>,
+    },
+
+    /// Force output state. A locked output ignores all other changes until
+    /// an override with `lock` false releases it.
+    OverrideOutput {
+        output: OutIdx,
+        state: bool,
+        lock: bool,
+        /// Address of the sender, like in SetOutput.
+        source: Option<u8>,
     },
 
     // Behave as if input was triggered
@@ -231,8 +427,32 @@ pub enum Message {
         cmd: shutters::Cmd,
     },
 
-    /// Better Ping. TODO: Handle RTR?
+    /// Drive all shutters into a limit to regain their known positions.
+    /// Sent as a resync ShutterCmd to all shutters.
+    ResyncAll {
+        direction: shutters::Limit,
+        /// Return to the prior estimated positions afterwards.
+        restore: bool,
+    },
+
+    /// Exchange the up and down outputs of a shutter with swapped motor
+    /// wires. Persisted by the node. Sent as a SwapDirection ShutterCmd.
+    SwapShutterDirection { shutter_idx: ShutterIdx },
+
+    /// Better Ping. Also decoded from an RTR frame of the STATUS id.
     RequestStatus,
+    /// Request a multi-frame diagnostic dump.
+    RequestDiagnostics,
+    /// Part of a diagnostic dump. Response to RequestDiagnostics.
+    DiagnosticsPart { index: u8, total: u8, data: [u8; 6] },
+    /// Assign a new bus address to the addressed node (not a broadcast).
+    SetAddress { new_addr: u8 },
+    /// Request a dump of the active bindings as Info frames.
+    RequestBindings,
+    /// Request the recent event trace as DiagnosticsPart frames.
+    RequestTrace,
+    /// Reset microvm runtime state to the just-loaded program state.
+    ResetRuntime,
     /// Initial Ping that has some simple data to return in Pong.
     Ping { body: u16 },
     /// Response to Ping.
@@ -258,6 +478,44 @@ pub enum Message {
 
     /// Call local procedure
     CallProcedure { proc_id: ProcIdx },
+
+    /// Set microvm register to a value.
+    SetRegister { reg: u8, value: u8 },
+    /// Request microvm register value.
+    GetRegister { reg: u8 },
+    /// Microvm register value. Response to GetRegister.
+    RegisterValue { reg: u8, value: u8 },
+
+    /// Store current outputs into a scene slot.
+    CaptureScene { slot: SceneIdx },
+    /// Restore outputs from a scene slot. With the optional sender address,
+    /// like in SetOutput.
+    RecallScene { slot: SceneIdx, source: Option<u8> },
+
+    /// Ignore local inputs while enabled. Remote commands still work.
+    SetMaintenance { enabled: bool },
+
+    /// Request shutter timing.
+    RequestShutterConfig { shutter_idx: ShutterIdx },
+    /// Shutter timing in deciseconds. Response to RequestShutterConfig.
+    ShutterConfig {
+        shutter_idx: ShutterIdx,
+        rise_time: u16,
+        drop_time: u16,
+        tilt_time: u16,
+    },
+    /// Adjust shutter timing, in deciseconds.
+    SetShutterConfig {
+        shutter_idx: ShutterIdx,
+        rise_time: u16,
+        drop_time: u16,
+        tilt_time: u16,
+    },
+    /// Shutter motor started or stopped. Sent as the ShutterMotion Info.
+    ShutterMotion {
+        shutter_idx: ShutterIdx,
+        state: shutters::Motion,
+    },
     /* TODO
     /// TODO: We will need something for OTA config updates.
     /// To whom this may concern (device ID), total length of OTA
@@ -284,6 +542,248 @@ pub enum Message {
      */
 }
 
+/// Why a received frame wasn't decoded into a message.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, defmt::Format)]
+pub enum RecvError {
+    /// Type 0 (msg_type::INVALID).
+    InvalidType,
+    /// Type reserved for the future (msg_type::GROUPED).
+    ReservedType,
+    /// Unknown type, not a request or a payload not matching the type.
+    Unparsed,
+}
+
+/// Standard CAN id split into the message type (upper 5 bits) and the
+/// device address (lower 6 bits). See the layout at the top.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, defmt::Format)]
+pub struct CanAddr {
+    msg_type: u8,
+    device: u8,
+}
+
+impl CanAddr {
+    pub const MAX_TYPE: u8 = 0x1F;
+    pub const MAX_DEVICE: u8 = 0x3F;
+    const TYPE_SHIFT: u16 = 6;
+    /// Standard part of an extended id, followed by the real type.
+    const EXTENDED_SHIFT: u32 = 18;
+    const EXTENDED_TYPE_SHIFT: u32 = 13;
+
+    /// None if the type or the device is out of range.
+    pub fn new(msg_type: u8, device: u8) -> Option<Self> {
+        (msg_type <= Self::MAX_TYPE && device <= Self::MAX_DEVICE)
+            .then_some(Self { msg_type, device })
+    }
+
+    /// Keep only the bits that fit, like the wire would.
+    pub fn truncated(msg_type: u8, device: u8) -> Self {
+        Self {
+            msg_type: msg_type & Self::MAX_TYPE,
+            device: device & Self::MAX_DEVICE,
+        }
+    }
+
+    pub fn msg_type(&self) -> u8 {
+        self.msg_type
+    }
+
+    pub fn device(&self) -> u8 {
+        self.device
+    }
+
+    /// Lower id wins the arbitration.
+    pub fn to_u16(self) -> u16 {
+        (self.msg_type as u16) << Self::TYPE_SHIFT | self.device as u16
+    }
+
+    /// None for ids over 11 bits.
+    pub fn from_u16(id: u16) -> Option<Self> {
+        if id > CanFrame::MAX_ID {
+            return None;
+        }
+        Some(Self {
+            msg_type: (id >> Self::TYPE_SHIFT) as u8,
+            device: id as u8 & Self::MAX_DEVICE,
+        })
+    }
+
+    /// Type arbitrated by a node with the priority `offset`. Never lowered to
+    /// the error types and never raised.
+    pub fn boosted_type(msg_type: u8, offset: u8) -> u8 {
+        msg_type
+            .saturating_sub(offset)
+            .max(msg_type::OVERRIDE_OUTPUT)
+            .min(msg_type)
+    }
+
+    /// Extended id arbitrating as the type lowered by `offset`. See the
+    /// layout at the top.
+    pub fn to_extended(self, offset: u8) -> u32 {
+        let base = Self {
+            msg_type: Self::boosted_type(self.msg_type, offset),
+            device: self.device,
+        };
+        (base.to_u16() as u32) << Self::EXTENDED_SHIFT
+            | (self.msg_type as u32) << Self::EXTENDED_TYPE_SHIFT
+    }
+
+    /// Decode an extended id sent with a priority offset. None for ids of
+    /// other protocols - unused bits set or the type raised instead.
+    pub fn from_extended(id: u32) -> Option<Self> {
+        if id > CanFrame::MAX_EXTENDED_ID || id & ((1 << Self::EXTENDED_TYPE_SHIFT) - 1) != 0 {
+            return None;
+        }
+        let base = Self::from_u16((id >> Self::EXTENDED_SHIFT) as u16)?;
+        let msg_type = (id >> Self::EXTENDED_TYPE_SHIFT) as u8 & Self::MAX_TYPE;
+        (base.msg_type <= msg_type).then_some(Self {
+            msg_type,
+            device: base.device,
+        })
+    }
+}
+
+/// Standard (11-bit id) or extended (29-bit id) CAN frame, independent of
+/// the CAN peripheral types. MessageRaw converts to and from it, so the codec
+/// can be tested off the hardware. Conversion to embassy frames is a thin
+/// adapter.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, defmt::Format)]
+pub struct CanFrame {
+    id: u32,
+    extended: bool,
+    len: u8,
+    data: [u8; 8],
+    rtr: bool,
+}
+
+impl CanFrame {
+    /// Largest standard id.
+    pub const MAX_ID: u16 = 0x7FF;
+    /// Largest extended id.
+    pub const MAX_EXTENDED_ID: u32 = 0x1FFF_FFFF;
+
+    /// Data frame. None for ids over 11 bits or more than 8 data bytes.
+    pub fn new(id: u16, data: &[u8]) -> Option<Self> {
+        if id > Self::MAX_ID {
+            return None;
+        }
+        Self::build(id as u32, false, data.len(), data, false)
+    }
+
+    /// Remote request for a frame of given length. Carries no data.
+    pub fn remote(id: u16, len: u8) -> Option<Self> {
+        if id > Self::MAX_ID {
+            return None;
+        }
+        Self::build(id as u32, false, len as usize, &[], true)
+    }
+
+    /// Extended data frame. None for ids not in the priority layout (see
+    /// CanAddr::from_extended) or more than 8 data bytes.
+    pub fn new_extended(id: u32, data: &[u8]) -> Option<Self> {
+        CanAddr::from_extended(id)?;
+        Self::build(id, true, data.len(), data, false)
+    }
+
+    /// Extended remote request, same limits as `new_extended`.
+    pub fn remote_extended(id: u32, len: u8) -> Option<Self> {
+        CanAddr::from_extended(id)?;
+        Self::build(id, true, len as usize, &[], true)
+    }
+
+    fn build(id: u32, extended: bool, len: usize, data: &[u8], rtr: bool) -> Option<Self> {
+        if len > MessageRaw::MAX_LENGTH {
+            return None;
+        }
+        let mut frame = Self {
+            id,
+            extended,
+            len: len as u8,
+            data: [0; 8],
+            rtr,
+        };
+        frame.data[0..data.len()].copy_from_slice(data);
+        Some(frame)
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn is_extended(&self) -> bool {
+        self.extended
+    }
+
+    /// Message type and address, from either id layout.
+    pub fn can_addr(&self) -> CanAddr {
+        if self.extended {
+            CanAddr::from_extended(self.id).expect("Checked on construction")
+        } else {
+            CanAddr::from_u16(self.id as u16).expect("Checked on construction")
+        }
+    }
+
+    /// Key ordered like the bus arbitration between standard and extended
+    /// frames - lower one wins. The standard part goes first, then the IDE
+    /// bit (standard wins the tie), then the rest of the extended id.
+    pub fn arbitration_key(&self) -> u32 {
+        if self.extended {
+            (self.id >> 18) << 19 | 1 << 18 | (self.id & 0x3FFFF)
+        } else {
+            self.id << 19
+        }
+    }
+
+    pub fn len(&self) -> u8 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn is_remote(&self) -> bool {
+        self.rtr
+    }
+
+    /// Payload; empty for remote requests.
+    pub fn data(&self) -> &[u8] {
+        if self.rtr {
+            &[]
+        } else {
+            &self.data[0..self.len as usize]
+        }
+    }
+
+    /// Adapt a received embassy frame. None for extended ids of other
+    /// protocols.
+    #[cfg(target_os = "none")]
+    pub fn from_embassy(frame: &can::frame::Frame) -> Option<Self> {
+        let header = frame.header();
+        let length = header.len().min(MessageRaw::MAX_LENGTH as u8);
+        let data = &frame.data()[0..length as usize];
+        match (header.id(), header.rtr()) {
+            (embedded_can::Id::Standard(id), false) => Self::new(id.as_raw(), data),
+            (embedded_can::Id::Standard(id), true) => Self::remote(id.as_raw(), length),
+            (embedded_can::Id::Extended(id), false) => Self::new_extended(id.as_raw(), data),
+            (embedded_can::Id::Extended(id), true) => Self::remote_extended(id.as_raw(), length),
+        }
+    }
+
+    #[cfg(target_os = "none")]
+    pub fn to_embassy(&self) -> can::frame::Frame {
+        let id = if self.extended {
+            let id = embedded_can::ExtendedId::new(self.id).expect("Id is limited to 29 bits");
+            embedded_can::Id::Extended(id)
+        } else {
+            let id =
+                embedded_can::StandardId::new(self.id as u16).expect("Id is limited to 11 bits");
+            embedded_can::Id::Standard(id)
+        };
+        let hdr = can::frame::Header::new(id, self.len, self.rtr);
+        can::frame::Frame::new(hdr, &self.data[0..self.len as usize]).unwrap()
+    }
+}
+
 /// Raw message prepared for sending or just received.
 #[derive(defmt::Format, Default)]
 pub struct MessageRaw {
@@ -293,15 +793,23 @@ pub struct MessageRaw {
 
     length: u8,
     data: [u8; 8],
+    /// Remote transmission request - asks the addressee to send a data frame
+    /// with this id. Length is the requested one, data is not used.
+    rtr: bool,
 }
 
 impl MessageRaw {
+    /// Max payload of a standard CAN frame.
+    pub const MAX_LENGTH: usize = 8;
+
     pub fn from_bytes(addr: u8, msg_type: u8, data: &[u8]) -> Self {
+        let data = Self::clamp_data(data);
         let mut raw = Self {
             addr,
             msg_type,
             length: data.len() as u8,
             data: [0; 8],
+            rtr: false,
         };
         raw.data[0..data.len()].copy_from_slice(data);
         raw
@@ -310,40 +818,129 @@ impl MessageRaw {
     /// Reconstruct from received data.
     pub fn from_can(can_addr: u16, data: &[u8]) -> Self {
         let (msg_type, addr) = Self::split_can_addr(can_addr);
-        let mut raw = Self {
+        Self::from_bytes(addr, msg_type, data)
+    }
+
+    /// Remote transmission request for a frame of given type and length.
+    pub fn remote_request(addr: u8, msg_type: u8, length: u8) -> Self {
+        Self {
             addr,
             msg_type,
-            length: data.len() as u8,
+            length: length.min(Self::MAX_LENGTH as u8),
             data: [0; 8],
+            rtr: true,
+        }
+    }
+
+    /// Reconstruct a received RTR frame.
+    pub fn from_can_remote(can_addr: u16, length: u8) -> Self {
+        let (msg_type, addr) = Self::split_can_addr(can_addr);
+        Self::remote_request(addr, msg_type, length)
+    }
+
+    pub fn is_remote(&self) -> bool {
+        self.rtr
+    }
+
+    /// Don't panic on malformed, too long frames. Truncate them instead.
+    fn clamp_data(data: &[u8]) -> &[u8] {
+        if data.len() > Self::MAX_LENGTH {
+            status::COUNTERS.can_frame_truncated.inc();
+            crate::warn_limited!(
+                50,
+                "Frame with {} bytes truncated to {}",
+                data.len(),
+                Self::MAX_LENGTH
+            );
+            &data[0..Self::MAX_LENGTH]
+        } else {
+            data
+        }
+    }
+
+    /// Reconstruct from a received frame.
+    pub fn from_frame(frame: &CanFrame) -> Self {
+        let can_addr = frame.can_addr();
+        let (addr, msg_type) = (can_addr.device(), can_addr.msg_type());
+        if frame.is_remote() {
+            Self::remote_request(addr, msg_type, frame.len())
+        } else {
+            Self::from_bytes(addr, msg_type, frame.data())
+        }
+    }
+
+    pub fn to_frame(&self) -> CanFrame {
+        let frame = if self.rtr {
+            CanFrame::remote(self.to_can_addr(), self.length)
+        } else {
+            CanFrame::new(self.to_can_addr(), self.data_as_slice())
         };
-        raw.data[0..data.len()].copy_from_slice(data);
-        raw
+        // Address is masked to 11 bits and length clamped on construction.
+        frame.expect("Raw message always fits a frame")
     }
 
+    /// Frame arbitrating with the priority `offset` - an extended one, if
+    /// the offset changes the arbitrated type. See the layout at the top.
+    pub fn to_prioritized_frame(&self, offset: u8) -> CanFrame {
+        let can_addr = CanAddr::truncated(self.msg_type, self.addr);
+        if CanAddr::boosted_type(can_addr.msg_type(), offset) == can_addr.msg_type() {
+            return self.to_frame();
+        }
+        let id = can_addr.to_extended(offset);
+        let frame = if self.rtr {
+            CanFrame::remote_extended(id, self.length)
+        } else {
+            CanFrame::new_extended(id, self.data_as_slice())
+        };
+        frame.expect("Raw message always fits a frame")
+    }
+
+    #[cfg(target_os = "none")]
     pub fn to_can_frame(&self) -> can::frame::Frame {
-        let standard_id = embedded_can::StandardId::new(self.to_can_addr())
-            .expect("This should create a message");
-        let id = embedded_can::Id::Standard(standard_id);
-        let hdr = can::frame::Header::new(id, self.length(), false);
-        can::frame::Frame::new(hdr, self.data_as_slice()).unwrap()
+        self.to_frame().to_embassy()
     }
 
-    /// Combine parts into 11-bit CAN address.
+    /// Combine parts into 11-bit CAN address. Lower one wins arbitration.
     pub fn to_can_addr(&self) -> u16 {
-        ((self.msg_type as u16 & 0x1F) << 6) | (self.addr as u16 & 0x3F)
+        CanAddr::truncated(self.msg_type, self.addr).to_u16()
     }
 
-    /// Split/parse 11 bit CAN address into msg type and device address
+    /// Split/parse 11 bit CAN address into msg type and device address.
+    /// Bits above 11 are ignored.
     pub fn split_can_addr(can_addr: u16) -> (u8, u8) {
-        let device_addr: u8 = (can_addr & 0x3F).try_into().unwrap();
-        let msg_type: u8 = ((can_addr >> 6) & 0x1F).try_into().unwrap();
-        (msg_type, device_addr)
+        let addr = CanAddr::from_u16(can_addr & CanFrame::MAX_ID).expect("Id is masked to 11 bits");
+        (addr.msg_type(), addr.device())
+    }
+
+    /// Sender of a request with `length` bytes of arguments, given in the
+    /// optional byte after them. None if the frame has neither length.
+    fn request_source(&self, length: u8) -> Option<Option<u8>> {
+        if self.length == length {
+            Some(None)
+        } else if self.length == length + 1 {
+            Some(Some(self.data[length as usize]))
+        } else {
+            None
+        }
+    }
+
+    /// Append the sender address to the request arguments, if known.
+    fn push_source(&mut self, source: Option<u8>) {
+        if let Some(source) = source {
+            self.data[self.length as usize] = source;
+            self.length += 1;
+        }
     }
 
     pub fn addr_type(&self) -> (u8, u8) {
         (self.addr, self.msg_type)
     }
 
+    /// False for the invalid type, which must never reach the bus.
+    pub fn is_sendable(&self) -> bool {
+        self.msg_type != msg_type::INVALID
+    }
+
     pub fn length(&self) -> u8 {
         self.length
     }
@@ -351,21 +948,202 @@ impl MessageRaw {
     pub fn data_as_slice(&self) -> &[u8] {
         &self.data[0..self.length as usize]
     }
+
+    /// Error code if this is an Error message. Decodes nothing else.
+    pub fn error_code(&self) -> Option<u32> {
+        if self.msg_type != msg_type::ERROR {
+            return None;
+        }
+        match Message::from_raw(self)? {
+            Message::Error { code } => Some(code),
+            _ => None,
+        }
+    }
+
+    /// Firmware version if this is the Started info. Info is not decoded by
+    /// from_raw, so this reads the frame directly.
+    pub fn started_version(&self) -> Option<[u8; 3]> {
+        if self.msg_type != msg_type::INFO || self.length != 6 {
+            return None;
+        }
+        // Skip the sequence nibble.
+        let code = u16::from_le_bytes([self.data[0], self.data[1] & 0x0f]);
+        if code != args::InfoCode::Started.to_bytes() {
+            return None;
+        }
+        let arg = u32::from_le_bytes([self.data[2], self.data[3], self.data[4], self.data[5]]);
+        Some(args::unpack_version(arg))
+    }
+
+    /// Shutter and its motion if this is the ShutterMotion info.
+    pub fn shutter_motion(&self) -> Option<(ShutterIdx, shutters::Motion)> {
+        if self.msg_type != msg_type::INFO || self.rtr || self.length != 6 {
+            return None;
+        }
+        // Skip the sequence nibble.
+        let code = u16::from_le_bytes([self.data[0], self.data[1] & 0x0f]);
+        if code != args::InfoCode::ShutterMotion.to_bytes() {
+            return None;
+        }
+        Some((self.data[2], shutters::Motion::from_u8(self.data[3])?))
+    }
+
+    /// Output and its new state if this is OutputChanged. Change
+    /// notifications are not decoded by from_raw, so this reads the frame.
+    pub fn output_changed(&self) -> Option<(OutIdx, bool)> {
+        if self.msg_type != msg_type::OUTPUT_CHANGED || self.rtr || self.length != 2 {
+            return None;
+        }
+        let state = args::OutputChangeRequest::from_u8(self.data[1])?.try_to_bool()?;
+        Some((self.data[0], state))
+    }
+
+    /// Input and its trigger if this is InputChanged.
+    pub fn input_changed(&self) -> Option<(InIdx, args::Trigger)> {
+        if self.msg_type != msg_type::INPUT_CHANGED || self.rtr || self.length != 2 {
+            return None;
+        }
+        Some((self.data[0], args::Trigger::from_u8(self.data[1])?))
+    }
+
+    /// Uptime, errors and warnings if this is the periodic Status. The
+    /// sequence nibble is masked out of the uptime.
+    pub fn status(&self) -> Option<(u32, u16, u16)> {
+        if self.msg_type != msg_type::STATUS || self.rtr || self.length != 8 {
+            return None;
+        }
+        let uptime = u32::from_le_bytes([self.data[0], self.data[1], self.data[2], self.data[3]]);
+        let errors = u16::from_le_bytes([self.data[4], self.data[5]]);
+        let warnings = u16::from_le_bytes([self.data[6], self.data[7]]);
+        Some((uptime & STATUS_UPTIME_MAX, errors, warnings))
+    }
+
+    /// Byte whose spare high nibble carries the sequence number. Info codes
+    /// stay under 4096 and the uptime (in seconds) under 2^28.
+    fn sequence_byte(&self) -> Option<usize> {
+        let byte = match self.msg_type {
+            msg_type::INFO => 1,
+            msg_type::STATUS => 3,
+            _ => return None,
+        };
+        (byte < self.length as usize).then_some(byte)
+    }
+
+    /// Sender sequence number 1-15. None for messages without the spare
+    /// bits and for senders that don't number the frames.
+    pub fn sequence(&self) -> Option<u8> {
+        let seq = self.data[self.sequence_byte()?] >> 4;
+        (seq != 0).then_some(seq)
+    }
+
+    /// Pack the sequence number. Returns false if the message can't carry it.
+    pub fn set_sequence(&mut self, seq: u8) -> bool {
+        let Some(byte) = self.sequence_byte() else {
+            return false;
+        };
+        self.data[byte] = (self.data[byte] & 0x0f) | (seq << 4);
+        true
+    }
+
+    /// Index, total and payload if this is a DiagnosticsPart message.
+    pub fn diagnostics_part(&self) -> Option<(u8, u8, [u8; 6])> {
+        if self.msg_type != msg_type::DIAGNOSTICS {
+            return None;
+        }
+        match Message::from_raw(self)? {
+            Message::DiagnosticsPart { index, total, data } => Some((index, total, data)),
+            _ => None,
+        }
+    }
 }
 
 impl Message {
+    /// RTR frames carry no data. Only the status can be requested this way.
+    fn from_remote_request(raw: &MessageRaw) -> Option<Self> {
+        match raw.msg_type {
+            msg_type::STATUS => Some(Message::RequestStatus),
+            _ => {
+                defmt::warn!("Unsupported remote request {:?}", raw);
+                None
+            }
+        }
+    }
+
+    /// Decode and validate shutter index and timing.
+    fn shutter_timing_from_raw(raw: &MessageRaw) -> Option<(ShutterIdx, shutters::Timing)> {
+        if raw.length != 7 {
+            defmt::warn!("Shutter config has invalid message length {:?}", raw);
+            return None;
+        }
+        let timing = shutters::Timing {
+            rise: u16::from_le_bytes([raw.data[1], raw.data[2]]),
+            drop: u16::from_le_bytes([raw.data[3], raw.data[4]]),
+            tilt: u16::from_le_bytes([raw.data[5], raw.data[6]]),
+        };
+        if !timing.is_valid() {
+            defmt::warn!("Shutter config has invalid timing {:?}", timing);
+            return None;
+        }
+        Some((raw.data[0], timing))
+    }
+
+    /// Decode a received message. See `decode` for the reason of a failure.
     pub fn from_raw(raw: &MessageRaw) -> Option<Self> {
+        Self::decode(raw).ok()
+    }
+
+    /// Decode a received message. Reserved types are rejected without
+    /// trying to parse them.
+    pub fn decode(raw: &MessageRaw) -> Result<Self, RecvError> {
+        match raw.msg_type {
+            msg_type::INVALID => {
+                crate::warn_limited!(50, "Received message of the invalid type from {}", raw.addr);
+                Err(RecvError::InvalidType)
+            }
+            msg_type::GROUPED => {
+                defmt::info!("Ignoring message of the reserved type {:?}", raw);
+                Err(RecvError::ReservedType)
+            }
+            _ => Self::parse(raw).ok_or(RecvError::Unparsed),
+        }
+    }
+
+    /// Payload length has to match the type exactly, so bytes the frame
+    /// didn't carry are never read.
+    fn parse(raw: &MessageRaw) -> Option<Self> {
+        // Constructors clamp it, but never trust it for indexing.
+        if raw.length as usize > MessageRaw::MAX_LENGTH {
+            defmt::warn!("Message declares too long payload {:?}", raw);
+            return None;
+        }
+        if raw.rtr {
+            return Self::from_remote_request(raw);
+        }
         match raw.msg_type {
             msg_type::SET_OUTPUT => {
-                if raw.length != 2 {
+                let Some(source) = raw.request_source(2) else {
                     defmt::warn!("Set output has invalid message length {:?}", raw);
                     return None;
-                }
+                };
 
                 let state = args::OutputChangeRequest::from_u8(raw.data[1])?;
                 Some(Message::SetOutput {
                     output: raw.data[0],
                     state,
+                    source,
+                })
+            }
+            msg_type::OVERRIDE_OUTPUT => {
+                let source = raw.request_source(3);
+                let Some(source) = source.filter(|_| raw.data[1] <= 1 && raw.data[2] <= 1) else {
+                    defmt::warn!("Override output has invalid message {:?}", raw);
+                    return None;
+                };
+                Some(Message::OverrideOutput {
+                    output: raw.data[0],
+                    state: raw.data[1] == 1,
+                    lock: raw.data[2] == 1,
+                    source,
                 })
             }
             msg_type::TRIGGER_INPUT => {
@@ -388,6 +1166,107 @@ impl Message {
                 let proc_id: ProcIdx = raw.data[0];
                 Some(Message::CallProcedure { proc_id })
             }
+            msg_type::SET_REGISTER => {
+                if raw.length != 2 {
+                    defmt::warn!("Set register has invalid message length {:?}", raw);
+                    return None;
+                }
+                Some(Message::SetRegister {
+                    reg: raw.data[0],
+                    value: raw.data[1],
+                })
+            }
+            msg_type::GET_REGISTER => {
+                if raw.length != 1 {
+                    defmt::warn!("Get register has invalid message length {:?}", raw);
+                    return None;
+                }
+                Some(Message::GetRegister { reg: raw.data[0] })
+            }
+            msg_type::REGISTER_VALUE => {
+                if raw.length != 2 {
+                    defmt::warn!("Register value has invalid message length {:?}", raw);
+                    return None;
+                }
+                Some(Message::RegisterValue {
+                    reg: raw.data[0],
+                    value: raw.data[1],
+                })
+            }
+            msg_type::CAPTURE_SCENE => {
+                if raw.length != 1 {
+                    defmt::warn!("Capture scene has invalid message length {:?}", raw);
+                    return None;
+                }
+                Some(Message::CaptureScene { slot: raw.data[0] })
+            }
+            msg_type::RECALL_SCENE => {
+                let Some(source) = raw.request_source(1) else {
+                    defmt::warn!("Recall scene has invalid message length {:?}", raw);
+                    return None;
+                };
+                Some(Message::RecallScene {
+                    slot: raw.data[0],
+                    source,
+                })
+            }
+            msg_type::SET_MAINTENANCE => {
+                if raw.length != 1 || raw.data[0] > 1 {
+                    defmt::warn!("Set maintenance has invalid message {:?}", raw);
+                    return None;
+                }
+                Some(Message::SetMaintenance {
+                    enabled: raw.data[0] == 1,
+                })
+            }
+            msg_type::CALL_SHUTTER => {
+                if raw.length != 7 {
+                    defmt::warn!("Shutter command has invalid message length {:?}", raw);
+                    return None;
+                }
+                let shutter_idx = raw.data[0];
+                let cmd = shutters::Cmd::from_raw(raw.data[1..6].try_into().ok()?)?;
+                Some(match cmd.resync() {
+                    Some((direction, restore)) if shutter_idx == shutters::ALL_SHUTTERS => {
+                        Message::ResyncAll { direction, restore }
+                    }
+                    _ if cmd == shutters::Cmd::SwapDirection => {
+                        Message::SwapShutterDirection { shutter_idx }
+                    }
+                    _ => Message::ShutterCmd { shutter_idx, cmd },
+                })
+            }
+            msg_type::REQUEST_SHUTTER_CONFIG => {
+                if raw.length != 1 {
+                    defmt::warn!(
+                        "Request shutter config has invalid message length {:?}",
+                        raw
+                    );
+                    return None;
+                }
+                Some(Message::RequestShutterConfig {
+                    shutter_idx: raw.data[0],
+                })
+            }
+            msg_type::SHUTTER_CONFIG | msg_type::SET_SHUTTER_CONFIG => {
+                let (shutter_idx, timing) = Self::shutter_timing_from_raw(raw)?;
+                let (rise_time, drop_time, tilt_time) = (timing.rise, timing.drop, timing.tilt);
+                Some(if raw.msg_type == msg_type::SHUTTER_CONFIG {
+                    Message::ShutterConfig {
+                        shutter_idx,
+                        rise_time,
+                        drop_time,
+                        tilt_time,
+                    }
+                } else {
+                    Message::SetShutterConfig {
+                        shutter_idx,
+                        rise_time,
+                        drop_time,
+                        tilt_time,
+                    }
+                })
+            }
             msg_type::TIME_ANNOUNCEMENT => {
                 if raw.length != 2 + 1 + 1 + 1 + 1 + 1 + 1 {
                     defmt::warn!("Time announcement has invalid message length {:?}", raw);
@@ -404,17 +1283,75 @@ impl Message {
                 })
             }
 
-            msg_type::REQUEST_STATUS => Some(Message::RequestStatus),
+            msg_type::REQUEST_STATUS => {
+                if raw.length >= 1 && raw.data[0] == msg_type::STATUS_DIAGNOSTICS {
+                    Some(Message::RequestDiagnostics)
+                } else if raw.length >= 1 && raw.data[0] == msg_type::STATUS_BINDINGS {
+                    Some(Message::RequestBindings)
+                } else if raw.length >= 1 && raw.data[0] == msg_type::STATUS_TRACE {
+                    Some(Message::RequestTrace)
+                } else if raw.length >= 1 && raw.data[0] == msg_type::STATUS_SET_ADDRESS {
+                    if raw.length != 2 {
+                        defmt::warn!("Set address has invalid message length {:?}", raw);
+                        return None;
+                    }
+                    Some(Message::SetAddress {
+                        new_addr: raw.data[1],
+                    })
+                } else {
+                    Some(Message::RequestStatus)
+                }
+            }
+            msg_type::DIAGNOSTICS => {
+                if raw.length != 8 {
+                    defmt::warn!("Diagnostics part has invalid message length {:?}", raw);
+                    return None;
+                }
+                let mut data = [0; 6];
+                data.copy_from_slice(&raw.data[2..8]);
+                Some(Message::DiagnosticsPart {
+                    index: raw.data[0],
+                    total: raw.data[1],
+                    data,
+                })
+            }
+            msg_type::RESET_RUNTIME => Some(Message::ResetRuntime),
+
+            msg_type::PING | msg_type::PONG => {
+                if raw.length != 2 {
+                    defmt::warn!("Ping/pong has invalid message length {:?}", raw);
+                    return None;
+                }
+                let body = u16::from_le_bytes([raw.data[0], raw.data[1]]);
+                Some(if raw.msg_type == msg_type::PING {
+                    Message::Ping { body }
+                } else {
+                    Message::Pong { body }
+                })
+            }
 
-            msg_type::PING => Some(Message::Ping {
-                body: u16::from_le_bytes([raw.data[0], raw.data[1]]),
-            }),
+            msg_type::ERROR => {
+                if raw.length != 4 {
+                    defmt::warn!("Error has invalid message length {:?}", raw);
+                    return None;
+                }
+                let mut code = [0; 4];
+                code.copy_from_slice(&raw.data[0..4]);
+                Some(Message::Error {
+                    code: u32::from_le_bytes(code),
+                })
+            }
 
-            msg_type::PONG => Some(Message::Pong {
-                body: u16::from_le_bytes([raw.data[0], raw.data[1]]),
-            }),
+            // The only info decoded - endpoints may follow shutter motion.
+            msg_type::INFO => match raw.shutter_motion() {
+                Some((shutter_idx, state)) => Some(Message::ShutterMotion { shutter_idx, state }),
+                None => {
+                    defmt::info!("Ignoring info message: {:?}", raw);
+                    None
+                }
+            },
 
-            msg_type::INFO | msg_type::ERROR | msg_type::STATUS | msg_type::STATUS_IO => {
+            msg_type::STATUS | msg_type::STATUS_IO => {
                 defmt::info!("Ignoring info/error/status message: {:?}", raw);
                 None
             }
@@ -451,11 +1388,29 @@ impl Message {
                 raw.data[0..2].copy_from_slice(&code.to_le_bytes());
                 raw.data[2..6].copy_from_slice(&arg.to_le_bytes());
             }
-            Message::SetOutput { output, state } => {
+            Message::SetOutput {
+                output,
+                state,
+                source,
+            } => {
                 raw.msg_type = msg_type::SET_OUTPUT;
                 raw.length = 2;
                 raw.data[0] = *output;
                 raw.data[1] = state.to_bytes();
+                raw.push_source(*source);
+            }
+            Message::OverrideOutput {
+                output,
+                state,
+                lock,
+                source,
+            } => {
+                raw.msg_type = msg_type::OVERRIDE_OUTPUT;
+                raw.length = 3;
+                raw.data[0] = *output;
+                raw.data[1] = *state as u8;
+                raw.data[2] = *lock as u8;
+                raw.push_source(*source);
             }
             Message::OutputChanged { output, state } => {
                 raw.msg_type = msg_type::OUTPUT_CHANGED;
@@ -489,6 +1444,75 @@ impl Message {
                 raw.length = 1;
                 raw.data[0] = *proc_id;
             }
+            Message::SetRegister { reg, value } => {
+                raw.msg_type = msg_type::SET_REGISTER;
+                raw.length = 2;
+                raw.data[0] = *reg;
+                raw.data[1] = *value;
+            }
+            Message::GetRegister { reg } => {
+                raw.msg_type = msg_type::GET_REGISTER;
+                raw.length = 1;
+                raw.data[0] = *reg;
+            }
+            Message::RegisterValue { reg, value } => {
+                raw.msg_type = msg_type::REGISTER_VALUE;
+                raw.length = 2;
+                raw.data[0] = *reg;
+                raw.data[1] = *value;
+            }
+            Message::CaptureScene { slot } => {
+                raw.msg_type = msg_type::CAPTURE_SCENE;
+                raw.length = 1;
+                raw.data[0] = *slot;
+            }
+            Message::RecallScene { slot, source } => {
+                raw.msg_type = msg_type::RECALL_SCENE;
+                raw.length = 1;
+                raw.data[0] = *slot;
+                raw.push_source(*source);
+            }
+            Message::SetMaintenance { enabled } => {
+                raw.msg_type = msg_type::SET_MAINTENANCE;
+                raw.length = 1;
+                raw.data[0] = *enabled as u8;
+            }
+            Message::RequestShutterConfig { shutter_idx } => {
+                raw.msg_type = msg_type::REQUEST_SHUTTER_CONFIG;
+                raw.length = 1;
+                raw.data[0] = *shutter_idx;
+            }
+            Message::ShutterConfig {
+                shutter_idx,
+                rise_time,
+                drop_time,
+                tilt_time,
+            }
+            | Message::SetShutterConfig {
+                shutter_idx,
+                rise_time,
+                drop_time,
+                tilt_time,
+            } => {
+                raw.msg_type = if matches!(self, Message::ShutterConfig { .. }) {
+                    msg_type::SHUTTER_CONFIG
+                } else {
+                    msg_type::SET_SHUTTER_CONFIG
+                };
+                raw.length = 7;
+                raw.data[0] = *shutter_idx;
+                raw.data[1..3].copy_from_slice(&rise_time.to_le_bytes());
+                raw.data[3..5].copy_from_slice(&drop_time.to_le_bytes());
+                raw.data[5..7].copy_from_slice(&tilt_time.to_le_bytes());
+            }
+            Message::ShutterMotion { shutter_idx, state } => {
+                let arg = u32::from_le_bytes([*shutter_idx, state.to_u8(), 0, 0]);
+                raw.msg_type = msg_type::INFO;
+                raw.length = 6;
+                raw.data[0..2]
+                    .copy_from_slice(&args::InfoCode::ShutterMotion.to_bytes().to_le_bytes());
+                raw.data[2..6].copy_from_slice(&arg.to_le_bytes());
+            }
             Message::ShutterCmd { shutter_idx, cmd } => {
                 raw.msg_type = msg_type::CALL_SHUTTER;
                 raw.length = 7;
@@ -496,6 +1520,20 @@ impl Message {
                 cmd.to_raw(&mut raw.data[1..6]);
             }
 
+            Message::ResyncAll { direction, restore } => {
+                let cmd = shutters::Cmd::resync_to(*direction, *restore);
+                raw.msg_type = msg_type::CALL_SHUTTER;
+                raw.length = 7;
+                raw.data[0] = shutters::ALL_SHUTTERS;
+                cmd.to_raw(&mut raw.data[1..6]);
+            }
+            Message::SwapShutterDirection { shutter_idx } => {
+                raw.msg_type = msg_type::CALL_SHUTTER;
+                raw.length = 7;
+                raw.data[0] = *shutter_idx;
+                shutters::Cmd::SwapDirection.to_raw(&mut raw.data[1..6]);
+            }
+
             Message::Status {
                 uptime,
                 errors,
@@ -503,6 +1541,7 @@ impl Message {
             } => {
                 raw.msg_type = msg_type::STATUS;
                 raw.length = 8;
+                let uptime = (*uptime).min(STATUS_UPTIME_MAX);
                 raw.data[0..4].copy_from_slice(&uptime.to_le_bytes());
                 raw.data[4..6].copy_from_slice(&errors.to_le_bytes());
                 raw.data[6..8].copy_from_slice(&warnings.to_le_bytes());
@@ -552,6 +1591,44 @@ impl Message {
                 raw.length = 0;
             }
 
+            Message::RequestDiagnostics => {
+                raw.msg_type = msg_type::REQUEST_STATUS;
+                raw.length = 1;
+                raw.data[0] = msg_type::STATUS_DIAGNOSTICS;
+            }
+
+            Message::SetAddress { new_addr } => {
+                raw.msg_type = msg_type::REQUEST_STATUS;
+                raw.length = 2;
+                raw.data[0] = msg_type::STATUS_SET_ADDRESS;
+                raw.data[1] = *new_addr;
+            }
+
+            Message::RequestBindings => {
+                raw.msg_type = msg_type::REQUEST_STATUS;
+                raw.length = 1;
+                raw.data[0] = msg_type::STATUS_BINDINGS;
+            }
+
+            Message::RequestTrace => {
+                raw.msg_type = msg_type::REQUEST_STATUS;
+                raw.length = 1;
+                raw.data[0] = msg_type::STATUS_TRACE;
+            }
+
+            Message::DiagnosticsPart { index, total, data } => {
+                raw.msg_type = msg_type::DIAGNOSTICS;
+                raw.length = 8;
+                raw.data[0] = *index;
+                raw.data[1] = *total;
+                raw.data[2..8].copy_from_slice(data);
+            }
+
+            Message::ResetRuntime => {
+                raw.msg_type = msg_type::RESET_RUNTIME;
+                raw.length = 0;
+            }
+
             /*
               TODO: Remote bytecode update.
               Message::MicrocodeUpdateInit { addr, length } => todo!(),
@@ -560,6 +1637,437 @@ impl Message {
               Message::MicrocodeUpdateAck { length } => todo!(),
              */
         }
+        defmt::debug_assert!(raw.is_sendable(), "Message {:?} has no type", self);
         raw
     }
 }
+
+pub mod tests {
+    use super::*;
+
+    pub fn too_long_frame_is_clamped() {
+        let truncated = status::COUNTERS.can_frame_truncated.get();
+        let data = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12];
+
+        let raw = MessageRaw::from_can(0x123, &data);
+        assert_eq!(raw.length(), 8);
+        assert_eq!(raw.data_as_slice(), &data[0..8]);
+
+        let raw = MessageRaw::from_bytes(1, msg_type::PING, &data);
+        assert_eq!(raw.length(), 8);
+        assert_eq!(status::COUNTERS.can_frame_truncated.get(), truncated + 2);
+
+        // Correct frames are untouched.
+        let raw = MessageRaw::from_can(0x123, &data[0..3]);
+        assert_eq!(raw.data_as_slice(), &data[0..3]);
+        assert_eq!(status::COUNTERS.can_frame_truncated.get(), truncated + 2);
+    }
+
+    pub fn reserved_types_rejected() {
+        // Zeroed frame - type 0 is never a message.
+        let raw = MessageRaw::from_frame(&CanFrame::new(0x005, &[1, 2]).unwrap());
+        assert_eq!(raw.addr_type(), (5, msg_type::INVALID));
+        assert_eq!(Message::decode(&raw).err(), Some(RecvError::InvalidType));
+        assert!(!raw.is_sendable());
+        let request = MessageRaw::from_can_remote(0x005, 0);
+        assert_eq!(
+            Message::decode(&request).err(),
+            Some(RecvError::InvalidType)
+        );
+
+        let raw = MessageRaw::from_bytes(5, msg_type::GROUPED, &[1]);
+        assert_eq!(Message::decode(&raw).err(), Some(RecvError::ReservedType));
+        assert!(raw.is_sendable());
+
+        // Other failures are told apart.
+        let raw = MessageRaw::from_bytes(5, msg_type::SET_OUTPUT, &[1]);
+        assert_eq!(Message::decode(&raw).err(), Some(RecvError::Unparsed));
+
+        // Encoded messages always have a type.
+        for message in [
+            Message::Ping { body: 1 },
+            Message::RequestStatus,
+            Message::ResetRuntime,
+        ] {
+            assert!(message.to_raw(5).is_sendable());
+        }
+    }
+
+    pub fn register_messages_round_trip() {
+        let raw = Message::SetRegister { reg: 3, value: 7 }.to_raw(1);
+        assert_eq!(raw.addr_type(), (1, msg_type::SET_REGISTER));
+        assert!(matches!(
+            Message::from_raw(&raw),
+            Some(Message::SetRegister { reg: 3, value: 7 })
+        ));
+
+        let raw = Message::GetRegister { reg: 31 }.to_raw(2);
+        assert_eq!(raw.length(), 1);
+        assert!(matches!(
+            Message::from_raw(&raw),
+            Some(Message::GetRegister { reg: 31 })
+        ));
+
+        let raw = Message::RegisterValue { reg: 5, value: 255 }.to_raw(2);
+        assert!(matches!(
+            Message::from_raw(&raw),
+            Some(Message::RegisterValue { reg: 5, value: 255 })
+        ));
+
+        // Invalid length is rejected.
+        let raw = MessageRaw::from_bytes(1, msg_type::SET_REGISTER, &[1]);
+        assert!(Message::from_raw(&raw).is_none());
+    }
+
+    pub fn started_version_round_trip() {
+        let version = [1, 12, 255];
+        let mut raw = Message::Info {
+            code: args::InfoCode::Started.to_bytes(),
+            arg: args::pack_version(version),
+        }
+        .to_raw(7);
+        assert_eq!(raw.started_version(), Some(version));
+        // Numbered frames decode the same.
+        assert!(raw.set_sequence(9));
+        assert_eq!(raw.started_version(), Some(version));
+        assert_eq!(args::unpack_version(0x0001_0205), [1, 2, 5]);
+
+        let other = Message::Info {
+            code: args::InfoCode::Bindings.to_bytes(),
+            arg: args::pack_version(version),
+        }
+        .to_raw(7);
+        assert_eq!(other.started_version(), None);
+        assert_eq!(Message::RequestStatus.to_raw(7).started_version(), None);
+    }
+
+    pub fn error_codes_round_trip() {
+        for code in args::ErrorCode::ALL {
+            assert_eq!(args::ErrorCode::from_u32(code.to_u32()), Some(code));
+            assert!(!code.name().is_empty());
+
+            let raw = Message::Error {
+                code: code.to_u32(),
+            }
+            .to_raw(3);
+            assert!(matches!(
+                Message::from_raw(&raw),
+                Some(Message::Error { .. })
+            ));
+            let decoded = raw.error_code().and_then(args::ErrorCode::from_u32);
+            assert_eq!(decoded, Some(code));
+        }
+        assert_eq!(Message::RequestStatus.to_raw(3).error_code(), None);
+        assert_eq!(args::ErrorCode::from_u32(0), None);
+        assert_eq!(args::ErrorCode::from_u32(u32::MAX), None);
+    }
+
+    pub fn remote_request_decoded() {
+        // RTR for the status id of node 7, as it comes from the bus.
+        let can_addr = ((msg_type::STATUS as u16) << 6) | 7;
+        let raw = MessageRaw::from_can_remote(can_addr, 8);
+        assert!(raw.is_remote());
+        assert_eq!(raw.addr_type(), (7, msg_type::STATUS));
+        assert!(matches!(
+            Message::from_raw(&raw),
+            Some(Message::RequestStatus)
+        ));
+        assert_eq!(raw.to_can_addr(), can_addr);
+        assert_eq!(raw.sequence(), None);
+
+        // Same id as a data frame is a (ignored) status, not a request.
+        let data = MessageRaw::from_can(can_addr, &[0; 8]);
+        assert!(!data.is_remote());
+        assert!(Message::from_raw(&data).is_none());
+
+        // Other remote requests are not supported.
+        let raw = MessageRaw::remote_request(7, msg_type::SET_OUTPUT, 2);
+        assert!(Message::from_raw(&raw).is_none());
+    }
+
+    pub fn truncated_payloads_rejected() {
+        let timing = (100, 120, 10);
+        let messages = [
+            Message::SetOutput {
+                output: 3,
+                state: args::OutputChangeRequest::Toggle,
+                source: Some(9),
+            },
+            Message::OverrideOutput {
+                output: 3,
+                state: true,
+                lock: true,
+                source: Some(9),
+            },
+            Message::TriggerInput {
+                input: 4,
+                trigger: args::Trigger::LongClick,
+            },
+            Message::CallProcedure { proc_id: 2 },
+            Message::SetRegister { reg: 1, value: 2 },
+            Message::GetRegister { reg: 1 },
+            Message::RegisterValue { reg: 1, value: 2 },
+            Message::CaptureScene { slot: 1 },
+            Message::RecallScene {
+                slot: 1,
+                source: Some(9),
+            },
+            Message::SetMaintenance { enabled: true },
+            Message::ShutterCmd {
+                shutter_idx: 1,
+                cmd: shutters::Cmd::Close,
+            },
+            Message::RequestShutterConfig { shutter_idx: 1 },
+            Message::ShutterConfig {
+                shutter_idx: 1,
+                rise_time: timing.0,
+                drop_time: timing.1,
+                tilt_time: timing.2,
+            },
+            Message::SetShutterConfig {
+                shutter_idx: 1,
+                rise_time: timing.0,
+                drop_time: timing.1,
+                tilt_time: timing.2,
+            },
+            Message::TimeAnnouncement {
+                year: 2026,
+                month: 10,
+                day: 18,
+                hour: 12,
+                minute: 0,
+                second: 0,
+                day_of_week: 6,
+            },
+            Message::DiagnosticsPart {
+                index: 0,
+                total: 2,
+                data: [1; 6],
+            },
+            Message::Ping { body: 0x1234 },
+            Message::Pong { body: 0x1234 },
+            Message::Error { code: 0x0102_0304 },
+        ];
+        for message in messages {
+            let raw = message.to_raw(5);
+            let (_addr, msg_type) = raw.addr_type();
+            assert!(Message::from_raw(&raw).is_some());
+
+            // Every shorter frame is rejected instead of reading missing bytes.
+            // Except for requests without their optional sender.
+            let data = raw.data_as_slice();
+            let optional = matches!(
+                message,
+                Message::SetOutput { .. }
+                    | Message::OverrideOutput { .. }
+                    | Message::RecallScene { .. }
+            );
+            for length in 0..data.len() {
+                let short = MessageRaw::from_bytes(5, msg_type, &data[0..length]);
+                let unsourced = optional && length == data.len() - 1;
+                assert_eq!(Message::from_raw(&short).is_some(), unsourced);
+            }
+
+            // As well as a longer one.
+            if data.len() < MessageRaw::MAX_LENGTH {
+                let mut long = [0; MessageRaw::MAX_LENGTH];
+                long[0..data.len()].copy_from_slice(data);
+                let long = MessageRaw::from_bytes(5, msg_type, &long[0..data.len() + 1]);
+                assert!(Message::from_raw(&long).is_none());
+            }
+        }
+    }
+
+    pub fn request_sender_optional() {
+        let request = |source| Message::SetOutput {
+            output: 3,
+            state: args::OutputChangeRequest::On,
+            source,
+        };
+        // Sender follows the arguments.
+        let raw = request(Some(12)).to_raw(5);
+        assert_eq!(raw.addr_type(), (5, msg_type::SET_OUTPUT));
+        assert_eq!(raw.data_as_slice(), &[3, 1, 12]);
+        assert!(matches!(
+            Message::from_raw(&raw),
+            Some(Message::SetOutput {
+                output: 3,
+                state: args::OutputChangeRequest::On,
+                source: Some(12),
+            })
+        ));
+        // Older senders don't name themselves.
+        let raw = request(None).to_raw(5);
+        assert_eq!(raw.data_as_slice(), &[3, 1]);
+        assert!(matches!(
+            Message::from_raw(&raw),
+            Some(Message::SetOutput { source: None, .. })
+        ));
+
+        let raw = MessageRaw::from_bytes(5, msg_type::OVERRIDE_OUTPUT, &[4, 1, 0, 7]);
+        assert!(matches!(
+            Message::from_raw(&raw),
+            Some(Message::OverrideOutput {
+                output: 4,
+                state: true,
+                lock: false,
+                source: Some(7),
+            })
+        ));
+        let raw = MessageRaw::from_bytes(5, msg_type::RECALL_SCENE, &[2, 7]);
+        assert!(matches!(
+            Message::from_raw(&raw),
+            Some(Message::RecallScene {
+                slot: 2,
+                source: Some(7),
+            })
+        ));
+    }
+
+    pub fn can_id_arbitration_order() {
+        let id = |msg: &Message, addr| msg.to_raw(addr).to_can_addr();
+        let output = Message::OutputChanged {
+            output: 1,
+            state: args::OutputChangeRequest::On,
+        };
+        let error = Message::Error { code: 1 };
+
+        // Type beats the address.
+        assert!(id(&error, 0x3F) < id(&output, 0x01));
+        // Same type - lower node address wins.
+        assert!(id(&output, 0x01) < id(&output, 0x02));
+
+        // Priority offset: node 0x30 lowers its notifications by 2 types.
+        let key = |msg: &Message, addr, offset| {
+            msg.to_raw(addr)
+                .to_prioritized_frame(offset)
+                .arbitration_key()
+        };
+        let input = Message::InputChanged {
+            input: 1,
+            trigger: args::Trigger::ShortClick,
+        };
+        let boosted = input.to_raw(0x30).to_prioritized_frame(2);
+        assert!(boosted.is_extended());
+        // Wins over the types it lost to before, of any node...
+        assert!(key(&output, 0x01, 0) < key(&input, 0x30, 0));
+        assert!(key(&input, 0x30, 2) < key(&output, 0x01, 0));
+        assert!(key(&input, 0x30, 2) < key(&input, 0x01, 0));
+        // ...ties the overrides by the address, standard frame first...
+        let override_output = Message::OverrideOutput {
+            output: 1,
+            state: true,
+            lock: false,
+            source: None,
+        };
+        assert!(key(&override_output, 0x01, 0) < key(&input, 0x30, 2));
+        assert!(key(&input, 0x30, 2) < key(&override_output, 0x31, 0));
+        let tie = override_output.to_raw(0x30).to_frame();
+        assert_eq!(boosted.id() >> 18, tie.id());
+        assert!(tie.arbitration_key() < boosted.arbitration_key());
+        // ...but never beats errors, however large the offset.
+        assert!(key(&error, 0x3F, 0) < key(&input, 0x01, 31));
+
+        // Receivers decode the same message.
+        let decoded = MessageRaw::from_frame(&boosted);
+        assert_eq!(decoded.addr_type(), (0x30, msg_type::INPUT_CHANGED));
+        assert_eq!(decoded.data_as_slice(), input.to_raw(0x30).data_as_slice());
+        // No offset, or nothing to lower - plain standard frame.
+        assert_eq!(
+            input.to_raw(0x30).to_prioritized_frame(0),
+            input.to_raw(0x30).to_frame()
+        );
+        assert!(!error.to_raw(0x30).to_prioritized_frame(5).is_extended());
+        // Extended frames of other protocols are not ours.
+        assert!(CanFrame::new_extended(boosted.id() | 1, &[]).is_none());
+        // Type raised instead of lowered.
+        let raised = (CanAddr::new(8, 1).unwrap().to_u16() as u32) << 18 | 4 << 13;
+        assert!(CanFrame::new_extended(raised, &[]).is_none());
+    }
+
+    pub fn can_addr_round_trip() {
+        for msg_type in 0..=CanAddr::MAX_TYPE {
+            for device in 0..=CanAddr::MAX_DEVICE {
+                let addr = CanAddr::new(msg_type, device).unwrap();
+                let id = addr.to_u16();
+                assert!(id <= CanFrame::MAX_ID);
+                assert_eq!(CanAddr::from_u16(id), Some(addr));
+                assert_eq!((addr.msg_type(), addr.device()), (msg_type, device));
+                assert_eq!(
+                    MessageRaw::from_bytes(device, msg_type, &[]).to_can_addr(),
+                    id
+                );
+            }
+        }
+        // Every 11-bit id maps to exactly one address.
+        for id in 0..=CanFrame::MAX_ID {
+            assert_eq!(CanAddr::from_u16(id).map(CanAddr::to_u16), Some(id));
+        }
+
+        // Out of range parts and ids are rejected.
+        assert_eq!(CanAddr::new(CanAddr::MAX_TYPE + 1, 0), None);
+        assert_eq!(CanAddr::new(0, CanAddr::MAX_DEVICE + 1), None);
+        assert_eq!(CanAddr::new(u8::MAX, u8::MAX), None);
+        assert_eq!(CanAddr::from_u16(CanFrame::MAX_ID + 1), None);
+        assert_eq!(CanAddr::from_u16(u16::MAX), None);
+    }
+
+    pub fn frame_codec() {
+        // Highest type and address use all 11 bits.
+        let raw = MessageRaw::from_bytes(0x3F, 0x1F, &[1, 2]);
+        let frame = raw.to_frame();
+        assert_eq!(frame.id(), CanFrame::MAX_ID as u32);
+        assert_eq!(frame.data(), &[1, 2]);
+        assert_eq!(MessageRaw::split_can_addr(frame.id() as u16), (0x1F, 0x3F));
+        let decoded = MessageRaw::from_frame(&frame);
+        assert_eq!(decoded.addr_type(), (0x3F, 0x1F));
+        assert_eq!(decoded.data_as_slice(), &[1, 2]);
+
+        // Lowest ones.
+        let frame = MessageRaw::from_bytes(0, 0, &[]).to_frame();
+        assert_eq!(frame.id(), 0);
+        assert!(frame.is_empty());
+        assert_eq!(MessageRaw::split_can_addr(0), (0, 0));
+
+        // Type and address don't bleed into each other.
+        assert_eq!(MessageRaw::from_bytes(0x3F, 0, &[]).to_can_addr(), 0x03F);
+        assert_eq!(MessageRaw::from_bytes(0, 0x1F, &[]).to_can_addr(), 0x7C0);
+        assert_eq!(MessageRaw::from_bytes(0x40, 0x20, &[]).to_can_addr(), 0);
+        // Bits above 11 are ignored.
+        assert_eq!(MessageRaw::split_can_addr(0xF800 | 0x041), (1, 1));
+
+        // Full length frame.
+        let data = [0xff, 1, 2, 3, 4, 5, 6, 0xfe];
+        let frame = CanFrame::new(0x123, &data).unwrap();
+        let raw = MessageRaw::from_frame(&frame);
+        assert_eq!(raw.length(), 8);
+        assert_eq!(raw.to_frame(), frame);
+
+        // Frame limits.
+        assert!(CanFrame::new(CanFrame::MAX_ID + 1, &[]).is_none());
+        assert!(CanFrame::new(1, &[0; 9]).is_none());
+        assert!(CanFrame::remote(1, 9).is_none());
+
+        // Remote request keeps the requested length, but carries no data.
+        let request = MessageRaw::remote_request(7, msg_type::STATUS, 8);
+        let frame = request.to_frame();
+        assert!(frame.is_remote());
+        assert_eq!(frame.len(), 8);
+        assert!(frame.data().is_empty());
+        let decoded = MessageRaw::from_frame(&frame);
+        assert!(decoded.is_remote());
+        assert_eq!(decoded.length(), 8);
+        assert!(matches!(
+            Message::from_raw(&decoded),
+            Some(Message::RequestStatus)
+        ));
+
+        // Messages survive the trip through a frame.
+        let raw = Message::SetAddress { new_addr: 9 }.to_raw(4);
+        let decoded = MessageRaw::from_frame(&raw.to_frame());
+        assert!(matches!(
+            Message::from_raw(&decoded),
+            Some(Message::SetAddress { new_addr: 9 })
+        ));
+    }
+}
@@ -1,4 +1,26 @@
+pub mod boot_guard;
+pub mod bus_filter;
+pub mod bus_watchdog;
+pub mod coalesce;
+pub mod diagnostics;
+pub mod external_rtc;
+#[cfg(target_os = "none")]
 pub mod interconnect;
 pub mod message;
+pub mod node_address;
+pub mod persistent_store;
+pub mod queue;
+pub mod rate_log;
+pub mod reply;
+pub mod retry;
+#[cfg(target_os = "none")]
+pub mod safe_shutdown;
+pub mod sequence;
+#[cfg(target_os = "none")]
+pub mod spawn;
+pub mod state_cache;
 pub mod status;
+pub mod time_sync;
+pub mod trace;
+#[cfg(target_os = "none")]
 pub mod usb_connect;
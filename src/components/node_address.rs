@@ -0,0 +1,113 @@
+/*
+ * Bus address of this node. Compile-time LOCAL_ADDRESS is the default, an
+ * installer can assign another one over the bus (Message::SetAddress). It's
+ * persisted in an RTC backup register, so it survives reboots.
+ *
+ * Frames are accepted by the CAN filter regardless of the address - the
+ * address check is done in software against the runtime address.
+ */
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use crate::config::{BROADCAST_ADDRESS, LOCAL_ADDRESS};
+
+/// Highest address assignable to a node. 0x3F is the broadcast.
+pub const MAX_ADDRESS: u8 = 0x3E;
+
+/// Marks a backup register with a stored address.
+const BACKUP_MAGIC: u32 = 0xADD0_0000;
+const BACKUP_MASK: u32 = 0xFFFF_FF00;
+
+/// Address of this node.
+pub static ADDRESS: NodeAddress = NodeAddress::new(LOCAL_ADDRESS);
+
+/// Address rejected by `NodeAddress::set`.
+#[derive(Debug, Eq, PartialEq, Clone, Copy, defmt::Format)]
+pub enum AddressError {
+    /// Past MAX_ADDRESS - broadcast or not a 6-bit address.
+    OutOfRange(u8),
+}
+
+pub struct NodeAddress {
+    addr: AtomicU8,
+}
+
+impl NodeAddress {
+    pub const fn new(addr: u8) -> Self {
+        Self {
+            addr: AtomicU8::new(addr),
+        }
+    }
+
+    pub fn get(&self) -> u8 {
+        self.addr.load(Ordering::Relaxed)
+    }
+
+    /// Change the address. Fails for addresses outside of the node range.
+    pub fn set(&self, addr: u8) -> Result<(), AddressError> {
+        if addr > MAX_ADDRESS {
+            return Err(AddressError::OutOfRange(addr));
+        }
+        self.addr.store(addr, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Message with this destination should be handled by us.
+    pub fn accepts(&self, addr: u8) -> bool {
+        addr == self.get() || addr == BROADCAST_ADDRESS
+    }
+
+    /// Encode the current address for a backup register.
+    pub fn to_backup(&self) -> u32 {
+        BACKUP_MAGIC | self.get() as u32
+    }
+
+    /// Restore the address from a backup register. Blank or invalid register
+    /// keeps the current (default) address.
+    pub fn restore(&self, raw: u32) -> bool {
+        if raw & BACKUP_MASK != BACKUP_MAGIC {
+            return false;
+        }
+        self.set(raw as u8).is_ok()
+    }
+}
+
+pub mod tests {
+    use super::*;
+    use crate::components::message::{Message, MessageRaw};
+
+    pub fn responds_to_new_address() {
+        let address = NodeAddress::new(1);
+        assert!(address.accepts(1));
+        assert!(address.accepts(BROADCAST_ADDRESS));
+        assert!(!address.accepts(7));
+
+        // Request through the wire.
+        let raw = Message::SetAddress { new_addr: 7 }.to_raw(1);
+        let raw = MessageRaw::from_can(raw.to_can_addr(), raw.data_as_slice());
+        let Some(Message::SetAddress { new_addr }) = Message::from_raw(&raw) else {
+            panic!("SetAddress expected");
+        };
+        assert!(address.accepts(raw.addr_type().0));
+        assert_eq!(address.set(new_addr), Ok(()));
+        assert!(address.accepts(7));
+        assert!(!address.accepts(1));
+
+        // Broadcast and beyond are not assignable.
+        assert_eq!(
+            address.set(BROADCAST_ADDRESS),
+            Err(AddressError::OutOfRange(BROADCAST_ADDRESS))
+        );
+        assert_eq!(address.set(0xff), Err(AddressError::OutOfRange(0xff)));
+        assert_eq!(address.get(), 7);
+
+        // Survives reboot.
+        let rebooted = NodeAddress::new(1);
+        assert!(rebooted.restore(address.to_backup()));
+        assert_eq!(rebooted.get(), 7);
+        // Blank or corrupt register keeps the default.
+        let rebooted = NodeAddress::new(1);
+        assert!(!rebooted.restore(0));
+        assert!(!rebooted.restore(BACKUP_MAGIC | 0x3f));
+        assert_eq!(rebooted.get(), 1);
+    }
+}
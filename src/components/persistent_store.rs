@@ -0,0 +1,233 @@
+/*
+ * Versioned, CRC-protected storage of small state blobs (shutter positions,
+ * configuration) that should survive a reboot. The medium is abstracted by
+ * the Storage trait - Board implements it over RTC backup registers, a flash
+ * page would work the same way. Blank or corrupt regions load as default.
+ *
+ * Layout: [MAGIC, version, size, 0] [payload, zero padded] [CRC-32 LE]
+ * padded to ALIGN bytes.
+ */
+
+/// Marks a written region. Both blank RTC (0x00) and flash (0xFF) differ.
+const MAGIC: u8 = 0xC5;
+const HEADER: usize = 4;
+const CRC: usize = 4;
+/// Flash is written in double words. Backup registers need words.
+const ALIGN: usize = 8;
+/// Largest supported payload.
+pub const MAX_PAYLOAD: usize = 64;
+const MAX_BLOB: usize = HEADER + MAX_PAYLOAD + CRC;
+
+/// State that can be persisted.
+pub trait Persist: Default {
+    /// Change whenever the layout changes. Stored blobs of other versions are
+    /// discarded.
+    const VERSION: u8;
+    /// Serialized size in bytes.
+    const SIZE: usize;
+
+    /// Write SIZE bytes into the buffer.
+    fn serialize(&self, buf: &mut [u8]);
+    /// Decode from SIZE bytes. None if the data makes no sense.
+    fn deserialize(buf: &[u8]) -> Option<Self>;
+}
+
+/// Medium with a fixed region reserved for a single blob.
+pub trait Storage {
+    /// Size of the region in bytes.
+    fn capacity(&self) -> usize;
+    fn read(&mut self, offset: usize, buf: &mut [u8]) -> Result<(), StoreError>;
+    fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), StoreError>;
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy, defmt::Format)]
+pub enum StoreError {
+    /// Blob doesn't fit into the payload limit or the storage region.
+    TooLarge,
+    /// Storage failed to read or write.
+    Storage,
+    /// Access outside of the region, or not aligned for the medium.
+    OutOfBounds,
+}
+
+/// CRC-32 (IEEE 802.3). Bitwise - blobs are tiny.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+/// Size of the blob holding `size` bytes of payload.
+const fn blob_size(size: usize) -> usize {
+    (HEADER + size + CRC).div_ceil(ALIGN) * ALIGN
+}
+
+pub struct PersistentStore<S: Storage> {
+    storage: S,
+}
+
+impl<S: Storage> PersistentStore<S> {
+    pub fn new(storage: S) -> Self {
+        Self { storage }
+    }
+
+    /// Load the state. Default if the region is blank, corrupt or of
+    /// a different version.
+    pub fn load<T: Persist>(&mut self) -> T {
+        let size = blob_size(T::SIZE);
+        if T::SIZE > MAX_PAYLOAD || size > self.storage.capacity() {
+            defmt::error!("Persisted state of {} bytes does not fit", T::SIZE);
+            return T::default();
+        }
+
+        let mut blob = [0; MAX_BLOB + ALIGN];
+        let blob = &mut blob[..size];
+        if self.storage.read(0, blob).is_err() {
+            defmt::warn!("Unable to read persisted state");
+            return T::default();
+        }
+
+        if blob[0] != MAGIC || blob[1] != T::VERSION || blob[2] as usize != T::SIZE {
+            defmt::info!("No persisted state of version {}", T::VERSION);
+            return T::default();
+        }
+        let crc_at = size - CRC;
+        let stored = u32::from_le_bytes(blob[crc_at..].try_into().unwrap());
+        if crc32(&blob[..crc_at]) != stored {
+            defmt::warn!("Persisted state is corrupt, using default");
+            return T::default();
+        }
+        T::deserialize(&blob[HEADER..HEADER + T::SIZE]).unwrap_or_default()
+    }
+
+    /// Serialize and write the state.
+    pub fn store<T: Persist>(&mut self, value: &T) -> Result<(), StoreError> {
+        let size = blob_size(T::SIZE);
+        if T::SIZE > MAX_PAYLOAD || size > self.storage.capacity() {
+            return Err(StoreError::TooLarge);
+        }
+
+        let mut blob = [0; MAX_BLOB + ALIGN];
+        let blob = &mut blob[..size];
+        blob[0] = MAGIC;
+        blob[1] = T::VERSION;
+        blob[2] = T::SIZE as u8;
+        value.serialize(&mut blob[HEADER..HEADER + T::SIZE]);
+        let crc_at = size - CRC;
+        let crc = crc32(&blob[..crc_at]);
+        blob[crc_at..].copy_from_slice(&crc.to_le_bytes());
+
+        self.storage.write(0, blob)
+    }
+}
+
+pub mod tests {
+    use super::*;
+
+    /// In-memory region. Starts blank, like erased flash.
+    struct FakeStorage {
+        data: [u8; 32],
+    }
+
+    impl Storage for FakeStorage {
+        fn capacity(&self) -> usize {
+            self.data.len()
+        }
+
+        fn read(&mut self, offset: usize, buf: &mut [u8]) -> Result<(), StoreError> {
+            let src = self
+                .data
+                .get(offset..offset + buf.len())
+                .ok_or(StoreError::OutOfBounds)?;
+            buf.copy_from_slice(src);
+            Ok(())
+        }
+
+        fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), StoreError> {
+            let dst = self
+                .data
+                .get_mut(offset..offset + data.len())
+                .ok_or(StoreError::OutOfBounds)?;
+            dst.copy_from_slice(data);
+            Ok(())
+        }
+    }
+
+    #[derive(Default, Debug, Eq, PartialEq)]
+    struct Sample {
+        counter: u16,
+        flags: [u8; 3],
+    }
+
+    impl Persist for Sample {
+        const VERSION: u8 = 1;
+        const SIZE: usize = 5;
+
+        fn serialize(&self, buf: &mut [u8]) {
+            buf[..2].copy_from_slice(&self.counter.to_le_bytes());
+            buf[2..5].copy_from_slice(&self.flags);
+        }
+
+        fn deserialize(buf: &[u8]) -> Option<Self> {
+            Some(Self {
+                counter: u16::from_le_bytes([buf[0], buf[1]]),
+                flags: [buf[2], buf[3], buf[4]],
+            })
+        }
+    }
+
+    /// Same layout, different version.
+    #[derive(Default, Debug, Eq, PartialEq)]
+    struct SampleV2(Sample);
+
+    impl Persist for SampleV2 {
+        const VERSION: u8 = 2;
+        const SIZE: usize = Sample::SIZE;
+
+        fn serialize(&self, buf: &mut [u8]) {
+            self.0.serialize(buf)
+        }
+
+        fn deserialize(buf: &[u8]) -> Option<Self> {
+            Sample::deserialize(buf).map(Self)
+        }
+    }
+
+    pub fn round_trip_and_corruption() {
+        // Standard check value.
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+
+        // Blank region on the first boot.
+        let mut store = PersistentStore::new(FakeStorage { data: [0xff; 32] });
+        assert_eq!(store.load::<Sample>(), Sample::default());
+
+        let sample = Sample {
+            counter: 0x1234,
+            flags: [1, 0, 7],
+        };
+        store.store(&sample).unwrap();
+        assert_eq!(store.load::<Sample>(), sample);
+
+        // Stored blob of another version is ignored.
+        assert_eq!(store.load::<SampleV2>(), SampleV2::default());
+
+        // Flipped bit is detected.
+        store.storage.data[HEADER + 1] ^= 0x10;
+        assert_eq!(store.load::<Sample>(), Sample::default());
+
+        // So is a corrupted CRC.
+        store.store(&sample).unwrap();
+        store.storage.data[blob_size(Sample::SIZE) - 1] ^= 0x01;
+        assert_eq!(store.load::<Sample>(), Sample::default());
+
+        // Zeroed region (blank backup registers).
+        store.storage.data = [0; 32];
+        assert_eq!(store.load::<Sample>(), Sample::default());
+    }
+}
@@ -0,0 +1,113 @@
+/*
+ * Sending into bounded queues with a configurable overflow policy. A node
+ * prioritizing responsiveness can drop events under a flood, while one
+ * prioritizing correctness blocks the producer until there's room.
+ */
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::channel::{Channel, TrySendError};
+
+use crate::config::OverflowPolicy;
+
+/// What a transmit does when the output queue has no room.
+pub enum WhenFull {
+    /// Output queue is full and can't immediately schedule message? Drop message.
+    Drop,
+    /// Output queue is full? Block until it's free. Might block indefinetely if CAN failed.
+    Block,
+    /// Wait a bit and retry, but don't block forever.
+    Wait,
+}
+
+/// Send the item according to the policy. Returns the item that was
+/// dropped - either the new one or the oldest queued one.
+pub async fn send<M: RawMutex, T, const N: usize>(
+    channel: &Channel<M, T, N>,
+    item: T,
+    policy: OverflowPolicy,
+) -> Option<T> {
+    match policy {
+        OverflowPolicy::Block => {
+            channel.send(item).await;
+            None
+        }
+        OverflowPolicy::DropNewest => match channel.try_send(item) {
+            Ok(()) => None,
+            Err(TrySendError::Full(item)) => Some(item),
+        },
+        OverflowPolicy::DropOldest => {
+            let mut item = item;
+            let mut dropped = None;
+            loop {
+                match channel.try_send(item) {
+                    Ok(()) => return dropped,
+                    Err(TrySendError::Full(rejected)) => {
+                        item = rejected;
+                        dropped = channel.try_receive().ok();
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub mod tests {
+    use super::*;
+    use embassy_futures::{block_on, join::join};
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+
+    type Queue = Channel<NoopRawMutex, u8, 2>;
+
+    fn full_queue() -> Queue {
+        let queue = Queue::new();
+        queue.try_send(1).unwrap();
+        queue.try_send(2).unwrap();
+        queue
+    }
+
+    fn drain(queue: &Queue) -> heapless::Vec<u8, 2> {
+        let mut items = heapless::Vec::new();
+        while let Ok(item) = queue.try_receive() {
+            items.push(item).unwrap();
+        }
+        items
+    }
+
+    pub fn overflow_policies() {
+        // New item is lost.
+        let queue = full_queue();
+        assert_eq!(
+            block_on(send(&queue, 3, OverflowPolicy::DropNewest)),
+            Some(3)
+        );
+        assert_eq!(drain(&queue), [1, 2]);
+
+        // Oldest item is replaced.
+        let queue = full_queue();
+        assert_eq!(
+            block_on(send(&queue, 3, OverflowPolicy::DropOldest)),
+            Some(1)
+        );
+        assert_eq!(drain(&queue), [2, 3]);
+
+        // Producer waits until the consumer makes room.
+        let queue = full_queue();
+        let (dropped, received) = block_on(join(
+            send(&queue, 3, OverflowPolicy::Block),
+            queue.receive(),
+        ));
+        assert_eq!(dropped, None);
+        assert_eq!(received, 1);
+        assert_eq!(drain(&queue), [2, 3]);
+
+        // Nothing is dropped while there's room.
+        let queue = Queue::new();
+        for policy in [
+            OverflowPolicy::Block,
+            OverflowPolicy::DropNewest,
+            OverflowPolicy::DropOldest,
+        ] {
+            assert_eq!(block_on(send(&queue, 4, policy)), None);
+            queue.try_receive().unwrap();
+        }
+    }
+}
@@ -0,0 +1,64 @@
+/*
+ * Rate-limited logging for warnings that can repeat in a tight loop (CAN
+ * frame errors, dead expanders). Each call site gets its own counter; the
+ * first occurrence is logged and then only every N-th, followed by a summary
+ * of how many were collapsed.
+ */
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// Per-call-site occurrence counter.
+pub struct RateLimit(AtomicU32);
+
+impl RateLimit {
+    pub const fn new() -> Self {
+        Self(AtomicU32::new(0))
+    }
+
+    /// Register an occurrence. Returns Some(number of suppressed occurrences
+    /// since the last logged one) if this one should be logged.
+    pub fn check(&self, every: u32) -> Option<u32> {
+        let seen = self.0.fetch_add(1, Ordering::Relaxed);
+        if every <= 1 || seen.is_multiple_of(every) {
+            Some(if seen == 0 { 0 } else { every.max(1) - 1 })
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Log with a given defmt level at most once every `every` occurrences.
+#[macro_export]
+macro_rules! log_limited {
+    ($level:ident, $every:expr, $($arg:tt)+) => {{
+        static LIMIT: $crate::components::rate_log::RateLimit =
+            $crate::components::rate_log::RateLimit::new();
+        if let Some(repeated) = LIMIT.check($every) {
+            defmt::$level!($($arg)+);
+            if repeated > 0 {
+                defmt::$level!("(previous message repeated {} times)", repeated);
+            }
+        }
+    }};
+}
+
+/// `defmt::warn!` logged once every `every` occurrences.
+#[macro_export]
+macro_rules! warn_limited {
+    ($every:expr, $($arg:tt)+) => {
+        $crate::log_limited!(warn, $every, $($arg)+)
+    };
+}
+
+/// `defmt::error!` logged once every `every` occurrences.
+#[macro_export]
+macro_rules! error_limited {
+    ($every:expr, $($arg:tt)+) => {
+        $crate::log_limited!(error, $every, $($arg)+)
+    };
+}
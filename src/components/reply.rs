@@ -0,0 +1,136 @@
+/*
+ * Request/response over the bus. The background read loop owns the receive
+ * stream, so a requester can't read it without racing the loop. Instead,
+ * while a request is open, the receive path offers a copy of every frame to
+ * the tap and the requester picks the first matching reply. One request at
+ * a time; frames are dropped when the tap is closed or full.
+ */
+use core::cell::Cell;
+
+use embassy_sync::blocking_mutex::{Mutex as BlockingMutex, raw::NoopRawMutex};
+use embassy_sync::channel::Channel;
+use embassy_sync::mutex::Mutex;
+use embassy_time::{Duration, Instant, with_deadline};
+
+use crate::components::message::{CanFrame, Message, MessageRaw};
+
+/// Frames buffered while the requester processes the previous one.
+const QUEUE: usize = 4;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, defmt::Format)]
+pub enum RecvError {
+    /// Request couldn't be transmitted.
+    NotSent,
+    /// No matching reply within the timeout.
+    Timeout,
+}
+
+pub struct ReplyTap {
+    open: BlockingMutex<NoopRawMutex, Cell<bool>>,
+    frames: Channel<NoopRawMutex, CanFrame, QUEUE>,
+    /// Serializes requests.
+    busy: Mutex<NoopRawMutex, ()>,
+}
+
+impl ReplyTap {
+    pub const fn new() -> Self {
+        Self {
+            open: BlockingMutex::new(Cell::new(false)),
+            frames: Channel::new(),
+            busy: Mutex::new(()),
+        }
+    }
+
+    /// Called for every received frame.
+    pub fn offer(&self, raw: &MessageRaw) {
+        if self.open.lock(|open| open.get()) && self.frames.try_send(raw.to_frame()).is_err() {
+            defmt::warn!("Reply queue full, dropped frame");
+        }
+    }
+
+    /// Send a request and wait for the first reply accepted by `matches`,
+    /// called with the sender address and the message.
+    pub async fn request(
+        &self,
+        send: impl Future<Output = bool>,
+        matches: impl Fn(u8, &Message) -> bool,
+        timeout: Duration,
+    ) -> Result<Message, RecvError> {
+        let _busy = self.busy.lock().await;
+        // Listen before sending - the reply can be quick.
+        self.frames.clear();
+        self.open.lock(|open| open.set(true));
+        let result = self.wait(send, matches, Instant::now() + timeout).await;
+        self.open.lock(|open| open.set(false));
+        result
+    }
+
+    async fn wait(
+        &self,
+        send: impl Future<Output = bool>,
+        matches: impl Fn(u8, &Message) -> bool,
+        deadline: Instant,
+    ) -> Result<Message, RecvError> {
+        if !send.await {
+            return Err(RecvError::NotSent);
+        }
+        loop {
+            let frame = with_deadline(deadline, self.frames.receive())
+                .await
+                .map_err(|_| RecvError::Timeout)?;
+            let raw = MessageRaw::from_frame(&frame);
+            if let Some(message) = Message::from_raw(&raw)
+                && matches(raw.addr_type().0, &message)
+            {
+                return Ok(message);
+            }
+        }
+    }
+}
+
+impl Default for ReplyTap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub mod tests {
+    use super::*;
+
+    pub fn ping_pong() {
+        let tap = ReplyTap::new();
+        let pong = |addr, body| Message::Pong { body }.to_raw(addr);
+
+        // Closed tap ignores the traffic.
+        tap.offer(&pong(3, 42));
+        assert!(tap.frames.is_empty());
+
+        // Mock transport: the bus answers while the request is sent.
+        let send = async {
+            tap.offer(&Message::RequestStatus.to_raw(3));
+            tap.offer(&pong(4, 42));
+            tap.offer(&pong(3, 7));
+            tap.offer(&pong(3, 42));
+            true
+        };
+        let matches = |addr, message: &Message| {
+            addr == 3 && matches!(message, Message::Pong { body } if *body == 42)
+        };
+        let timeout = Duration::from_millis(50);
+        let reply = embassy_futures::block_on(tap.request(send, matches, timeout));
+        assert!(matches!(reply, Ok(Message::Pong { body: 42 })));
+
+        // Closed again.
+        tap.offer(&pong(3, 42));
+        assert!(tap.frames.is_empty());
+
+        // No answer.
+        let start = Instant::now();
+        let reply = embassy_futures::block_on(tap.request(async { true }, matches, timeout));
+        assert!(matches!(reply, Err(RecvError::Timeout)));
+        assert!(start.elapsed() >= timeout);
+
+        let reply = embassy_futures::block_on(tap.request(async { false }, matches, timeout));
+        assert!(matches!(reply, Err(RecvError::NotSent)));
+    }
+}
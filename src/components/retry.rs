@@ -0,0 +1,109 @@
+/*
+ * Shared failure handling for peripherals that fail intermittently (I²C
+ * expanders, CAN). Errors are counted with a leaky counter - each success
+ * forgives one failure - so occasional glitches are tolerated, while a dead
+ * connection eventually hits the limit and the node reboots.
+ */
+use core::sync::atomic::{AtomicU16, Ordering};
+use embassy_time::Duration;
+
+/// Longest backoff is the base multiplied by 2^MAX_BACKOFF_SHIFT.
+const MAX_BACKOFF_SHIFT: u32 = 5;
+
+/// What the caller should do after a failure.
+#[derive(Debug, Eq, PartialEq, Clone, Copy, defmt::Format)]
+pub enum RetryAction {
+    /// Retry right away.
+    Continue,
+    /// Wait before retrying.
+    Backoff(Duration),
+    /// Too many errors. The connection is considered dead.
+    Panic,
+}
+
+pub struct RetryPolicy {
+    /// Errors over this limit require a panic.
+    max_errors: u16,
+    /// Backoff after the first failure, doubled with each next one. Zero
+    /// disables the backoff.
+    backoff_base: Duration,
+    errors: AtomicU16,
+}
+
+impl RetryPolicy {
+    /// Error count that never causes a panic.
+    pub const UNLIMITED: u16 = u16::MAX;
+
+    pub const fn new(max_errors: u16, backoff_base: Duration) -> Self {
+        Self {
+            max_errors,
+            backoff_base,
+            errors: AtomicU16::new(0),
+        }
+    }
+
+    /// Current error count.
+    pub fn errors(&self) -> u16 {
+        self.errors.load(Ordering::Relaxed)
+    }
+
+    /// Operation succeeded - forgive a single failure.
+    pub fn record_success(&self) {
+        let errors = self.errors();
+        if errors > 0 {
+            self.errors.store(errors - 1, Ordering::Relaxed);
+        }
+    }
+
+    /// Operation failed. Returns how to proceed.
+    pub fn record_failure(&self) -> RetryAction {
+        let errors = self.errors().saturating_add(1);
+        self.errors.store(errors, Ordering::Relaxed);
+
+        if self.max_errors != Self::UNLIMITED && errors > self.max_errors {
+            return RetryAction::Panic;
+        }
+        if self.backoff_base.as_ticks() == 0 {
+            return RetryAction::Continue;
+        }
+        let shift = (errors as u32 - 1).min(MAX_BACKOFF_SHIFT);
+        RetryAction::Backoff(self.backoff_base * (1 << shift))
+    }
+}
+
+pub mod tests {
+    use super::*;
+
+    pub fn policy_transitions() {
+        let base = Duration::from_millis(10);
+        let policy = RetryPolicy::new(3, base);
+        assert_eq!(policy.record_failure(), RetryAction::Backoff(base));
+        assert_eq!(policy.record_failure(), RetryAction::Backoff(base * 2));
+
+        // Success forgives a single failure.
+        policy.record_success();
+        assert_eq!(policy.errors(), 1);
+        assert_eq!(policy.record_failure(), RetryAction::Backoff(base * 2));
+        assert_eq!(policy.record_failure(), RetryAction::Backoff(base * 4));
+        assert_eq!(policy.record_failure(), RetryAction::Panic);
+
+        // Counter doesn't underflow.
+        let policy = RetryPolicy::new(60, Duration::from_ticks(0));
+        policy.record_success();
+        assert_eq!(policy.errors(), 0);
+        for _ in 0..60 {
+            assert_eq!(policy.record_failure(), RetryAction::Continue);
+        }
+        assert_eq!(policy.record_failure(), RetryAction::Panic);
+
+        // Backoff is capped and unlimited policy never panics.
+        let policy = RetryPolicy::new(RetryPolicy::UNLIMITED, base);
+        for _ in 0..100 {
+            assert_ne!(policy.record_failure(), RetryAction::Panic);
+        }
+        assert_eq!(
+            policy.record_failure(),
+            RetryAction::Backoff(base * (1 << MAX_BACKOFF_SHIFT))
+        );
+    }
+}
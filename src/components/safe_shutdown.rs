@@ -0,0 +1,72 @@
+/*
+ * Panic handling with a safe-shutdown hook.
+ *
+ * Before the node halts or reboots after a panic, all outputs are driven to
+ * their safe (inactive) level. Whether the node reboots or stays halted is
+ * selected by `config::PANIC_POLICY` (`panic-halt` feature).
+ *
+ * Constraints:
+ * - The hook runs in the panic context. Executor tasks don't run anymore, so
+ *   async code is driven by a busy `block_on` with a timeout.
+ * - If the panicking task held the outputs lock, outputs are left as they are -
+ *   we can't safely take the lock from under it.
+ * - Expander outputs need working I²C and time driver interrupts. If the panic
+ *   happened within an interrupt handler the expander write will time out.
+ *   Native pins are written synchronously and always work.
+ */
+use core::cell::Cell;
+use core::panic::PanicInfo;
+
+use embassy_sync::blocking_mutex::CriticalSectionMutex;
+
+use crate::config::{self, PanicPolicy};
+
+/// Something that can bring the hardware into a known-safe state.
+pub trait SafeShutdown {
+    /// Drive all outputs to their safe level. Must not block indefinitely.
+    fn safe_shutdown(&self);
+}
+
+/// Registered hook reference.
+#[derive(Clone, Copy)]
+struct Hook(&'static dyn SafeShutdown);
+
+// We run on a single core and the hook is only called from the panic handler.
+unsafe impl Send for Hook {}
+
+static HOOK: CriticalSectionMutex<Cell<Option<Hook>>> = CriticalSectionMutex::new(Cell::new(None));
+
+/// Register a hook called before the node halts or reboots on panic.
+pub fn register(hook: &'static dyn SafeShutdown) {
+    HOOK.lock(|cell| cell.set(Some(Hook(hook))));
+}
+
+/// Run registered hook (if any).
+pub fn run_hook() {
+    // Take the hook so a panic within the hook won't call it recursively.
+    let hook = HOOK.lock(|cell| cell.take());
+    if let Some(Hook(hook)) = hook {
+        hook.safe_shutdown();
+    }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    defmt::error!("Panic: {}", defmt::Display2Format(info));
+
+    run_hook();
+
+    match config::PANIC_POLICY {
+        PanicPolicy::Reboot => {
+            defmt::error!("Outputs are safe - rebooting");
+            cortex_m::peripheral::SCB::sys_reset();
+        }
+        PanicPolicy::Halt => {
+            defmt::error!("Outputs are safe - halting");
+            cortex_m::interrupt::disable();
+            loop {
+                cortex_m::asm::wfi();
+            }
+        }
+    }
+}
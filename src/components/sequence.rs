@@ -0,0 +1,160 @@
+/*
+ * Per-sender sequence numbers for detecting lost frames. A node rejoining
+ * after a bus-off might have missed frames and nothing else tells us. Frames
+ * with spare bits (see MessageRaw::set_sequence) carry a 4-bit number that
+ * is incremented per such frame. Zero means "not numbered", so numbers go
+ * 1..=15 and wrap back to 1.
+ */
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use crate::components::message::MessageRaw;
+
+/// Highest sequence number.
+const SEQUENCE_MAX: u8 = 15;
+/// Node addresses are 6 bit.
+const ADDRESSES: usize = 64;
+
+/// Number following the given one.
+pub fn next(seq: u8) -> u8 {
+    if seq >= SEQUENCE_MAX { 1 } else { seq + 1 }
+}
+
+/// Numbers transmitted frames of this node.
+pub struct Sequencer {
+    last: AtomicU8,
+}
+
+impl Default for Sequencer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sequencer {
+    pub const fn new() -> Self {
+        Self {
+            last: AtomicU8::new(0),
+        }
+    }
+
+    /// Number the frame if it has room for it.
+    pub fn stamp(&self, raw: &mut MessageRaw) {
+        let seq = next(self.last.load(Ordering::Relaxed));
+        if raw.set_sequence(seq) {
+            self.last.store(seq, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Result of checking a received sequence number.
+#[derive(Debug, Eq, PartialEq, Clone, Copy, defmt::Format)]
+pub enum SequenceCheck {
+    /// First numbered frame from this sender.
+    First,
+    InOrder,
+    /// Same number as the last frame.
+    Duplicate,
+    /// Sender started numbering from the beginning - probably rebooted.
+    Restarted,
+    /// Given number of frames was lost.
+    Gap(u8),
+}
+
+/// Tracks the last sequence number of each sender.
+pub struct SequenceTracker {
+    /// Zero until the first numbered frame.
+    last: [u8; ADDRESSES],
+    /// Total number of frames detected as lost.
+    pub lost: u32,
+}
+
+impl Default for SequenceTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SequenceTracker {
+    pub const fn new() -> Self {
+        Self {
+            last: [0; ADDRESSES],
+            lost: 0,
+        }
+    }
+
+    pub fn check(&mut self, addr: u8, seq: u8) -> SequenceCheck {
+        let Some(last) = self.last.get_mut(addr as usize) else {
+            return SequenceCheck::First;
+        };
+        let previous = core::mem::replace(last, seq);
+        if previous == 0 {
+            return SequenceCheck::First;
+        }
+        let expected = next(previous);
+        if seq == expected {
+            SequenceCheck::InOrder
+        } else if seq == previous {
+            SequenceCheck::Duplicate
+        } else if seq == 1 {
+            SequenceCheck::Restarted
+        } else {
+            // Distance on the 1..=15 ring.
+            let missed = (seq + SEQUENCE_MAX - expected) % SEQUENCE_MAX;
+            self.lost += missed as u32;
+            SequenceCheck::Gap(missed)
+        }
+    }
+}
+
+pub mod tests {
+    use super::*;
+    use crate::components::message::Message;
+
+    pub fn detects_skipped() {
+        let sequencer = Sequencer::new();
+        let mut tracker = SequenceTracker::new();
+        let info = Message::Info {
+            code: 0x0abc,
+            arg: 0xdead_beef,
+        };
+
+        let stamp = || {
+            let mut raw = info.to_raw(5);
+            sequencer.stamp(&mut raw);
+            raw
+        };
+        let first = stamp();
+        assert_eq!(first.sequence(), Some(1));
+        // Number uses only the spare bits.
+        assert_eq!(first.data_as_slice(), &[0xbc, 0x1a, 0xef, 0xbe, 0xad, 0xde]);
+        assert_eq!(tracker.check(5, 1), SequenceCheck::First);
+        assert_eq!(
+            tracker.check(5, stamp().sequence().unwrap()),
+            SequenceCheck::InOrder
+        );
+
+        // A frame lost on the way.
+        let lost = stamp();
+        assert_eq!(lost.sequence(), Some(3));
+        assert_eq!(
+            tracker.check(5, stamp().sequence().unwrap()),
+            SequenceCheck::Gap(1)
+        );
+        assert_eq!(tracker.lost, 1);
+        assert_eq!(tracker.check(5, 4), SequenceCheck::Duplicate);
+
+        // Senders are tracked independently and numbers wrap over zero.
+        assert_eq!(tracker.check(6, 14), SequenceCheck::First);
+        assert_eq!(tracker.check(6, 15), SequenceCheck::InOrder);
+        assert_eq!(tracker.check(6, 2), SequenceCheck::Gap(1));
+        assert_eq!(tracker.check(6, 1), SequenceCheck::Restarted);
+        assert_eq!(tracker.lost, 2);
+
+        // Frames without spare bits and unnumbered frames carry no number.
+        let mut raw = Message::Ping { body: 1 }.to_raw(5);
+        sequencer.stamp(&mut raw);
+        assert_eq!(raw.sequence(), None);
+        assert_eq!(info.to_raw(5).sequence(), None);
+        assert_eq!(stamp().sequence(), Some(5));
+    }
+}
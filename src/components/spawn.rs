@@ -0,0 +1,154 @@
+/*
+ * Boot-time task spawning. A failed spawn (eg. an exhausted task pool) used
+ * to panic on unwrap with an opaque message. All spawns are attempted, the
+ * failures are logged with the task name and broadcast as TaskSpawnFailed
+ * errors carrying the task id. The node halts only when an essential task is
+ * missing, otherwise it continues in a degraded mode.
+ */
+use heapless::Vec;
+
+use crate::components::interconnect::Interconnect;
+use crate::components::message::{Message, args::ErrorCode};
+use crate::components::queue::WhenFull;
+
+/// Tasks spawned at boot. Id is sent in the error detail.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, defmt::Format)]
+#[repr(u8)]
+pub enum TaskId {
+    Status = 1,
+    UsbTransceiver = 2,
+    ExpanderInputs = 3,
+    EventPump = 4,
+    EventConverter = 5,
+    ReadInterconnect = 6,
+    ReadUsb = 7,
+    SafeMode = 8,
+    RotaryEncoder = 9,
+    AdcInput = 10,
+}
+
+impl TaskId {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Status => "status",
+            Self::UsbTransceiver => "usb_transceiver",
+            Self::ExpanderInputs => "expander_inputs",
+            Self::EventPump => "event_pump",
+            Self::EventConverter => "event_converter",
+            Self::ReadInterconnect => "read_interconnect",
+            Self::ReadUsb => "read_usb",
+            Self::SafeMode => "safe_mode",
+            Self::RotaryEncoder => "rotary_encoder",
+            Self::AdcInput => "adc_input",
+        }
+    }
+}
+
+/// Max number of failures remembered.
+const MAX_FAILURES: usize = 8;
+
+/// Failed spawns of a boot stage.
+#[must_use = "Spawn failures need to be reported"]
+#[derive(Default)]
+pub struct SpawnReport {
+    /// Failed task and whether the node can run without it.
+    failed: Vec<(TaskId, bool), MAX_FAILURES>,
+}
+
+impl SpawnReport {
+    pub const fn new() -> Self {
+        Self { failed: Vec::new() }
+    }
+
+    /// Attempt a spawn and record its failure. Returns true if spawned.
+    pub fn spawn<E: defmt::Format>(
+        &mut self,
+        task: TaskId,
+        essential: bool,
+        spawn: impl FnOnce() -> Result<(), E>,
+    ) -> bool {
+        let Err(err) = spawn() else {
+            return true;
+        };
+        defmt::error!("Unable to spawn task {}: {:?}", task.name(), err);
+        // Full list still halts if any essential task failed.
+        if self.failed.push((task, essential)).is_err() && essential {
+            self.failed[MAX_FAILURES - 1] = (task, essential);
+        }
+        false
+    }
+
+    /// Tasks that failed to spawn.
+    pub fn failed(&self) -> impl Iterator<Item = TaskId> + '_ {
+        self.failed.iter().map(|(task, _)| *task)
+    }
+
+    /// Node can't work without some of the failed tasks.
+    pub fn is_fatal(&self) -> bool {
+        self.failed.iter().any(|(_, essential)| *essential)
+    }
+
+    /// Error messages identifying the failed tasks.
+    pub fn messages(&self) -> impl Iterator<Item = Message> + '_ {
+        self.failed().map(|task| Message::Error {
+            code: ErrorCode::TaskSpawnFailed.with_detail(task as u8),
+        })
+    }
+
+    /// Broadcast the failures. Halts if an essential task is missing.
+    pub async fn report(self, interconnect: &Interconnect) {
+        // Don't hang the boot if the bus is down too.
+        for message in self.messages() {
+            interconnect
+                .transmit_response(&message, WhenFull::Drop)
+                .await;
+        }
+        if self.is_fatal() {
+            defmt::panic!("Essential task failed to spawn");
+        }
+        if !self.failed.is_empty() {
+            defmt::warn!("Running in degraded mode");
+        }
+    }
+}
+
+pub mod tests {
+    use super::*;
+
+    pub fn failed_spawn_reported() {
+        let mut report = SpawnReport::new();
+        assert!(report.spawn(TaskId::Status, false, || Ok::<(), ()>(())));
+        assert!(report.failed().next().is_none());
+        assert!(!report.is_fatal());
+
+        // Pool exhaustion of an optional task - degraded mode.
+        assert!(!report.spawn(TaskId::ExpanderInputs, false, || Err(())));
+        assert!(!report.is_fatal());
+        let codes: Vec<u32, MAX_FAILURES> = report
+            .messages()
+            .map(|message| match message {
+                Message::Error { code } => code,
+                _ => panic!("Error expected"),
+            })
+            .collect();
+        assert_eq!(codes.len(), 1);
+        assert_eq!(
+            ErrorCode::from_u32(codes[0]),
+            Some(ErrorCode::TaskSpawnFailed)
+        );
+        assert_eq!(ErrorCode::detail(codes[0]), TaskId::ExpanderInputs as u8);
+
+        // Missing essential task halts the node.
+        assert!(!report.spawn(TaskId::ReadInterconnect, true, || Err(())));
+        assert!(report.is_fatal());
+        assert_eq!(report.failed().count(), 2);
+
+        // Essential failure is kept even when the list is full.
+        let mut report = SpawnReport::new();
+        for _ in 0..MAX_FAILURES {
+            let _ = report.spawn(TaskId::Status, false, || Err(()));
+        }
+        let _ = report.spawn(TaskId::EventPump, true, || Err(()));
+        assert!(report.is_fatal());
+    }
+}
@@ -0,0 +1,235 @@
+/*
+ * Gate-side cache of the bus state. Outputs, last input triggers and the
+ * status of each node are taken from the OutputChanged, InputChanged and
+ * Status frames passing through the gate, so a host (re)connecting over USB
+ * can get the whole state instead of waiting for changes it missed.
+ *
+ * The dump is requested with a gate command packet (see bus_filter):
+ * [GATE_COMMAND] [STATE_DUMP] [0]
+ * and answered with regular frame packets, as if the nodes sent them again:
+ * OutputChanged per known output, InputChanged per known input and the last
+ * Status of each node.
+ */
+use core::cell::RefCell;
+
+use embassy_sync::blocking_mutex::CriticalSectionMutex;
+use heapless::Vec;
+
+use crate::buttonsmash::consts::{InIdx, MAX_OUTPUTS, OutIdx};
+use crate::components::bus_filter::GATE_COMMAND;
+use crate::components::message::{Message, MessageRaw, args};
+
+/// Gate command dumping the cached state.
+pub const STATE_DUMP: u8 = 0x02;
+/// Max number of cached nodes.
+pub const MAX_NODES: usize = 16;
+/// Max number of cached inputs per node.
+pub const MAX_INPUTS: usize = 32;
+
+/// Known state of a single node.
+#[derive(Clone)]
+pub struct NodeState {
+    addr: u8,
+    /// Outputs with a known state.
+    known: u128,
+    /// States of the known outputs.
+    on: u128,
+    /// Last trigger of each input.
+    inputs: Vec<(InIdx, args::Trigger), MAX_INPUTS>,
+    /// Uptime, errors and warnings of the last Status.
+    status: Option<(u32, u16, u16)>,
+}
+
+impl NodeState {
+    const fn new(addr: u8) -> Self {
+        Self {
+            addr,
+            known: 0,
+            on: 0,
+            inputs: Vec::new(),
+            status: None,
+        }
+    }
+
+    pub fn addr(&self) -> u8 {
+        self.addr
+    }
+
+    /// Cached state of an output, None if not seen yet.
+    pub fn output(&self, out: OutIdx) -> Option<bool> {
+        let bit = 1u128.checked_shl(out as u32)?;
+        (self.known & bit != 0).then_some(self.on & bit != 0)
+    }
+
+    fn set_output(&mut self, out: OutIdx, on: bool) -> bool {
+        if out as usize >= MAX_OUTPUTS {
+            return false;
+        }
+        let bit = 1 << out;
+        self.known |= bit;
+        if on {
+            self.on |= bit;
+        } else {
+            self.on &= !bit;
+        }
+        true
+    }
+
+    fn set_input(&mut self, input: InIdx, trigger: args::Trigger) -> bool {
+        if let Some(entry) = self.inputs.iter_mut().find(|(idx, _)| *idx == input) {
+            entry.1 = trigger;
+            return true;
+        }
+        self.inputs.push((input, trigger)).is_ok()
+    }
+
+    /// The state as frames of this node.
+    pub fn dump(&self) -> impl Iterator<Item = MessageRaw> + '_ {
+        let outputs = (0..MAX_OUTPUTS as u8).filter_map(|out| {
+            let on = self.output(out)?;
+            let message = Message::OutputChanged {
+                output: out,
+                state: args::OutputChangeRequest::from_bool(on),
+            };
+            Some(message.to_raw(self.addr))
+        });
+        let inputs = self.inputs.iter().map(|(input, trigger)| {
+            let message = Message::InputChanged {
+                input: *input,
+                trigger: *trigger,
+            };
+            message.to_raw(self.addr)
+        });
+        let status = self.status.map(|(uptime, errors, warnings)| {
+            let message = Message::Status {
+                uptime,
+                errors,
+                warnings,
+            };
+            message.to_raw(self.addr)
+        });
+        outputs.chain(inputs).chain(status)
+    }
+}
+
+/// Latest state of the nodes seen on the bus.
+pub struct StateCache {
+    nodes: Vec<NodeState, MAX_NODES>,
+}
+
+impl Default for StateCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StateCache {
+    pub const fn new() -> Self {
+        Self { nodes: Vec::new() }
+    }
+
+    fn node_mut(&mut self, addr: u8) -> Option<&mut NodeState> {
+        let pos = match self.nodes.iter().position(|node| node.addr == addr) {
+            Some(pos) => pos,
+            None => {
+                if self.nodes.push(NodeState::new(addr)).is_err() {
+                    crate::warn_limited!(50, "State cache is full, node {} not cached", addr);
+                    return None;
+                }
+                self.nodes.len() - 1
+            }
+        };
+        Some(&mut self.nodes[pos])
+    }
+
+    /// Store the state carried by a frame. Returns false if it carries none
+    /// or doesn't fit.
+    pub fn update(&mut self, raw: &MessageRaw) -> bool {
+        let (addr, _) = raw.addr_type();
+        if let Some((out, on)) = raw.output_changed() {
+            self.node_mut(addr)
+                .is_some_and(|node| node.set_output(out, on))
+        } else if let Some((input, trigger)) = raw.input_changed() {
+            self.node_mut(addr)
+                .is_some_and(|node| node.set_input(input, trigger))
+        } else if let Some(status) = raw.status() {
+            self.node_mut(addr).is_some_and(|node| {
+                node.status = Some(status);
+                true
+            })
+        } else {
+            false
+        }
+    }
+
+    /// Copy of a node at a position, to be dumped outside of the lock.
+    pub fn node(&self, pos: usize) -> Option<NodeState> {
+        self.nodes.get(pos).cloned()
+    }
+
+    /// Is the USB packet the dump command?
+    pub fn is_dump_command(packet: &[u8]) -> bool {
+        packet == [GATE_COMMAND, STATE_DUMP, 0]
+    }
+}
+
+/// Cache updated on the gate forward path.
+pub static STATE_CACHE: CriticalSectionMutex<RefCell<StateCache>> =
+    CriticalSectionMutex::new(RefCell::new(StateCache::new()));
+
+pub mod tests {
+    use super::*;
+
+    pub fn dump_has_latest_outputs() {
+        let mut cache = StateCache::new();
+        let changed = |addr, output, on| {
+            Message::OutputChanged {
+                output,
+                state: args::OutputChangeRequest::from_bool(on),
+            }
+            .to_raw(addr)
+        };
+        for (addr, output, on) in [
+            (5, 1, true),
+            (5, 2, true),
+            (7, 1, true),
+            (5, 1, false),
+            (5, 3, true),
+            (5, 2, false),
+        ] {
+            assert!(cache.update(&changed(addr, output, on)));
+        }
+        let status = Message::Status {
+            uptime: 100,
+            errors: 1,
+            warnings: 0,
+        };
+        assert!(cache.update(&status.to_raw(7)));
+        // Requests don't change the state.
+        let request = Message::SetOutput {
+            output: 4,
+            state: args::OutputChangeRequest::On,
+            source: None,
+        };
+        assert!(!cache.update(&request.to_raw(5)));
+
+        let node = cache.node(0).unwrap();
+        assert_eq!(node.addr(), 5);
+        let outputs: Vec<(OutIdx, bool), 8> =
+            node.dump().filter_map(|raw| raw.output_changed()).collect();
+        assert_eq!(outputs.as_slice(), &[(1, false), (2, false), (3, true)]);
+        assert_eq!(node.output(4), None);
+
+        let node = cache.node(1).unwrap();
+        assert_eq!(node.addr(), 7);
+        let frames: Vec<MessageRaw, 8> = node.dump().collect();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].output_changed(), Some((1, true)));
+        assert_eq!(frames[1].status(), Some((100, 1, 0)));
+        assert!(frames.iter().all(|raw| raw.addr_type().0 == 7));
+        assert!(cache.node(2).is_none());
+
+        assert!(StateCache::is_dump_command(&[GATE_COMMAND, STATE_DUMP, 0]));
+        assert!(!StateCache::is_dump_command(&[GATE_COMMAND, STATE_DUMP]));
+    }
+}
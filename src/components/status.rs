@@ -1,11 +1,24 @@
-use core::cell::UnsafeCell;
-use core::sync::atomic::{AtomicU32, Ordering};
+use core::cell::{RefCell, UnsafeCell};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use defmt::info;
-use embassy_stm32::gpio::Output;
 use embassy_time::{Duration, Instant, with_timeout};
+use embedded_hal::digital::PinState;
+use embedded_hal::pwm::SetDutyCycle;
+use heapless::Vec;
 
-use embassy_sync::blocking_mutex::raw::NoopRawMutex;
-use embassy_sync::channel::Channel;
+use crate::components::message::{Message, args};
+use crate::config::{CodeBlink, StatusCode};
+use crate::io::logical_output::{LogicalOutput, Polarity};
+
+use embassy_sync::blocking_mutex::{Mutex, raw::NoopRawMutex};
+use embassy_sync::signal::Signal;
+
+/// Whole seconds between boot and now. Saturates instead of wrapping on
+/// very long runs (and gives 0 for a clock before the boot).
+pub fn uptime_secs(boot_time: Instant, now: Instant) -> u32 {
+    let secs = now.saturating_duration_since(boot_time).as_secs();
+    u32::try_from(secs).unwrap_or(u32::MAX)
+}
 
 /// Simplify API of atomics for this usecase.
 pub struct Counter(AtomicU32);
@@ -51,8 +64,12 @@ pub struct Counters {
     pub can_frame_error: Counter,
     /// Output CAN queue is full.
     pub can_queue_full: Counter,
+    /// Received CAN frame was longer than 8 bytes and got truncated.
+    pub can_frame_truncated: Counter,
     /// Output CAN queue was full and we either dropped message immediately or waited and dropped.
     pub can_drop: Counter,
+    /// Actuator pin failed to switch.
+    pub actuator_error: Counter,
 }
 
 pub static COUNTERS: Counters = Counters {
@@ -62,10 +79,55 @@ pub static COUNTERS: Counters = Counters {
     expander_output_error: Counter::new(),
     can_frame_error: Counter::new(),
     can_queue_full: Counter::new(),
+    can_frame_truncated: Counter::new(),
     can_drop: Counter::new(),
+    actuator_error: Counter::new(),
 };
 
 impl Counters {
+    /// Number of counters.
+    pub const COUNT: usize = 9;
+
+    /// Current values in the declaration order.
+    pub fn values(&self) -> [u32; Self::COUNT] {
+        [
+            self.input_queue_full.get(),
+            self.output_queue_full.get(),
+            self.expander_input_error.get(),
+            self.expander_output_error.get(),
+            self.can_frame_error.get(),
+            self.can_queue_full.get(),
+            self.can_frame_truncated.get(),
+            self.can_drop.get(),
+            self.actuator_error.get(),
+        ]
+    }
+
+    /// Failures and recoverable problems (full queues, drops), saturated.
+    pub fn totals(&self) -> (u16, u16) {
+        let sum = |counters: &[&Counter]| {
+            let total: u32 = counters
+                .iter()
+                .map(|c| c.get())
+                .fold(0, u32::saturating_add);
+            total.min(u16::MAX as u32) as u16
+        };
+        let errors = sum(&[
+            &self.expander_input_error,
+            &self.expander_output_error,
+            &self.can_frame_error,
+            &self.actuator_error,
+        ]);
+        let warnings = sum(&[
+            &self.input_queue_full,
+            &self.output_queue_full,
+            &self.can_queue_full,
+            &self.can_frame_truncated,
+            &self.can_drop,
+        ]);
+        (errors, warnings)
+    }
+
     /// Has any problem been detected?
     pub fn has_problem(&self) -> bool {
         self.input_queue_full.get() > 0
@@ -74,11 +136,13 @@ impl Counters {
             || self.expander_output_error.get() > 0
             || self.can_frame_error.get() > 0
             || self.can_queue_full.get() > 0
+            || self.can_frame_truncated.get() > 0
             || self.can_drop.get() > 0
+            || self.actuator_error.get() > 0
     }
 }
 
-#[derive(Debug, PartialEq, Eq, defmt::Format)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, defmt::Format)]
 pub enum Blink {
     /// Just started
     Init,
@@ -90,50 +154,258 @@ pub enum Blink {
     Warning,
     /// We are mostly IDLE, but some error happened.
     Attention,
+    /// Maintenance mode - local inputs are ignored.
+    Maintenance,
+    /// Emitted error/info code as a number of slow blinks.
+    Code(u8),
 }
 
 impl Blink {
-    fn to_time(&self) -> (Duration, Duration, usize) {
+    /// Higher severity states are not dropped in favor of the lower ones.
+    fn severity(&self) -> u8 {
+        match self {
+            Blink::Idle => 0,
+            Blink::Init | Blink::Active => 1,
+            Blink::Maintenance => 2,
+            Blink::Warning | Blink::Attention | Blink::Code(_) => 3,
+        }
+    }
+
+    fn to_time(self) -> (Duration, Duration, usize) {
         let (on, off, count) = match self {
             // Externally triggered
             Blink::Active => (10, 50, 8),
             Blink::Warning => (100, 100, 10),
+            // Count is the number of repeats after the first blink.
+            Blink::Code(blinks) => (400, 400, blinks.saturating_sub(1) as usize),
 
             // Special internal
             Blink::Init => (200, 200, 3),
             Blink::Idle => (10, 3000, 0),
             Blink::Attention => (300, 3000, 0),
+            Blink::Maintenance => (1000, 1000, 0),
         };
         (Duration::from_millis(on), Duration::from_millis(off), count)
     }
 }
 
+/// Is it the code of the message? Error details are ignored.
+fn code_matches(code: StatusCode, message: &Message) -> bool {
+    match (code, message) {
+        (StatusCode::Error(error), Message::Error { code }) => {
+            args::ErrorCode::from_u32(*code) == Some(error)
+        }
+        (StatusCode::Info(info), Message::Info { code, .. }) => info.to_bytes() == *code,
+        _ => false,
+    }
+}
+
+/// Pending LED states. Identical states are coalesced. When full, a new state
+/// evicts a queued one of lower severity, so a warning is not lost in a burst
+/// of activity.
+pub struct BlinkQueue<const N: usize> {
+    pending: Mutex<NoopRawMutex, RefCell<Vec<Blink, N>>>,
+    signal: Signal<NoopRawMutex, ()>,
+}
+
+impl<const N: usize> Default for BlinkQueue<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> BlinkQueue<N> {
+    pub const fn new() -> Self {
+        Self {
+            pending: Mutex::new(RefCell::new(Vec::new())),
+            signal: Signal::new(),
+        }
+    }
+
+    /// Queue a state. Returns false if it was dropped.
+    pub fn push(&self, blink: Blink) -> bool {
+        let queued = self.pending.lock(|pending| {
+            let mut pending = pending.borrow_mut();
+            if pending.contains(&blink) {
+                return true;
+            }
+            if pending.push(blink).is_ok() {
+                return true;
+            }
+            // Full. Evict the latest queued state of the lowest severity.
+            let Some((pos, weakest)) = pending
+                .iter()
+                .enumerate()
+                .rev()
+                .min_by_key(|(_, queued)| queued.severity())
+            else {
+                return false;
+            };
+            if weakest.severity() >= blink.severity() {
+                return false;
+            }
+            pending.remove(pos);
+            pending.push(blink).is_ok()
+        });
+        if queued {
+            self.signal.signal(());
+        }
+        queued
+    }
+
+    /// Take the most severe pending state. Oldest first if equal.
+    pub fn try_pop(&self) -> Option<Blink> {
+        self.pending.lock(|pending| {
+            let mut pending = pending.borrow_mut();
+            let (pos, _) = pending
+                .iter()
+                .enumerate()
+                .rev()
+                .max_by_key(|(_, queued)| queued.severity())?;
+            Some(pending.remove(pos))
+        })
+    }
+
+    /// Wait for a state.
+    pub async fn pop(&self) -> Blink {
+        loop {
+            if let Some(blink) = self.try_pop() {
+                return blink;
+            }
+            self.signal.wait().await;
+        }
+    }
+}
+
+/// Brightness of the idle blink on dimmable LEDs, 0-100%.
+const IDLE_BRIGHTNESS: u8 = 20;
+
+/// Physical line driving the status LED.
+pub trait LedLine {
+    fn set_level(&mut self, level: PinState);
+
+    /// Set the share of time at the high level, 0-100%. Returns false if the
+    /// line can't be dimmed.
+    fn set_duty(&mut self, _high_percent: u8) -> bool {
+        false
+    }
+}
+
+/// PWM channel for a dimmable LED.
+pub struct PwmLed<P>(pub P);
+
+impl<P: SetDutyCycle> LedLine for PwmLed<P> {
+    fn set_level(&mut self, level: PinState) {
+        let _ = match level {
+            PinState::High => self.0.set_duty_cycle_fully_on(),
+            PinState::Low => self.0.set_duty_cycle_fully_off(),
+        };
+    }
+
+    fn set_duty(&mut self, high_percent: u8) -> bool {
+        self.0.set_duty_cycle_percent(high_percent).is_ok()
+    }
+}
+
+/// LED with a polarity. All LED writes go through it.
+pub struct Led<L> {
+    line: L,
+    output: LogicalOutput,
+}
+
+impl<L: LedLine> Led<L> {
+    pub fn new(line: L, polarity: Polarity) -> Self {
+        Self {
+            line,
+            output: LogicalOutput::new(polarity),
+        }
+    }
+
+    /// Light the LED with a brightness of 0-100%. Lines that can't be dimmed
+    /// are lit fully.
+    pub fn set(&mut self, on: bool, brightness: u8) {
+        let on = on && brightness > 0;
+        self.output.set_written(on);
+        if on && brightness < 100 {
+            let high_percent = match self.output.level(true) {
+                PinState::High => brightness,
+                PinState::Low => 100 - brightness,
+            };
+            if self.line.set_duty(high_percent) {
+                return;
+            }
+        }
+        self.line.set_level(self.output.level(on));
+    }
+}
+
 /// Controls status LED.
-pub struct Status {
-    led: UnsafeCell<Output<'static>>,
-    channel: Channel<NoopRawMutex, Blink, 3>,
+pub struct Status<L: LedLine> {
+    led: UnsafeCell<Led<L>>,
+    queue: BlinkQueue<3>,
+    /// Maintenance mode is shown instead of idle/attention.
+    maintenance: AtomicBool,
+    /// Lasting problem that needs attention (eg. silent bus).
+    attention: AtomicBool,
+    /// Patterns of emitted codes.
+    code_blinks: &'static [CodeBlink],
 
     pub boot_time: Instant,
 }
 
-impl Status {
-    pub fn new(led: Output<'static>) -> Self {
-        let channel = Channel::<NoopRawMutex, Blink, 3>::new();
+impl<L: LedLine> Status<L> {
+    pub fn new(led: L, polarity: Polarity) -> Self {
         Status {
-            led: UnsafeCell::new(led),
-            channel,
+            led: UnsafeCell::new(Led::new(led, polarity)),
+            queue: BlinkQueue::new(),
+            maintenance: AtomicBool::new(false),
+            attention: AtomicBool::new(false),
+            code_blinks: &[],
             boot_time: Instant::now(),
         }
     }
 
-    /// Set state to be displayed. Might block if queue full.
+    /// Show emitted codes with the patterns of the table.
+    pub fn with_code_blinks(mut self, code_blinks: &'static [CodeBlink]) -> Self {
+        self.code_blinks = code_blinks;
+        self
+    }
+
+    /// State showing an emitted message. Mapped codes get their pattern,
+    /// other errors the generic warning. None - nothing to show.
+    pub fn blink_for(&self, message: &Message) -> Option<Blink> {
+        let mapped = self
+            .code_blinks
+            .iter()
+            .find(|blink| code_matches(blink.code, message));
+        match (mapped, message) {
+            (Some(blink), _) => Some(Blink::Code(blink.blinks)),
+            (None, Message::Error { .. }) => Some(Blink::Warning),
+            (None, _) => None,
+        }
+    }
+
+    /// Show an emitted error/info frame on the LED.
+    pub fn show_message(&self, message: &Message) {
+        if let Some(blink) = self.blink_for(message) {
+            self.try_set_state(blink);
+        }
+    }
+
+    /// Seconds since boot, saturated.
+    pub fn uptime_secs(&self) -> u32 {
+        uptime_secs(self.boot_time, Instant::now())
+    }
+
+    /// Set state to be displayed. Never blocks, see `BlinkQueue` for what
+    /// happens when queue is full.
     pub async fn set_state(&self, blink: Blink) {
-        self.channel.send(blink).await;
+        self.try_set_state(blink);
     }
 
     /// Don't block and ignore failures.
     pub fn try_set_state(&self, blink: Blink) {
-        let _ = self.channel.try_send(blink);
+        self.queue.push(blink);
     }
 
     /// Set state to active errorlessly.
@@ -146,18 +418,40 @@ impl Status {
         self.try_set_state(Blink::Warning);
     }
 
+    /// Show (or stop showing) the maintenance mode.
+    pub fn set_maintenance(&self, enabled: bool) {
+        self.maintenance.store(enabled, Ordering::Relaxed);
+        self.try_set_state(if enabled {
+            Blink::Maintenance
+        } else {
+            Blink::Idle
+        });
+    }
+
+    /// Show (or stop showing) the attention state instead of idle.
+    pub fn set_attention(&self, enabled: bool) {
+        self.attention.store(enabled, Ordering::Relaxed);
+        self.try_set_state(if enabled {
+            Blink::Attention
+        } else {
+            Blink::Idle
+        });
+    }
+
     async fn read_wait(
         &self,
         timeout: Duration,
+        current: &mut Blink,
         on_t: &mut Duration,
         off_t: &mut Duration,
         count: &mut usize,
     ) {
-        let result = with_timeout(timeout, self.channel.receive()).await;
+        let result = with_timeout(timeout, self.queue.pop()).await;
         if let Ok(incoming) = result {
             // Data or timeout interrupted with data.
             let (new_on_t, new_off_t, new_count) = incoming.to_time();
             info!("System status: {:?}", incoming);
+            *current = incoming;
             *on_t = new_on_t;
             *off_t = new_off_t;
             *count = new_count;
@@ -169,24 +463,33 @@ impl Status {
     pub async fn update_loop(&self) {
         // That's safe if there's only one update loop running.
         let led = unsafe { &mut *self.led.get() };
-        let (mut on_t, mut off_t, mut count) = Blink::Init.to_time();
+        let mut current = Blink::Init;
+        let (mut on_t, mut off_t, mut count) = current.to_time();
         let mut cnt = 0;
         loop {
-            led.set_high();
-            self.read_wait(on_t, &mut on_t, &mut off_t, &mut count)
+            let brightness = if current == Blink::Idle {
+                IDLE_BRIGHTNESS
+            } else {
+                100
+            };
+            led.set(true, brightness);
+            self.read_wait(on_t, &mut current, &mut on_t, &mut off_t, &mut count)
                 .await;
 
-            led.set_low();
-            self.read_wait(off_t, &mut on_t, &mut off_t, &mut count)
+            led.set(false, 0);
+            self.read_wait(off_t, &mut current, &mut on_t, &mut off_t, &mut count)
                 .await;
 
             // When we reach count 0 - get back to blinking the idle/attention time. Count 0 means forever.
             if count == 0 {
-                if COUNTERS.has_problem() {
-                    (on_t, off_t, count) = Blink::Attention.to_time();
+                current = if self.maintenance.load(Ordering::Relaxed) {
+                    Blink::Maintenance
+                } else if COUNTERS.has_problem() || self.attention.load(Ordering::Relaxed) {
+                    Blink::Attention
                 } else {
-                    (on_t, off_t, count) = Blink::Idle.to_time();
-                }
+                    Blink::Idle
+                };
+                (on_t, off_t, count) = current.to_time();
             } else {
                 count -= 1;
             }
@@ -198,3 +501,184 @@ impl Status {
         }
     }
 }
+
+pub mod tests {
+    use super::*;
+
+    pub fn warning_not_starved() {
+        let queue: BlinkQueue<3> = BlinkQueue::new();
+
+        // Repeated states are coalesced.
+        for _ in 0..10 {
+            assert!(queue.push(Blink::Active));
+        }
+        assert_eq!(queue.try_pop(), Some(Blink::Active));
+        assert_eq!(queue.try_pop(), None);
+
+        // Fill the queue with lower severity states.
+        assert!(queue.push(Blink::Active));
+        assert!(queue.push(Blink::Idle));
+        assert!(queue.push(Blink::Init));
+        assert!(queue.push(Blink::Warning));
+        for _ in 0..10 {
+            queue.push(Blink::Active);
+            queue.push(Blink::Init);
+        }
+        assert!(queue.push(Blink::Attention));
+        assert!(queue.push(Blink::Maintenance));
+        // Only more severe states are queued - activity is dropped now.
+        assert!(!queue.push(Blink::Active));
+
+        assert_eq!(queue.try_pop(), Some(Blink::Warning));
+        assert_eq!(queue.try_pop(), Some(Blink::Attention));
+        assert_eq!(queue.try_pop(), Some(Blink::Maintenance));
+        assert_eq!(queue.try_pop(), None);
+    }
+
+    /// Line that records the physical state.
+    #[derive(Default)]
+    struct MockLine {
+        level: Option<PinState>,
+        duty: Option<u8>,
+        dimmable: bool,
+    }
+
+    impl LedLine for MockLine {
+        fn set_level(&mut self, level: PinState) {
+            self.level = Some(level);
+            self.duty = None;
+        }
+
+        fn set_duty(&mut self, high_percent: u8) -> bool {
+            if self.dimmable {
+                self.level = None;
+                self.duty = Some(high_percent);
+            }
+            self.dimmable
+        }
+    }
+
+    pub fn led_polarity() {
+        let mut high = Led::new(MockLine::default(), Polarity::ActiveHigh);
+        high.set(true, 100);
+        assert_eq!(high.line.level, Some(PinState::High));
+        high.set(false, 100);
+        assert_eq!(high.line.level, Some(PinState::Low));
+
+        let mut low = Led::new(MockLine::default(), Polarity::ActiveLow);
+        low.set(true, 100);
+        assert_eq!(low.line.level, Some(PinState::Low));
+        low.set(false, 100);
+        assert_eq!(low.line.level, Some(PinState::High));
+
+        // Plain pins ignore the brightness, but zero is off.
+        low.set(true, IDLE_BRIGHTNESS);
+        assert_eq!(low.line.level, Some(PinState::Low));
+        low.set(true, 0);
+        assert_eq!(low.line.level, Some(PinState::High));
+
+        // Dimming inverts the duty for active-low LEDs.
+        let line = MockLine {
+            dimmable: true,
+            ..Default::default()
+        };
+        let mut low = Led::new(line, Polarity::ActiveLow);
+        low.set(true, 20);
+        assert_eq!(low.line.duty, Some(80));
+        low.set(false, 20);
+        assert_eq!(low.line.level, Some(PinState::High));
+
+        let line = MockLine {
+            dimmable: true,
+            ..Default::default()
+        };
+        let mut high = Led::new(line, Polarity::ActiveHigh);
+        high.set(true, 20);
+        assert_eq!(high.line.duty, Some(20));
+        high.set(true, 100);
+        assert_eq!(high.line.level, Some(PinState::High));
+    }
+
+    pub fn code_blink_patterns() {
+        use args::{ErrorCode, InfoCode};
+
+        static CODE_BLINKS: [CodeBlink; 2] = [
+            CodeBlink {
+                code: StatusCode::Error(ErrorCode::CanBusOff),
+                blinks: 2,
+            },
+            CodeBlink {
+                code: StatusCode::Info(InfoCode::Started),
+                blinks: 1,
+            },
+        ];
+        let status =
+            Status::new(MockLine::default(), Polarity::ActiveHigh).with_code_blinks(&CODE_BLINKS);
+        let error = |code: ErrorCode| Message::Error {
+            code: code.to_u32(),
+        };
+
+        // Mapped error gets its pattern instead of the generic warning.
+        status.show_message(&error(ErrorCode::CanBusOff));
+        assert_eq!(status.queue.try_pop(), Some(Blink::Code(2)));
+        let detailed = Message::Error {
+            code: ErrorCode::CanBusOff.with_detail(3),
+        };
+        assert_eq!(status.blink_for(&detailed), Some(Blink::Code(2)));
+        assert_eq!(
+            status.blink_for(&error(ErrorCode::QueueOverflow)),
+            Some(Blink::Warning)
+        );
+
+        // Infos only when mapped, other messages never.
+        let info = |code: InfoCode| Message::Info {
+            code: code.to_bytes(),
+            arg: 0,
+        };
+        assert_eq!(
+            status.blink_for(&info(InfoCode::Started)),
+            Some(Blink::Code(1))
+        );
+        assert_eq!(status.blink_for(&info(InfoCode::PulseCount)), None);
+        assert_eq!(status.blink_for(&Message::RequestStatus), None);
+
+        // Pattern blinks the code number of times.
+        assert_eq!(Blink::Code(2).to_time().2, 1);
+        assert!(status.queue.try_pop().is_none());
+    }
+
+    pub fn uptime_saturates() {
+        use crate::components::message::{Message, STATUS_UPTIME_MAX};
+
+        let boot = Instant::from_secs(10);
+        assert_eq!(uptime_secs(boot, boot), 0);
+        assert_eq!(uptime_secs(boot, boot + Duration::from_millis(1999)), 1);
+        // Clock before the boot doesn't underflow.
+        assert_eq!(uptime_secs(boot, Instant::from_secs(5)), 0);
+
+        // Near the u32 boundary it sticks to the max instead of wrapping.
+        let max = Duration::from_secs(u32::MAX as u64);
+        assert_eq!(
+            uptime_secs(boot, boot + max - Duration::from_secs(1)),
+            u32::MAX - 1
+        );
+        assert_eq!(uptime_secs(boot, boot + max), u32::MAX);
+        assert_eq!(
+            uptime_secs(boot, boot + max + Duration::from_secs(5)),
+            u32::MAX
+        );
+
+        // Status frame saturates too, keeping the sequence nibble intact.
+        let message = Message::Status {
+            uptime: uptime_secs(boot, boot + max),
+            errors: 0,
+            warnings: 0,
+        };
+        let mut raw = message.to_raw(1);
+        assert!(raw.set_sequence(3));
+        let data = raw.data_as_slice();
+        let uptime = u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
+        assert_eq!(uptime & STATUS_UPTIME_MAX, STATUS_UPTIME_MAX);
+        assert_eq!(raw.sequence(), Some(3));
+    }
+}
@@ -0,0 +1,95 @@
+/*
+ * Decides whether a TimeAnnouncement should set the RTC. The first one is
+ * authoritative. Later ones only correct a real drift: differences under
+ * `min_correction` are ignored, so the clock isn't jerked by every
+ * announcement, and jumps over `max_jump` are rejected as a gate bug.
+ */
+use crate::config::TimeSyncPolicy;
+
+/// What to do with an announced time.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, defmt::Format)]
+pub enum TimeDecision {
+    /// Set the RTC.
+    Apply,
+    /// Close enough to the RTC - nothing to correct.
+    InSync,
+    /// Implausible jump in seconds. Ignored.
+    Reject(i64),
+}
+
+/// Seconds since 1970-01-01 of a civil date and time. Fields are not
+/// validated.
+pub fn epoch_secs(year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> i64 {
+    // Days from civil - counted from March, so the leap day is the last one.
+    let year = year as i64 - if month <= 2 { 1 } else { 0 };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+    days * 86_400 + hour as i64 * 3600 + minute as i64 * 60 + second as i64
+}
+
+pub struct TimeSync {
+    policy: TimeSyncPolicy,
+    /// RTC was set by an announcement already.
+    synced: bool,
+}
+
+impl TimeSync {
+    pub const fn new(policy: TimeSyncPolicy) -> Self {
+        Self {
+            policy,
+            synced: false,
+        }
+    }
+
+    /// Compare the announced time with the RTC (both in epoch seconds).
+    pub fn check(&self, rtc: i64, announced: i64) -> TimeDecision {
+        if !self.synced {
+            return TimeDecision::Apply;
+        }
+        let diff = announced - rtc;
+        match diff.unsigned_abs() {
+            d if d > self.policy.max_jump_s as u64 => TimeDecision::Reject(diff),
+            d if d < self.policy.min_correction_s as u64 => TimeDecision::InSync,
+            _ => TimeDecision::Apply,
+        }
+    }
+
+    /// RTC was set successfully.
+    pub fn applied(&mut self) {
+        self.synced = true;
+    }
+}
+
+pub mod tests {
+    use super::*;
+
+    pub fn drift_and_jumps() {
+        assert_eq!(epoch_secs(1970, 1, 1, 0, 0, 0), 0);
+        assert_eq!(epoch_secs(2000, 3, 1, 0, 0, 0), 951_868_800);
+        assert_eq!(epoch_secs(2024, 2, 29, 12, 30, 15), 1_709_209_815);
+
+        // Policy the boards ship with.
+        let mut sync = TimeSync::new(crate::config::board::TIME_SYNC);
+        let rtc = epoch_secs(2025, 6, 1, 12, 0, 0);
+
+        // First announcement sets the RTC, whatever it says.
+        let far = epoch_secs(2035, 6, 1, 12, 0, 0);
+        assert_eq!(sync.check(rtc, far), TimeDecision::Apply);
+        sync.applied();
+
+        // Drift of a second is corrected.
+        assert_eq!(sync.check(rtc, rtc + 1), TimeDecision::Apply);
+        assert_eq!(sync.check(rtc, rtc), TimeDecision::InSync);
+        // DST change is still applied, 10 years is a gate bug.
+        assert_eq!(sync.check(rtc, rtc + 3600), TimeDecision::Apply);
+        assert_eq!(sync.check(rtc, far), TimeDecision::Reject(far - rtc));
+        assert_eq!(
+            sync.check(rtc, rtc - (far - rtc)),
+            TimeDecision::Reject(rtc - far)
+        );
+    }
+}
@@ -0,0 +1,209 @@
+/*
+ * Event trace for post-mortem debugging. The last TRACE_LEN input triggers,
+ * output changes and errors are kept in a RAM ring with timestamps. Live
+ * defmt logs are gone once the node is reset, the trace can be requested
+ * over the bus afterwards (RequestTrace) while the node still runs. RTC
+ * backup registers are too few and already taken by the outputs, scenes and
+ * shutter positions.
+ *
+ * Entries are sent as DiagnosticsPart frames with TRACE_PART set in the
+ * index, oldest first. Entry layout (little endian):
+ * [ms since boot, wrapping: 4] [kind << 4 | sub: 1] [argument: 1]
+ */
+use core::cell::RefCell;
+
+use embassy_sync::blocking_mutex::CriticalSectionMutex;
+use embassy_time::Instant;
+use heapless::{Deque, Vec};
+
+use crate::components::diagnostics::PART_SIZE;
+use crate::components::message::Message;
+use crate::io::events::{IoIdx, Trigger};
+
+/// Number of events remembered. A click takes three (activated, click,
+/// deactivated). Must fit the 7-bit frame index.
+pub const TRACE_LEN: usize = 64;
+/// Index flag of a DiagnosticsPart carrying a trace entry.
+pub const TRACE_PART: u8 = 0x80;
+
+mod kind {
+    pub const INPUT: u8 = 1;
+    pub const OUTPUT: u8 = 2;
+    pub const ERROR: u8 = 3;
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, defmt::Format)]
+pub enum TraceEvent {
+    /// Input trigger passed to the executor.
+    Input { input: IoIdx, trigger: Trigger },
+    /// Output changed by the executor.
+    Output { output: IoIdx, state: bool },
+    /// Error reported to the bus (ErrorCode value).
+    Error { code: u8 },
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, defmt::Format)]
+pub struct TraceEntry {
+    /// Milliseconds since boot. Wraps after ~49 days.
+    pub at_ms: u32,
+    pub event: TraceEvent,
+}
+
+impl TraceEntry {
+    pub fn serialize(&self) -> [u8; PART_SIZE] {
+        let mut buf = [0; PART_SIZE];
+        buf[0..4].copy_from_slice(&self.at_ms.to_le_bytes());
+        (buf[4], buf[5]) = match self.event {
+            TraceEvent::Input { input, trigger } => ((kind::INPUT << 4) | trigger as u8, input),
+            TraceEvent::Output { output, state } => ((kind::OUTPUT << 4) | state as u8, output),
+            TraceEvent::Error { code } => (kind::ERROR << 4, code),
+        };
+        buf
+    }
+
+    pub fn deserialize(buf: &[u8; PART_SIZE]) -> Option<Self> {
+        let sub = buf[4] & 0x0f;
+        let event = match buf[4] >> 4 {
+            kind::INPUT => TraceEvent::Input {
+                input: buf[5],
+                trigger: Trigger::from_u8(sub)?,
+            },
+            kind::OUTPUT => TraceEvent::Output {
+                output: buf[5],
+                state: sub != 0,
+            },
+            kind::ERROR => TraceEvent::Error { code: buf[5] },
+            _ => return None,
+        };
+        Some(Self {
+            at_ms: u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]),
+            event,
+        })
+    }
+}
+
+/// Ring of the most recent events. Oldest are overwritten.
+pub struct Trace<const N: usize> {
+    ring: CriticalSectionMutex<RefCell<Deque<TraceEntry, N>>>,
+}
+
+impl<const N: usize> Trace<N> {
+    pub const fn new() -> Self {
+        Self {
+            ring: CriticalSectionMutex::new(RefCell::new(Deque::new())),
+        }
+    }
+
+    pub fn record(&self, at: Instant, event: TraceEvent) {
+        let entry = TraceEntry {
+            at_ms: at.as_millis() as u32,
+            event,
+        };
+        self.ring.lock(|ring| {
+            let mut ring = ring.borrow_mut();
+            if ring.is_full() {
+                ring.pop_front();
+            }
+            // There is room now.
+            let _ = ring.push_back(entry);
+        });
+    }
+
+    /// Copy of the entries, oldest first.
+    pub fn entries(&self) -> Vec<TraceEntry, N> {
+        self.ring
+            .lock(|ring| ring.borrow().iter().copied().collect())
+    }
+
+    /// Trace dump as messages to transmit in order.
+    pub fn messages(&self) -> impl Iterator<Item = Message> {
+        let entries = self.entries();
+        let total = entries.len() as u8;
+        entries
+            .into_iter()
+            .enumerate()
+            .map(move |(index, entry)| Message::DiagnosticsPart {
+                index: TRACE_PART | index as u8,
+                total,
+                data: entry.serialize(),
+            })
+    }
+}
+
+impl<const N: usize> Default for Trace<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Node-wide trace.
+pub static TRACE: Trace<TRACE_LEN> = Trace::new();
+
+/// Record an event in the node-wide trace.
+pub fn record(event: TraceEvent) {
+    TRACE.record(Instant::now(), event);
+}
+
+pub mod tests {
+    use super::*;
+
+    pub fn keeps_last_entries() {
+        const N: usize = 4;
+        let trace: Trace<N> = Trace::new();
+        assert!(trace.entries().is_empty());
+
+        for idx in 0..N + 5 {
+            let event = TraceEvent::Output {
+                output: idx as u8,
+                state: idx % 2 == 0,
+            };
+            trace.record(Instant::from_millis(100 * idx as u64), event);
+        }
+
+        // Last N, oldest first.
+        let entries = trace.entries();
+        assert_eq!(entries.len(), N);
+        for (pos, entry) in entries.iter().enumerate() {
+            let idx = pos + 5;
+            assert_eq!(entry.at_ms, 100 * idx as u32);
+            assert_eq!(
+                entry.event,
+                TraceEvent::Output {
+                    output: idx as u8,
+                    state: idx % 2 == 0
+                }
+            );
+        }
+
+        // Through the wire.
+        trace.record(
+            Instant::from_millis(5000),
+            TraceEvent::Input {
+                input: 3,
+                trigger: Trigger::LongClick,
+            },
+        );
+        trace.record(Instant::from_millis(5001), TraceEvent::Error { code: 40 });
+        let mut decoded = Vec::<TraceEntry, N>::new();
+        for (pos, message) in trace.messages().enumerate() {
+            let (index, total, data) = message.to_raw(1).diagnostics_part().unwrap();
+            assert_eq!(index, TRACE_PART | pos as u8);
+            assert_eq!(total, N as u8);
+            assert!(
+                decoded
+                    .push(TraceEntry::deserialize(&data).unwrap())
+                    .is_ok()
+            );
+        }
+        assert_eq!(decoded, trace.entries());
+        assert_eq!(decoded[3].event, TraceEvent::Error { code: 40 });
+        assert_eq!(
+            decoded[2].event,
+            TraceEvent::Input {
+                input: 3,
+                trigger: Trigger::LongClick
+            }
+        );
+        assert_eq!(TraceEntry::deserialize(&[0; PART_SIZE]), None);
+    }
+}
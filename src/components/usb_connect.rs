@@ -133,6 +133,111 @@ impl CommPacket {
     }
 }
 
+/// Size of the reassembly buffer. Fits at least two full USB reads.
+const DECODER_SIZE: usize = 2 * MAX_PACKET_SIZE;
+
+/// Reassembles packets from a byte stream. CDC-ACM doesn't preserve packet
+/// boundaries - a packet can be split between reads, or multiple packets can
+/// arrive in a single one.
+pub struct CommDecoder {
+    buf: [u8; DECODER_SIZE],
+    len: usize,
+}
+
+impl Default for CommDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CommDecoder {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; DECODER_SIZE],
+            len: 0,
+        }
+    }
+
+    /// Forget any partially received data.
+    pub fn reset(&mut self) {
+        self.len = 0;
+    }
+
+    /// Drop `count` bytes from the start of the buffer.
+    fn consume(&mut self, count: usize) {
+        let count = count.min(self.len);
+        self.buf.copy_within(count..self.len, 0);
+        self.len -= count;
+    }
+
+    /// Append received bytes. If the buffer overflows, the oldest bytes are
+    /// dropped - they could not form a valid packet anyway.
+    pub fn push(&mut self, data: &[u8]) {
+        let data = if data.len() > DECODER_SIZE {
+            defmt::warn!("USB decoder: dropping {} bytes", data.len() - DECODER_SIZE);
+            &data[data.len() - DECODER_SIZE..]
+        } else {
+            data
+        };
+        let free = DECODER_SIZE - self.len;
+        if data.len() > free {
+            defmt::warn!("USB decoder overflow, dropping {} bytes", data.len() - free);
+            self.consume(data.len() - free);
+        }
+        self.buf[self.len..self.len + data.len()].copy_from_slice(data);
+        self.len += data.len();
+    }
+
+    /// Parse next complete packet, if any. Skips garbage until synchronized.
+    pub fn next_packet(&mut self) -> Option<CommPacket> {
+        loop {
+            // Find the start of a packet.
+            let start = self.buf[0..self.len]
+                .iter()
+                .position(|b| *b == CommPacket::SYNC_BYTE_1);
+            match start {
+                Some(0) => {}
+                Some(start) => {
+                    defmt::warn!("USB decoder: skipping {} bytes to resync", start);
+                    self.consume(start);
+                }
+                None => {
+                    self.reset();
+                    return None;
+                }
+            }
+
+            if self.len < 2 {
+                // Wait for the second sync byte.
+                return None;
+            }
+
+            match self.buf[1] {
+                CommPacket::SYNC_BYTE_2_CAN => {}
+                CommPacket::_SYNC_BYTE_2_FDCAN => {
+                    defmt::warn!("Ignoring unhandled FDCAN on USB");
+                    self.consume(2);
+                    continue;
+                }
+                _ => {
+                    // False start. Try from the next byte.
+                    self.consume(1);
+                    continue;
+                }
+            }
+
+            if self.len < CAN_PACKET_SIZE {
+                // Partial packet - wait for the rest.
+                return None;
+            }
+
+            let packet = CommPacket::from_slice(&self.buf[2..CAN_PACKET_SIZE]);
+            self.consume(CAN_PACKET_SIZE);
+            return Some(packet);
+        }
+    }
+}
+
 pub type CommChannel = Channel<ThreadModeRawMutex, CommPacket, 3>;
 
 /// We use Serial interface for simplicity, but send PACKETS of data.
@@ -160,6 +265,7 @@ impl CommProtocol {
 
     /// Connection handler
     async fn forwarder(&self, class: &mut MyClass) -> Result<(), Disconnected> {
+        let mut decoder = CommDecoder::new();
         loop {
             let mut usb_buf = [0; 64];
             let usb_reader = class.read_packet(&mut usb_buf);
@@ -170,7 +276,8 @@ impl CommProtocol {
                     match bytes {
                         Ok(bytes) => {
                             defmt::info!("USB RX: {} {:?}", bytes, &usb_buf[0..bytes]);
-                            if let Some(msg) = CommPacket::deserialize_from(&usb_buf[0..bytes]) {
+                            decoder.push(&usb_buf[0..bytes]);
+                            while let Some(msg) = decoder.next_packet() {
                                 if !self.usb_down.is_empty() {
                                     defmt::warn!(
                                         "Non-empty queue (len={}) when sending msg from USB.",
@@ -178,7 +285,6 @@ impl CommProtocol {
                                     );
                                 }
                                 self.usb_down.send(msg).await;
-                                continue;
                             }
                         }
                         Err(err) => {
@@ -277,3 +383,53 @@ impl UsbConnect {
         join(usb, connector_future).await;
     }
 }
+
+pub mod tests {
+    use super::*;
+
+    /// Serialized test packet with a recognizable body.
+    fn packet(marker: u8) -> [u8; CAN_PACKET_SIZE] {
+        let mut body = [0u8; CAN_MESSAGE_SIZE];
+        body[0] = marker;
+        body[1] = 0x08;
+        body[2] = 2;
+        body[3] = marker;
+        let mut buf = [0u8; CAN_PACKET_SIZE];
+        CommPacket::from_slice(&body).serialize_as_can(&mut buf);
+        buf
+    }
+
+    pub fn decoder_reassembles() {
+        let first = packet(1);
+        let second = packet(2);
+        let mut decoder = CommDecoder::new();
+
+        // Split in the middle of the header.
+        decoder.push(&first[0..1]);
+        assert!(decoder.next_packet().is_none());
+        decoder.push(&first[1..5]);
+        assert!(decoder.next_packet().is_none());
+        decoder.push(&first[5..]);
+        let msg = decoder.next_packet().unwrap();
+        assert_eq!(msg.as_slice(), &first[2..]);
+        assert!(decoder.next_packet().is_none());
+
+        // Two packets in a single read, with garbage in front.
+        let mut stream = [0u8; 3 + 2 * CAN_PACKET_SIZE];
+        stream[0..3].copy_from_slice(&[0x00, CommPacket::SYNC_BYTE_1, 0x55]);
+        stream[3..3 + CAN_PACKET_SIZE].copy_from_slice(&first);
+        stream[3 + CAN_PACKET_SIZE..].copy_from_slice(&second);
+        decoder.push(&stream);
+        assert_eq!(decoder.next_packet().unwrap().as_slice(), &first[2..]);
+        assert_eq!(decoder.next_packet().unwrap().as_slice(), &second[2..]);
+        assert!(decoder.next_packet().is_none());
+
+        // Second packet split across reads right after the first.
+        decoder.push(&first);
+        decoder.push(&second[0..7]);
+        assert_eq!(decoder.next_packet().unwrap().as_slice(), &first[2..]);
+        assert!(decoder.next_packet().is_none());
+        decoder.push(&second[7..]);
+        assert_eq!(decoder.next_packet().unwrap().as_slice(), &second[2..]);
+    }
+}
@@ -1,4 +1,5 @@
 /* Constants configuring the crate */
+use crate::components::message::args::{ErrorCode, InfoCode};
 
 /* NOTE: This could be generics maybe, but maybe const is good enough. */
 // pub const MAX_ACTIONS: usize = 32;
@@ -26,9 +27,243 @@ pub const LOCAL_ADDRESS: u8 = 11;
 
 pub const BROADCAST_ADDRESS: u8 = 0x3f;
 
+/// What to do after a panic, once outputs were driven to their safe level.
+pub enum PanicPolicy {
+    /// Reboot and start over. Outputs get re-energized by the program.
+    Reboot,
+    /// Stay halted with outputs off until someone power-cycles the node.
+    Halt,
+}
+
+#[cfg(feature = "panic-halt")]
+pub const PANIC_POLICY: PanicPolicy = PanicPolicy::Halt;
+#[cfg(not(feature = "panic-halt"))]
+pub const PANIC_POLICY: PanicPolicy = PanicPolicy::Reboot;
+
+/// Role of the node on the bus.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, defmt::Format)]
+pub enum Role {
+    /// IO controller: local inputs/outputs driven by the micro VM.
+    Ctrl,
+    /// CAN <-> USB gate for the home automation server.
+    Gate,
+}
+
+/// How the role is chosen at boot.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, defmt::Format)]
+pub enum RoleSelect {
+    /// Always the same role.
+    Fixed(Role),
+    /// Active strapping pin (see board::ROLE_STRAP) selects Gate, inactive
+    /// one - Ctrl.
+    Strap,
+}
+
+#[cfg(feature = "role-strap")]
+pub const ROLE_SELECT: RoleSelect = RoleSelect::Strap;
+#[cfg(not(feature = "role-strap"))]
+pub const ROLE_SELECT: RoleSelect = RoleSelect::Fixed(Role::Ctrl);
+
+/// Output state applied on power-on.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, defmt::Format)]
+pub enum StartupOutputs {
+    /// All outputs inactive.
+    AllOff,
+    /// All outputs active (always-on equipment).
+    AllOn,
+    /// State from before the reboot, persisted in RTC backup registers.
+    /// Falls back to AllOff if nothing was persisted.
+    RestoreLast,
+}
+
+/// What to do when a producer finds an event queue full.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, defmt::Format)]
+pub enum OverflowPolicy {
+    /// Wait for room. Nothing is lost, but the producer stalls.
+    Block,
+    /// Drop the item being sent. Queued items are kept.
+    DropNewest,
+    /// Drop the oldest queued item to make room for the new one.
+    DropOldest,
+}
+
+/// When a TimeAnnouncement sets the RTC once it was set already.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, defmt::Format)]
+pub struct TimeSyncPolicy {
+    /// Smaller differences are not corrected [s].
+    pub min_correction_s: u32,
+    /// Larger jumps are rejected as bogus [s].
+    pub max_jump_s: u32,
+}
+
+/// Crash loop detection at boot.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, defmt::Format)]
+pub struct SafeModePolicy {
+    /// Boots in a row that didn't reach `stable_s` before entering safe mode.
+    pub max_faults: u8,
+    /// Uptime after which a boot counts as successful [s].
+    pub stable_s: u32,
+}
+
+/// Outputs of a single expander switched together, referenced by `id` from
+/// opcodes, so the group is defined in one place.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, defmt::Format)]
+pub struct OutputGroup {
+    pub id: u8,
+    /// Lines of the expander in the group.
+    pub mask: u16,
+    /// Position of the expander among the output expanders.
+    pub expander_id: u8,
+}
+
+/// Code of an error or info frame emitted by the node.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, defmt::Format)]
+pub enum StatusCode {
+    Error(ErrorCode),
+    Info(InfoCode),
+}
+
+/// Status LED shows the code as a number of slow blinks, so it can be told
+/// apart in the field without a probe.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, defmt::Format)]
+pub struct CodeBlink {
+    pub code: StatusCode,
+    pub blinks: u8,
+}
+
+/// How local inputs drive the outputs.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, defmt::Format)]
+pub enum InputMode {
+    /// Bindings and procedures of the micro VM.
+    Microvm,
+    /// Fixed (input, output) pairs: a click toggles the output. No executor.
+    Direct(&'static [(u8, u8)]),
+}
+
 /// Module with per-deployment configuration options.
 #[cfg(feature = "bus-addr-1")]
 pub mod board {
+    use super::{
+        CodeBlink, ErrorCode, InputMode, OutputGroup, OverflowPolicy, SafeModePolicy,
+        StartupOutputs, StatusCode, TimeSyncPolicy,
+    };
+    use crate::buttonsmash::Command;
+    use crate::io::events::IoIdx;
+    #[cfg(target_os = "none")]
+    use crate::io::{adc_inputs::Thresholds, logical_output::Polarity, native_inputs::InputConfig};
+    #[cfg(target_os = "none")]
+    use embassy_stm32::gpio::Pull;
+    use embassy_time::Duration;
+
+    /// Types by which this node's notifications are lowered to win the CAN
+    /// arbitration, 0 for none. See the id layout in components::message.
+    pub const TX_PRIORITY_OFFSET: u8 = 0;
+
+    /// Power-on output state.
+    pub const STARTUP_OUTPUTS: StartupOutputs = StartupOutputs::AllOff;
+
+    /// Microvm or a direct input to output mapping.
+    pub const INPUT_MODE: InputMode = InputMode::Microvm;
+
+    /// Correct drift of a second and more, reject jumps over a day (DST is
+    /// an hour).
+    pub const TIME_SYNC: TimeSyncPolicy = TimeSyncPolicy {
+        min_correction_s: 1,
+        max_jump_s: 24 * 3600,
+    };
+
+    /// Safe mode after 3 boots in a row crashing within a minute.
+    pub const SAFE_MODE: SafeModePolicy = SafeModePolicy {
+        max_faults: 3,
+        stable_s: 60,
+    };
+
+    /// Inputs counting pulses (eg. of a meter) instead of debounced clicks.
+    pub const PULSE_INPUTS: &[IoIdx] = &[];
+
+    /// Inputs (input, ms) with a long-press threshold other than the default
+    /// 400ms, eg. a shorter one for shutter buttons held to move.
+    pub const LONG_PRESS_MS: &[(IoIdx, u32)] = &[];
+
+    /// Clicks of an input closer to its previous click are dropped, for
+    /// flaky switches. Zero disables.
+    pub const MIN_CLICK_INTERVAL: Duration = Duration::from_millis(0);
+
+    /// Executed on a short click of inputs without a binding, so every
+    /// button does something observable before it's configured. None
+    /// disables it.
+    pub const DEFAULT_COMMAND: Option<Command> = None;
+
+    /// I²C addresses of the input, sensor and output expanders
+    /// (0x20 + A2A1A0 strapping).
+    pub const INPUT_EXPANDER_ADDR: u8 = 0x27;
+    pub const SENSOR_EXPANDER_ADDR: u8 = 0x23;
+    pub const OUTPUT_EXPANDER_ADDR: u8 = 0x20;
+
+    /// Expanders may not respond yet this long after boot (slow power ramp),
+    /// their configuration failures are not counted as errors until then.
+    pub const EXPANDER_STARTUP_GRACE: Duration = Duration::from_millis(500);
+
+    /// Output groups toggled together by ToggleGroup.
+    pub const OUTPUT_GROUPS: &[OutputGroup] = &[];
+
+    /// Native pins (output, native pin position) driving critical expander
+    /// outputs while their expander is offline.
+    pub const OUTPUT_FALLBACKS: &[(IoIdx, usize)] = &[];
+
+    /// DS3231 on the shared I²C bus keeps the time instead of the internal RTC.
+    pub const EXTERNAL_RTC: bool = false;
+
+    /// Blink patterns of emitted codes. Other errors blink a generic warning.
+    pub const CODE_BLINKS: &[CodeBlink] = &[
+        CodeBlink {
+            code: StatusCode::Error(ErrorCode::CanBusOff),
+            blinks: 2,
+        },
+        CodeBlink {
+            code: StatusCode::Error(ErrorCode::ExpanderMissing),
+            blinks: 3,
+        },
+        CodeBlink {
+            code: StatusCode::Error(ErrorCode::ExpanderOutputFailure),
+            blinks: 4,
+        },
+        CodeBlink {
+            code: StatusCode::Error(ErrorCode::ProgramInvalid),
+            blinks: 5,
+        },
+        CodeBlink {
+            code: StatusCode::Error(ErrorCode::ShutterOverTravel),
+            blinks: 6,
+        },
+        CodeBlink {
+            code: StatusCode::Error(ErrorCode::FeedbackLoop),
+            blinks: 7,
+        },
+    ];
+
+    /// Handling of full input/event queues.
+    pub const QUEUE_OVERFLOW: OverflowPolicy = OverflowPolicy::Block;
+
+    /// Status LED is lit by driving its line low.
+    pub const STATUS_LED_ACTIVE_LOW: bool = false;
+
+    /// Role strapping pin: tied to ground selects the Gate role.
+    #[cfg(target_os = "none")]
+    pub const ROLE_STRAP: InputConfig = InputConfig::new(Pull::Up, Polarity::ActiveLow);
+
+    /// Rotary encoder on PA0 (A) and PA1 (B): input index of its rotations,
+    /// None if not fitted.
+    pub const ROTARY_ENCODER: Option<IoIdx> = None;
+    /// Encoder contacts switch to ground.
+    #[cfg(target_os = "none")]
+    pub const ROTARY_ENCODER_PINS: InputConfig = InputConfig::new(Pull::Up, Polarity::ActiveLow);
+
+    /// Analog sensor on PA2 as a virtual switch: its input index and the
+    /// thresholds in raw ADC units, None if not fitted.
+    #[cfg(target_os = "none")]
+    pub const ADC_INPUT: Option<(IoIdx, Thresholds)> = None;
+
     #[rustfmt::skip]
     pub const ACTIVE_LOW: [bool; 24] = [
         true, true, true, true, true, false, true, true,
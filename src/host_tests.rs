@@ -0,0 +1,340 @@
+/*
+ * Host runner of the hardware independent tests: `just test-host`. The same
+ * tests run on the device from tests/main.rs, here only the buttonsmash ones
+ * - the board is not needed to run programs and shutters on mocks.
+ */
+
+/// Logs are discarded on the host.
+#[defmt::global_logger]
+struct NullLogger;
+
+unsafe impl defmt::Logger for NullLogger {
+    fn acquire() {}
+    unsafe fn flush() {}
+    unsafe fn release() {}
+    unsafe fn write(_bytes: &[u8]) {}
+}
+
+/// Failed defmt assertions fail the test.
+#[defmt::panic_handler]
+fn defmt_panic() -> ! {
+    panic!("defmt panic")
+}
+
+#[test]
+fn single_shutter() {
+    use crate::buttonsmash::shutters;
+    shutters::tests::single_shutter();
+}
+
+#[test]
+fn shutter_min_pulse() {
+    use crate::buttonsmash::shutters;
+    shutters::tests::min_pulse();
+}
+
+#[test]
+fn shutter_group_stagger() {
+    use crate::buttonsmash::shutters;
+    shutters::tests::grouped_start_stagger();
+}
+
+#[test]
+fn shutter_group_fan_out() {
+    use crate::buttonsmash::shutters;
+    shutters::tests::group_fan_out();
+}
+
+#[test]
+fn shutter_group_close_staggered() {
+    use crate::buttonsmash::shutters;
+    shutters::tests::group_close_staggered();
+}
+
+#[test]
+fn shutter_resync_all_out_of_sync() {
+    use crate::buttonsmash::shutters;
+    shutters::tests::resync_all_out_of_sync();
+}
+
+#[test]
+fn shutter_positions_serialization() {
+    use crate::buttonsmash::shutters;
+    shutters::tests::positions_serialization();
+}
+
+#[test]
+fn shutter_over_travel_cap() {
+    use crate::buttonsmash::shutters;
+    shutters::tests::over_travel_cap();
+}
+
+#[test]
+fn shutters_stuck_sweep() {
+    use crate::buttonsmash::shutters;
+    shutters::tests::stuck_shutter_swept();
+}
+
+#[test]
+fn shutter_config_round_trip() {
+    use crate::buttonsmash::shutters;
+    shutters::tests::config_round_trip();
+}
+
+#[test]
+fn shutter_dispatch() {
+    use crate::buttonsmash::shutters;
+    shutters::tests::dispatch_reaches_manager();
+}
+
+#[test]
+fn shutter_projected_position() {
+    use crate::buttonsmash::shutters;
+    shutters::tests::projected_halfway();
+}
+
+#[test]
+fn shutter_park_all() {
+    use crate::buttonsmash::shutters;
+    shutters::tests::park_mid_drop();
+}
+
+#[test]
+fn shutter_hysteresis_per_shutter() {
+    use crate::buttonsmash::shutters;
+    shutters::tests::per_shutter_hysteresis();
+}
+
+#[test]
+fn shutter_resync() {
+    use crate::buttonsmash::shutters;
+    shutters::tests::resync_to_closed();
+}
+
+#[test]
+fn shutter_swap_direction() {
+    use crate::buttonsmash::shutters;
+    shutters::tests::swap_direction();
+}
+
+#[test]
+fn shutter_min_movement() {
+    use crate::buttonsmash::shutters;
+    shutters::tests::tiny_moves_ignored();
+}
+
+#[test]
+fn shutter_persist_throttle() {
+    use crate::buttonsmash::shutters;
+    shutters::tests::persist_throttled();
+}
+
+#[test]
+fn shutter_motion_events() {
+    use crate::buttonsmash::shutters;
+    shutters::tests::motion_events_full_open();
+}
+
+#[test]
+fn bindings() {
+    use crate::buttonsmash::bindings;
+    bindings::tests::it_adds_and_finds();
+}
+
+#[test]
+fn bindings_default_command() {
+    use crate::buttonsmash::bindings;
+    bindings::tests::unbound_key_emits_default();
+}
+
+#[test]
+fn bindings_dump() {
+    use crate::buttonsmash::bindings;
+    bindings::tests::dump_lists_bound();
+}
+
+#[test]
+fn bindings_command_origin() {
+    use crate::buttonsmash::bindings;
+    bindings::tests::button_command_origin();
+}
+
+#[test]
+fn momentary_outputs() {
+    use crate::buttonsmash::momentary;
+    momentary::tests::momentary_release_and_timeout();
+}
+
+#[test]
+fn timed_output() {
+    use crate::buttonsmash::timed;
+    timed::tests::on_then_off_after_time();
+}
+
+#[test]
+fn pulsed_output() {
+    use crate::buttonsmash::pulsed;
+    pulsed::tests::pulses_then_restores();
+}
+
+#[test]
+fn maintenance_mode() {
+    use crate::buttonsmash::maintenance;
+    maintenance::tests::maintenance_drops_local_events();
+}
+
+#[test]
+fn chord_binding() {
+    use crate::buttonsmash::microvm;
+    microvm::tests::chord_replaces_tap_binding();
+}
+
+#[test]
+fn chord_slots() {
+    use crate::buttonsmash::chords;
+    chords::tests::chord_slots_limited();
+}
+
+#[test]
+fn registers() {
+    use crate::buttonsmash::microvm;
+    microvm::tests::set_register_changes_called_proc();
+}
+
+#[test]
+fn runtime_reset() {
+    use crate::buttonsmash::microvm;
+    microvm::tests::runtime_state_resets();
+}
+
+#[test]
+fn small_executor() {
+    use crate::buttonsmash::microvm;
+    microvm::tests::small_procedure_table();
+}
+
+#[test]
+fn program_unknown_output() {
+    use crate::buttonsmash::microvm;
+    microvm::tests::unknown_output_is_rejected();
+}
+
+#[test]
+fn program_missing_setup() {
+    use crate::buttonsmash::microvm;
+    microvm::tests::missing_setup_is_rejected();
+}
+
+#[test]
+fn program_reload() {
+    use crate::buttonsmash::microvm;
+    microvm::tests::reload_replaces_bindings();
+}
+
+#[test]
+fn microvm_multi_binding() {
+    use crate::buttonsmash::microvm;
+    microvm::tests::multi_binding_actions();
+}
+
+#[test]
+fn microvm_edge_binding_trigger() {
+    use crate::buttonsmash::microvm;
+    microvm::tests::edge_binding_passes_trigger();
+}
+
+#[test]
+fn microvm_default_program() {
+    use crate::buttonsmash::microvm;
+    microvm::tests::default_program_runs_on_mock();
+}
+
+#[test]
+fn microvm_runaway_procedure() {
+    use crate::buttonsmash::microvm;
+    microvm::tests::runaway_procedure_aborted();
+}
+
+#[test]
+fn microvm_reload_parks() {
+    use crate::buttonsmash::microvm;
+    microvm::tests::reload_waits_for_park();
+}
+
+#[test]
+fn microvm_remote_output_ack() {
+    use crate::buttonsmash::microvm;
+    microvm::tests::remote_set_output_acknowledged();
+}
+
+#[test]
+fn microvm_toggle_multi() {
+    use crate::buttonsmash::microvm;
+    microvm::tests::short_click_toggles_all_listed();
+}
+
+#[test]
+fn microvm_deferred_layer_hold() {
+    use crate::buttonsmash::microvm;
+    microvm::tests::deferred_layer_hold_ignores_tap();
+}
+
+#[test]
+fn microvm_locked_output() {
+    use crate::buttonsmash::microvm;
+    microvm::tests::locked_output_ignores_changes();
+}
+
+#[test]
+fn microvm_rotary_dims() {
+    use crate::buttonsmash::microvm;
+    microvm::tests::rotary_dims_output();
+}
+
+#[test]
+fn microvm_bindings_dump() {
+    use crate::buttonsmash::microvm;
+    microvm::tests::bindings_dump();
+}
+
+#[test]
+fn microvm_scene_batch() {
+    use crate::buttonsmash::microvm;
+    microvm::tests::scene_recalled_in_one_write();
+}
+
+#[test]
+fn microvm_feedback_loop() {
+    use crate::buttonsmash::microvm;
+    microvm::tests::remote_feedback_loop_suppressed();
+}
+
+#[test]
+fn microvm_remote_origin() {
+    use crate::buttonsmash::microvm;
+    microvm::tests::remote_origin_from_sender();
+}
+
+#[test]
+fn microvm_activate_for() {
+    use crate::buttonsmash::microvm;
+    microvm::tests::activate_for_expires();
+}
+
+#[test]
+fn microvm_pulse_output() {
+    use crate::buttonsmash::microvm;
+    microvm::tests::pulse_restores_prior_state();
+}
+
+#[test]
+fn feedback_guard_suppression() {
+    use crate::buttonsmash::feedback;
+    feedback::tests::alternating_changes_suppressed();
+}
+
+#[test]
+fn scene_capture_recall() {
+    use crate::buttonsmash::scenes;
+    scenes::tests::capture_and_recall();
+}
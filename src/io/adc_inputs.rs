@@ -0,0 +1,218 @@
+/*
+ * Analog inputs (light or temperature sensors) as a source of switch events.
+ * Reading crossing the activation threshold emits Activated, crossing back
+ * over the deactivation threshold emits Deactivated. The gap between the two
+ * thresholds is a hysteresis, so a reading hovering around a single value
+ * doesn't flap. Events go into the InputChannel, so bindings and microvm
+ * handle sensors the same way as buttons.
+ */
+use embassy_stm32::adc::{Adc, AdcChannel, DefaultInstance, SampleTime};
+use embassy_time::{Duration, Instant, Timer};
+
+use crate::components::queue;
+use crate::config;
+use crate::io::events::{self, InputChannel, IoIdx, SwitchEvent, SwitchState};
+
+/// Analog sensors change slowly - no need to sample often.
+pub const SCAN_PERIOD: Duration = Duration::from_millis(200);
+
+/// Long sampling, sensor dividers are high impedance.
+pub const SAMPLE_TIME: SampleTime = SampleTime::CYCLES247_5;
+
+/// Source of raw analog readings.
+pub trait Sampler {
+    fn sample(&mut self) -> u16;
+}
+
+/// Native ADC pin of the µC.
+pub struct NativeAdc<'d, T: DefaultInstance, C: AdcChannel<T>> {
+    adc: Adc<'d, T>,
+    channel: C,
+}
+
+impl<'d, T: DefaultInstance, C: AdcChannel<T>> NativeAdc<'d, T, C> {
+    pub fn new(adc: Adc<'d, T>, channel: C) -> Self {
+        Self { adc, channel }
+    }
+}
+
+impl<T: DefaultInstance, C: AdcChannel<T>> Sampler for NativeAdc<'_, T, C> {
+    fn sample(&mut self) -> u16 {
+        self.adc.blocking_read(&mut self.channel, SAMPLE_TIME)
+    }
+}
+
+/// Hysteresis thresholds in raw ADC units. Activate above deactivate means
+/// "active when high" (eg. bright); swapped means "active when low" (dark).
+#[derive(Copy, Clone, Debug, Eq, PartialEq, defmt::Format)]
+pub struct Thresholds {
+    pub activate: u16,
+    pub deactivate: u16,
+}
+
+impl Thresholds {
+    pub const fn new(activate: u16, deactivate: u16) -> Self {
+        Self {
+            activate,
+            deactivate,
+        }
+    }
+
+    fn active_high(&self) -> bool {
+        self.activate >= self.deactivate
+    }
+
+    fn activates(&self, reading: u16) -> bool {
+        if self.active_high() {
+            reading >= self.activate
+        } else {
+            reading <= self.activate
+        }
+    }
+
+    fn deactivates(&self, reading: u16) -> bool {
+        if self.active_high() {
+            reading <= self.deactivate
+        } else {
+            reading >= self.deactivate
+        }
+    }
+}
+
+/// Single analog input turned into a virtual switch.
+pub struct AdcInput {
+    switch_id: IoIdx,
+    thresholds: Thresholds,
+    /// When the input got activated.
+    active_since: Option<Instant>,
+}
+
+impl AdcInput {
+    pub fn new(switch_id: IoIdx, thresholds: Thresholds) -> Self {
+        if let Err(pos) = events::check_indices(&[switch_id]) {
+            defmt::panic!("ADC input uses reserved input index 0 at {}", pos);
+        }
+        Self {
+            switch_id,
+            thresholds,
+            active_since: None,
+        }
+    }
+
+    /// Process a reading. Returns an event when the state changes.
+    pub fn update(&mut self, reading: u16, now: Instant) -> Option<SwitchEvent> {
+        let state = match self.active_since {
+            None if self.thresholds.activates(reading) => {
+                self.active_since = Some(now);
+                SwitchState::Activated
+            }
+            Some(since) if self.thresholds.deactivates(reading) => {
+                self.active_since = None;
+                let ms = now.saturating_duration_since(since).as_millis();
+                SwitchState::Deactivated(ms.min(u32::MAX as u64) as u32)
+            }
+            _ => return None,
+        };
+        Some(SwitchEvent {
+            switch_id: self.switch_id,
+            state,
+            at: now,
+        })
+    }
+}
+
+/// Samples an ADC pin and feeds the input queue.
+pub struct AdcInputSource<S: Sampler> {
+    sampler: S,
+    input: AdcInput,
+    queue: &'static InputChannel,
+}
+
+impl<S: Sampler> AdcInputSource<S> {
+    pub fn new(sampler: S, input: AdcInput, queue: &'static InputChannel) -> Self {
+        Self {
+            sampler,
+            input,
+            queue,
+        }
+    }
+
+    /// Sample once and queue a resulting event.
+    pub async fn poll(&mut self) {
+        let reading = self.sampler.sample();
+        if let Some(event) = self.input.update(reading, Instant::now()) {
+            defmt::info!(
+                "ADC input {} reading {}: {:?}",
+                event.switch_id,
+                reading,
+                event.state
+            );
+            if let Some(dropped) =
+                queue::send(self.queue, event, config::board::QUEUE_OVERFLOW).await
+            {
+                defmt::warn!("Input queue is full, dropped ADC event {:?}", dropped);
+            }
+        }
+    }
+
+    pub async fn run(&mut self) -> ! {
+        loop {
+            self.poll().await;
+            Timer::after(SCAN_PERIOD).await;
+        }
+    }
+}
+
+pub mod tests {
+    use super::*;
+
+    /// ADC returning prepared readings.
+    struct FakeAdc {
+        readings: &'static [u16],
+        pos: usize,
+    }
+
+    impl Sampler for FakeAdc {
+        fn sample(&mut self) -> u16 {
+            let reading = self.readings[self.pos.min(self.readings.len() - 1)];
+            self.pos += 1;
+            reading
+        }
+    }
+
+    pub fn threshold_crossing() {
+        // Light sensor: on above 3000, off below 2000.
+        let mut input = AdcInput::new(5, Thresholds::new(3000, 2000));
+        let mut adc = FakeAdc {
+            readings: &[1000, 2900, 3100, 3500, 2500, 2100, 1900, 2500],
+            pos: 0,
+        };
+        let mut states = heapless::Vec::<(u8, SwitchState), 8>::new();
+        for tick in 0..8 {
+            let now = Instant::from_millis(tick * 200);
+            if let Some(event) = input.update(adc.sample(), now) {
+                assert!(states.push((tick as u8, event.state)).is_ok());
+                assert_eq!(event.switch_id, 5);
+                assert_eq!(event.at, now);
+            }
+        }
+        // Activated on crossing up, hysteresis holds it until below 2000.
+        assert_eq!(states.len(), 2);
+        assert!(matches!(states[0], (2, SwitchState::Activated)));
+        assert!(matches!(states[1], (6, SwitchState::Deactivated(800))));
+
+        // Swapped thresholds: active when dark.
+        let mut input = AdcInput::new(6, Thresholds::new(500, 800));
+        let now = Instant::from_millis(0);
+        assert!(input.update(600, now).is_none());
+        assert!(matches!(
+            input.update(400, now).map(|e| e.state),
+            Some(SwitchState::Activated)
+        ));
+        assert!(input.update(700, now).is_none());
+        assert!(matches!(
+            input.update(900, now).map(|e| e.state),
+            Some(SwitchState::Deactivated(0))
+        ));
+    }
+}
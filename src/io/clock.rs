@@ -0,0 +1,25 @@
+/*
+ * Time source of the scanner loops. The board runs them on embassy_time,
+ * tests step a fake clock so whole loops run without waiting.
+ */
+use embassy_time::{Duration, Instant, Timer};
+
+/// Current time and a delay.
+#[allow(async_fn_in_trait)]
+pub trait Clock {
+    fn now(&self) -> Instant;
+    async fn delay(&self, duration: Duration);
+}
+
+/// Clock of the embassy time driver.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    async fn delay(&self, duration: Duration) {
+        Timer::after(duration).await
+    }
+}
@@ -1,60 +1,540 @@
+use defmt::unwrap;
+use embassy_time::{Duration, Instant};
+use heapless::Vec;
+
 use crate::buttonsmash::{Event, EventChannel};
-use crate::io::events::{InputChannel, SwitchState, Trigger};
+use crate::components::queue;
+use crate::components::trace::{self, TraceEvent};
+use crate::config;
+use crate::io::events::{InputChannel, IoIdx, SwitchEvent, SwitchState, Trigger};
 
-/// Max time [ms] until which the activation ends in ShortClick.
+/// Max time [ms] until which the activation ends in ShortClick. Inputs can
+/// override it (see EventConverter::with_long_press).
 const MAX_SHORT_MS: u32 = 400;
 
-#[embassy_executor::task(pool_size = 1)]
-pub async fn run_event_converter(input_q: &'static InputChannel, output_q: &'static EventChannel) {
-    loop {
-        let input_event = input_q.receive().await;
+/// Max number of high-level events generated from a single input event.
+const MAX_EVENTS: usize = 3;
+
+/// Emit LongActivated on every Active scan instead of once per hold.
+const AUTO_REPEAT: bool = false;
+
+/// Short click within this time after the previous one of the same input is
+/// a DoubleClick. Off by default - it doesn't change the clicks of existing
+/// keys.
+const DOUBLE_CLICK_WINDOW: Option<Duration> = None;
+
+/// Number of recent clicks remembered for the rate limit. Interval is short,
+/// so only a few inputs can click within it.
+const RECENT_CLICKS: usize = 8;
+
+/// Convert low-level switch state into high-level button events.
+pub fn convert(input_event: &SwitchEvent) -> Vec<Event, MAX_EVENTS> {
+    convert_with_threshold(input_event, MAX_SHORT_MS)
+}
+
+/// Convert with a long-press threshold [ms] of the input.
+pub fn convert_with_threshold(
+    input_event: &SwitchEvent,
+    max_short_ms: u32,
+) -> Vec<Event, MAX_EVENTS> {
+    let mut events = Vec::new();
+    let mut emit = |trigger| {
+        unwrap!(events.push(Event::new_button(
+            input_event.switch_id,
+            trigger,
+            input_event.at
+        )));
+    };
+
+    match input_event.state {
+        SwitchState::Activated => {
+            emit(Trigger::Activated);
+        }
+        SwitchState::Active(ms) => {
+            // We were activated and are still active. For a some period of time.
+            // Repeated on each scan - EventConverter deduplicates it.
+            if ms >= max_short_ms {
+                emit(Trigger::LongActivated);
+            }
+        }
+        SwitchState::Deactivated(ms) => {
+            // We were activated, maybe longactivated, now we deactivate.
+            if ms <= max_short_ms {
+                emit(Trigger::ShortClick);
+            } else {
+                emit(Trigger::LongClick);
+                emit(Trigger::LongDeactivated);
+            }
+            emit(Trigger::Deactivated);
+        }
+        SwitchState::Pulses(edges) => {
+            unwrap!(events.push(Event::PulseCount(input_event.switch_id, edges)));
+        }
+        SwitchState::Rotated(steps) => {
+            unwrap!(events.push(Event::Rotate(input_event.switch_id, steps)));
+        }
+    }
+    events
+}
+
+/// Stateful converter which emits LongActivated once per hold and
+/// rate-limits clicks of flaky switches.
+pub struct EventConverter {
+    auto_repeat: bool,
+    /// Bitmap of inputs which already emitted LongActivated in this hold.
+    long_activated: [u32; 8],
+    min_click_interval: Duration,
+    /// Last emitted clicks.
+    recent_clicks: [Option<(IoIdx, Instant)>; RECENT_CLICKS],
+    double_click_window: Option<Duration>,
+    /// Last short click which can still become a double click.
+    last_short: Option<(IoIdx, Instant)>,
+    /// Long-press thresholds [ms] of inputs not using MAX_SHORT_MS.
+    long_press: &'static [(IoIdx, u32)],
+}
+
+impl EventConverter {
+    pub const fn new(auto_repeat: bool) -> Self {
+        Self {
+            auto_repeat,
+            long_activated: [0; 8],
+            min_click_interval: Duration::from_ticks(0),
+            recent_clicks: [None; RECENT_CLICKS],
+            double_click_window: None,
+            last_short: None,
+            long_press: &[],
+        }
+    }
+
+    /// Use per-input (input, ms) long-press thresholds. Other inputs keep
+    /// the default.
+    pub const fn with_long_press(mut self, thresholds: &'static [(IoIdx, u32)]) -> Self {
+        self.long_press = thresholds;
+        self
+    }
+
+    /// Long-press threshold [ms] of an input.
+    fn max_short_ms(&self, switch_id: IoIdx) -> u32 {
+        self.long_press
+            .iter()
+            .find(|(id, _)| *id == switch_id)
+            .map_or(MAX_SHORT_MS, |(_, ms)| *ms)
+    }
+
+    /// Turn a second short click within the window into a DoubleClick. None
+    /// disables.
+    pub const fn with_double_click_window(mut self, window: Option<Duration>) -> Self {
+        self.double_click_window = window;
+        self
+    }
+
+    /// Record a short click. Returns true if it completes a double click.
+    fn is_double_click(&mut self, switch_id: IoIdx, at: Instant) -> bool {
+        let Some(window) = self.double_click_window else {
+            return false;
+        };
+        match self.last_short {
+            Some((id, since))
+                if id == switch_id && at.saturating_duration_since(since) <= window =>
+            {
+                // Third click starts over.
+                self.last_short = None;
+                true
+            }
+            _ => {
+                self.last_short = Some((switch_id, at));
+                false
+            }
+        }
+    }
+
+    /// Drop Short/LongClicks of an input within the interval since its
+    /// previous click.
+    pub const fn with_min_click_interval(mut self, interval: Duration) -> Self {
+        self.min_click_interval = interval;
+        self
+    }
+
+    /// Record a click. Returns false if it came too soon after the previous
+    /// one and should be dropped.
+    fn allow_click(&mut self, switch_id: IoIdx, at: Instant) -> bool {
+        if self.min_click_interval.as_ticks() == 0 {
+            return true;
+        }
+        let recent = |since: Instant| at.saturating_duration_since(since) < self.min_click_interval;
+        if self
+            .recent_clicks
+            .iter()
+            .flatten()
+            .any(|(id, since)| *id == switch_id && recent(*since))
+        {
+            return false;
+        }
+        // Reuse the slot of this input, or a free one, or the oldest one.
+        let slot = match self
+            .recent_clicks
+            .iter()
+            .position(|slot| matches!(slot, Some((id, _)) if *id == switch_id))
+            .or_else(|| self.recent_clicks.iter().position(Option::is_none))
+        {
+            Some(pos) => &mut self.recent_clicks[pos],
+            None => self
+                .recent_clicks
+                .iter_mut()
+                .min_by_key(|slot| slot.map(|(_, since)| since))
+                .unwrap(),
+        };
+        *slot = Some((switch_id, at));
+        true
+    }
+
+    /// Set the flag of the switch, return the previous value.
+    fn mark(&mut self, switch_id: IoIdx, value: bool) -> bool {
+        let word = &mut self.long_activated[switch_id as usize / 32];
+        let bit = 1 << (switch_id % 32);
+        let previous = *word & bit != 0;
+        if value {
+            *word |= bit;
+        } else {
+            *word &= !bit;
+        }
+        previous
+    }
+
+    pub fn convert(&mut self, input_event: &SwitchEvent) -> Vec<Event, MAX_EVENTS> {
+        let max_short_ms = self.max_short_ms(input_event.switch_id);
         match input_event.state {
-            SwitchState::Activated => {
-                output_q
-                    .send(Event::new_button(input_event.switch_id, Trigger::Activated))
-                    .await;
+            SwitchState::Activated | SwitchState::Deactivated(_) => {
+                self.mark(input_event.switch_id, false);
             }
             SwitchState::Active(ms) => {
-                // We were activated and are still active. For a some period of time.
-                if ms >= MAX_SHORT_MS {
-                    /* TODO: Should this be repeated... or deduplicated? */
-                    output_q
-                        .send(Event::new_button(
-                            input_event.switch_id,
-                            Trigger::LongActivated,
-                        ))
-                        .await;
+                if ms >= max_short_ms && !self.auto_repeat && self.mark(input_event.switch_id, true)
+                {
+                    return Vec::new();
                 }
             }
-            SwitchState::Deactivated(ms) => {
-                // We were activated, maybe longactivated, now we deactivate.
-                if ms <= MAX_SHORT_MS {
-                    output_q
-                        .send(Event::new_button(
-                            input_event.switch_id,
-                            Trigger::ShortClick,
-                        ))
-                        .await;
-                } else {
-                    output_q
-                        .send(Event::new_button(input_event.switch_id, Trigger::LongClick))
-                        .await;
-
-                    output_q
-                        .send(Event::new_button(
-                            input_event.switch_id,
-                            Trigger::LongDeactivated,
-                        ))
-                        .await;
+            SwitchState::Pulses(_) | SwitchState::Rotated(_) => {}
+        }
+        let mut events = convert_with_threshold(input_event, max_short_ms);
+        if matches!(input_event.state, SwitchState::Deactivated(_))
+            && !self.allow_click(input_event.switch_id, input_event.at)
+        {
+            defmt::info!(
+                "Input {} clicked too often - dropping",
+                input_event.switch_id
+            );
+            events.retain(|event| {
+                !matches!(event, Event::ButtonEvent(button)
+                    if matches!(button.trigger, Trigger::ShortClick | Trigger::LongClick))
+            });
+        }
+        for event in events.iter_mut() {
+            if let Event::ButtonEvent(button) = event {
+                match button.trigger {
+                    Trigger::ShortClick => {
+                        if self.is_double_click(button.switch_id, button.at) {
+                            button.trigger = Trigger::DoubleClick;
+                        }
+                    }
+                    // Long click in between breaks the sequence.
+                    Trigger::LongClick => self.last_short = None,
+                    _ => {}
                 }
+            }
+        }
+        events
+    }
+}
+
+/// Converter configured like the running one.
+pub const fn board_converter() -> EventConverter {
+    EventConverter::new(AUTO_REPEAT)
+        .with_min_click_interval(config::board::MIN_CLICK_INTERVAL)
+        .with_double_click_window(DOUBLE_CLICK_WINDOW)
+        .with_long_press(config::board::LONG_PRESS_MS)
+}
 
-                output_q
-                    .send(Event::new_button(
-                        input_event.switch_id,
-                        Trigger::Deactivated,
-                    ))
-                    .await;
+#[embassy_executor::task(pool_size = 1)]
+pub async fn run_event_converter(input_q: &'static InputChannel, output_q: &'static EventChannel) {
+    let mut converter = board_converter();
+    loop {
+        let input_event = input_q.receive().await;
+        for event in converter.convert(&input_event) {
+            if let Event::ButtonEvent(button) = &event {
+                trace::TRACE.record(
+                    button.at,
+                    TraceEvent::Input {
+                        input: button.switch_id,
+                        trigger: button.trigger,
+                    },
+                );
+            }
+            if let Some(dropped) = queue::send(output_q, event, config::board::QUEUE_OVERFLOW).await
+            {
+                defmt::warn!("Event queue is full, dropped {:?}", dropped);
             }
         }
     }
 }
+
+pub mod tests {
+    use super::*;
+    use embassy_time::Instant;
+
+    pub fn timestamp_is_preserved() {
+        let at = Instant::from_millis(1234);
+        for state in [
+            SwitchState::Activated,
+            SwitchState::Active(1000),
+            SwitchState::Deactivated(100),
+            SwitchState::Deactivated(1000),
+        ] {
+            let events = convert(&SwitchEvent {
+                switch_id: 7,
+                state,
+                at,
+            });
+            assert!(!events.is_empty());
+            for event in events {
+                match event {
+                    Event::ButtonEvent(button) => {
+                        assert_eq!(button.switch_id, 7);
+                        assert_eq!(button.at, at);
+                    }
+                    _ => panic!("Only button events expected"),
+                }
+            }
+        }
+    }
+
+    fn long_activations(
+        converter: &mut EventConverter,
+        switch_id: IoIdx,
+        state: SwitchState,
+    ) -> usize {
+        let events = converter.convert(&SwitchEvent {
+            switch_id,
+            state,
+            at: Instant::from_millis(0),
+        });
+        events
+            .iter()
+            .filter(|event| {
+                matches!(event, Event::ButtonEvent(button) if button.trigger == Trigger::LongActivated)
+            })
+            .count()
+    }
+
+    pub fn long_activated_once() {
+        let mut converter = EventConverter::new(false);
+        let mut count = long_activations(&mut converter, 3, SwitchState::Activated);
+        for ms in [100, 400, 500, 600, 1000] {
+            count += long_activations(&mut converter, 3, SwitchState::Active(ms));
+        }
+        // Other switch is tracked separately.
+        assert_eq!(
+            long_activations(&mut converter, 40, SwitchState::Active(500)),
+            1
+        );
+        count += long_activations(&mut converter, 3, SwitchState::Deactivated(1100));
+        assert_eq!(count, 1);
+
+        // Next hold fires again.
+        long_activations(&mut converter, 3, SwitchState::Activated);
+        assert_eq!(
+            long_activations(&mut converter, 3, SwitchState::Active(500)),
+            1
+        );
+        assert_eq!(
+            long_activations(&mut converter, 3, SwitchState::Active(600)),
+            0
+        );
+
+        // Auto-repeat keeps the old behaviour.
+        let mut converter = EventConverter::new(true);
+        assert_eq!(
+            long_activations(&mut converter, 3, SwitchState::Active(500)),
+            1
+        );
+        assert_eq!(
+            long_activations(&mut converter, 3, SwitchState::Active(600)),
+            1
+        );
+    }
+
+    fn clicks(converter: &mut EventConverter, switch_id: IoIdx, at: u64) -> usize {
+        let events = converter.convert(&SwitchEvent {
+            switch_id,
+            state: SwitchState::Deactivated(100),
+            at: Instant::from_millis(at),
+        });
+        // Deactivation is never dropped.
+        assert!(events.iter().any(|event| {
+            matches!(event, Event::ButtonEvent(button) if button.trigger == Trigger::Deactivated)
+        }));
+        events
+            .iter()
+            .filter(|event| {
+                matches!(event, Event::ButtonEvent(button) if button.trigger == Trigger::ShortClick)
+            })
+            .count()
+    }
+
+    /// Input event (switch, state, at ms) and the triggers it must produce,
+    /// in order.
+    type Step = (IoIdx, SwitchState, u64, &'static [Trigger]);
+
+    fn replay(name: &str, mut converter: EventConverter, steps: &[Step]) {
+        for (step, (switch_id, state, at, expected)) in steps.iter().enumerate() {
+            let events = converter.convert(&SwitchEvent {
+                switch_id: *switch_id,
+                state: state.clone(),
+                at: Instant::from_millis(*at),
+            });
+            let triggers: Vec<Trigger, MAX_EVENTS> = events
+                .iter()
+                .map(|event| match event {
+                    Event::ButtonEvent(button) => {
+                        assert_eq!(button.switch_id, *switch_id);
+                        button.trigger
+                    }
+                    _ => panic!("{}: only button events expected", name),
+                })
+                .collect();
+            assert_eq!(triggers.as_slice(), *expected, "{} step {}", name, step);
+        }
+    }
+
+    pub fn converter_contract_replay() {
+        use SwitchState::{Activated, Active, Deactivated};
+        use Trigger::{
+            Activated as A, Deactivated as D, DoubleClick, LongActivated, LongClick,
+            LongDeactivated, ShortClick,
+        };
+
+        replay(
+            "short click",
+            board_converter(),
+            &[
+                (3, Activated, 0, &[A]),
+                (3, Deactivated(100), 100, &[ShortClick, D]),
+            ],
+        );
+        replay(
+            "long click",
+            board_converter(),
+            &[
+                (3, Activated, 0, &[A]),
+                (3, Active(200), 200, &[]),
+                (3, Active(400), 400, &[LongActivated]),
+                (3, Active(600), 600, &[]),
+                (3, Deactivated(800), 800, &[LongClick, LongDeactivated, D]),
+            ],
+        );
+        replay(
+            "hold repeat",
+            EventConverter::new(true),
+            &[
+                (3, Activated, 0, &[A]),
+                (3, Active(400), 400, &[LongActivated]),
+                (3, Active(450), 450, &[LongActivated]),
+                (3, Deactivated(500), 500, &[LongClick, LongDeactivated, D]),
+            ],
+        );
+        let double_click =
+            || board_converter().with_double_click_window(Some(Duration::from_millis(500)));
+        replay(
+            "double click off by default",
+            board_converter(),
+            &[
+                (3, Deactivated(100), 100, &[ShortClick, D]),
+                (3, Deactivated(100), 300, &[ShortClick, D]),
+            ],
+        );
+        replay(
+            "double click",
+            double_click(),
+            &[
+                (3, Deactivated(100), 100, &[ShortClick, D]),
+                (3, Deactivated(100), 300, &[DoubleClick, D]),
+                // Third click starts over.
+                (3, Deactivated(100), 500, &[ShortClick, D]),
+                // Too late.
+                (3, Deactivated(100), 1100, &[ShortClick, D]),
+            ],
+        );
+        replay(
+            "double click broken",
+            double_click(),
+            &[
+                (3, Deactivated(100), 0, &[ShortClick, D]),
+                (3, Deactivated(800), 200, &[LongClick, LongDeactivated, D]),
+                (3, Deactivated(100), 300, &[ShortClick, D]),
+                // Other input in between.
+                (4, Deactivated(100), 400, &[ShortClick, D]),
+                (3, Deactivated(100), 500, &[ShortClick, D]),
+            ],
+        );
+        replay(
+            "glitch suppression",
+            EventConverter::new(false).with_min_click_interval(Duration::from_millis(200)),
+            &[
+                (3, Deactivated(80), 1000, &[ShortClick, D]),
+                // Release is never dropped.
+                (3, Activated, 1040, &[A]),
+                (3, Deactivated(60), 1100, &[D]),
+                (3, Deactivated(80), 1200, &[ShortClick, D]),
+            ],
+        );
+    }
+
+    pub fn long_press_per_input() {
+        use SwitchState::{Active, Deactivated};
+        use Trigger::{Deactivated as D, LongActivated, LongClick, LongDeactivated, ShortClick};
+
+        // Input 5 is a shutter button with a shorter threshold.
+        static THRESHOLDS: [(IoIdx, u32); 1] = [(5, 200)];
+        let converter = || EventConverter::new(false).with_long_press(&THRESHOLDS);
+
+        replay(
+            "default threshold",
+            converter(),
+            &[
+                (3, Active(300), 300, &[]),
+                (3, Deactivated(300), 300, &[ShortClick, D]),
+            ],
+        );
+        replay(
+            "short threshold",
+            converter(),
+            &[
+                (5, Active(200), 200, &[LongActivated]),
+                (5, Active(300), 300, &[]),
+                (5, Deactivated(300), 300, &[LongClick, LongDeactivated, D]),
+            ],
+        );
+    }
+
+    pub fn click_rate_limited() {
+        let mut converter =
+            EventConverter::new(false).with_min_click_interval(Duration::from_millis(200));
+        assert_eq!(clicks(&mut converter, 3, 1000), 1);
+        assert_eq!(clicks(&mut converter, 3, 1050), 0);
+        // Other inputs are not affected.
+        assert_eq!(clicks(&mut converter, 4, 1060), 1);
+        // Interval counts from the last emitted click.
+        assert_eq!(clicks(&mut converter, 3, 1199), 0);
+        assert_eq!(clicks(&mut converter, 3, 1200), 1);
+
+        // More inputs than remembered clicks.
+        for switch_id in 10..10 + RECENT_CLICKS as u8 {
+            assert_eq!(clicks(&mut converter, switch_id, 1300), 1);
+        }
+        assert_eq!(clicks(&mut converter, 10, 1350), 0);
+
+        // Disabled by default.
+        let mut converter = EventConverter::new(false);
+        assert_eq!(clicks(&mut converter, 3, 1000), 1);
+        assert_eq!(clicks(&mut converter, 3, 1001), 1);
+    }
+}
@@ -1,8 +1,23 @@
 use defmt::Format;
-use embassy_sync::{blocking_mutex::raw::ThreadModeRawMutex, channel::Channel};
+use embassy_sync::channel::Channel;
+use embassy_time::Instant;
 
 pub type IoIdx = u8;
 
+/// Index `0` is reserved. Microvm uses it as a virtual input for layers
+/// activated by code (LayerPush/LayerSet) - a real IO with this index would
+/// cause phantom layer deactivations.
+pub const RESERVED_IDX: IoIdx = 0;
+
+/// Check that no real IO uses the reserved index. Returns position of the
+/// offending entry.
+pub fn check_indices(indices: &[IoIdx]) -> Result<(), usize> {
+    match indices.iter().position(|idx| *idx == RESERVED_IDX) {
+        Some(pos) => Err(pos),
+        None => Ok(()),
+    }
+}
+
 /// Debounced Input switch state
 #[derive(Format, Clone)]
 pub enum SwitchState {
@@ -12,6 +27,11 @@ pub enum SwitchState {
     Active(u32),
     /// Released with a time it was pressed (in quantified ms)
     Deactivated(u32),
+    /// Edges counted on a pulse input since its previous report. Pulse
+    /// inputs are not debounced.
+    Pulses(u16),
+    /// Rotary encoder turned by detents, positive clockwise.
+    Rotated(i8),
 }
 
 /// Event transmitted over a channel
@@ -19,12 +39,14 @@ pub enum SwitchState {
 pub struct SwitchEvent {
     pub switch_id: IoIdx,
     pub state: SwitchState,
+    /// When the input scan detected this state.
+    pub at: Instant,
 }
 
 /// Higher level switch abstraction.
 /// eg. Activated -> LongActivated -> LongClick -> LongDeactivated -> Deactivated.
 /// Activated -> ShortClick -> Deactivated
-#[derive(Copy, Clone, Eq, PartialEq, Format)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Format)]
 #[repr(u8)]
 pub enum Trigger {
     /// Short click activation; longer than debounce period, but shorter than a
@@ -40,6 +62,9 @@ pub enum Trigger {
     LongActivated,
     /// Deactivation after LongActivated was triggered
     LongDeactivated,
+    /// Second short click shortly after the previous one. Replaces its
+    /// ShortClick; unbound double clicks act as short clicks.
+    DoubleClick,
 }
 
 /// Event transmitted over a channel
@@ -47,13 +72,75 @@ pub enum Trigger {
 pub struct ButtonEvent {
     pub switch_id: IoIdx,
     pub trigger: Trigger,
+    /// When the originating input state was detected. Used to track latency.
+    pub at: Instant,
 }
 
+/// Mutex of the event channels. They are used from the thread mode only -
+/// except in the host tests, running on many threads.
+#[cfg(target_os = "none")]
+pub type ChannelMutex = embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
+#[cfg(not(target_os = "none"))]
+pub type ChannelMutex = embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+
 /// Channel to transport Raw, low-level IO events
-pub type InputChannel = Channel<ThreadModeRawMutex, SwitchEvent, 5>;
+pub type InputChannel = Channel<ChannelMutex, SwitchEvent, 5>;
+
+/// Why an output couldn't be set.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Format)]
+pub enum OutputFault {
+    /// Expander didn't acknowledge - missing, unpowered or wrong address.
+    NoAcknowledge,
+    /// Bus error, arbitration loss or overrun.
+    Bus,
+    /// Line past the 16 lines of an expander.
+    InvalidLine,
+    /// Output index is not mapped.
+    UnknownOutput,
+}
+
+/// Failed output write with the expander and line it happened on.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Format)]
+pub struct OutputError {
+    pub expander: u8,
+    pub line: u8,
+    pub fault: OutputFault,
+}
+
+impl OutputError {
+    /// Expander and line of outputs that aren't on an expander.
+    pub const NONE: u8 = 0x0F;
+
+    pub const fn unknown() -> Self {
+        Self {
+            expander: Self::NONE,
+            line: Self::NONE,
+            fault: OutputFault::UnknownOutput,
+        }
+    }
+
+    /// Error detail byte sent over the bus: expander in the high nibble,
+    /// line in the low one.
+    pub fn detail(&self) -> u8 {
+        (self.expander.min(Self::NONE) << 4) | self.line.min(Self::NONE)
+    }
+}
 
 /// Any expanders that group multiple IOs together in batches of 16.
 pub(crate) trait GroupedOutputs {
-    async fn set_high(&mut self, idx: u8) -> Result<(), ()>;
-    async fn set_low(&mut self, idx: u8) -> Result<(), ()>;
+    async fn set_high(&mut self, idx: u8) -> Result<(), OutputError>;
+    async fn set_low(&mut self, idx: u8) -> Result<(), OutputError>;
+
+    /// Set levels (true - high) of multiple IOs. Implementations should do it
+    /// in a single write, so the IOs change at once.
+    async fn set_levels(&mut self, levels: &[(u8, bool)]) -> Result<(), OutputError> {
+        for (idx, high) in levels {
+            if *high {
+                self.set_high(*idx).await?;
+            } else {
+                self.set_low(*idx).await?;
+            }
+        }
+        Ok(())
+    }
 }
@@ -1,15 +1,225 @@
-use crate::components::status::{self, Status};
+use crate::components::queue;
+use crate::components::retry::{RetryAction, RetryPolicy};
+use crate::components::status::{self, LedLine, Status};
+use crate::config;
+use crate::io::clock::{Clock, SystemClock};
 use crate::io::events::{self, InputChannel, IoIdx};
 use crate::io::pcf8575::Pcf8575;
 use core::sync::atomic::AtomicBool;
-use core::sync::atomic::{AtomicU16, Ordering};
+use core::sync::atomic::{AtomicU32, Ordering};
+use embassy_stm32::gpio::Output;
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use embassy_sync::mutex::Mutex;
-use embassy_time::{Duration, Timer};
+use embassy_time::{Duration, Instant, with_timeout};
 use embedded_hal_async::i2c::I2c;
 
+/// Scan period right after an input change - better latency and debounce resolution.
+const FAST_SCAN_PERIOD: Duration = Duration::from_millis(10);
+/// Scan period when nothing happens - saves the I²C bandwidth.
+const IDLE_SCAN_PERIOD: Duration = Duration::from_millis(50);
+/// How long to keep scanning fast after the last input activity.
+const FAST_SCAN_WINDOW: Duration = Duration::from_millis(2000);
+/// Time [ms] input has to be active to be considered activated (debounce).
+const MIN_ACTIVE_MS: u32 = 60;
+/// Errors after which a required expander is considered dead.
+const MAX_ERRORS: u16 = 60;
+/// How long to wait for the expander lock before giving up on a transfer.
+const LOCK_TIMEOUT: Duration = Duration::from_millis(20);
+/// Retry period of the expander configuration within the startup grace.
+const STARTUP_RETRY_PERIOD: Duration = Duration::from_millis(20);
+/// How often edge counts of pulse inputs are reported.
+const PULSE_REPORT_PERIOD: Duration = Duration::from_secs(10);
+
+/// Lock the expander for a single transfer only, so others sharing it can
+/// interleave. Lock contention past the timeout counts as a failed transfer.
+pub async fn with_expander<BUS: I2c, T>(
+    expander: &Mutex<NoopRawMutex, Pcf8575<BUS>>,
+    op: impl AsyncFnOnce(&mut Pcf8575<BUS>) -> Result<T, ()>,
+) -> Result<T, ()> {
+    let Ok(mut expander) = with_timeout(LOCK_TIMEOUT, expander.lock()).await else {
+        defmt::warn!("Timeout while waiting for the expander lock");
+        return Err(());
+    };
+    op(&mut expander).await
+}
+
+/// Why the expander couldn't be configured.
+#[derive(Debug, Eq, PartialEq, Clone, Copy, defmt::Format)]
+pub enum InitFailure {
+    /// Failed within the startup grace - probably still powering up.
+    NotReady,
+    /// Failed after the grace, counts as an error.
+    Failed,
+}
+
+/// Configure all pins as inputs (high). Failures until `ready_by` are not
+/// errors - the expander might be still powering up.
+pub async fn configure<BUS: I2c>(
+    expander: &Mutex<NoopRawMutex, Pcf8575<BUS>>,
+    ready_by: Instant,
+    now: Instant,
+) -> Result<(), InitFailure> {
+    match with_expander(expander, async |e| e.write(0xffff).await.map_err(|_| ())).await {
+        Ok(()) => Ok(()),
+        Err(()) if now < ready_by => Err(InitFailure::NotReady),
+        Err(()) => Err(InitFailure::Failed),
+    }
+}
+
+/// Last read inputs with the online flag in a single word, so a reader never
+/// sees the value of one scan with the flag of another. Layout: bits 0-15
+/// inputs, bit 16 online, bits 20-23 sequence of the store.
+pub struct InputSnapshot(AtomicU32);
+
+impl Default for InputSnapshot {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InputSnapshot {
+    const ONLINE: u32 = 1 << 16;
+    const SEQ_SHIFT: u32 = 20;
+
+    pub const fn new() -> Self {
+        Self(AtomicU32::new(0))
+    }
+
+    /// Store inputs of a successful read, or None if the expander is offline.
+    pub fn store(&self, bytes: Option<u16>) {
+        let seq = (self.seq().wrapping_add(1) & 0x0F) as u32;
+        let word = match bytes {
+            Some(bytes) => bytes as u32 | Self::ONLINE,
+            None => 0,
+        };
+        self.0
+            .store(word | (seq << Self::SEQ_SHIFT), Ordering::Relaxed);
+    }
+
+    /// Inputs of the last read, None if the expander is offline.
+    pub fn load(&self) -> Option<u16> {
+        let word = self.0.load(Ordering::Relaxed);
+        (word & Self::ONLINE != 0).then_some(word as u16)
+    }
+
+    /// Sequence of the last store (4 bits), changes on each scan.
+    pub fn seq(&self) -> u8 {
+        (self.0.load(Ordering::Relaxed) >> Self::SEQ_SHIFT) as u8 & 0x0F
+    }
+}
+
+/// Adaptive scan period: fast for a while after activity, slow when idle.
+pub struct ScanPeriod {
+    fast_until: Option<Instant>,
+    /// Never relax to the idle period.
+    pinned: bool,
+}
+
+impl Default for ScanPeriod {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ScanPeriod {
+    pub const fn new() -> Self {
+        Self {
+            fast_until: None,
+            pinned: false,
+        }
+    }
+
+    /// Always fast - pulses are counted between scans, so a pulse shorter
+    /// than the idle period would be lost.
+    pub const fn pinned() -> Self {
+        Self {
+            fast_until: None,
+            pinned: true,
+        }
+    }
+
+    /// Input activity was seen - scan fast for a while.
+    pub fn on_activity(&mut self, now: Instant) {
+        self.fast_until = Some(now + FAST_SCAN_WINDOW);
+    }
+
+    /// Period to wait until next scan.
+    pub fn period(&self, now: Instant) -> Duration {
+        match self.fast_until {
+            _ if self.pinned => FAST_SCAN_PERIOD,
+            Some(until) if now < until => FAST_SCAN_PERIOD,
+            _ => IDLE_SCAN_PERIOD,
+        }
+    }
+}
+
+/// Edge counter of inputs which bypass the debounce, eg. pulse outputs of
+/// meters. Counts are accumulated between periodic reports. Edges are seen
+/// between scans, pulses have to be longer than FAST_SCAN_PERIOD.
+pub struct PulseCounter {
+    /// Positions of the pulse inputs.
+    mask: u16,
+    /// Previous scan, none before the first one.
+    last: Option<u16>,
+    counts: [u16; 16],
+    /// Start of the current report period.
+    since: Option<Instant>,
+}
+
+impl PulseCounter {
+    pub const fn new(mask: u16) -> Self {
+        Self {
+            mask,
+            last: None,
+            counts: [0; 16],
+            since: None,
+        }
+    }
+
+    pub fn is_pulse(&self, pos: usize) -> bool {
+        self.mask & (1 << pos) != 0
+    }
+
+    /// Count edges of pulse inputs since the previous scan. Returns true if
+    /// any was seen. First scan only takes the initial levels.
+    pub fn scan(&mut self, bytes: u16) -> bool {
+        let Some(last) = self.last.replace(bytes) else {
+            return false;
+        };
+        let changed = (last ^ bytes) & self.mask;
+        for (pos, count) in self.counts.iter_mut().enumerate() {
+            if changed & (1 << pos) != 0 {
+                *count = count.saturating_add(1);
+            }
+        }
+        changed != 0
+    }
+
+    /// Once per period take the counts of inputs which saw any edges, as
+    /// (position, edges).
+    pub fn report(&mut self, now: Instant, period: Duration) -> heapless::Vec<(usize, u16), 16> {
+        let mut report = heapless::Vec::new();
+        let Some(since) = self.since else {
+            self.since = Some(now);
+            return report;
+        };
+        if now.saturating_duration_since(since) < period {
+            return report;
+        }
+        self.since = Some(now);
+        for (pos, count) in self.counts.iter_mut().enumerate() {
+            if *count > 0 {
+                // Can't overflow, there are 16 positions.
+                let _ = report.push((pos, *count));
+                *count = 0;
+            }
+        }
+        report
+    }
+}
+
 /// Read inputs (switches) and generate events.
-pub struct ExpanderInputs<BUS: I2c> {
+pub struct ExpanderInputs<BUS: I2c, L: LedLine + 'static = Output<'static>> {
     /// Indices of connected PINs
     io_indices: [IoIdx; 16],
 
@@ -22,42 +232,71 @@ pub struct ExpanderInputs<BUS: I2c> {
     // We output events into this queue.
     queue: &'static InputChannel,
 
-    /// Internal error counter that will cause panic if unreachable for too long.
-    errors: AtomicU16,
+    /// Error counter that will cause panic if unreachable for too long.
+    retry: RetryPolicy,
 
-    /// True if expander responds
-    expander_online: AtomicBool,
+    /// Expander shares the address with another device - its reads are garbage.
+    disabled: AtomicBool,
 
-    /// Last read value from expander.
-    last_input: AtomicU16,
+    /// Last read value from expander, if it responds.
+    last_input: InputSnapshot,
 
     /// For notifing about problems with expander,
-    status: &'static Status,
+    status: &'static Status<L>,
 
     /// Is this expander required? Or it might be absent?
     required: bool,
+
+    /// Positions of inputs counting pulses instead of debouncing.
+    pulse_mask: u16,
+
+    /// Time after start within which the expander may not respond yet.
+    startup_grace: Duration,
 }
 
-impl<BUS: I2c> ExpanderInputs<BUS> {
+impl<BUS: I2c, L: LedLine + 'static> ExpanderInputs<BUS, L> {
     pub fn new(
         expander: Pcf8575<BUS>,
         id: u8,
         io_indices: [IoIdx; 16],
         queue: &'static InputChannel,
-        status: &'static Status,
+        status: &'static Status<L>,
         required: bool,
     ) -> Self {
+        if let Err(pos) = events::check_indices(&io_indices) {
+            defmt::panic!("Expander {} uses reserved input index 0 at {}", id, pos);
+        }
         Self {
             io_indices,
             expander: Mutex::new(expander),
             id,
             queue,
-            errors: AtomicU16::new(0),
-            expander_online: AtomicBool::new(false),
-            last_input: AtomicU16::new(0),
+            retry: RetryPolicy::new(MAX_ERRORS, Duration::from_ticks(0)),
+            disabled: AtomicBool::new(false),
+            last_input: InputSnapshot::new(),
             status,
             required,
+            pulse_mask: 0,
+            startup_grace: Duration::from_ticks(0),
+        }
+    }
+
+    /// Don't count configuration failures as errors within the time after
+    /// start, eg. on a slow power ramp.
+    pub fn with_startup_grace(mut self, grace: Duration) -> Self {
+        self.startup_grace = grace;
+        self
+    }
+
+    /// Count edges of the given inputs without debouncing and report them
+    /// periodically. Indices not handled by this expander are ignored.
+    pub fn with_pulse_inputs(mut self, inputs: &[IoIdx]) -> Self {
+        for (pos, index) in self.io_indices.iter().enumerate() {
+            if inputs.contains(index) {
+                self.pulse_mask |= 1 << pos;
+            }
         }
+        self
     }
 
     async fn transmit(&self, event: events::SwitchEvent) {
@@ -67,8 +306,13 @@ impl<BUS: I2c> ExpanderInputs<BUS> {
         }
         self.status.is_warning();
         status::COUNTERS.input_queue_full.inc();
-        defmt::error!("Input event queue is full! Might block");
-        self.queue.send(event).await;
+        defmt::error!(
+            "Input event queue is full! Policy {:?}",
+            config::board::QUEUE_OVERFLOW
+        );
+        if let Some(dropped) = queue::send(self.queue, event, config::board::QUEUE_OVERFLOW).await {
+            defmt::warn!("Dropped input event {:?}", dropped);
+        }
     }
 
     pub fn get_indices(&self) -> &[u8; 16] {
@@ -79,11 +323,18 @@ impl<BUS: I2c> ExpanderInputs<BUS> {
         self.id
     }
 
+    /// Stop scanning and never report the expander as present. Used when the
+    /// init probe finds an address conflict.
+    pub fn disable(&self) {
+        self.disabled.store(true, Ordering::Relaxed);
+        self.last_input.store(None);
+    }
+
     pub fn get_inputs(&self) -> Option<[(u8, bool); 16]> {
-        let input = self.last_input.load(Ordering::Relaxed);
-        if !self.expander_online.load(Ordering::Relaxed) {
+        if self.disabled.load(Ordering::Relaxed) {
             return None;
         }
+        let input = self.last_input.load()?;
 
         let mut data: [(u8, bool); 16] = [(0, false); 16];
         for (pos, index) in self.io_indices.iter().enumerate() {
@@ -92,126 +343,176 @@ impl<BUS: I2c> ExpanderInputs<BUS> {
         Some(data)
     }
 
+    /// Panic if the retry policy gave up on the expander.
+    fn check_dead(&self, action: RetryAction) {
+        if action == RetryAction::Panic {
+            defmt::panic!(
+                "Expander {} connection seems dead after {} errors",
+                self.id,
+                self.retry.errors()
+            );
+        }
+    }
+
     /// Active scanner loop that observes the expander and generates events when input changes.
     pub async fn run(&self) -> ! {
+        self.scan_loop(&SystemClock).await
+    }
+
+    /// Scanner loop timed by the given clock.
+    pub async fn scan_loop(&self, clock: &impl Clock) -> ! {
         /*
          * Let's start with a generic NO switches. So we set outputs to HIGH and
          * watch for LOW state which is active.
          */
         let mut initialized = false;
-        let mut expander = self.expander.lock().await;
 
         defmt::info!("Starting expander scanning loop");
 
-        const LOOP_WAIT_MS: u32 = 30;
-        const MIN_TIME: u16 = 2;
         const ACTIVE_LEVEL: bool = false;
 
-        /* Amount of time the switch is active */
-        let mut state = [0u16; 16];
+        /* Amount of time [ms] the switch is active */
+        let mut state = [0u32; 16];
+        let mut scan_period = if self.pulse_mask != 0 {
+            ScanPeriod::pinned()
+        } else {
+            ScanPeriod::new()
+        };
+        let mut last_scan = clock.now();
+        let mut pulses = PulseCounter::new(self.pulse_mask);
+        let ready_by = clock.now() + self.startup_grace;
 
         loop {
+            if self.disabled.load(Ordering::Relaxed) {
+                clock.delay(Duration::from_millis(1000)).await;
+                last_scan = clock.now();
+                continue;
+            }
+
             if !initialized {
                 // Initialize as high to use them as inputs.
-                if expander.write(0xffff).await.is_ok() {
-                    initialized = true;
-                } else {
-                    if self.required {
-                        status::COUNTERS.expander_input_error.inc();
-                        self.status.is_warning();
-                        let errs = self.errors.fetch_add(1, Ordering::Relaxed);
-                        defmt::error!("Unable to configure expander {}. Errors={}", self.id, errs);
-                        if errs > 60 {
-                            defmt::panic!(
-                                "Expander {} connection seems dead after {} errors",
+                match configure(&self.expander, ready_by, clock.now()).await {
+                    Ok(()) => initialized = true,
+                    Err(InitFailure::NotReady) => {
+                        defmt::debug!("Expander {} not ready yet", self.id);
+                        clock.delay(STARTUP_RETRY_PERIOD).await;
+                        last_scan = clock.now();
+                        continue;
+                    }
+                    Err(InitFailure::Failed) => {
+                        if self.required {
+                            status::COUNTERS.expander_input_error.inc();
+                            self.status.is_warning();
+                            let action = self.retry.record_failure();
+                            crate::error_limited!(
+                                10,
+                                "Unable to configure expander {}. Errors={}",
                                 self.id,
-                                errs
+                                self.retry.errors()
                             );
+                            self.check_dead(action);
                         }
+                        self.last_input.store(None);
+                        clock.delay(Duration::from_millis(1000)).await;
+                        last_scan = clock.now();
+                        continue;
                     }
-                    self.expander_online.store(false, Ordering::Relaxed);
-                    Timer::after(Duration::from_millis(1000)).await;
-                    continue;
                 }
             }
 
-            Timer::after(Duration::from_millis(LOOP_WAIT_MS.into())).await;
+            clock.delay(scan_period.period(clock.now())).await;
 
-            let bytes = if let Ok(bytes) = expander.read().await {
-                if self.errors.load(Ordering::Relaxed) > 0 {
-                    self.errors.fetch_sub(1, Ordering::Relaxed);
-                }
-                self.last_input.store(bytes, Ordering::Relaxed);
-                self.expander_online.store(true, Ordering::Relaxed);
-                bytes
-            } else {
-                // Reading failed. If intermittent, we can accept it.
-                let errs = self.errors.load(Ordering::Relaxed) + 1;
-                self.errors.store(errs, Ordering::Relaxed);
-
-                self.last_input.store(0, Ordering::Relaxed);
-                self.expander_online.store(false, Ordering::Relaxed);
-
-                // TODO: After failure we might need to reinitialize as inputs.
-                // TODO: initialized = false; Test it.
-
-                if self.required {
-                    status::COUNTERS.expander_input_error.inc();
-                    self.status.is_warning();
-                    defmt::error!("Unable to read expander {}. Errors={}", self.id, errs);
-                    if errs > 60 {
-                        defmt::panic!(
-                            "Expander {} connection seems dead after {} errors",
+            let bytes =
+                if let Ok(bytes) = with_expander(&self.expander, async |e| e.read().await).await {
+                    self.retry.record_success();
+                    self.last_input.store(Some(bytes));
+                    bytes
+                } else {
+                    // Reading failed. If intermittent, we can accept it.
+                    let action = self.retry.record_failure();
+
+                    self.last_input.store(None);
+
+                    // TODO: After failure we might need to reinitialize as inputs.
+                    // TODO: initialized = false; Test it.
+
+                    if self.required {
+                        status::COUNTERS.expander_input_error.inc();
+                        self.status.is_warning();
+                        crate::error_limited!(
+                            10,
+                            "Unable to read expander {}. Errors={}",
                             self.id,
-                            errs
+                            self.retry.errors()
                         );
+                        self.check_dead(action);
                     }
-                }
-                continue;
-            };
+                    // Inputs weren't seen, don't count the time as held.
+                    last_scan = clock.now();
+                    continue;
+                };
+
+            let now = clock.now();
+            let elapsed_ms = now.saturating_duration_since(last_scan).as_millis() as u32;
+            last_scan = now;
+
+            if pulses.scan(bytes) {
+                scan_period.on_activity(now);
+            }
+            for (pos, edges) in pulses.report(now, PULSE_REPORT_PERIOD) {
+                self.transmit(events::SwitchEvent {
+                    switch_id: self.io_indices[pos],
+                    state: events::SwitchState::Pulses(edges),
+                    at: now,
+                })
+                .await;
+            }
 
             for (pos, entry) in state.iter_mut().enumerate() {
+                if pulses.is_pulse(pos) {
+                    continue;
+                }
                 let value = (bytes & (1 << pos)) != 0;
 
                 if value == ACTIVE_LEVEL {
                     /* Switch is pressed (or maybe noise/contact bouncing) */
-                    *entry = entry.saturating_add(1);
-
-                    match (*entry).cmp(&MIN_TIME) {
-                        core::cmp::Ordering::Equal => {
-                            /* Just activated */
-                            self.transmit(events::SwitchEvent {
-                                switch_id: self.io_indices[pos],
-                                state: events::SwitchState::Activated,
-                            })
-                            .await;
-                        }
-                        core::cmp::Ordering::Greater => {
-                            /* Was activated and still is active */
-                            let time_active = LOOP_WAIT_MS * (*entry as u32);
-                            self.transmit(events::SwitchEvent {
-                                switch_id: self.io_indices[pos],
-                                state: events::SwitchState::Active(time_active),
-                            })
-                            .await;
-                        }
-                        _ => {
-                            /* Not yet active */
-                            defmt::info!(
-                                "new active level state id={} idx={} state={}",
-                                self.id,
-                                pos,
-                                entry
-                            );
-                        }
+                    scan_period.on_activity(now);
+                    let previous = *entry;
+                    *entry = entry.saturating_add(elapsed_ms);
+
+                    if previous >= MIN_ACTIVE_MS {
+                        /* Was activated and still is active */
+                        self.transmit(events::SwitchEvent {
+                            switch_id: self.io_indices[pos],
+                            state: events::SwitchState::Active(*entry),
+                            at: now,
+                        })
+                        .await;
+                    } else if *entry >= MIN_ACTIVE_MS {
+                        /* Just activated */
+                        self.transmit(events::SwitchEvent {
+                            switch_id: self.io_indices[pos],
+                            state: events::SwitchState::Activated,
+                            at: now,
+                        })
+                        .await;
+                    } else {
+                        /* Not yet active */
+                        defmt::info!(
+                            "new active level state id={} idx={} state={}",
+                            self.id,
+                            pos,
+                            entry
+                        );
                     }
                 } else {
-                    if *entry >= MIN_TIME {
+                    if *entry >= MIN_ACTIVE_MS {
                         /* Was active, now it just got deactivated */
-                        let time_active = LOOP_WAIT_MS * (*entry as u32);
+                        scan_period.on_activity(now);
                         self.transmit(events::SwitchEvent {
                             switch_id: self.io_indices[pos],
-                            state: events::SwitchState::Deactivated(time_active),
+                            state: events::SwitchState::Deactivated(*entry),
+                            at: now,
                         })
                         .await;
                     }
@@ -222,3 +523,618 @@ impl<BUS: I2c> ExpanderInputs<BUS> {
         }
     }
 }
+
+pub mod tests {
+    use super::*;
+
+    pub fn reserved_index_is_rejected() {
+        let mut indices = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16];
+        assert_eq!(events::check_indices(&indices), Ok(()));
+        indices[4] = events::RESERVED_IDX;
+        assert_eq!(events::check_indices(&indices), Err(4));
+    }
+
+    pub fn scan_period_shortens_after_edge() {
+        let mut period = ScanPeriod::new();
+        let start = Instant::from_millis(10_000);
+        assert_eq!(period.period(start), IDLE_SCAN_PERIOD);
+
+        period.on_activity(start);
+        assert_eq!(period.period(start), FAST_SCAN_PERIOD);
+        assert_eq!(
+            period.period(start + FAST_SCAN_WINDOW - Duration::from_millis(1)),
+            FAST_SCAN_PERIOD
+        );
+
+        // Relaxes back when nothing happens.
+        assert_eq!(period.period(start + FAST_SCAN_WINDOW), IDLE_SCAN_PERIOD);
+
+        // Pulse inputs keep it fast.
+        let period = ScanPeriod::pinned();
+        assert_eq!(period.period(start), FAST_SCAN_PERIOD);
+        assert_eq!(
+            period.period(start + FAST_SCAN_WINDOW * 10),
+            FAST_SCAN_PERIOD
+        );
+
+        // Debounce time doesn't depend on the scan period.
+        assert!(MIN_ACTIVE_MS as u64 > IDLE_SCAN_PERIOD.as_millis());
+        assert_eq!(MIN_ACTIVE_MS as u64 % FAST_SCAN_PERIOD.as_millis(), 0);
+    }
+
+    pub fn input_snapshot_never_tears() {
+        use embassy_futures::{join::join, yield_now};
+
+        let snapshot = InputSnapshot::new();
+        assert_eq!(snapshot.load(), None);
+
+        // Scanner going on and offline, reader polling in between every step.
+        const SCANS: u16 = 200;
+        let writer = async {
+            for scan in 0..SCANS {
+                let bytes = (scan % 3 != 0).then_some(scan | 0x8000);
+                snapshot.store(bytes);
+                yield_now().await;
+            }
+        };
+        let reader = async {
+            let mut last_seq = snapshot.seq();
+            for _ in 0..SCANS {
+                let seq = snapshot.seq();
+                // Online value always comes from an online scan.
+                if let Some(bytes) = snapshot.load() {
+                    assert_eq!(bytes & 0x8000, 0x8000);
+                    assert_ne!((bytes & 0x7FFF) % 3, 0);
+                }
+                // Single writer - the sequence moves by one per store.
+                assert!(seq == last_seq || seq == (last_seq + 1) & 0x0F);
+                last_seq = seq;
+                yield_now().await;
+            }
+        };
+        embassy_futures::block_on(join(writer, reader));
+
+        // Offline never keeps stale inputs.
+        snapshot.store(Some(0xFFFF));
+        snapshot.store(None);
+        assert_eq!(snapshot.load(), None);
+        snapshot.store(Some(0));
+        assert_eq!(snapshot.load(), Some(0));
+    }
+
+    pub fn pulse_inputs_count_edges() {
+        /// Expander whose input 3 toggles on every read, input 4 is held.
+        struct TogglingBus {
+            level: bool,
+        }
+
+        impl embedded_hal_async::i2c::ErrorType for TogglingBus {
+            type Error = core::convert::Infallible;
+        }
+
+        impl I2c for TogglingBus {
+            async fn transaction(
+                &mut self,
+                _address: u8,
+                operations: &mut [embedded_hal_async::i2c::Operation<'_>],
+            ) -> Result<(), Self::Error> {
+                for operation in operations {
+                    if let embedded_hal_async::i2c::Operation::Read(buf) = operation {
+                        let bytes: u16 = if self.level { 0xffff } else { !(1 << 3) };
+                        buf.copy_from_slice(&(bytes & !(1 << 4)).to_le_bytes());
+                        self.level = !self.level;
+                    }
+                }
+                Ok(())
+            }
+        }
+
+        let expander: Mutex<NoopRawMutex, _> =
+            Mutex::new(Pcf8575::new(TogglingBus { level: true }, true, true, true));
+        let mut counter = PulseCounter::new(1 << 3);
+        assert!(counter.is_pulse(3));
+        assert!(!counter.is_pulse(4));
+
+        let start = Instant::from_millis(1000);
+        assert!(counter.report(start, PULSE_REPORT_PERIOD).is_empty());
+        // Toggles are faster than the debounce time.
+        let mut edges = 0;
+        for _ in 0..8 {
+            let bytes =
+                embassy_futures::block_on(with_expander(&expander, async |e| e.read().await));
+            if counter.scan(bytes.unwrap()) {
+                edges += 1;
+            }
+        }
+        // First read only takes the levels.
+        assert_eq!(edges, 7);
+        let half = PULSE_REPORT_PERIOD / 2;
+        assert!(counter.report(start + half, PULSE_REPORT_PERIOD).is_empty());
+        assert_eq!(
+            counter
+                .report(start + PULSE_REPORT_PERIOD, PULSE_REPORT_PERIOD)
+                .as_slice(),
+            &[(3, 7)]
+        );
+        // Counts restart, quiet inputs aren't reported.
+        assert!(
+            counter
+                .report(start + PULSE_REPORT_PERIOD * 2, PULSE_REPORT_PERIOD)
+                .is_empty()
+        );
+    }
+
+    pub fn slow_expander_within_startup_grace() {
+        /// Expander NAKing the first few transfers.
+        struct WarmingUpBus {
+            naks: u8,
+        }
+
+        impl embedded_hal_async::i2c::ErrorType for WarmingUpBus {
+            type Error = embedded_hal_async::i2c::ErrorKind;
+        }
+
+        impl I2c for WarmingUpBus {
+            async fn transaction(
+                &mut self,
+                _address: u8,
+                _operations: &mut [embedded_hal_async::i2c::Operation<'_>],
+            ) -> Result<(), Self::Error> {
+                if self.naks > 0 {
+                    self.naks -= 1;
+                    return Err(embedded_hal_async::i2c::ErrorKind::NoAcknowledge(
+                        embedded_hal_async::i2c::NoAcknowledgeSource::Address,
+                    ));
+                }
+                Ok(())
+            }
+        }
+
+        let expander: Mutex<NoopRawMutex, _> =
+            Mutex::new(Pcf8575::new(WarmingUpBus { naks: 3 }, true, true, true));
+        let retry = RetryPolicy::new(MAX_ERRORS, Duration::from_ticks(0));
+        let start = Instant::from_millis(1000);
+        let ready_by = start + Duration::from_millis(100);
+
+        // Retried like the scanner loop does, errors counted only when failed.
+        let mut now = start;
+        let mut result = Err(InitFailure::NotReady);
+        for _ in 0..10 {
+            result = embassy_futures::block_on(configure(&expander, ready_by, now));
+            match result {
+                Ok(()) => break,
+                Err(InitFailure::NotReady) => now += STARTUP_RETRY_PERIOD,
+                Err(InitFailure::Failed) => {
+                    retry.record_failure();
+                }
+            }
+        }
+        assert_eq!(result, Ok(()));
+        assert_eq!(retry.errors(), 0);
+        assert!(now < ready_by);
+
+        // Past the grace the expander is considered failing.
+        let expander: Mutex<NoopRawMutex, _> =
+            Mutex::new(Pcf8575::new(WarmingUpBus { naks: 1 }, true, true, true));
+        assert_eq!(
+            embassy_futures::block_on(configure(&expander, ready_by, ready_by)),
+            Err(InitFailure::Failed)
+        );
+    }
+
+    /// Bus recording the order of accesses.
+    struct MockBus {
+        log: heapless::Vec<u8, 16>,
+    }
+
+    impl embedded_hal_async::i2c::ErrorType for MockBus {
+        type Error = core::convert::Infallible;
+    }
+
+    impl I2c for MockBus {
+        async fn transaction(
+            &mut self,
+            address: u8,
+            operations: &mut [embedded_hal_async::i2c::Operation<'_>],
+        ) -> Result<(), Self::Error> {
+            for operation in operations {
+                if let embedded_hal_async::i2c::Operation::Read(buf) = operation {
+                    buf.fill(address);
+                }
+            }
+            self.log.push(address).unwrap();
+            Ok(())
+        }
+    }
+
+    pub fn shared_bus_readers_interleave() {
+        use embassy_embedded_hal::shared_bus::asynch::i2c::I2cDevice;
+        use embassy_futures::{join::join, yield_now};
+
+        type Expander<'a> = Mutex<NoopRawMutex, Pcf8575<I2cDevice<'a, NoopRawMutex, MockBus>>>;
+
+        let bus = Mutex::new(MockBus {
+            log: heapless::Vec::new(),
+        });
+        let first: Expander = Mutex::new(Pcf8575::new(I2cDevice::new(&bus), true, true, true));
+        let second: Expander = Mutex::new(Pcf8575::new(I2cDevice::new(&bus), false, true, true));
+
+        // Scanner loops yield between reads, like on the scan timer.
+        let reader = async |expander: &Expander| {
+            for _ in 0..3 {
+                let bytes = with_expander(expander, async |e| e.read().await).await;
+                assert!(bytes.is_ok());
+                yield_now().await;
+            }
+        };
+        embassy_futures::block_on(join(reader(&first), reader(&second)));
+        assert_eq!(
+            embassy_futures::block_on(bus.lock()).log.as_slice(),
+            &[0x27, 0x26, 0x27, 0x26, 0x27, 0x26]
+        );
+
+        // Expander is free between transfers, a held lock times out.
+        let guard = embassy_futures::block_on(first.lock());
+        let result = embassy_futures::block_on(with_expander(&first, async |e| e.read().await));
+        assert!(result.is_err());
+        drop(guard);
+        let result = embassy_futures::block_on(with_expander(&first, async |e| e.read().await));
+        assert_eq!(result, Ok(0x2727));
+    }
+
+    /// Time moves only by the delays.
+    struct FakeClock {
+        now: core::cell::Cell<Instant>,
+    }
+
+    impl Clock for FakeClock {
+        fn now(&self) -> Instant {
+            self.now.get()
+        }
+
+        async fn delay(&self, duration: Duration) {
+            self.now.set(self.now.get() + duration);
+            embassy_futures::yield_now().await;
+        }
+    }
+
+    /// Expander with the first input held for a number of reads.
+    struct PressBus {
+        pressed_reads: u8,
+    }
+
+    impl embedded_hal_async::i2c::ErrorType for PressBus {
+        type Error = core::convert::Infallible;
+    }
+
+    impl I2c for PressBus {
+        async fn transaction(
+            &mut self,
+            _address: u8,
+            operations: &mut [embedded_hal_async::i2c::Operation<'_>],
+        ) -> Result<(), Self::Error> {
+            for operation in operations {
+                if let embedded_hal_async::i2c::Operation::Read(buf) = operation {
+                    let bytes: u16 = if self.pressed_reads > 0 {
+                        self.pressed_reads -= 1;
+                        !1
+                    } else {
+                        0xffff
+                    };
+                    buf.copy_from_slice(&bytes.to_le_bytes());
+                }
+            }
+            Ok(())
+        }
+    }
+
+    struct NoLed;
+
+    impl LedLine for NoLed {
+        fn set_level(&mut self, _level: embedded_hal::digital::PinState) {}
+    }
+
+    pub fn debounce_on_fake_clock() {
+        use crate::io::events::SwitchState;
+        use crate::io::logical_output::Polarity;
+        use core::cell::Cell;
+        use embassy_futures::select::{Either, select};
+        use static_cell::StaticCell;
+
+        static QUEUE: InputChannel = InputChannel::new();
+        static STATUS: StaticCell<Status<NoLed>> = StaticCell::new();
+        let status = STATUS.init(Status::new(NoLed, Polarity::ActiveHigh));
+        let indices = core::array::from_fn(|pos| pos as u8 + 1);
+        let bus = PressBus { pressed_reads: 3 };
+        let inputs = ExpanderInputs::new(
+            Pcf8575::new(bus, true, true, true),
+            0,
+            indices,
+            &QUEUE,
+            status,
+            true,
+        );
+        let start = Instant::from_millis(1000);
+        let clock = FakeClock {
+            now: Cell::new(start),
+        };
+
+        let mut seen: heapless::Vec<events::SwitchEvent, 3> = heapless::Vec::new();
+        let collect = async {
+            while !seen.is_full() {
+                let _ = seen.push(QUEUE.receive().await);
+            }
+        };
+        match embassy_futures::block_on(select(inputs.scan_loop(&clock), collect)) {
+            Either::First(_) => unreachable!(),
+            Either::Second(()) => {}
+        }
+
+        // Idle scan, then fast ones once the press is seen.
+        let ms = |ms| start + Duration::from_millis(ms);
+        assert!(seen.iter().all(|event| event.switch_id == 1));
+        assert!(matches!(seen[0].state, SwitchState::Activated));
+        assert_eq!(seen[0].at, ms(60));
+        assert!(matches!(seen[1].state, SwitchState::Active(70)));
+        assert_eq!(seen[1].at, ms(70));
+        assert!(matches!(seen[2].state, SwitchState::Deactivated(70)));
+        assert_eq!(seen[2].at, ms(80));
+    }
+
+    /// Expander answering transfers from a script: a read value or a NAK.
+    /// Released inputs once the script ends.
+    struct ScriptedBus {
+        script: &'static [Option<u16>],
+        pos: usize,
+    }
+
+    impl embedded_hal_async::i2c::ErrorType for ScriptedBus {
+        type Error = embedded_hal_async::i2c::ErrorKind;
+    }
+
+    impl I2c for ScriptedBus {
+        async fn transaction(
+            &mut self,
+            _address: u8,
+            operations: &mut [embedded_hal_async::i2c::Operation<'_>],
+        ) -> Result<(), Self::Error> {
+            let step = self.script.get(self.pos).copied().unwrap_or(Some(0xffff));
+            self.pos += 1;
+            let Some(bytes) = step else {
+                return Err(embedded_hal_async::i2c::ErrorKind::NoAcknowledge(
+                    embedded_hal_async::i2c::NoAcknowledgeSource::Address,
+                ));
+            };
+            for operation in operations {
+                if let embedded_hal_async::i2c::Operation::Read(buf) = operation {
+                    buf.copy_from_slice(&bytes.to_le_bytes());
+                }
+            }
+            Ok(())
+        }
+    }
+
+    pub fn held_time_skips_failed_scans() {
+        use crate::io::events::SwitchState;
+        use crate::io::logical_output::Polarity;
+        use core::cell::Cell;
+        use embassy_futures::select::{Either, select};
+        use static_cell::StaticCell;
+
+        static QUEUE: InputChannel = InputChannel::new();
+        static STATUS: StaticCell<Status<NoLed>> = StaticCell::new();
+        let status = STATUS.init(Status::new(NoLed, Polarity::ActiveHigh));
+        let indices = core::array::from_fn(|pos| pos as u8 + 1);
+        // Warming up, configured at 60ms, then held with a failed read.
+        let bus = ScriptedBus {
+            script: &[
+                None,
+                None,
+                None,
+                Some(0xffff),
+                Some(!1),
+                None,
+                Some(!1),
+                Some(!1),
+            ],
+            pos: 0,
+        };
+        let inputs = ExpanderInputs::new(
+            Pcf8575::new(bus, true, true, true),
+            0,
+            indices,
+            &QUEUE,
+            status,
+            true,
+        )
+        .with_startup_grace(Duration::from_millis(100));
+        let start = Instant::from_millis(1000);
+        let clock = FakeClock {
+            now: Cell::new(start),
+        };
+
+        let mut seen: heapless::Vec<events::SwitchEvent, 3> = heapless::Vec::new();
+        let collect = async {
+            while !seen.is_full() {
+                let _ = seen.push(QUEUE.receive().await);
+            }
+        };
+        match embassy_futures::block_on(select(inputs.scan_loop(&clock), collect)) {
+            Either::First(_) => unreachable!(),
+            Either::Second(()) => {}
+        }
+
+        // First seen at 110ms. Neither the startup nor the failed read at
+        // 120ms count as held.
+        let ms = |ms| start + Duration::from_millis(ms);
+        assert!(matches!(seen[0].state, SwitchState::Activated));
+        assert_eq!(seen[0].at, ms(130));
+        assert!(matches!(seen[1].state, SwitchState::Active(70)));
+        assert_eq!(seen[1].at, ms(140));
+        assert!(matches!(seen[2].state, SwitchState::Deactivated(70)));
+        assert_eq!(seen[2].at, ms(150));
+    }
+
+    /// Meter pulsing 15ms low on the first input every 100ms, off the
+    /// idle scan phase.
+    struct MeterBus<'a> {
+        now: &'a core::cell::Cell<Instant>,
+    }
+
+    impl embedded_hal_async::i2c::ErrorType for MeterBus<'_> {
+        type Error = core::convert::Infallible;
+    }
+
+    impl I2c for MeterBus<'_> {
+        async fn transaction(
+            &mut self,
+            _address: u8,
+            operations: &mut [embedded_hal_async::i2c::Operation<'_>],
+        ) -> Result<(), Self::Error> {
+            for operation in operations {
+                if let embedded_hal_async::i2c::Operation::Read(buf) = operation {
+                    let phase = self.now.get().as_millis() % 100;
+                    let bytes: u16 = if (20..35).contains(&phase) {
+                        !1
+                    } else {
+                        0xffff
+                    };
+                    buf.copy_from_slice(&bytes.to_le_bytes());
+                }
+            }
+            Ok(())
+        }
+    }
+
+    pub fn short_pulses_counted_on_fake_clock() {
+        use crate::io::events::SwitchState;
+        use crate::io::logical_output::Polarity;
+        use core::cell::Cell;
+        use embassy_futures::select::{Either, select};
+        use static_cell::StaticCell;
+
+        static QUEUE: InputChannel = InputChannel::new();
+        static STATUS: StaticCell<Status<NoLed>> = StaticCell::new();
+        let status = STATUS.init(Status::new(NoLed, Polarity::ActiveHigh));
+        let clock = FakeClock {
+            now: Cell::new(Instant::from_millis(1000)),
+        };
+        let indices = core::array::from_fn(|pos| pos as u8 + 1);
+        let bus = MeterBus { now: &clock.now };
+        let inputs = ExpanderInputs::new(
+            Pcf8575::new(bus, true, true, true),
+            0,
+            indices,
+            &QUEUE,
+            status,
+            true,
+        )
+        .with_pulse_inputs(&[1]);
+
+        // Idle scans (x00, x50) would never see a pulse.
+        let deadline = clock.now() + PULSE_REPORT_PERIOD + IDLE_SCAN_PERIOD;
+        let report = async {
+            while clock.now() < deadline {
+                if let Ok(event) = QUEUE.try_receive() {
+                    return Some(event);
+                }
+                embassy_futures::yield_now().await;
+            }
+            None
+        };
+        let event = match embassy_futures::block_on(select(inputs.scan_loop(&clock), report)) {
+            Either::First(_) => unreachable!(),
+            Either::Second(event) => event.expect("Pulses reported"),
+        };
+        assert_eq!(event.switch_id, 1);
+        // Both edges of each of the 100 pulses within the report period.
+        assert!(matches!(event.state, SwitchState::Pulses(200)));
+    }
+
+    /// Expander with the first input held, counting reads per expander
+    /// (by the low address bit).
+    struct CountingBus {
+        reads: [usize; 2],
+    }
+
+    impl embedded_hal_async::i2c::ErrorType for CountingBus {
+        type Error = core::convert::Infallible;
+    }
+
+    impl I2c for CountingBus {
+        async fn transaction(
+            &mut self,
+            address: u8,
+            operations: &mut [embedded_hal_async::i2c::Operation<'_>],
+        ) -> Result<(), Self::Error> {
+            for operation in operations {
+                if let embedded_hal_async::i2c::Operation::Read(buf) = operation {
+                    self.reads[address as usize & 1] += 1;
+                    buf.copy_from_slice(&(!1u16).to_le_bytes());
+                }
+            }
+            Ok(())
+        }
+    }
+
+    pub fn shared_bus_scan_loops_progress() {
+        use crate::io::events::SwitchState;
+        use crate::io::logical_output::Polarity;
+        use core::cell::Cell;
+        use embassy_embedded_hal::shared_bus::asynch::i2c::I2cDevice;
+        use embassy_futures::join::join;
+        use embassy_futures::select::{Either, select};
+        use static_cell::StaticCell;
+
+        static QUEUE: InputChannel = InputChannel::new();
+        static STATUS: StaticCell<Status<NoLed>> = StaticCell::new();
+        let status: &'static _ = STATUS.init(Status::new(NoLed, Polarity::ActiveHigh));
+
+        let bus: Mutex<NoopRawMutex, _> = Mutex::new(CountingBus { reads: [0; 2] });
+        let expander = |low_address, first_index: u8| {
+            ExpanderInputs::new(
+                Pcf8575::new(I2cDevice::new(&bus), low_address, true, true),
+                first_index,
+                core::array::from_fn(|pos| first_index + pos as u8),
+                &QUEUE,
+                status,
+                true,
+            )
+        };
+        let first = expander(true, 1);
+        let second = expander(false, 17);
+        let clock = FakeClock {
+            now: Cell::new(Instant::from_millis(1000)),
+        };
+
+        // Checked between the scans, while both loops are running: the bus is
+        // free and both keep scanning.
+        const SCANS: usize = 20;
+        let loops = join(first.scan_loop(&clock), second.scan_loop(&clock));
+        let mut activated = (false, false);
+        let watch = async {
+            loop {
+                while let Ok(event) = QUEUE.try_receive() {
+                    if matches!(event.state, SwitchState::Activated) {
+                        match event.switch_id {
+                            1 => activated.0 = true,
+                            17 => activated.1 = true,
+                            _ => {}
+                        }
+                    }
+                }
+                let reads = bus.try_lock().expect("Bus free between scans").reads;
+                assert!(reads[0].abs_diff(reads[1]) <= 1, "Loop starved {:?}", reads);
+                if reads.iter().all(|&count| count >= SCANS) {
+                    return;
+                }
+                embassy_futures::yield_now().await;
+            }
+        };
+        match embassy_futures::block_on(select(loops, watch)) {
+            Either::First(_) => unreachable!(),
+            Either::Second(()) => {}
+        }
+        assert_eq!(activated, (true, true));
+    }
+}
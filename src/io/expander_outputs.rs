@@ -1,53 +1,196 @@
-use crate::io::events::GroupedOutputs;
+use crate::io::events::{GroupedOutputs, OutputError, OutputFault};
 use crate::io::pcf8575::Pcf8575;
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use embassy_sync::mutex::Mutex;
-use embedded_hal_async::i2c::I2c;
+use embedded_hal_async::i2c::{ErrorKind, I2c};
 
-/// Read inputs (switches) and generate events.
+/// PCF8575 lines are high after power-on. That's off for active-low relays;
+/// IndexedOutputs sets the logical state right after init.
+const POWER_ON_LEVELS: u16 = 0xffff;
+
+impl From<ErrorKind> for OutputFault {
+    fn from(kind: ErrorKind) -> Self {
+        match kind {
+            ErrorKind::NoAcknowledge(_) => Self::NoAcknowledge,
+            _ => Self::Bus,
+        }
+    }
+}
+
+/// Physical levels of expander output lines. Polarity is handled by
+/// LogicalOutput.
 pub struct ExpanderOutputs<BUS: I2c> {
     /// shared i2c bus
     expander: Mutex<NoopRawMutex, Pcf8575<BUS>>,
 
+    /// Position among the output expanders, reported in errors.
+    id: u8,
+
+    /// Line levels (1 - high), including pending changes.
     state: u16,
+
+    /// Lines changed by set_pending and not written yet.
+    pending: u16,
 }
 
 impl<BUS: I2c> ExpanderOutputs<BUS> {
-    pub fn new(expander: Pcf8575<BUS>) -> Self {
+    pub fn new(id: u8, expander: Pcf8575<BUS>) -> Self {
         Self {
             expander: Mutex::new(expander),
-            state: 0xffff,
+            id,
+            state: POWER_ON_LEVELS,
+            pending: 0,
         }
     }
 
-    pub async fn reset(&mut self) -> Result<(), ()> {
-        self.state = 0xffff;
-        self.expander.lock().await.write(self.state).await
+    pub async fn reset(&mut self) -> Result<(), OutputError> {
+        self.state = POWER_ON_LEVELS;
+        self.pending = 0;
+        self.write(0).await
     }
 
-    pub async fn set(&mut self, idx: u8, high: bool) -> Result<(), ()> {
-        let mask = 1 << idx;
-        if mask == 0 {
-            defmt::error!("Unable to find IO idx on given outputs");
-            return Err(());
+    pub async fn set(&mut self, idx: u8, high: bool) -> Result<(), OutputError> {
+        self.set_many(&[(idx, high)]).await
+    }
+
+    /// Change levels of multiple lines with a single write. Nothing is
+    /// changed if any of the lines is invalid.
+    pub async fn set_many(&mut self, levels: &[(u8, bool)]) -> Result<(), OutputError> {
+        if let Some((idx, _)) = levels.iter().find(|(idx, _)| Self::mask(*idx).is_none()) {
+            return Err(self.invalid_line(*idx));
+        }
+        for (idx, high) in levels {
+            self.set_pending(*idx, *high)?;
         }
+        self.commit().await
+    }
 
+    /// Change the level of a line without writing it. Call `commit` to
+    /// write all pending changes at once.
+    pub fn set_pending(&mut self, idx: u8, high: bool) -> Result<(), OutputError> {
+        let Some(mask) = Self::mask(idx) else {
+            return Err(self.invalid_line(idx));
+        };
         if high {
             self.state |= mask;
         } else {
             self.state &= !mask;
         }
+        self.pending |= mask;
+        Ok(())
+    }
+
+    /// Write pending changes in a single write. A failed write is reported
+    /// on the lowest pending line.
+    pub async fn commit(&mut self) -> Result<(), OutputError> {
+        if self.pending == 0 {
+            return Ok(());
+        }
+        let line = self.pending.trailing_zeros() as u8;
+        self.pending = 0;
+        self.write(line).await
+    }
+
+    fn mask(idx: u8) -> Option<u16> {
+        1u16.checked_shl(idx as u32)
+    }
+
+    fn invalid_line(&self, idx: u8) -> OutputError {
+        defmt::error!("Unable to find IO idx on given outputs");
+        OutputError {
+            expander: self.id,
+            line: idx,
+            fault: OutputFault::InvalidLine,
+        }
+    }
 
-        self.expander.lock().await.write(self.state).await
+    async fn write(&mut self, line: u8) -> Result<(), OutputError> {
+        self.expander
+            .lock()
+            .await
+            .write(self.state)
+            .await
+            .map_err(|kind| OutputError {
+                expander: self.id,
+                line,
+                fault: kind.into(),
+            })
     }
 }
 
 impl<BUS: I2c> GroupedOutputs for ExpanderOutputs<BUS> {
-    async fn set_high(&mut self, idx: u8) -> Result<(), ()> {
+    async fn set_high(&mut self, idx: u8) -> Result<(), OutputError> {
         self.set(idx, true).await
     }
 
-    async fn set_low(&mut self, idx: u8) -> Result<(), ()> {
+    async fn set_low(&mut self, idx: u8) -> Result<(), OutputError> {
         self.set(idx, false).await
     }
+
+    async fn set_levels(&mut self, levels: &[(u8, bool)]) -> Result<(), OutputError> {
+        self.set_many(levels).await
+    }
+}
+
+pub mod tests {
+    use super::*;
+    use core::cell::RefCell;
+    use embedded_hal_async::i2c::{ErrorType, Operation};
+
+    /// Bus recording written port values.
+    struct RecordingBus<'a> {
+        writes: &'a RefCell<heapless::Vec<u16, 8>>,
+    }
+
+    impl ErrorType for RecordingBus<'_> {
+        type Error = core::convert::Infallible;
+    }
+
+    impl I2c for RecordingBus<'_> {
+        async fn transaction(
+            &mut self,
+            _address: u8,
+            operations: &mut [Operation<'_>],
+        ) -> Result<(), Self::Error> {
+            for operation in operations {
+                if let Operation::Write(bytes) = operation {
+                    let _ = self
+                        .writes
+                        .borrow_mut()
+                        .push(u16::from_le_bytes([bytes[0], bytes[1]]));
+                }
+            }
+            Ok(())
+        }
+    }
+
+    pub fn pending_changes_in_one_write() {
+        let writes = RefCell::new(heapless::Vec::new());
+        let bus = RecordingBus { writes: &writes };
+        let mut outputs = ExpanderOutputs::new(0, Pcf8575::new(bus, false, false, false));
+
+        for (idx, high) in [(0, false), (3, false), (5, false), (15, true)] {
+            assert!(outputs.set_pending(idx, high).is_ok());
+        }
+        assert!(writes.borrow().is_empty());
+        assert!(embassy_futures::block_on(outputs.commit()).is_ok());
+        assert_eq!(writes.borrow().as_slice(), &[0xffd6]);
+
+        // Nothing pending - nothing written.
+        assert!(embassy_futures::block_on(outputs.commit()).is_ok());
+        assert_eq!(writes.borrow().len(), 1);
+
+        // Single changes are still written at once.
+        assert!(embassy_futures::block_on(outputs.set(3, true)).is_ok());
+        assert_eq!(writes.borrow().as_slice(), &[0xffd6, 0xffde]);
+
+        // Invalid line changes nothing.
+        let result = embassy_futures::block_on(outputs.set_many(&[(1, false), (16, true)]));
+        assert_eq!(
+            result.map_err(|err| err.fault),
+            Err(OutputFault::InvalidLine)
+        );
+        assert!(embassy_futures::block_on(outputs.commit()).is_ok());
+        assert_eq!(writes.borrow().len(), 2);
+    }
 }
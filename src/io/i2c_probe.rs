@@ -0,0 +1,113 @@
+/*
+ * Init-time I²C bus probe. Two expanders wired to the same address would
+ * both answer and silently corrupt each other's reads/writes, and an absent
+ * expander should not be reported as present.
+ */
+use embedded_hal_async::i2c::I2c;
+
+use crate::components::message::args::ErrorCode;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, defmt::Format)]
+pub enum ProbeResult {
+    /// Device responded.
+    Present,
+    /// No response on the address.
+    Missing,
+    /// Address configured for more than one device.
+    Conflict,
+}
+
+impl ProbeResult {
+    /// Code broadcast in Message::Error. None if there's no problem.
+    pub fn error_code(self) -> Option<ErrorCode> {
+        match self {
+            Self::Present => None,
+            Self::Missing => Some(ErrorCode::ExpanderMissing),
+            Self::Conflict => Some(ErrorCode::ExpanderAddressConflict),
+        }
+    }
+}
+
+/// Probe each configured address once with a read.
+pub async fn probe<BUS: I2c, const N: usize>(bus: &mut BUS, addrs: &[u8; N]) -> [ProbeResult; N] {
+    let mut results = [ProbeResult::Missing; N];
+    for (pos, addr) in addrs.iter().enumerate() {
+        if addrs.iter().filter(|other| *other == addr).count() > 1 {
+            defmt::error!("I2C address {:#x} is configured for multiple devices", addr);
+            results[pos] = ProbeResult::Conflict;
+            continue;
+        }
+
+        let mut buf = [0; 2];
+        results[pos] = if bus.read(*addr, &mut buf).await.is_ok() {
+            ProbeResult::Present
+        } else {
+            defmt::error!("I2C device {:#x} does not respond", addr);
+            ProbeResult::Missing
+        };
+    }
+    results
+}
+
+pub mod tests {
+    use super::*;
+    use embedded_hal_async::i2c::{ErrorKind, ErrorType, NoAcknowledgeSource, Operation};
+
+    /// Bus with devices responding on given addresses.
+    struct FakeBus {
+        present: &'static [u8],
+        reads: usize,
+    }
+
+    impl ErrorType for FakeBus {
+        type Error = ErrorKind;
+    }
+
+    impl I2c for FakeBus {
+        async fn transaction(
+            &mut self,
+            address: u8,
+            operations: &mut [Operation<'_>],
+        ) -> Result<(), Self::Error> {
+            self.reads += 1;
+            if !self.present.contains(&address) {
+                return Err(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address));
+            }
+            for operation in operations {
+                if let Operation::Read(buf) = operation {
+                    buf.fill(0xff);
+                }
+            }
+            Ok(())
+        }
+    }
+
+    pub fn address_conflict_is_detected() {
+        // Two input expanders mapped to 0x27 by a wiring mistake.
+        let mut bus = FakeBus {
+            present: &[0x27, 0x20],
+            reads: 0,
+        };
+        let results = embassy_futures::block_on(probe(&mut bus, &[0x27, 0x27, 0x20]));
+        assert_eq!(
+            results,
+            [
+                ProbeResult::Conflict,
+                ProbeResult::Conflict,
+                ProbeResult::Present
+            ]
+        );
+        // Conflicting addresses are not read.
+        assert_eq!(bus.reads, 1);
+
+        // Absent device.
+        let mut bus = FakeBus {
+            present: &[0x27],
+            reads: 0,
+        };
+        let results = embassy_futures::block_on(probe(&mut bus, &[0x27, 0x26]));
+        assert_eq!(results, [ProbeResult::Present, ProbeResult::Missing]);
+        assert_eq!(results[0].error_code(), None);
+        assert_eq!(results[1].error_code(), Some(ErrorCode::ExpanderMissing));
+    }
+}
@@ -1,5 +1,34 @@
-use crate::io::events::{GroupedOutputs, IoIdx};
-use embedded_hal::digital::OutputPin;
+use crate::config::{OutputGroup, StartupOutputs};
+use crate::io::events::{GroupedOutputs, IoIdx, OutputError, check_indices};
+use crate::io::logical_output::{LogicalOutput, Polarity};
+use embedded_hal::digital::{OutputPin, PinState};
+
+/// Output state to set on power-on according to the policy. `last` is the
+/// persisted state from before the reboot, if any.
+pub fn startup_state<const IN: usize>(
+    policy: StartupOutputs,
+    last: Option<[bool; IN]>,
+) -> [bool; IN] {
+    match policy {
+        StartupOutputs::AllOff => [false; IN],
+        StartupOutputs::AllOn => [true; IN],
+        StartupOutputs::RestoreLast => last.unwrap_or_else(|| {
+            defmt::warn!("No persisted output state - starting with outputs off");
+            [false; IN]
+        }),
+    }
+}
+
+/// State of a mutually exclusive pair of outputs (eg. shutter motor up/down).
+#[derive(Copy, Clone, Eq, PartialEq, Debug, defmt::Format)]
+pub enum Direction {
+    /// Both outputs inactive.
+    Stop,
+    /// Only the first (up) output active.
+    Up,
+    /// Only the second (down) output active.
+    Down,
+}
 
 pub(crate) struct IndexedOutputs<
     const INDICES_N: usize,
@@ -10,14 +39,17 @@ pub(crate) struct IndexedOutputs<
 > {
     /// Numerical indices of given input/outputs - a unified mapping.
     indices: [u8; INDICES_N],
-    /// Current known output status (true - high, false - low)
-    state: [bool; INDICES_N],
-    /// Which outputs should be low to be active?
-    active_low: [bool; INDICES_N],
+    /// Current known output state and polarity.
+    outputs: [LogicalOutput; INDICES_N],
     /// IO Expanders (16-bit PCF*)
     grouped: [ET; EXPANDER_N],
     /// Native pins.
     native: [P; NATIVE_N],
+    /// Groups of expander outputs.
+    groups: &'static [OutputGroup],
+    /// Native pin driven instead of the expander output when the expander
+    /// write fails.
+    fallbacks: [Option<usize>; INDICES_N],
 }
 
 impl<const IN: usize, const EN: usize, const NN: usize, ET: GroupedOutputs, P: OutputPin>
@@ -33,13 +65,110 @@ impl<const IN: usize, const EN: usize, const NN: usize, ET: GroupedOutputs, P: O
         indices: [u8; IN],
         active_low: [bool; IN],
     ) -> Self {
+        if let Err(pos) = check_indices(&indices) {
+            defmt::panic!("Output at {} uses reserved index 0", pos);
+        }
         IndexedOutputs {
             grouped,
-            state: [false; IN],
-            active_low,
+            outputs: active_low.map(|low| LogicalOutput::new(Polarity::from_active_low(low))),
             native,
             indices,
+            groups: &[],
+            fallbacks: [None; IN],
+        }
+    }
+
+    /// Configure native pins (io index, native pin position) taking over
+    /// expander outputs when their expander is offline. The pins should be
+    /// spare ones, not mapped to other indices.
+    pub fn with_fallbacks(mut self, fallbacks: &[(IoIdx, usize)]) -> Self {
+        for (io_idx, native) in fallbacks {
+            let Some(position) = self.find_id(*io_idx) else {
+                defmt::panic!("Fallback for unknown output {}", io_idx);
+            };
+            if position / 16 >= EN || *native >= NN {
+                defmt::panic!("Invalid fallback of output {} to pin {}", io_idx, native);
+            }
+            self.fallbacks[position] = Some(*native);
+        }
+        self
+    }
+
+    /// Define output groups. Lines of a group must be connected outputs.
+    pub fn with_groups(mut self, groups: &'static [OutputGroup]) -> Self {
+        for group in groups {
+            let first = group.expander_id as usize * 16;
+            if group.expander_id as usize >= EN
+                || first + (16 - group.mask.leading_zeros() as usize) > IN
+            {
+                defmt::panic!("Output group {} doesn't match the outputs", group.id);
+            }
+        }
+        self.groups = groups;
+        self
+    }
+
+    /// Positions of the outputs in a group.
+    fn group_positions(
+        &self,
+        id: u8,
+    ) -> Option<impl Iterator<Item = usize> + Clone + use<IN, EN, NN, ET, P>> {
+        let group = self.groups.iter().find(|group| group.id == id)?;
+        let first = group.expander_id as usize * 16;
+        let mask = group.mask;
+        Some(
+            (0..16)
+                .filter(move |bit| mask & (1 << bit) != 0)
+                .map(move |bit| first + bit),
+        )
+    }
+
+    /// Outputs of a group, None if there is no such group.
+    pub fn group_members(&self, id: u8) -> Option<heapless::Vec<IoIdx, 16>> {
+        Some(
+            self.group_positions(id)?
+                .map(|pos| self.indices[pos])
+                .collect(),
+        )
+    }
+
+    /// Group is on if any of its outputs is on.
+    pub fn group_state(&self, id: u8) -> Option<bool> {
+        Some(
+            self.group_positions(id)?
+                .any(|pos| self.outputs[pos].is_on()),
+        )
+    }
+
+    /// Set all outputs of a group with a single expander write.
+    pub async fn set_group(&mut self, id: u8, on: bool) -> Result<(), OutputError> {
+        let Some(positions) = self.group_positions(id) else {
+            defmt::error!("Unable to find output group {}", id);
+            return Err(OutputError::unknown());
+        };
+        let mut levels: heapless::Vec<(u8, bool), 16> = heapless::Vec::new();
+        let mut expander_no = 0;
+        for pos in positions.clone() {
+            expander_no = pos / 16;
+            // Group covers at most the 16 lines of an expander.
+            let _ = levels.push((
+                (pos % 16) as u8,
+                self.outputs[pos].level(on) == PinState::High,
+            ));
+        }
+        self.grouped[expander_no].set_levels(&levels).await?;
+        for pos in positions {
+            self.outputs[pos].set_written(on);
         }
+        Ok(())
+    }
+
+    /// Turn the group off if any of its outputs is on, otherwise on. Return
+    /// the new state.
+    pub async fn toggle_group(&mut self, id: u8) -> Result<bool, OutputError> {
+        let on = !self.group_state(id).ok_or(OutputError::unknown())?;
+        self.set_group(id, on).await?;
+        Ok(on)
     }
 
     /// Find IO Index within the list.
@@ -57,74 +186,505 @@ impl<const IN: usize, const EN: usize, const NN: usize, ET: GroupedOutputs, P: O
     pub fn get_all(&self) -> [(u8, bool); IN] {
         let mut status = [(0, false); IN];
         for (i, io_idx) in self.indices.iter().enumerate() {
-            status[i] = (*io_idx, self.state[i]);
+            status[i] = (*io_idx, self.outputs[i].is_on());
         }
         status
     }
 
-    /// Set all outputs to stored values (false by default)
-    pub async fn init_outputs(&mut self) -> Result<(), ()> {
-        for (io_idx, high) in self.get_all() {
-            self.set(io_idx, high).await?;
+    /// Set all outputs to the initial state (see `startup_state`).
+    pub async fn init_outputs(&mut self, initial: [bool; IN]) -> Result<(), OutputError> {
+        let changes: heapless::Vec<(IoIdx, bool), IN> =
+            self.indices.iter().copied().zip(initial).collect();
+        self.set_many(&changes).await
+    }
+
+    /// Deactivate all outputs. Failed outputs don't stop the others - Ok only
+    /// if all were turned off, otherwise the error of the last failed output.
+    pub async fn all_off(&mut self) -> Result<(), OutputError> {
+        let changes = self.get_all().map(|(io_idx, _)| (io_idx, false));
+        self.set_many(&changes).await
+    }
+
+    /// Set several outputs, lines of each expander with a single write.
+    /// A failed write is retried output by output (using the fallbacks), so
+    /// failed outputs don't stop the others - Ok only if all were set,
+    /// otherwise the error of the last failure.
+    pub async fn set_many(&mut self, changes: &[(IoIdx, bool)]) -> Result<(), OutputError> {
+        let mut single: heapless::Vec<(IoIdx, bool), IN> = heapless::Vec::new();
+        for expander_no in 0..EN {
+            let mut levels: heapless::Vec<(u8, bool), 16> = heapless::Vec::new();
+            let mut written: heapless::Vec<(usize, bool), 16> = heapless::Vec::new();
+            for (io_idx, on) in changes {
+                let Some(position) = self.find_id(*io_idx) else {
+                    continue;
+                };
+                if position / 16 != expander_no {
+                    continue;
+                }
+                let high = self.outputs[position].level(*on) == PinState::High;
+                if levels.push(((position % 16) as u8, high)).is_err()
+                    || written.push((position, *on)).is_err()
+                {
+                    // Listed twice - fall back to separate writes.
+                    let _ = single.push((*io_idx, *on));
+                }
+            }
+            if levels.is_empty() {
+                continue;
+            }
+            match self.grouped[expander_no].set_levels(&levels).await {
+                Ok(()) => {
+                    for (position, on) in written {
+                        self.outputs[position].set_written(on);
+                    }
+                }
+                Err(_) => {
+                    for (position, on) in written {
+                        let _ = single.push((self.indices[position], on));
+                    }
+                }
+            }
         }
-        Ok(())
+        // Native pins and unknown outputs are set one by one.
+        for (io_idx, on) in changes {
+            let on_expander = self
+                .find_id(*io_idx)
+                .is_some_and(|position| position / 16 < EN);
+            if !on_expander {
+                let _ = single.push((*io_idx, *on));
+            }
+        }
+        let mut result = Ok(());
+        for (io_idx, on) in single {
+            if let Err(err) = self.set(io_idx, on).await {
+                result = Err(err);
+            }
+        }
+        result
     }
 
     /// Read output state as we set it (doesn't read the PIN state).
     pub fn get(&self, io_idx: IoIdx) -> Option<bool> {
-        Some(self.state[self.find_id(io_idx)?])
+        Some(self.outputs[self.find_id(io_idx)?].is_on())
     }
 
     /// Toggle output and state. Return new state.
-    pub async fn toggle(&mut self, io_idx: IoIdx) -> Result<bool, ()> {
-        let position = self.find_id(io_idx).ok_or(())?;
+    pub async fn toggle(&mut self, io_idx: IoIdx) -> Result<bool, OutputError> {
+        let position = self.find_id(io_idx).ok_or(OutputError::unknown())?;
 
-        let current = self.state[position];
+        let current = self.outputs[position].is_on();
         self.set(io_idx, !current).await?;
         Ok(!current)
     }
 
-    /// Set output based on IO index.
-    pub async fn set(&mut self, io_idx: IoIdx, high: bool) -> Result<(), ()> {
-        if let Some(position) = self.find_id(io_idx) {
-            let expander_no = position / 16;
-
-            // Physical output direction.
-            let mut set_as_high = high;
-            if self.active_low[position] {
-                set_as_high = !set_as_high;
-            }
+    /// Drive a mutually exclusive pair. Both outputs are never active at
+    /// once: on a shared expander both levels change in a single write,
+    /// otherwise the released output is written before the activated one.
+    pub async fn set_exclusive(
+        &mut self,
+        up: IoIdx,
+        down: IoIdx,
+        direction: Direction,
+    ) -> Result<(), OutputError> {
+        let (Some(up_pos), Some(down_pos)) = (self.find_id(up), self.find_id(down)) else {
+            defmt::error!("Unable to find output pair {}/{}", up, down);
+            return Err(OutputError::unknown());
+        };
+        if up_pos == down_pos {
+            defmt::error!("Output pair uses the same output {}", up);
+            return Err(OutputError::unknown());
+        }
+        let (up_on, down_on) = match direction {
+            Direction::Stop => (false, false),
+            Direction::Up => (true, false),
+            Direction::Down => (false, true),
+        };
 
-            if expander_no >= self.grouped.len() {
-                // That indexes into native PIN
-                let native_pos = position - (expander_no * 16);
-                if set_as_high {
-                    self.native[native_pos]
-                        .set_high()
-                        .expect("native pin error");
-                } else {
-                    self.native[native_pos].set_low().expect("native pin error");
-                }
-                self.state[position] = high;
-                return Ok(());
+        let expander_no = up_pos / 16;
+        if expander_no < self.grouped.len() && down_pos / 16 == expander_no {
+            let levels = [
+                (
+                    (up_pos % 16) as u8,
+                    self.outputs[up_pos].level(up_on) == PinState::High,
+                ),
+                (
+                    (down_pos % 16) as u8,
+                    self.outputs[down_pos].level(down_on) == PinState::High,
+                ),
+            ];
+            self.grouped[expander_no].set_levels(&levels).await?;
+            self.outputs[up_pos].set_written(up_on);
+            self.outputs[down_pos].set_written(down_on);
+            Ok(())
+        } else {
+            // Break before make.
+            let (first, second) = if up_on {
+                ((down, down_on), (up, up_on))
             } else {
-                let expander = &mut self.grouped[expander_no];
-                let io_within = position - expander_no * 16;
-                if io_within >= 16 {
-                    defmt::panic!("Calculated IO within expander is invalid");
-                }
-                let io_within = io_within as u8;
-                if set_as_high {
-                    expander.set_high(io_within).await?
-                } else {
-                    expander.set_low(io_within).await?
+                ((up, up_on), (down, down_on))
+            };
+            self.set(first.0, first.1).await?;
+            self.set(second.0, second.1).await
+        }
+    }
+
+    /// Set output based on IO index.
+    pub async fn set(&mut self, io_idx: IoIdx, on: bool) -> Result<(), OutputError> {
+        let Some(position) = self.find_id(io_idx) else {
+            defmt::error!("Unable to find output with ID {}", io_idx);
+            return Err(OutputError::unknown());
+        };
+        let expander_no = position / 16;
+
+        if expander_no >= self.grouped.len() {
+            // That indexes into native PIN
+            let pin = &mut self.native[position - expander_no * 16];
+            self.outputs[position]
+                .write(on, async |level| {
+                    pin.set_state(level).expect("native pin error");
+                    Ok(())
+                })
+                .await
+        } else {
+            let expander = &mut self.grouped[expander_no];
+            let io_within = position - expander_no * 16;
+            if io_within >= 16 {
+                defmt::panic!("Calculated IO within expander is invalid");
+            }
+            let io_within = io_within as u8;
+            let result = self.outputs[position]
+                .write(on, async |level| match level {
+                    PinState::High => expander.set_high(io_within).await,
+                    PinState::Low => expander.set_low(io_within).await,
+                })
+                .await;
+            match self.fallbacks[position] {
+                Some(native) if result.is_err() => {
+                    defmt::warn!(
+                        "Expander of output {} is offline, failing over to native pin {}",
+                        io_idx,
+                        native
+                    );
+                    let pin = &mut self.native[native];
+                    self.outputs[position]
+                        .write(on, async |level| {
+                            pin.set_state(level).expect("native pin error");
+                            Ok(())
+                        })
+                        .await
                 }
-                self.state[position] = high;
+                _ => result,
+            }
+        }
+    }
+}
+
+pub mod tests {
+    use super::*;
+    use crate::io::events::OutputFault;
+    use core::convert::Infallible;
+    use embedded_hal::digital::ErrorType;
+
+    /// Expander that remembers levels that were set.
+    struct FakeExpander {
+        levels: [Option<bool>; 16],
+        /// Levels after each write.
+        writes: heapless::Vec<[Option<bool>; 16], 16>,
+        /// Writes fail.
+        offline: bool,
+        /// Writes touching this line fail.
+        broken_line: Option<u8>,
+        id: u8,
+    }
+
+    impl FakeExpander {
+        fn new() -> Self {
+            Self {
+                levels: [None; 16],
+                writes: heapless::Vec::new(),
+                offline: false,
+                broken_line: None,
+                id: 0,
+            }
+        }
+
+        fn write(&mut self, levels: &[(u8, bool)]) -> Result<(), OutputError> {
+            let id = self.id;
+            let error = |line, fault| OutputError {
+                expander: id,
+                line,
+                fault,
+            };
+            if self.offline {
+                return Err(error(levels[0].0, OutputFault::NoAcknowledge));
+            }
+            if let Some((line, _)) = levels
+                .iter()
+                .find(|(idx, _)| Some(*idx) == self.broken_line)
+            {
+                return Err(error(*line, OutputFault::Bus));
             }
+            for (idx, high) in levels {
+                self.levels[*idx as usize] = Some(*high);
+            }
+            self.writes
+                .push(self.levels)
+                .map_err(|_| error(levels[0].0, OutputFault::Bus))
+        }
+    }
+
+    impl GroupedOutputs for FakeExpander {
+        async fn set_high(&mut self, idx: u8) -> Result<(), OutputError> {
+            self.write(&[(idx, true)])
+        }
+        async fn set_low(&mut self, idx: u8) -> Result<(), OutputError> {
+            self.write(&[(idx, false)])
+        }
+        async fn set_levels(&mut self, levels: &[(u8, bool)]) -> Result<(), OutputError> {
+            self.write(levels)
+        }
+    }
+
+    struct NoPin;
+
+    impl ErrorType for NoPin {
+        type Error = Infallible;
+    }
+
+    impl OutputPin for NoPin {
+        fn set_high(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+        fn set_low(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    /// Native pin remembering its level.
+    struct FakePin {
+        high: Option<bool>,
+    }
+
+    impl ErrorType for FakePin {
+        type Error = Infallible;
+    }
+
+    impl OutputPin for FakePin {
+        fn set_high(&mut self) -> Result<(), Self::Error> {
+            self.high = Some(true);
+            Ok(())
+        }
+        fn set_low(&mut self) -> Result<(), Self::Error> {
+            self.high = Some(false);
             Ok(())
-        } else {
-            defmt::error!("Unable to find output with ID {}", io_idx);
-            Err(())
         }
     }
+
+    pub fn offline_expander_fails_over() {
+        // Output 2 (active-low) has a fallback on the second native pin.
+        let mut outputs: IndexedOutputs<4, 1, 2, FakeExpander, FakePin> = IndexedOutputs::new(
+            [FakeExpander::new()],
+            [FakePin { high: None }, FakePin { high: None }],
+            [1, 2, 51, 52],
+            [false, true, false, false],
+        )
+        .with_fallbacks(&[(2, 1)]);
+
+        // Expander online - fallback pin is untouched.
+        assert!(embassy_futures::block_on(outputs.set(2, true)).is_ok());
+        assert_eq!(outputs.grouped[0].levels[1], Some(false));
+        assert_eq!(outputs.native[1].high, None);
+
+        outputs.grouped[0].offline = true;
+        assert!(embassy_futures::block_on(outputs.set(2, false)).is_ok());
+        assert_eq!(outputs.native[1].high, Some(true));
+        assert_eq!(outputs.get(2), Some(false));
+        assert_eq!(embassy_futures::block_on(outputs.toggle(2)), Ok(true));
+        assert_eq!(outputs.native[1].high, Some(false));
+
+        // Output without a fallback fails.
+        assert!(embassy_futures::block_on(outputs.set(1, true)).is_err());
+        assert_eq!(outputs.get(1), Some(false));
+        assert_eq!(outputs.native[0].high, None);
+    }
+
+    /// Levels set on the expander by init for a given policy.
+    fn init_levels(policy: StartupOutputs, last: Option<[bool; 4]>) -> [Option<bool>; 4] {
+        let expander = FakeExpander::new();
+        let mut outputs: IndexedOutputs<4, 1, 0, FakeExpander, NoPin> =
+            IndexedOutputs::new([expander], [], [1, 2, 3, 4], [true, true, false, false]);
+        let initial = startup_state(policy, last);
+        assert!(embassy_futures::block_on(outputs.init_outputs(initial)).is_ok());
+        assert_eq!(outputs.get_all().map(|(_, on)| on), initial);
+
+        let mut levels = [None; 4];
+        levels.copy_from_slice(&outputs.grouped[0].levels[0..4]);
+        levels
+    }
+
+    pub fn startup_policies() {
+        let some = |a, b, c, d| [Some(a), Some(b), Some(c), Some(d)];
+        let last = Some([true, false, true, false]);
+
+        // First two outputs are active-low.
+        assert_eq!(
+            init_levels(StartupOutputs::AllOff, last),
+            some(true, true, false, false)
+        );
+        assert_eq!(
+            init_levels(StartupOutputs::AllOn, last),
+            some(false, false, true, true)
+        );
+        assert_eq!(
+            init_levels(StartupOutputs::RestoreLast, last),
+            some(false, true, true, false)
+        );
+        // Nothing persisted.
+        assert_eq!(
+            init_levels(StartupOutputs::RestoreLast, None),
+            some(true, true, false, false)
+        );
+    }
+
+    pub fn group_toggled_in_single_write() {
+        static GROUPS: [OutputGroup; 1] = [OutputGroup {
+            id: 7,
+            mask: 0b1_0101,
+            expander_id: 0,
+        }];
+        let mut outputs: IndexedOutputs<6, 1, 0, FakeExpander, NoPin> =
+            IndexedOutputs::new([FakeExpander::new()], [], [1, 2, 3, 4, 5, 6], [false; 6])
+                .with_groups(&GROUPS);
+        assert_eq!(outputs.group_members(7).unwrap().as_slice(), &[1, 3, 5]);
+        assert_eq!(outputs.group_state(7), Some(false));
+
+        // Outputs 1, 3 and 5 switch on together.
+        assert_eq!(embassy_futures::block_on(outputs.toggle_group(7)), Ok(true));
+        let writes = &outputs.grouped[0].writes;
+        assert_eq!(writes.len(), 1);
+        assert_eq!(
+            writes[0][0..6],
+            [Some(true), None, Some(true), None, Some(true), None]
+        );
+        assert_eq!(outputs.group_state(7), Some(true));
+
+        // Any output on turns the whole group off.
+        assert!(embassy_futures::block_on(outputs.set(1, false)).is_ok());
+        assert_eq!(outputs.group_state(7), Some(true));
+        assert_eq!(
+            embassy_futures::block_on(outputs.toggle_group(7)),
+            Ok(false)
+        );
+        assert_eq!(outputs.grouped[0].writes.len(), 3);
+        assert_eq!(outputs.get(3), Some(false));
+        assert_eq!(outputs.get(5), Some(false));
+
+        // Unknown group.
+        assert!(embassy_futures::block_on(outputs.toggle_group(8)).is_err());
+        assert_eq!(outputs.group_members(8), None);
+    }
+
+    pub fn batched_changes_single_write() {
+        // Outputs 1-4 on the expander, 51 on a native pin, fallback for 2.
+        let mut outputs: IndexedOutputs<17, 1, 2, FakeExpander, FakePin> = IndexedOutputs::new(
+            [FakeExpander::new()],
+            [FakePin { high: None }, FakePin { high: None }],
+            core::array::from_fn(|pos| if pos < 16 { pos as u8 + 1 } else { 51 }),
+            [false; 17],
+        )
+        .with_fallbacks(&[(2, 1)]);
+
+        let changes = [(1, true), (2, true), (3, true), (4, true), (51, true)];
+        assert!(embassy_futures::block_on(outputs.set_many(&changes)).is_ok());
+        let writes = &outputs.grouped[0].writes;
+        assert_eq!(writes.len(), 1);
+        assert_eq!(
+            writes[0][0..5],
+            [Some(true), Some(true), Some(true), Some(true), None]
+        );
+        assert_eq!(outputs.native[0].high, Some(true));
+        assert_eq!(outputs.get(51), Some(true));
+
+        // Failed write is retried per output: the one with a fallback still
+        // changes, error of the other is reported.
+        outputs.grouped[0].offline = true;
+        let result = embassy_futures::block_on(outputs.set_many(&[(1, false), (2, false)]));
+        assert_eq!(result.map_err(|err| err.line), Err(0));
+        assert_eq!(outputs.get(1), Some(true));
+        assert_eq!(outputs.get(2), Some(false));
+        assert_eq!(outputs.native[1].high, Some(false));
+    }
+
+    pub fn exclusive_pair_interlock() {
+        // Active-low shutter relays: up = 1, down = 2.
+        let mut outputs: IndexedOutputs<4, 1, 0, FakeExpander, NoPin> = IndexedOutputs::new(
+            [FakeExpander::new()],
+            [],
+            [1, 2, 3, 4],
+            [true, true, false, false],
+        );
+        for direction in [
+            Direction::Up,
+            Direction::Down,
+            Direction::Up,
+            Direction::Stop,
+            Direction::Down,
+        ] {
+            let writes = outputs.grouped[0].writes.len();
+            assert!(embassy_futures::block_on(outputs.set_exclusive(1, 2, direction)).is_ok());
+            // Direction changes in a single write.
+            assert_eq!(outputs.grouped[0].writes.len(), writes + 1);
+            assert_eq!(outputs.get(1), Some(direction == Direction::Up));
+            assert_eq!(outputs.get(2), Some(direction == Direction::Down));
+        }
+
+        // Low level is active - both lines were never low at once.
+        for levels in &outputs.grouped[0].writes {
+            assert_ne!((levels[0], levels[1]), (Some(false), Some(false)));
+        }
+
+        // Pair needs two distinct known outputs.
+        assert!(embassy_futures::block_on(outputs.set_exclusive(1, 1, Direction::Up)).is_err());
+        assert!(embassy_futures::block_on(outputs.set_exclusive(1, 9, Direction::Up)).is_err());
+    }
+
+    pub fn error_identifies_line() {
+        let second = FakeExpander {
+            id: 1,
+            broken_line: Some(2),
+            ..FakeExpander::new()
+        };
+        let mut outputs: IndexedOutputs<20, 2, 0, FakeExpander, NoPin> = IndexedOutputs::new(
+            [FakeExpander::new(), second],
+            [],
+            core::array::from_fn(|pos| pos as u8 + 1),
+            [false; 20],
+        );
+
+        // Output 19 is the third line of the second expander.
+        let expected = OutputError {
+            expander: 1,
+            line: 2,
+            fault: OutputFault::Bus,
+        };
+        assert_eq!(
+            embassy_futures::block_on(outputs.set(19, true)),
+            Err(expected)
+        );
+        assert_eq!(embassy_futures::block_on(outputs.toggle(19)), Err(expected));
+        assert_eq!(outputs.get(19), Some(false));
+        assert_eq!(expected.detail(), 0x12);
+
+        // Neighbours still work.
+        assert!(embassy_futures::block_on(outputs.set(18, true)).is_ok());
+        assert!(embassy_futures::block_on(outputs.set(3, true)).is_ok());
+
+        // All off reports the failure but continues.
+        assert_eq!(embassy_futures::block_on(outputs.all_off()), Err(expected));
+        assert_eq!(outputs.get(18), Some(false));
+
+        let unknown = embassy_futures::block_on(outputs.set(40, true)).unwrap_err();
+        assert_eq!(unknown.fault, OutputFault::UnknownOutput);
+        assert_eq!(unknown.detail(), 0xFF);
+    }
 }
@@ -0,0 +1,102 @@
+/*
+ * Logical output state (on/off) and its mapping to the physical pin level.
+ * Relay boards are usually active-low, so "on" means driving the line low.
+ * This is the only place that inverts the level - everything above works
+ * with logical states.
+ */
+use embedded_hal::digital::PinState;
+
+/// Pin level that activates the output.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, defmt::Format)]
+pub enum Polarity {
+    ActiveHigh,
+    ActiveLow,
+}
+
+impl Polarity {
+    pub const fn from_active_low(active_low: bool) -> Self {
+        if active_low {
+            Self::ActiveLow
+        } else {
+            Self::ActiveHigh
+        }
+    }
+}
+
+/// Output with a logical state and a polarity.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, defmt::Format)]
+pub struct LogicalOutput {
+    polarity: Polarity,
+    /// Last state written.
+    on: bool,
+}
+
+impl LogicalOutput {
+    /// Output starts as off.
+    pub const fn new(polarity: Polarity) -> Self {
+        Self {
+            polarity,
+            on: false,
+        }
+    }
+
+    pub fn is_on(&self) -> bool {
+        self.on
+    }
+
+    /// Physical level for a logical state.
+    pub fn level(&self, on: bool) -> PinState {
+        match (self.polarity, on) {
+            (Polarity::ActiveHigh, true) | (Polarity::ActiveLow, false) => PinState::High,
+            (Polarity::ActiveHigh, false) | (Polarity::ActiveLow, true) => PinState::Low,
+        }
+    }
+
+    /// Record the state after its level was written along with other
+    /// outputs (see GroupedOutputs::set_levels).
+    pub fn set_written(&mut self, on: bool) {
+        self.on = on;
+    }
+
+    /// Write the logical state using a physical level writer. State is
+    /// updated only if the write succeeds.
+    pub async fn write<E>(
+        &mut self,
+        on: bool,
+        writer: impl AsyncFnOnce(PinState) -> Result<(), E>,
+    ) -> Result<(), E> {
+        writer(self.level(on)).await?;
+        self.on = on;
+        Ok(())
+    }
+}
+
+pub mod tests {
+    use super::*;
+
+    pub fn polarity_mapping() {
+        let high = LogicalOutput::new(Polarity::ActiveHigh);
+        assert_eq!(high.level(true), PinState::High);
+        assert_eq!(high.level(false), PinState::Low);
+
+        let low = LogicalOutput::new(Polarity::from_active_low(true));
+        assert_eq!(low.level(true), PinState::Low);
+        assert_eq!(low.level(false), PinState::High);
+        assert!(!low.is_on());
+
+        // State follows successful writes only.
+        let mut output = low;
+        let mut written = None;
+        let result: Result<(), ()> = embassy_futures::block_on(output.write(true, async |level| {
+            written = Some(level);
+            Ok(())
+        }));
+        assert!(result.is_ok());
+        assert_eq!(written, Some(PinState::Low));
+        assert!(output.is_on());
+
+        let result = embassy_futures::block_on(output.write(false, async |_| Err(())));
+        assert!(result.is_err());
+        assert!(output.is_on());
+    }
+}
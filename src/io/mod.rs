@@ -1,6 +1,16 @@
+#[cfg(target_os = "none")]
+pub mod adc_inputs;
+pub mod clock;
 pub mod event_converter;
 pub mod events;
+#[cfg(target_os = "none")]
 pub mod expander_inputs;
 pub mod expander_outputs;
+pub mod i2c_probe;
 pub mod indexed_outputs;
+pub mod logical_output;
+#[cfg(target_os = "none")]
+pub mod native_inputs;
 pub mod pcf8575;
+#[cfg(target_os = "none")]
+pub mod rotary_encoder;
@@ -0,0 +1,130 @@
+/*
+ * Native µC input pins. The pin is configured once (pull resistor) and the
+ * configured Input is kept for reads. Active level maps the physical level
+ * to a logical state, like LogicalOutput does for outputs.
+ */
+use core::cell::RefCell;
+use embassy_stm32::Peri;
+use embassy_stm32::gpio::{Input, Pin, Pull};
+use embassy_sync::blocking_mutex::{Mutex, raw::NoopRawMutex};
+use embedded_hal::digital::InputPin;
+
+use crate::io::logical_output::Polarity;
+
+/// Pull resistor and active level of a native input.
+#[derive(Copy, Clone)]
+pub struct InputConfig {
+    pub pull: Pull,
+    pub polarity: Polarity,
+}
+
+impl InputConfig {
+    pub const fn new(pull: Pull, polarity: Polarity) -> Self {
+        Self { pull, polarity }
+    }
+}
+
+/// Configured native input.
+pub struct NativeInput<P> {
+    pin: Mutex<NoopRawMutex, RefCell<P>>,
+    config: InputConfig,
+}
+
+impl NativeInput<Input<'static>> {
+    /// Configure the pin once according to the config.
+    pub fn configure(pin: Peri<'static, impl Pin>, config: InputConfig) -> Self {
+        Self::new(Input::new(pin, config.pull), config)
+    }
+}
+
+impl<P: InputPin> NativeInput<P> {
+    /// Wrap a pin that was already configured with `config.pull`.
+    pub fn new(pin: P, config: InputConfig) -> Self {
+        Self {
+            pin: Mutex::new(RefCell::new(pin)),
+            config,
+        }
+    }
+
+    pub fn config(&self) -> InputConfig {
+        self.config
+    }
+
+    /// Logical state of the input.
+    pub fn is_active(&self) -> Result<bool, P::Error> {
+        let high = self.pin.lock(|pin| pin.borrow_mut().is_high())?;
+        Ok(match self.config.polarity {
+            Polarity::ActiveHigh => high,
+            Polarity::ActiveLow => !high,
+        })
+    }
+}
+
+pub mod tests {
+    use super::*;
+    use core::convert::Infallible;
+    use embedded_hal::digital::ErrorType;
+
+    /// Line that is either driven or left floating.
+    struct FakePin {
+        pull: Pull,
+        driven: Option<bool>,
+        reads: usize,
+    }
+
+    impl FakePin {
+        fn new(pull: Pull, driven: Option<bool>) -> Self {
+            Self {
+                pull,
+                driven,
+                reads: 0,
+            }
+        }
+    }
+
+    impl ErrorType for FakePin {
+        type Error = Infallible;
+    }
+
+    impl InputPin for FakePin {
+        fn is_high(&mut self) -> Result<bool, Infallible> {
+            self.reads += 1;
+            Ok(match (self.driven, self.pull) {
+                (Some(high), _) => high,
+                (None, Pull::Up) => true,
+                (None, Pull::Down) => false,
+                // Undefined in reality.
+                (None, Pull::None) => false,
+            })
+        }
+
+        fn is_low(&mut self) -> Result<bool, Infallible> {
+            self.is_high().map(|high| !high)
+        }
+    }
+
+    fn read(pull: Pull, polarity: Polarity, driven: Option<bool>) -> bool {
+        let config = InputConfig::new(pull, polarity);
+        let input = NativeInput::new(FakePin::new(pull, driven), config);
+        let active = input.is_active().unwrap();
+        // Same pin on the next read.
+        assert_eq!(input.is_active().unwrap(), active);
+        assert_eq!(input.pin.lock(|pin| pin.borrow().reads), 2);
+        active
+    }
+
+    pub fn pull_settings() {
+        // Switch to ground with a pull-up: closed is active.
+        assert!(!read(Pull::Up, Polarity::ActiveLow, None));
+        assert!(read(Pull::Up, Polarity::ActiveLow, Some(false)));
+
+        // Switch to VCC with a pull-down: closed is active.
+        assert!(!read(Pull::Down, Polarity::ActiveHigh, None));
+        assert!(read(Pull::Down, Polarity::ActiveHigh, Some(true)));
+
+        // Externally driven line without a pull.
+        assert!(read(Pull::None, Polarity::ActiveHigh, Some(true)));
+        assert!(!read(Pull::None, Polarity::ActiveHigh, Some(false)));
+        assert!(read(Pull::None, Polarity::ActiveLow, Some(false)));
+    }
+}
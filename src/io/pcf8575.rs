@@ -1,4 +1,4 @@
-use embedded_hal_async::i2c;
+use embedded_hal_async::i2c::{self, Error as _};
 
 /// Thin wrapper over PCF8575 module.
 /// TODO: Handle INT line and read only when triggered. Here... or layer higher?
@@ -10,12 +10,29 @@ pub struct Pcf8575<BUS: i2c::I2c> {
     addr: u8,
 }
 
+/// Address with all address pins (A2-A0) low.
+pub const BASE_ADDR: u8 = 0x20;
+/// Address with all address pins high.
+pub const MAX_ADDR: u8 = 0x27;
+
 impl<BUS: i2c::I2c> Pcf8575<BUS> {
     pub fn new(i2c: BUS, a0: bool, a1: bool, a2: bool) -> Self {
-        let addr = 0x20 | ((a2 as u8) << 2) | ((a1 as u8) << 1) | (a0 as u8);
+        let addr = BASE_ADDR | ((a2 as u8) << 2) | ((a1 as u8) << 1) | (a0 as u8);
         Self { i2c, addr }
     }
 
+    /// Expander at a raw I²C address. None outside of 0x20-0x27.
+    pub fn with_address(i2c: BUS, addr: u8) -> Option<Self> {
+        (BASE_ADDR..=MAX_ADDR)
+            .contains(&addr)
+            .then_some(Self { i2c, addr })
+    }
+
+    /// I2C address of the expander.
+    pub fn addr(&self) -> u8 {
+        self.addr
+    }
+
     /// Byte order: port 0 (P07-P00), port 1 (P17-P10)
     pub async fn read(&mut self) -> Result<u16, ()> {
         let mut buf = [0, 0];
@@ -23,9 +40,47 @@ impl<BUS: i2c::I2c> Pcf8575<BUS> {
         Ok(u16::from_le_bytes(buf))
     }
 
-    pub async fn write(&mut self, data: u16) -> Result<(), ()> {
+    /// Fails with the kind of the bus error, so outputs can tell a missing
+    /// expander from a disturbed bus.
+    pub async fn write(&mut self, data: u16) -> Result<(), i2c::ErrorKind> {
         let buf = data.to_le_bytes();
-        self.i2c.write(self.addr, &buf).await.map_err(|_e| ())?;
+        self.i2c
+            .write(self.addr, &buf)
+            .await
+            .map_err(|e| e.kind())?;
         Ok(())
     }
 }
+
+pub mod tests {
+    use super::*;
+
+    /// Bus that's never used.
+    struct NoBus;
+
+    impl i2c::ErrorType for NoBus {
+        type Error = core::convert::Infallible;
+    }
+
+    impl i2c::I2c for NoBus {
+        async fn transaction(
+            &mut self,
+            _address: u8,
+            _operations: &mut [i2c::Operation<'_>],
+        ) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    pub fn raw_address_matches_pins() {
+        let strapped = Pcf8575::new(NoBus, true, true, false);
+        let raw = Pcf8575::with_address(NoBus, 0x23).unwrap();
+        assert_eq!(raw.addr(), strapped.addr());
+        assert_eq!(Pcf8575::new(NoBus, false, false, false).addr(), BASE_ADDR);
+        assert_eq!(Pcf8575::new(NoBus, true, true, true).addr(), MAX_ADDR);
+
+        for addr in [0x00, 0x1F, 0x28, 0x38, 0xFF] {
+            assert!(Pcf8575::with_address(NoBus, addr).is_none());
+        }
+    }
+}
@@ -0,0 +1,172 @@
+/*
+ * Rotary encoder on two native input pins as a source of relative steps.
+ * The pins follow a Gray code: 00 -> 01 -> 11 -> 10 -> 00 is one detent
+ * clockwise, the reverse one counter-clockwise. A sample has to be read
+ * twice in a row to count (debounce) and a contact bouncing between two
+ * neighbouring states cancels itself out. Jumps over a state (both pins
+ * changed) are ignored - direction is unknown.
+ */
+use embassy_time::{Duration, Instant, Timer};
+use embedded_hal::digital::InputPin;
+
+use crate::components::queue;
+use crate::config;
+use crate::io::events::{self, InputChannel, IoIdx, SwitchEvent, SwitchState};
+use crate::io::native_inputs::NativeInput;
+
+/// Fast enough not to miss a state of a quickly turned knob.
+pub const SCAN_PERIOD: Duration = Duration::from_millis(2);
+
+/// Gray code transitions per detent.
+const STEPS_PER_DETENT: i8 = 4;
+
+/// Quarter-steps indexed by (previous state << 2) | new state.
+const TRANSITIONS: [i8; 16] = [0, 1, -1, 0, -1, 0, 0, 1, 1, 0, 0, -1, 0, -1, 1, 0];
+
+/// Quadrature decoder of one encoder.
+pub struct Quadrature {
+    /// Last accepted state of the pins (A << 1 | B).
+    state: u8,
+    /// Sample seen in the previous poll, not accepted yet.
+    pending: u8,
+    /// Quarter-steps since the last detent.
+    quarters: i8,
+}
+
+impl Quadrature {
+    /// Start at the detent state the encoder rests in.
+    pub const fn new(a: bool, b: bool) -> Self {
+        let state = (a as u8) << 1 | b as u8;
+        Self {
+            state,
+            pending: state,
+            quarters: 0,
+        }
+    }
+
+    /// Process a sample of the pins. Returns +1 (clockwise) or -1 once a
+    /// full detent was turned.
+    pub fn update(&mut self, a: bool, b: bool) -> Option<i8> {
+        let sample = (a as u8) << 1 | b as u8;
+        let stable = sample == self.pending;
+        self.pending = sample;
+        if !stable || sample == self.state {
+            return None;
+        }
+
+        self.quarters += TRANSITIONS[((self.state << 2) | sample) as usize];
+        self.state = sample;
+        if self.quarters >= STEPS_PER_DETENT {
+            self.quarters = 0;
+            Some(1)
+        } else if self.quarters <= -STEPS_PER_DETENT {
+            self.quarters = 0;
+            Some(-1)
+        } else {
+            None
+        }
+    }
+}
+
+/// Polls the encoder pins and feeds the input queue.
+pub struct RotaryEncoderSource<P: InputPin> {
+    switch_id: IoIdx,
+    a: NativeInput<P>,
+    b: NativeInput<P>,
+    decoder: Quadrature,
+    queue: &'static InputChannel,
+}
+
+impl<P: InputPin> RotaryEncoderSource<P> {
+    pub fn new(
+        switch_id: IoIdx,
+        a: NativeInput<P>,
+        b: NativeInput<P>,
+        queue: &'static InputChannel,
+    ) -> Self {
+        if let Err(pos) = events::check_indices(&[switch_id]) {
+            defmt::panic!("Rotary encoder uses reserved input index 0 at {}", pos);
+        }
+        let decoder = Quadrature::new(
+            a.is_active().unwrap_or(false),
+            b.is_active().unwrap_or(false),
+        );
+        Self {
+            switch_id,
+            a,
+            b,
+            decoder,
+            queue,
+        }
+    }
+
+    /// Sample once and queue a resulting step.
+    pub async fn poll(&mut self) {
+        let (Ok(a), Ok(b)) = (self.a.is_active(), self.b.is_active()) else {
+            crate::warn_limited!(100, "Unable to read rotary encoder {}", self.switch_id);
+            return;
+        };
+        let Some(steps) = self.decoder.update(a, b) else {
+            return;
+        };
+        let event = SwitchEvent {
+            switch_id: self.switch_id,
+            state: SwitchState::Rotated(steps),
+            at: Instant::now(),
+        };
+        if let Some(dropped) = queue::send(self.queue, event, config::board::QUEUE_OVERFLOW).await {
+            defmt::warn!("Input queue is full, dropped rotation {:?}", dropped);
+        }
+    }
+
+    pub async fn run(&mut self) -> ! {
+        loop {
+            self.poll().await;
+            Timer::after(SCAN_PERIOD).await;
+        }
+    }
+}
+
+pub mod tests {
+    use super::*;
+
+    /// Feed each (A, B) sample twice, as a steady signal is polled.
+    fn feed(decoder: &mut Quadrature, samples: &[(bool, bool)]) -> heapless::Vec<i8, 8> {
+        let mut steps = heapless::Vec::new();
+        for &(a, b) in samples {
+            for _ in 0..2 {
+                if let Some(step) = decoder.update(a, b) {
+                    steps.push(step).unwrap();
+                }
+            }
+        }
+        steps
+    }
+
+    pub fn quadrature_steps() {
+        const CW: [(bool, bool); 4] = [(false, true), (true, true), (true, false), (false, false)];
+        const CCW: [(bool, bool); 4] = [(true, false), (true, true), (false, true), (false, false)];
+
+        let mut decoder = Quadrature::new(false, false);
+        assert_eq!(feed(&mut decoder, &CW).as_slice(), &[1]);
+        assert_eq!(feed(&mut decoder, &CW).as_slice(), &[1]);
+        assert_eq!(feed(&mut decoder, &CCW).as_slice(), &[-1]);
+
+        // Partial turn back and forth is not a step.
+        let wiggle = [(false, true), (false, false), (false, true), (false, false)];
+        assert!(feed(&mut decoder, &wiggle).is_empty());
+
+        // Single-sample glitches are ignored.
+        assert_eq!(decoder.update(true, true), None);
+        assert_eq!(decoder.update(false, false), None);
+        assert!(feed(&mut decoder, &CCW[..3]).is_empty());
+        assert_eq!(decoder.update(true, false), None);
+        assert_eq!(decoder.update(false, false), None);
+        assert_eq!(decoder.update(false, false), Some(-1));
+
+        // Jump over a state has no direction.
+        let mut decoder = Quadrature::new(false, false);
+        assert!(feed(&mut decoder, &[(true, true), (false, false)]).is_empty());
+        assert_eq!(feed(&mut decoder, &CW).as_slice(), &[1]);
+    }
+}
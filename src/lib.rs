@@ -1,5 +1,6 @@
-#![no_std]
-#![no_main]
+// Host tests (`just test-host`) run with std.
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), no_main)]
 
 // Adding/multiplying const expressions
 // #![feature(generic_const_exprs)]
@@ -11,17 +12,74 @@ pub mod intercom;
 pub mod status;
 */
 pub mod app;
+#[cfg(target_os = "none")]
 pub mod boards;
 pub mod buttonsmash;
 pub mod components;
 pub mod config;
 pub mod io;
 
-pub fn stack_addr() {
+#[cfg(all(test, not(target_os = "none")))]
+mod host_tests;
+
+/// Bytes of stack used at the call site. Stack grows down from the end of RAM.
+pub fn stack_used() -> u32 {
     let a: u32 = 0;
     let ap = &a as *const u32;
     let mem_size = 32768;
-    let diff = mem_size - (ap as u64).wrapping_sub(0x20000000u64);
+    (mem_size - (ap as u64).wrapping_sub(0x20000000u64)) as u32
+}
+
+/// Fill value of the never used stack, see paint_stack.
+#[cfg(target_os = "none")]
+const STACK_PAINT: u32 = 0x5AC4_5AC4;
+
+#[cfg(target_os = "none")]
+unsafe extern "C" {
+    /// Top of the stack, from memory.x.
+    static _stack_start: u32;
+}
+
+/// Paint the free stack below the caller, so stack_high_water can find the
+/// deepest use later. Call once, early in main.
+#[cfg(target_os = "none")]
+pub fn paint_stack() {
+    let mark: u32 = 0;
+    // Leave some room for the frame of this call.
+    let top = (&mark as *const u32).wrapping_sub(16);
+    let mut word = cortex_m_rt::heap_start();
+    while (word as *const u32) < top {
+        // SAFETY: Between the static data and the stack pointer - unused.
+        unsafe {
+            word.write_volatile(STACK_PAINT);
+            word = word.add(1);
+        }
+    }
+}
+
+/// Deepest stack use [bytes] since paint_stack: the paint left intact on the
+/// bottom of the stack wasn't ever reached.
+#[cfg(target_os = "none")]
+pub fn stack_high_water() -> u32 {
+    let top = &raw const _stack_start;
+    let mut word = cortex_m_rt::heap_start() as *const u32;
+    // SAFETY: Reads of the RAM between the static data and the stack top.
+    while word < top && unsafe { word.read_volatile() } == STACK_PAINT {
+        word = word.wrapping_add(1);
+    }
+    (top as usize - word as usize) as u32
+}
+
+/// Host stack isn't painted.
+#[cfg(not(target_os = "none"))]
+pub fn stack_high_water() -> u32 {
+    0
+}
+
+pub fn stack_addr() {
+    let a: u32 = 0;
+    let ap = &a as *const u32;
+    let diff = stack_used();
     defmt::info!(
         "CUR Stack address is {:#02x}, {:#02x}, {} bytes",
         ap,
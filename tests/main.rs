@@ -14,10 +14,345 @@ mod tests {
     async fn init() {}
 
     #[test]
-    #[timeout(3)]
-    async fn single_shutter() {
+    fn single_shutter() {
         use io_ctrl::buttonsmash::shutters;
-        shutters::tests::single_shutter().await;
+        shutters::tests::single_shutter();
+    }
+
+    #[test]
+    fn shutter_min_pulse() {
+        use io_ctrl::buttonsmash::shutters;
+        shutters::tests::min_pulse();
+    }
+
+    #[test]
+    fn shutter_group_stagger() {
+        use io_ctrl::buttonsmash::shutters;
+        shutters::tests::grouped_start_stagger();
+    }
+
+    #[test]
+    fn shutter_group_fan_out() {
+        use io_ctrl::buttonsmash::shutters;
+        shutters::tests::group_fan_out();
+    }
+
+    #[test]
+    fn shutter_group_close_staggered() {
+        use io_ctrl::buttonsmash::shutters;
+        shutters::tests::group_close_staggered();
+    }
+
+    #[test]
+    fn shutter_resync_all_out_of_sync() {
+        use io_ctrl::buttonsmash::shutters;
+        shutters::tests::resync_all_out_of_sync();
+    }
+
+    #[test]
+    fn shutter_positions_serialization() {
+        use io_ctrl::buttonsmash::shutters;
+        shutters::tests::positions_serialization();
+    }
+
+    #[test]
+    fn shutter_over_travel_cap() {
+        use io_ctrl::buttonsmash::shutters;
+        shutters::tests::over_travel_cap();
+    }
+
+    #[test]
+    fn shutters_stuck_sweep() {
+        use io_ctrl::buttonsmash::shutters;
+        shutters::tests::stuck_shutter_swept();
+    }
+
+    #[test]
+    fn shutter_config_round_trip() {
+        use io_ctrl::buttonsmash::shutters;
+        shutters::tests::config_round_trip();
+    }
+
+    #[test]
+    fn shutter_dispatch() {
+        use io_ctrl::buttonsmash::shutters;
+        shutters::tests::dispatch_reaches_manager();
+    }
+
+    #[test]
+    fn shutter_projected_position() {
+        use io_ctrl::buttonsmash::shutters;
+        shutters::tests::projected_halfway();
+    }
+
+    #[test]
+    fn shutter_park_all() {
+        use io_ctrl::buttonsmash::shutters;
+        shutters::tests::park_mid_drop();
+    }
+
+    #[test]
+    fn shutter_hysteresis_per_shutter() {
+        use io_ctrl::buttonsmash::shutters;
+        shutters::tests::per_shutter_hysteresis();
+    }
+
+    #[test]
+    fn shutter_resync() {
+        use io_ctrl::buttonsmash::shutters;
+        shutters::tests::resync_to_closed();
+    }
+
+    #[test]
+    fn shutter_swap_direction() {
+        use io_ctrl::buttonsmash::shutters;
+        shutters::tests::swap_direction();
+    }
+
+    #[test]
+    fn shutter_min_movement() {
+        use io_ctrl::buttonsmash::shutters;
+        shutters::tests::tiny_moves_ignored();
+    }
+
+    #[test]
+    fn shutter_persist_throttle() {
+        use io_ctrl::buttonsmash::shutters;
+        shutters::tests::persist_throttled();
+    }
+
+    #[test]
+    fn shutter_motion_events() {
+        use io_ctrl::buttonsmash::shutters;
+        shutters::tests::motion_events_full_open();
+    }
+
+    #[test]
+    fn actuator_failure_surfaced() {
+        use io_ctrl::app::actuator;
+        actuator::tests::failed_disable_is_surfaced();
+    }
+
+    #[test]
+    fn direct_mode_toggle() {
+        use io_ctrl::app::direct;
+        direct::tests::short_click_toggles();
+    }
+
+    #[test]
+    fn persistent_store() {
+        use io_ctrl::components::persistent_store;
+        persistent_store::tests::round_trip_and_corruption();
+    }
+
+    #[test]
+    fn diagnostics_dump() {
+        use io_ctrl::components::diagnostics;
+        diagnostics::tests::dump_round_trip();
+    }
+
+    #[test]
+    fn event_trace_ring() {
+        use io_ctrl::components::trace;
+        trace::tests::keeps_last_entries();
+    }
+
+    #[test]
+    fn time_announcement_policy() {
+        use io_ctrl::components::time_sync;
+        time_sync::tests::drift_and_jumps();
+    }
+
+    #[test]
+    fn boot_crash_loop_safe_mode() {
+        use io_ctrl::components::boot_guard;
+        boot_guard::tests::crash_loop_enters_safe_mode();
+    }
+
+    #[test]
+    fn gate_usb_filter() {
+        use io_ctrl::components::bus_filter;
+        bus_filter::tests::allowlist_forwarding();
+    }
+
+    #[test]
+    fn interconnect_request_reply() {
+        use io_ctrl::components::reply;
+        reply::tests::ping_pong();
+    }
+
+    #[test]
+    fn retry_policy() {
+        use io_ctrl::components::retry;
+        retry::tests::policy_transitions();
+    }
+
+    #[test]
+    fn coalesce_set_output_burst() {
+        use io_ctrl::components::coalesce;
+        coalesce::tests::last_command_wins();
+    }
+
+    #[test]
+    fn node_address_change() {
+        use io_ctrl::components::node_address;
+        node_address::tests::responds_to_new_address();
+    }
+
+    #[test]
+    fn bus_watchdog_silence() {
+        use io_ctrl::components::bus_watchdog;
+        bus_watchdog::tests::silent_then_recovered();
+    }
+
+    #[test]
+    fn spawn_failure_reported() {
+        use io_ctrl::components::spawn;
+        spawn::tests::failed_spawn_reported();
+    }
+
+    #[test]
+    fn sequence_gap_detection() {
+        use io_ctrl::components::sequence;
+        sequence::tests::detects_skipped();
+    }
+
+    #[test]
+    fn queue_overflow_policies() {
+        use io_ctrl::components::queue;
+        queue::tests::overflow_policies();
+    }
+
+    #[test]
+    fn role_selection() {
+        use io_ctrl::app::role;
+        role::tests::role_selects_task_set();
+    }
+
+    #[test]
+    fn output_polarity() {
+        use io_ctrl::io::logical_output;
+        logical_output::tests::polarity_mapping();
+    }
+
+    #[test]
+    fn status_warning_priority() {
+        use io_ctrl::components::status;
+        status::tests::warning_not_starved();
+    }
+
+    #[test]
+    fn status_led_polarity() {
+        use io_ctrl::components::status;
+        status::tests::led_polarity();
+    }
+
+    #[test]
+    fn status_code_blinks() {
+        use io_ctrl::components::status;
+        status::tests::code_blink_patterns();
+    }
+
+    #[test]
+    fn status_uptime_saturation() {
+        use io_ctrl::components::status;
+        status::tests::uptime_saturates();
+    }
+
+    #[test]
+    fn event_converter_timestamp() {
+        use io_ctrl::io::event_converter;
+        event_converter::tests::timestamp_is_preserved();
+    }
+
+    #[test]
+    fn event_converter_long_activated_once() {
+        use io_ctrl::io::event_converter;
+        event_converter::tests::long_activated_once();
+    }
+
+    #[test]
+    fn event_converter_click_rate_limit() {
+        use io_ctrl::io::event_converter;
+        event_converter::tests::click_rate_limited();
+    }
+
+    #[test]
+    fn event_converter_contract() {
+        use io_ctrl::io::event_converter;
+        event_converter::tests::converter_contract_replay();
+    }
+
+    #[test]
+    fn event_converter_long_press_per_input() {
+        use io_ctrl::io::event_converter;
+        event_converter::tests::long_press_per_input();
+    }
+
+    #[test]
+    fn adaptive_scan_period() {
+        use io_ctrl::io::expander_inputs;
+        expander_inputs::tests::scan_period_shortens_after_edge();
+    }
+
+    #[test]
+    fn reserved_io_index() {
+        use io_ctrl::io::expander_inputs;
+        expander_inputs::tests::reserved_index_is_rejected();
+    }
+
+    #[test]
+    fn expander_shared_bus() {
+        use io_ctrl::io::expander_inputs;
+        expander_inputs::tests::shared_bus_readers_interleave();
+    }
+
+    #[test]
+    fn expander_input_snapshot() {
+        use io_ctrl::io::expander_inputs;
+        expander_inputs::tests::input_snapshot_never_tears();
+    }
+
+    #[test]
+    fn expander_pulse_inputs() {
+        use io_ctrl::io::expander_inputs;
+        expander_inputs::tests::pulse_inputs_count_edges();
+    }
+
+    #[test]
+    fn expander_startup_grace() {
+        use io_ctrl::io::expander_inputs;
+        expander_inputs::tests::slow_expander_within_startup_grace();
+    }
+
+    #[test]
+    fn expander_inputs_fake_clock() {
+        use io_ctrl::io::expander_inputs;
+        expander_inputs::tests::debounce_on_fake_clock();
+    }
+
+    #[test]
+    fn expander_inputs_failed_scans() {
+        use io_ctrl::io::expander_inputs;
+        expander_inputs::tests::held_time_skips_failed_scans();
+    }
+
+    #[test]
+    fn expander_inputs_short_pulses() {
+        use io_ctrl::io::expander_inputs;
+        expander_inputs::tests::short_pulses_counted_on_fake_clock();
+    }
+
+    #[test]
+    fn rotary_encoder_quadrature() {
+        use io_ctrl::io::rotary_encoder;
+        rotary_encoder::tests::quadrature_steps();
+    }
+
+    #[test]
+    fn expander_inputs_shared_bus_loops() {
+        use io_ctrl::io::expander_inputs;
+        expander_inputs::tests::shared_bus_scan_loops_progress();
     }
 
     #[test]
@@ -25,4 +360,352 @@ mod tests {
         use io_ctrl::buttonsmash::bindings;
         bindings::tests::it_adds_and_finds();
     }
+
+    #[test]
+    fn bindings_default_command() {
+        use io_ctrl::buttonsmash::bindings;
+        bindings::tests::unbound_key_emits_default();
+    }
+
+    #[test]
+    fn bindings_dump() {
+        use io_ctrl::buttonsmash::bindings;
+        bindings::tests::dump_lists_bound();
+    }
+
+    #[test]
+    fn bindings_command_origin() {
+        use io_ctrl::buttonsmash::bindings;
+        bindings::tests::button_command_origin();
+    }
+
+    #[test]
+    fn register_messages() {
+        use io_ctrl::components::message;
+        message::tests::register_messages_round_trip();
+    }
+
+    #[test]
+    fn frame_length_clamp() {
+        use io_ctrl::components::message;
+        message::tests::too_long_frame_is_clamped();
+    }
+
+    #[test]
+    fn error_codes() {
+        use io_ctrl::components::message;
+        message::tests::error_codes_round_trip();
+    }
+
+    #[test]
+    fn message_remote_request() {
+        use io_ctrl::components::message;
+        message::tests::remote_request_decoded();
+    }
+
+    #[test]
+    fn state_cache_dump() {
+        use io_ctrl::components::state_cache;
+        state_cache::tests::dump_has_latest_outputs();
+    }
+
+    #[test]
+    fn message_reserved_types() {
+        use io_ctrl::components::message;
+        message::tests::reserved_types_rejected();
+    }
+
+    #[test]
+    fn message_can_addr_round_trip() {
+        use io_ctrl::components::message;
+        message::tests::can_addr_round_trip();
+    }
+
+    #[test]
+    fn message_frame_codec() {
+        use io_ctrl::components::message;
+        message::tests::frame_codec();
+    }
+
+    #[test]
+    fn external_rtc_preferred() {
+        use io_ctrl::components::external_rtc;
+        external_rtc::tests::external_rtc_preferred();
+    }
+
+    #[test]
+    fn message_arbitration_order() {
+        use io_ctrl::components::message;
+        message::tests::can_id_arbitration_order();
+    }
+
+    #[test]
+    fn message_started_version() {
+        use io_ctrl::components::message;
+        message::tests::started_version_round_trip();
+    }
+
+    #[test]
+    fn message_truncated_payloads() {
+        use io_ctrl::components::message;
+        message::tests::truncated_payloads_rejected();
+    }
+
+    #[test]
+    fn message_request_sender() {
+        use io_ctrl::components::message;
+        message::tests::request_sender_optional();
+    }
+
+    #[test]
+    fn usb_decoder() {
+        use io_ctrl::components::usb_connect;
+        usb_connect::tests::decoder_reassembles();
+    }
+
+    #[test]
+    fn momentary_outputs() {
+        use io_ctrl::buttonsmash::momentary;
+        momentary::tests::momentary_release_and_timeout();
+    }
+
+    #[test]
+    fn timed_output() {
+        use io_ctrl::buttonsmash::timed;
+        timed::tests::on_then_off_after_time();
+    }
+
+    #[test]
+    fn pulsed_output() {
+        use io_ctrl::buttonsmash::pulsed;
+        pulsed::tests::pulses_then_restores();
+    }
+
+    #[test]
+    fn maintenance_mode() {
+        use io_ctrl::buttonsmash::maintenance;
+        maintenance::tests::maintenance_drops_local_events();
+    }
+
+    #[test]
+    fn chord_binding() {
+        use io_ctrl::buttonsmash::microvm;
+        microvm::tests::chord_replaces_tap_binding();
+    }
+
+    #[test]
+    fn chord_slots() {
+        use io_ctrl::buttonsmash::chords;
+        chords::tests::chord_slots_limited();
+    }
+
+    #[test]
+    fn registers() {
+        use io_ctrl::buttonsmash::microvm;
+        microvm::tests::set_register_changes_called_proc();
+    }
+
+    #[test]
+    fn runtime_reset() {
+        use io_ctrl::buttonsmash::microvm;
+        microvm::tests::runtime_state_resets();
+    }
+
+    #[test]
+    fn small_executor() {
+        use io_ctrl::buttonsmash::microvm;
+        microvm::tests::small_procedure_table();
+    }
+
+    #[test]
+    fn program_unknown_output() {
+        use io_ctrl::buttonsmash::microvm;
+        microvm::tests::unknown_output_is_rejected();
+    }
+
+    #[test]
+    fn program_missing_setup() {
+        use io_ctrl::buttonsmash::microvm;
+        microvm::tests::missing_setup_is_rejected();
+    }
+
+    #[test]
+    fn program_reload() {
+        use io_ctrl::buttonsmash::microvm;
+        microvm::tests::reload_replaces_bindings();
+    }
+
+    #[test]
+    fn microvm_multi_binding() {
+        use io_ctrl::buttonsmash::microvm;
+        microvm::tests::multi_binding_actions();
+    }
+
+    #[test]
+    fn microvm_edge_binding_trigger() {
+        use io_ctrl::buttonsmash::microvm;
+        microvm::tests::edge_binding_passes_trigger();
+    }
+
+    #[test]
+    fn microvm_default_program() {
+        use io_ctrl::buttonsmash::microvm;
+        microvm::tests::default_program_runs_on_mock();
+    }
+
+    #[test]
+    fn microvm_runaway_procedure() {
+        use io_ctrl::buttonsmash::microvm;
+        microvm::tests::runaway_procedure_aborted();
+    }
+
+    #[test]
+    fn microvm_reload_parks() {
+        use io_ctrl::buttonsmash::microvm;
+        microvm::tests::reload_waits_for_park();
+    }
+
+    #[test]
+    fn microvm_remote_output_ack() {
+        use io_ctrl::buttonsmash::microvm;
+        microvm::tests::remote_set_output_acknowledged();
+    }
+
+    #[test]
+    fn microvm_toggle_multi() {
+        use io_ctrl::buttonsmash::microvm;
+        microvm::tests::short_click_toggles_all_listed();
+    }
+
+    #[test]
+    fn microvm_deferred_layer_hold() {
+        use io_ctrl::buttonsmash::microvm;
+        microvm::tests::deferred_layer_hold_ignores_tap();
+    }
+
+    #[test]
+    fn microvm_locked_output() {
+        use io_ctrl::buttonsmash::microvm;
+        microvm::tests::locked_output_ignores_changes();
+    }
+
+    #[test]
+    fn microvm_rotary_dims() {
+        use io_ctrl::buttonsmash::microvm;
+        microvm::tests::rotary_dims_output();
+    }
+
+    #[test]
+    fn microvm_bindings_dump() {
+        use io_ctrl::buttonsmash::microvm;
+        microvm::tests::bindings_dump();
+    }
+
+    #[test]
+    fn microvm_scene_batch() {
+        use io_ctrl::buttonsmash::microvm;
+        microvm::tests::scene_recalled_in_one_write();
+    }
+
+    #[test]
+    fn microvm_feedback_loop() {
+        use io_ctrl::buttonsmash::microvm;
+        microvm::tests::remote_feedback_loop_suppressed();
+    }
+
+    #[test]
+    fn microvm_remote_origin() {
+        use io_ctrl::buttonsmash::microvm;
+        microvm::tests::remote_origin_from_sender();
+    }
+
+    #[test]
+    fn microvm_activate_for() {
+        use io_ctrl::buttonsmash::microvm;
+        microvm::tests::activate_for_expires();
+    }
+
+    #[test]
+    fn microvm_pulse_output() {
+        use io_ctrl::buttonsmash::microvm;
+        microvm::tests::pulse_restores_prior_state();
+    }
+
+    #[test]
+    fn feedback_guard_suppression() {
+        use io_ctrl::buttonsmash::feedback;
+        feedback::tests::alternating_changes_suppressed();
+    }
+
+    #[test]
+    fn scene_capture_recall() {
+        use io_ctrl::buttonsmash::scenes;
+        scenes::tests::capture_and_recall();
+    }
+
+    #[test]
+    fn i2c_address_conflict() {
+        use io_ctrl::io::i2c_probe;
+        i2c_probe::tests::address_conflict_is_detected();
+    }
+
+    #[test]
+    fn startup_output_policy() {
+        use io_ctrl::io::indexed_outputs;
+        indexed_outputs::tests::startup_policies();
+    }
+
+    #[test]
+    fn native_input_pull() {
+        use io_ctrl::io::native_inputs;
+        native_inputs::tests::pull_settings();
+    }
+
+    #[test]
+    fn adc_input_threshold() {
+        use io_ctrl::io::adc_inputs;
+        adc_inputs::tests::threshold_crossing();
+    }
+
+    #[test]
+    fn output_group_toggle() {
+        use io_ctrl::io::indexed_outputs;
+        indexed_outputs::tests::group_toggled_in_single_write();
+    }
+
+    #[test]
+    fn output_expander_failover() {
+        use io_ctrl::io::indexed_outputs;
+        indexed_outputs::tests::offline_expander_fails_over();
+    }
+
+    #[test]
+    fn output_batched_changes() {
+        use io_ctrl::io::indexed_outputs;
+        indexed_outputs::tests::batched_changes_single_write();
+    }
+
+    #[test]
+    fn output_exclusive_pair() {
+        use io_ctrl::io::indexed_outputs;
+        indexed_outputs::tests::exclusive_pair_interlock();
+    }
+
+    #[test]
+    fn output_error_identifies_line() {
+        use io_ctrl::io::indexed_outputs;
+        indexed_outputs::tests::error_identifies_line();
+    }
+
+    #[test]
+    fn pcf8575_raw_address() {
+        use io_ctrl::io::pcf8575;
+        pcf8575::tests::raw_address_matches_pins();
+    }
+
+    #[test]
+    fn expander_outputs_pending_commit() {
+        use io_ctrl::io::expander_outputs;
+        expander_outputs::tests::pending_changes_in_one_write();
+    }
 }